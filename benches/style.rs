@@ -0,0 +1,102 @@
+//! Times `style::style_tree` on a generated page against stylesheets of growing size, next to
+//! `style::linear_style_tree`, which tests every element against every rule on the same page and
+//! stylesheets, and on a long run of identical siblings.
+//!
+//! Run with `cargo bench --bench style`. With rules hashed by their rightmost id, class and tag
+//! name, the time per element should stay nearly flat as the rule count grows, while the linear
+//! baseline grows with it. Identical siblings should be much cheaper still, since all but the first
+//! reuse a shared style.
+//!
+//! Pass `--features parallel` to time `style::parallel_style_tree` instead.
+
+use ponyrender::{css, dom, parser, style};
+use std::time::Instant;
+
+const ELEMENTS: usize = 2_000;
+const ITERATIONS: u32 = 5;
+const ROWS: usize = 10_000;
+
+fn page() -> String {
+    let mut html = String::from("<div id=\"page\">");
+    for section in 0..ELEMENTS / 20 {
+        html.push_str(&format!("<div class=\"section section-{}\"><ul>", section));
+        for item in 0..19 {
+            html.push_str(&format!("<li class=\"item item-{}\">row</li>", item));
+        }
+        html.push_str("</ul></div>");
+    }
+    html.push_str("</div>");
+    html
+}
+
+fn rows() -> String {
+    let mut html = String::from("<ul>");
+    for _ in 0..ROWS {
+        html.push_str("<li class=\"row\">row</li>");
+    }
+    html.push_str("</ul>");
+    html
+}
+
+fn stylesheet(rules: usize) -> String {
+    let mut css = String::new();
+    for rule in 0..rules {
+        match rule % 4 {
+            0 => css.push_str(&format!(".rule-{} {{ padding: {}px; }}\n", rule, rule % 7)),
+            1 => css.push_str(&format!(".section-{} .item {{ margin: 1px; }}\n", rule)),
+            2 => css.push_str(&format!("#rule-{} li {{ display: block; }}\n", rule)),
+            _ => css.push_str(&format!("ul > .item-{} {{ padding: 2px; }}\n", rule)),
+        }
+    }
+    css
+}
+
+fn hashed<'a>(root: &'a dom::Node, stylesheets: &[css::Stylesheet]) -> style::StyledNode<'a> {
+    #[cfg(feature = "parallel")]
+    return style::parallel_style_tree(root, stylesheets);
+    #[cfg(not(feature = "parallel"))]
+    return style::style_tree(root, stylesheets);
+}
+
+fn time<'a>(
+    style_tree: fn(&'a dom::Node, &[css::Stylesheet]) -> style::StyledNode<'a>,
+    root: &'a dom::Node,
+    stylesheets: &[css::Stylesheet],
+    elements: usize,
+) -> String {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        style_tree(root, stylesheets);
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    format!(
+        "{:>10.2?} per tree, {:>8.2?} per element",
+        elapsed,
+        elapsed / elements as u32
+    )
+}
+
+fn main() {
+    let root = parser::parse_html(page());
+    for rules in [100, 1_000, 10_000] {
+        let stylesheets = [parser::parse_css(stylesheet(rules), &root)];
+        println!(
+            "{:>6} rules: hashed {}",
+            rules,
+            time(hashed, &root, &stylesheets, ELEMENTS)
+        );
+        println!(
+            "{:>6} rules: linear {}",
+            rules,
+            time(style::linear_style_tree, &root, &stylesheets, ELEMENTS)
+        );
+    }
+
+    let root = parser::parse_html(rows());
+    let stylesheets = [parser::parse_css(stylesheet(1_000), &root)];
+    println!(
+        "{:>6} rows:  hashed {}",
+        ROWS,
+        time(hashed, &root, &stylesheets, ROWS)
+    );
+}
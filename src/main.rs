@@ -1,6 +1,6 @@
-mod modules;
 use image::DynamicImage::ImageRgba8;
-use modules::*;
+use ponyrender::*;
+use std::env;
 use std::fs::read_to_string;
 use std::path::Path;
 
@@ -22,11 +22,53 @@ fn main() {
         margin: Default::default(),
     };
 
+    let args: Vec<String> = env::args().collect();
     let root_node = parser::parse_html(html);
-    let stylesheet = parser::parse_css(css, &root_node);
-    let style_root = style::style_tree(&root_node, &stylesheet);
-    let layout_root = layout::layout_tree(&style_root, initial_containing_block);
-    let canvas = display::paint(&layout_root, initial_containing_block.content);
+    let mut stylesheets = vec![parser::parse_css(css, &root_node)];
+    if !args.iter().any(|arg| arg == "--no-ua-stylesheet") {
+        stylesheets.push(parser::parse_user_agent_css());
+    }
+
+    let mut fonts = font::FontDatabase::new();
+    if let Some(dir) = args
+        .iter()
+        .position(|arg| arg == "--font-dir")
+        .and_then(|i| args.get(i + 1))
+    {
+        fonts
+            .load_directory(Path::new(dir))
+            .expect("Failed to read font directory");
+    }
+    // `@font-face` sources are relative to the document.
+    fonts.add_font_faces(&stylesheets, Path::new("tests"));
+
+    #[cfg(feature = "parallel")]
+    let style_root = style::parallel_style_tree(&root_node, &stylesheets);
+    #[cfg(not(feature = "parallel"))]
+    let style_root = style::style_tree(&root_node, &stylesheets);
+    // `--scroll <x>,<y>` renders the page scrolled to that position.
+    let scroll = args
+        .iter()
+        .position(|arg| arg == "--scroll")
+        .and_then(|i| args.get(i + 1))
+        .map(|position| {
+            let (x, y) = position.split_once(',').expect("Expected --scroll <x>,<y>");
+            (
+                x.parse().expect("Invalid scroll position"),
+                y.parse().expect("Invalid scroll position"),
+            )
+        })
+        .unwrap_or((0.0, 0.0));
+    // Images are loaded from paths relative to the document too.
+    let images = images::ImageCache::new(Path::new("tests"));
+    let layout_root = layout::layout_scrolled_tree(
+        &style_root,
+        initial_containing_block,
+        scroll,
+        &fonts,
+        &images,
+    );
+    let canvas = display::paint(&layout_root, initial_containing_block.content, &images);
 
     let filename = "output.png";
 
@@ -0,0 +1,133 @@
+use super::dom::ElementData;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+const KEY_BITS: u32 = 12;
+const KEY_MASK: u32 = (1 << KEY_BITS) - 1;
+const ARRAY_SIZE: usize = 1 << KEY_BITS;
+
+/// A counting Bloom filter holding the tag names, ids and classes of the ancestors of the element
+/// being styled.
+///
+/// Counters rather than bits let elements be removed again when the style traversal leaves them.
+/// A `false` from `might_contain` is definite, so descendant selectors naming something absent from
+/// every ancestor can be rejected without walking up the tree.
+#[derive(Clone)]
+pub struct AncestorFilter {
+    counters: Vec<u8>,
+}
+
+impl AncestorFilter {
+    pub fn new() -> AncestorFilter {
+        AncestorFilter {
+            counters: vec![0; ARRAY_SIZE],
+        }
+    }
+
+    pub fn push_element(&mut self, elem: &ElementData) {
+        for hash in element_hashes(elem) {
+            self.insert_hash(hash);
+        }
+    }
+
+    pub fn pop_element(&mut self, elem: &ElementData) {
+        for hash in element_hashes(elem) {
+            self.remove_hash(hash);
+        }
+    }
+
+    pub fn might_contain(&self, hash: u32) -> bool {
+        self.counters[first_slot(hash)] != 0 && self.counters[second_slot(hash)] != 0
+    }
+
+    fn insert_hash(&mut self, hash: u32) {
+        for slot in [first_slot(hash), second_slot(hash)] {
+            // A saturated counter can no longer be decremented safely, so it stays set for good.
+            self.counters[slot] = self.counters[slot].saturating_add(1);
+        }
+    }
+
+    fn remove_hash(&mut self, hash: u32) {
+        for slot in [first_slot(hash), second_slot(hash)] {
+            let counter = &mut self.counters[slot];
+            if *counter != u8::MAX {
+                *counter -= 1;
+            }
+        }
+    }
+}
+
+impl Default for AncestorFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn first_slot(hash: u32) -> usize {
+    (hash & KEY_MASK) as usize
+}
+
+fn second_slot(hash: u32) -> usize {
+    ((hash >> KEY_BITS) & KEY_MASK) as usize
+}
+
+fn hash_str(kind: char, value: &str) -> u32 {
+    let mut hasher = DefaultHasher::new();
+    kind.hash(&mut hasher);
+    value.hash(&mut hasher);
+    hasher.finish() as u32
+}
+
+pub fn tag_hash(tag_name: &str) -> u32 {
+    hash_str('t', tag_name)
+}
+
+pub fn id_hash(id: &str) -> u32 {
+    hash_str('#', id)
+}
+
+pub fn class_hash(class: &str) -> u32 {
+    hash_str('.', class)
+}
+
+fn element_hashes(elem: &ElementData) -> Vec<u32> {
+    let mut hashes = vec![tag_hash(&elem.tag_name)];
+    hashes.extend(elem.id().map(|id| id_hash(id)));
+    hashes.extend(elem.classes().into_iter().map(class_hash));
+    hashes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::dom::AttrMap;
+    use super::*;
+
+    fn elem(tag_name: &str, class: &str) -> ElementData {
+        let mut attributes = AttrMap::new();
+        attributes.insert("class".to_string(), class.to_string());
+        ElementData {
+            tag_name: tag_name.to_string(),
+            attributes,
+        }
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        let mut filter = AncestorFilter::new();
+        let outer = elem("ul", "menu");
+        let inner = elem("li", "item");
+
+        filter.push_element(&outer);
+        filter.push_element(&inner);
+        assert!(filter.might_contain(tag_hash("ul")));
+        assert!(filter.might_contain(class_hash("item")));
+
+        filter.pop_element(&inner);
+        assert!(filter.might_contain(class_hash("menu")));
+        assert!(!filter.might_contain(tag_hash("li")));
+        assert!(!filter.might_contain(class_hash("item")));
+
+        filter.pop_element(&outer);
+        assert!(!filter.might_contain(tag_hash("ul")));
+    }
+}
@@ -1,8 +1,20 @@
+use std::fmt;
+
 pub type Specificity = (usize, usize, usize);
 
+/// Where a stylesheet comes from. Declarations from a later origin win the cascade regardless of
+/// selector specificity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Origin {
+    UserAgent,
+    Author,
+}
+
 #[derive(Debug, PartialEq)]
 pub struct Stylesheet {
+    pub origin: Origin,
     pub rules: Vec<Rule>,
+    pub font_faces: Vec<FontFaceRule>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -11,19 +23,46 @@ pub struct Rule {
     pub declarations: Vec<Declaration>,
 }
 
+/// An `@font-face` rule, whose declarations are font descriptors like `src` and `font-weight`.
+#[derive(Debug, PartialEq)]
+pub struct FontFaceRule {
+    pub declarations: Vec<Declaration>,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum Selector {
     Simple(SimpleSelector),
+    /// `left combinator right`, where `right` is matched against the element itself and `left`
+    /// against one of its ancestors.
+    Complex(Box<Selector>, Combinator, SimpleSelector),
 }
 
 impl Selector {
     pub fn specificity(&self) -> Specificity {
-        let Selector::Simple(ref simple) = *self;
-        let a = simple.id.iter().count();
-        let b = simple.class.len();
-        let c = simple.tag_name.iter().count();
-        (a, b, c)
+        match *self {
+            Selector::Simple(ref simple) => simple.specificity(),
+            Selector::Complex(ref left, _, ref right) => {
+                let (a, b, c) = left.specificity();
+                let (d, e, f) = right.specificity();
+                (a + d, b + e, c + f)
+            }
+        }
     }
+
+    /// The rightmost compound selector, which the element itself must match.
+    pub fn subject(&self) -> &SimpleSelector {
+        match *self {
+            Selector::Simple(ref simple) | Selector::Complex(_, _, ref simple) => simple,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Combinator {
+    /// Whitespace: `div p`.
+    Descendant,
+    /// `div > p`.
+    Child,
 }
 
 #[derive(Debug, PartialEq)]
@@ -31,6 +70,18 @@ pub struct SimpleSelector {
     pub tag_name: Option<String>,
     pub id: Option<String>,
     pub class: Vec<String>,
+    /// The pseudo-element the selector styles instead of the element itself, like `marker` in
+    /// `li::marker`.
+    pub pseudo_element: Option<String>,
+}
+
+impl SimpleSelector {
+    pub fn specificity(&self) -> Specificity {
+        let a = self.id.iter().count();
+        let b = self.class.len();
+        let c = self.tag_name.iter().count() + self.pseudo_element.iter().count();
+        (a, b, c)
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -43,7 +94,17 @@ pub struct Declaration {
 pub enum Value {
     Keyword(String),
     Length(f32, Unit),
+    /// A number without a unit, like `line-height: 1.5`.
+    Number(f32),
     Color(Color),
+    /// A quoted string, like a family name in `font-family: "DejaVu Sans"`.
+    String(String),
+    Url(String),
+    Gradient(Box<Gradient>),
+    /// Comma-separated values, like `font-family: Arial, sans-serif`.
+    List(Vec<Value>),
+    /// Space-separated values, like `"liga" 0` in `font-feature-settings`.
+    Sequence(Vec<Value>),
 }
 
 impl Value {
@@ -54,11 +115,200 @@ impl Value {
             _ => 0.0,
         }
     }
+
+    /// Return the size of a length or percentage in px, with percentages taken of `reference`.
+    pub fn resolve(&self, reference: f32) -> f32 {
+        match *self {
+            Value::Length(percentage, Unit::Percent) => percentage / 100.0 * reference,
+            _ => self.to_px(),
+        }
+    }
+
+    /// Return the parts of a space-separated value, or the value itself if it is not one.
+    pub fn parts(&self) -> &[Value] {
+        match self {
+            Value::Sequence(values) => values,
+            value => std::slice::from_ref(value),
+        }
+    }
+
+    /// Return the keywords among the parts of a space-separated value, like `row dense`.
+    pub fn keywords(&self) -> Vec<&str> {
+        self.parts()
+            .iter()
+            .filter_map(|part| match part {
+                Value::Keyword(keyword) => Some(keyword.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Value {
+    /// Serialize the value as CSS text.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Keyword(ref keyword) => write!(f, "{}", keyword),
+            Value::Length(length, Unit::Px) => write!(f, "{}px", length),
+            Value::Length(length, Unit::Em) => write!(f, "{}em", length),
+            Value::Length(length, Unit::Percent) => write!(f, "{}%", length),
+            Value::Length(length, Unit::Fr) => write!(f, "{}fr", length),
+            Value::Length(length, Unit::Deg) => write!(f, "{}deg", length),
+            Value::Number(number) => write!(f, "{}", number),
+            Value::Color(ref color) => write!(f, "{}", color),
+            Value::String(ref string) => write!(f, "{:?}", string),
+            Value::Url(ref url) => write!(f, "url({:?})", url),
+            Value::Gradient(ref gradient) => write!(f, "{}", gradient),
+            Value::List(ref values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                Ok(())
+            }
+            Value::Sequence(ref values) => {
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                Ok(())
+            }
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Unit {
     Px,
+    Em,
+    Percent,
+    /// A fraction of the free space in a grid container.
+    Fr,
+    /// An angle, like that of a gradient. Angles in other units are converted to degrees.
+    Deg,
+}
+
+/// A gradient image, like `linear-gradient(to right, #ff0000, #0000ff)`. Gradients have no size
+/// of their own, and fill whatever area they are drawn in.
+///
+/// http://www.w3.org/TR/css-images-3/#gradients
+#[derive(Debug, PartialEq, Clone)]
+pub struct Gradient {
+    pub shape: GradientShape,
+    /// Whether the stops repeat over and over past both ends, as in `repeating-linear-gradient`.
+    pub repeating: bool,
+    pub stops: Vec<ColorStop>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum GradientShape {
+    /// Colors change along a line through the center of the area.
+    Linear(GradientLine),
+    /// Colors change outwards from a center, to an ending shape that is a circle or an ellipse.
+    /// The position of the center is kept as the parts of a `<position>`.
+    Radial {
+        circle: bool,
+        size: RadialSize,
+        position: Vec<Value>,
+    },
+    /// Colors change around a center, clockwise from an angle in degrees.
+    Conic { from: f32, position: Vec<Value> },
+}
+
+/// The direction of the line of a linear gradient.
+#[derive(Debug, PartialEq, Clone)]
+pub enum GradientLine {
+    /// An angle in degrees, clockwise from pointing up, like `90deg` or `to right`.
+    Angle(f32),
+    /// Toward a corner, by its sides: -1 for the left or top and 1 for the right or bottom.
+    Corner(f32, f32),
+}
+
+/// How far a radial gradient reaches: to its ending shape.
+#[derive(Debug, PartialEq, Clone)]
+pub enum RadialSize {
+    ClosestSide,
+    FarthestSide,
+    ClosestCorner,
+    FarthestCorner,
+    /// The radius of a circle, or the horizontal and vertical radii of an ellipse.
+    Radii(Vec<Value>),
+}
+
+/// A color at a position along a gradient: a length or percentage along its line or ray, or an
+/// angle or percentage of a turn around a conic one. Stops without one are spread out evenly.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ColorStop {
+    pub color: Color,
+    pub position: Option<Value>,
+    /// Where between the stop before and this one the colors are mixed half and half, if not
+    /// halfway.
+    pub hint: Option<Value>,
+}
+
+impl fmt::Display for Gradient {
+    /// Serialize the gradient as CSS text.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.repeating {
+            write!(f, "repeating-")?;
+        }
+        let join = |values: &[Value]| {
+            values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        match self.shape {
+            GradientShape::Linear(GradientLine::Angle(angle)) => {
+                write!(f, "linear-gradient({}deg", angle)?
+            }
+            GradientShape::Linear(GradientLine::Corner(x, y)) => write!(
+                f,
+                "linear-gradient(to {} {}",
+                if x < 0.0 { "left" } else { "right" },
+                if y < 0.0 { "top" } else { "bottom" }
+            )?,
+            GradientShape::Radial {
+                circle,
+                ref size,
+                ref position,
+            } => {
+                let size = match size {
+                    RadialSize::ClosestSide => "closest-side".to_string(),
+                    RadialSize::FarthestSide => "farthest-side".to_string(),
+                    RadialSize::ClosestCorner => "closest-corner".to_string(),
+                    RadialSize::FarthestCorner => "farthest-corner".to_string(),
+                    RadialSize::Radii(radii) => join(radii),
+                };
+                let shape = if circle { "circle" } else { "ellipse" };
+                write!(f, "radial-gradient({} {}", shape, size)?;
+                if !position.is_empty() {
+                    write!(f, " at {}", join(position))?;
+                }
+            }
+            GradientShape::Conic { from, ref position } => {
+                write!(f, "conic-gradient(from {}deg", from)?;
+                if !position.is_empty() {
+                    write!(f, " at {}", join(position))?;
+                }
+            }
+        }
+        for stop in &self.stops {
+            if let Some(ref hint) = stop.hint {
+                write!(f, ", {}", hint)?;
+            }
+            write!(f, ", {}", stop.color)?;
+            if let Some(ref position) = stop.position {
+                write!(f, " {}", position)?;
+            }
+        }
+        write!(f, ")")
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -68,3 +318,19 @@ pub struct Color {
     pub blue: u8,
     pub alpha: u8,
 }
+
+impl fmt::Display for Color {
+    /// Serialize the color the way browsers report computed colors, e.g. `rgb(255, 0, 0)`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.alpha == 255 {
+            write!(f, "rgb({}, {}, {})", self.red, self.green, self.blue)
+        } else {
+            let alpha = (self.alpha as f32 / 255.0 * 1000.0).round() / 1000.0;
+            write!(
+                f,
+                "rgba({}, {}, {}, {})",
+                self.red, self.green, self.blue, alpha
+            )
+        }
+    }
+}
@@ -1,8 +1,17 @@
-use super::css::{Color, Value};
-use super::layout::{BoxType, LayoutBox, Rect};
+use super::css::{Color, Gradient, Value};
+use super::font::{font_size, Glyph};
+use super::images::{Image, ImageCache};
+use super::layout::{BoxType, FloatSide, LayoutBox, Position, Rect};
+use super::style::StyledNode;
+use background::render_background;
+use gradient::GradientFill;
 
-pub fn paint(layout_root: &LayoutBox, bounds: Rect) -> Canvas {
-    let display_list = build_display_list(layout_root);
+mod background;
+mod gradient;
+
+/// Paint a laid out document, with background images loaded from `images`.
+pub fn paint(layout_root: &LayoutBox, bounds: Rect, images: &ImageCache) -> Canvas {
+    let display_list = build_display_list(layout_root, images);
     let mut canvas = Canvas::new(bounds.width as usize, bounds.height as usize);
     for item in display_list {
         canvas.paint_item(&item);
@@ -15,94 +24,671 @@ type DisplayList = Vec<DisplayCommand>;
 #[derive(Debug)]
 enum DisplayCommand {
     SolidColor(Color, Rect),
+    Text(GlyphRun),
+    /// A text decoration line drawn along a rectangle as tall as the line is thick.
+    Decoration(DecorationStyle, Color, Rect),
+    /// An image scaled to cover a rectangle.
+    Image(Image, Rect, ImageFilter),
+    /// A gradient drawn to fill a rectangle.
+    Gradient(Gradient, Rect),
+    /// Commands painted as a group, which is then composited with the given opacity.
+    Opacity(f32, DisplayList),
+    /// Clip the commands up to the matching `PopClip` to a rectangle, inside any clip already
+    /// pushed.
+    PushClip(Rect),
+    PopClip,
+}
+
+/// A run of glyphs in one size and color, positioned relative to (`x`, `y`).
+#[derive(Debug)]
+struct GlyphRun {
+    size: f32,
+    color: Color,
+    x: f32,
+    y: f32,
+    glyphs: Vec<Glyph>,
+}
+
+/// Which line a text decoration draws, from `text-decoration-line`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DecorationLine {
+    Underline,
+    Overline,
+    LineThrough,
+}
+
+/// How a text decoration line is drawn, from `text-decoration-style`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DecorationStyle {
+    Solid,
+    Double,
+    Dotted,
+    Dashed,
+    Wavy,
 }
 
-fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
+/// How an image is resampled when it is drawn at another size than its own, from
+/// `image-rendering`.
+///
+/// http://www.w3.org/TR/css-images-3/#the-image-rendering
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ImageFilter {
+    /// Interpolate between pixels when scaling up, and average them when scaling down.
+    Smooth,
+    /// Take the nearest pixel, keeping hard edges between pixels.
+    Nearest,
+}
+
+/// A text decoration set by a box, painted across the text it contains.
+#[derive(Clone, Debug)]
+struct Decoration {
+    line: DecorationLine,
+    style: DecorationStyle,
+    color: Color,
+}
+
+fn build_display_list(layout_root: &LayoutBox, images: &ImageCache) -> DisplayList {
     let mut list = Vec::new();
-    render_layout_box(&mut list, layout_root);
+    // The root element always forms a stacking context.
+    render_stacking_context(&mut list, layout_root, images);
     list
 }
 
-fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
-    render_background(list, layout_box);
-    render_borders(list, layout_box);
+/// Paint a box that forms a stacking context and its descendants, in the order of CSS 2.1
+/// Appendix E: the box's background and borders, the stacking contexts with negative `z-index`,
+/// the blocks, floats and inline content in the flow, and then the positioned descendants and
+/// stacking contexts with a `z-index` of zero or more.
+///
+/// http://www.w3.org/TR/CSS2/zindex.html
+fn render_stacking_context(list: &mut DisplayList, layout_box: &LayoutBox, images: &ImageCache) {
+    let mut layers = Vec::new();
+    let clips = clip_rect(layout_box).map(|rect| (rect, is_positioned(layout_box)));
+    for child in &layout_box.children {
+        collect_layers(child, clips.as_slice(), &mut layers);
+    }
+    // The sort is stable, so layers with the same `z-index` stay in tree order.
+    layers.sort_by_key(|&(z_index, ..)| z_index);
 
+    let opacity = opacity(layout_box);
+    let mut group = Vec::new();
+    let target = if opacity < 1.0 {
+        &mut group
+    } else {
+        &mut *list
+    };
+    render_background(target, layout_box, images);
+    render_borders(target, layout_box);
+    for (_, layer, clips) in layers.iter().filter(|&&(z_index, ..)| z_index < 0) {
+        render_clipped_layer(target, layer, clips, images);
+    }
+    render_flow(target, layout_box, images);
+    for (_, layer, clips) in layers.iter().filter(|&&(z_index, ..)| z_index >= 0) {
+        render_clipped_layer(target, layer, clips, images);
+    }
+    if opacity < 1.0 {
+        list.push(DisplayCommand::Opacity(opacity, group));
+    }
+}
+
+/// Find the descendants that paint in layers of their own in a stacking context, with their
+/// `z-index`: the stacking contexts in it, and the positioned boxes, which paint as if they formed
+/// one but leave their positioned descendants to the stacking context they are in.
+///
+/// Each layer comes with the clips of the boxes it is in that clip their content, which only
+/// apply to the boxes they contain. `clips` are those of the ancestors of `layout_box` below the
+/// stacking context, each with whether there is a positioned box from the clipping box on down,
+/// which absolutely positioned descendants are then in.
+fn collect_layers<'b, 'a>(
+    layout_box: &'b LayoutBox<'a>,
+    clips: &[(Rect, bool)],
+    layers: &mut Vec<(i32, &'b LayoutBox<'a>, Vec<Rect>)>,
+) {
+    let mut clips = match style_node(layout_box).map(Position::of) {
+        Some(Position::Fixed) => Vec::new(),
+        Some(Position::Absolute) => clips.iter().filter(|&&(_, contains)| contains).collect(),
+        _ => clips.iter().collect(),
+    }
+    .into_iter()
+    .map(|&(rect, contains)| (rect, contains || is_positioned(layout_box)))
+    .collect::<Vec<_>>();
+    let rects = || clips.iter().map(|&(rect, _)| rect).collect();
+    if let Some(z_index) = stacking_context_z_index(layout_box) {
+        layers.push((z_index, layout_box, rects()));
+        return;
+    }
+    if is_positioned(layout_box) {
+        layers.push((0, layout_box, rects()));
+    }
+    clips.extend(clip_rect(layout_box).map(|rect| (rect, is_positioned(layout_box))));
     for child in &layout_box.children {
-        render_layout_box(list, child);
+        collect_layers(child, &clips, layers);
     }
 }
 
-fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
-    if let Some(color) = get_color(layout_box, "background") {
-        list.push(DisplayCommand::SolidColor(
-            color,
-            layout_box.clone().dimensions.border_box(),
-        ))
+/// Paint a layer inside the clips of the boxes it is in.
+fn render_clipped_layer(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox,
+    clips: &[Rect],
+    images: &ImageCache,
+) {
+    for &clip in clips {
+        list.push(DisplayCommand::PushClip(clip));
+    }
+    render_layer(list, layout_box, images);
+    for _ in clips {
+        list.push(DisplayCommand::PopClip);
     }
 }
 
-fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
+fn render_layer(list: &mut DisplayList, layout_box: &LayoutBox, images: &ImageCache) {
+    if stacking_context_z_index(layout_box).is_some() {
+        render_stacking_context(list, layout_box, images);
+    } else {
+        render_background(list, layout_box, images);
+        render_borders(list, layout_box);
+        render_flow(list, layout_box, images);
+    }
+}
+
+/// Paint the descendants of a box that are in its layer: the backgrounds and borders of blocks,
+/// then floats, each as if it were a layer of its own, then inline content. Atomic inlines paint
+/// with the inline content, each as if it were a layer of its own.
+fn render_flow(list: &mut DisplayList, layout_box: &LayoutBox, images: &ImageCache) {
+    clipped(list, layout_box, |list| {
+        render_blocks(list, layout_box, images);
+        render_floats(list, layout_box, images);
+        render_inline_content(list, layout_box, &text_decorations(layout_box), images);
+    });
+}
+
+fn render_blocks(list: &mut DisplayList, layout_box: &LayoutBox, images: &ImageCache) {
+    for child in in_flow_children(layout_box) {
+        match child.box_type {
+            BoxType::BlockNode(_) => {
+                render_background(list, child, images);
+                render_borders(list, child);
+            }
+            BoxType::LineBox => continue,
+            _ => {}
+        }
+        clipped(list, child, |list| render_blocks(list, child, images));
+    }
+}
+
+fn render_floats(list: &mut DisplayList, layout_box: &LayoutBox, images: &ImageCache) {
+    for child in layout_box.children.iter().filter(|child| in_layer(child)) {
+        if is_float(child) {
+            render_background(list, child, images);
+            render_borders(list, child);
+            render_flow(list, child, images);
+        } else if child.box_type != BoxType::LineBox {
+            clipped(list, child, |list| render_floats(list, child, images));
+        }
+    }
+}
+
+/// Paint with `render` inside the padding box of a box whose `overflow` clips its content.
+fn clipped(list: &mut DisplayList, layout_box: &LayoutBox, render: impl FnOnce(&mut DisplayList)) {
+    match clip_rect(layout_box) {
+        Some(rect) => {
+            list.push(DisplayCommand::PushClip(rect));
+            render(list);
+            list.push(DisplayCommand::PopClip);
+        }
+        None => render(list),
+    }
+}
+
+/// The rectangle a box clips its content to: its padding box, if its `overflow` is `hidden`,
+/// `clip`, `scroll` or `auto`.
+///
+/// http://www.w3.org/TR/css-overflow-3/#overflow-properties
+fn clip_rect(layout_box: &LayoutBox) -> Option<Rect> {
     match layout_box.box_type {
-        BoxType::BlockNode(style) | BoxType::InlineNode(style) => match style.value(name) {
-            Some(Value::Color(color)) => Some(color),
+        BoxType::BlockNode(style) => match style.value("overflow") {
+            Some(Value::Keyword(overflow)) if overflow != "visible" => {
+                Some(layout_box.dimensions.padding_box())
+            }
             _ => None,
         },
-        BoxType::AnonymousBlock => None,
+        _ => None,
+    }
+}
+
+/// Paint the inline content under a box, with the text decorations of the boxes it is in.
+/// Decorations propagate to the text of descendants in the flow, but not to atomic inlines, floats
+/// or positioned boxes. The image of a replaced element is its content.
+fn render_inline_content(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox,
+    decorations: &[Decoration],
+    images: &ImageCache,
+) {
+    render_image(list, layout_box);
+    for child in in_flow_children(layout_box) {
+        match child.box_type {
+            BoxType::InlineNode(_) => {
+                render_background(list, child, images);
+                render_borders(list, child);
+            }
+            // Lines through text go over it, and other lines under it.
+            BoxType::TextRun(..) => {
+                let (through, other): (Vec<_>, Vec<_>) = decorations
+                    .iter()
+                    .partition(|decoration| decoration.line == DecorationLine::LineThrough);
+                render_decorations(list, child, other);
+                render_text(list, child);
+                render_decorations(list, child, through);
+            }
+            BoxType::BlockNode(_) if is_fragment(layout_box) => {
+                render_background(list, child, images);
+                render_borders(list, child);
+                render_flow(list, child, images);
+                continue;
+            }
+            _ => {}
+        }
+        let decorations = [decorations, &text_decorations(child)].concat();
+        clipped(list, child, |list| {
+            render_inline_content(list, child, &decorations, images)
+        });
+    }
+}
+
+/// Whether a box is a line box or a fragment of an inline box on one, whose block children are
+/// atomic inlines.
+fn is_fragment(layout_box: &LayoutBox) -> bool {
+    matches!(
+        layout_box.box_type,
+        BoxType::LineBox | BoxType::InlineNode(_)
+    )
+}
+
+/// The children of a box that paint in its layer and are not floats.
+fn in_flow_children<'b, 'a>(
+    layout_box: &'b LayoutBox<'a>,
+) -> impl Iterator<Item = &'b LayoutBox<'a>> {
+    layout_box
+        .children
+        .iter()
+        .filter(|child| in_layer(child) && !is_float(child))
+}
+
+/// Whether a box paints in the layer of its parent rather than in a layer of its own.
+fn in_layer(layout_box: &LayoutBox) -> bool {
+    !is_positioned(layout_box) && stacking_context_z_index(layout_box).is_none()
+}
+
+fn is_positioned(layout_box: &LayoutBox) -> bool {
+    style_node(layout_box).is_some_and(|style| Position::of(style) != Position::Static)
+}
+
+fn is_float(layout_box: &LayoutBox) -> bool {
+    matches!(layout_box.box_type, BoxType::BlockNode(style) if FloatSide::of(style).is_some())
+}
+
+/// The `z-index` of a box if it forms a stacking context: positioned boxes with a `z-index`, and
+/// boxes with an `opacity` below 1 or a `transform`, which are at zero.
+///
+/// http://www.w3.org/TR/css-color-3/#transparency
+fn stacking_context_z_index(layout_box: &LayoutBox) -> Option<i32> {
+    let style = style_node(layout_box)?;
+    match style.value("z-index") {
+        Some(Value::Number(z_index)) if is_positioned(layout_box) => Some(z_index as i32),
+        _ if opacity(layout_box) < 1.0 => Some(0),
+        Some(_) | None => match style.value("transform") {
+            Some(Value::Keyword(keyword)) if keyword == "none" => None,
+            Some(_) => Some(0),
+            None => None,
+        },
+    }
+}
+
+fn opacity(layout_box: &LayoutBox) -> f32 {
+    match style_node(layout_box).and_then(|style| style.value("opacity")) {
+        Some(Value::Number(opacity)) => opacity.clamp(0.0, 1.0),
+        _ => 1.0,
+    }
+}
+
+fn style_node<'a>(layout_box: &LayoutBox<'a>) -> Option<&'a StyledNode<'a>> {
+    match layout_box.box_type {
+        BoxType::BlockNode(style) | BoxType::InlineNode(style) => Some(style),
+        BoxType::AnonymousBlock
+        | BoxType::AnonymousTablePart(..)
+        | BoxType::Marker(_)
+        | BoxType::LineBox
+        | BoxType::TextRun(..) => None,
+    }
+}
+
+fn render_text(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let (style, glyphs) = match layout_box.box_type {
+        BoxType::TextRun(style, _, ref glyphs) => (style, glyphs),
+        _ => return,
+    };
+    let color = match style.value("color") {
+        Some(Value::Color(color)) => color,
+        _ => Color {
+            red: 0,
+            green: 0,
+            blue: 0,
+            alpha: 255,
+        },
+    };
+    let content = layout_box.dimensions.content;
+    list.push(DisplayCommand::Text(GlyphRun {
+        size: font_size(style),
+        color,
+        x: content.x,
+        y: content.y,
+        glyphs: glyphs.clone(),
+    }));
+}
+
+/// Paint the image of a replaced element in its content box, sized by `object-fit` and placed
+/// by `object-position`. Whatever falls outside the content box is clipped.
+///
+/// http://www.w3.org/TR/css-images-3/#the-object-fit
+fn render_image(list: &mut DisplayList, layout_box: &LayoutBox) {
+    let (image, style) = match (&layout_box.image, style_node(layout_box)) {
+        (Some(image), Some(style)) => (image, style),
+        _ => return,
+    };
+    let content = layout_box.dimensions.content;
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    if width == 0.0 || height == 0.0 {
+        return;
+    }
+    let contain = (content.width / width).min(content.height / height);
+    let (width, height) = match style.value("object-fit") {
+        Some(Value::Keyword(fit)) if fit == "contain" => (width * contain, height * contain),
+        Some(Value::Keyword(fit)) if fit == "cover" => {
+            let cover = (content.width / width).max(content.height / height);
+            (width * cover, height * cover)
+        }
+        Some(Value::Keyword(fit)) if fit == "none" => (width, height),
+        Some(Value::Keyword(fit)) if fit == "scale-down" => {
+            let scale = contain.min(1.0);
+            (width * scale, height * scale)
+        }
+        _ => (content.width, content.height),
+    };
+    let (x, y) = position(
+        &tokens(style.value("object-position")),
+        (content.width - width, content.height - height),
+    );
+    let rect = Rect {
+        x: content.x + x,
+        y: content.y + y,
+        width,
+        height,
+    };
+    let filter = image_filter(style);
+    let overflows = rect.intersection(content) != rect;
+    if overflows {
+        list.push(DisplayCommand::PushClip(content));
+    }
+    list.push(DisplayCommand::Image(image.clone(), rect, filter));
+    if overflows {
+        list.push(DisplayCommand::PopClip);
+    }
+}
+
+fn image_filter(style: &StyledNode) -> ImageFilter {
+    match style.value("image-rendering") {
+        Some(Value::Keyword(rendering))
+            if rendering == "pixelated" || rendering == "crisp-edges" =>
+        {
+            ImageFilter::Nearest
+        }
+        _ => ImageFilter::Smooth,
+    }
+}
+
+/// The parts of a space-separated value.
+fn tokens(value: Option<Value>) -> Vec<Value> {
+    match value {
+        Some(Value::Sequence(values)) => values,
+        Some(value) => vec![value],
+        None => Vec::new(),
+    }
+}
+
+/// Where a `<position>`, like that of `object-position`, puts an object with `free` space around
+/// it horizontally and vertically: its offset from the left and top of that space. Percentages
+/// and keywords are of the free space, so `right` lines the right edges up. Takes one or two
+/// values, or two keywords each followed by an offset from that edge, and centers by default.
+///
+/// http://www.w3.org/TR/css-values-4/#position
+fn position(parts: &[Value], (free_width, free_height): (f32, f32)) -> (f32, f32) {
+    let is_keyword = |part: &Value, names: [&str; 2]| matches!(part, Value::Keyword(keyword) if names.contains(&&**keyword));
+    let vertical = |part: &Value| is_keyword(part, ["top", "bottom"]);
+    let horizontal = |part: &Value| is_keyword(part, ["left", "right"]);
+    let offset = |part: Option<&Value>, free: f32| match part {
+        Some(Value::Keyword(keyword)) if keyword == "left" || keyword == "top" => 0.0,
+        Some(Value::Keyword(keyword)) if keyword == "right" || keyword == "bottom" => free,
+        Some(Value::Keyword(_)) | None => free / 2.0,
+        Some(length) => length.resolve(free),
+    };
+    // An offset from the right or bottom edge.
+    let from_edge = |edge: &Value, length: &Value, free: f32| {
+        let length = length.resolve(free);
+        if is_keyword(edge, ["right", "bottom"]) {
+            free - length
+        } else {
+            length
+        }
+    };
+    match parts {
+        [first, first_offset, second, second_offset]
+            if matches!(first, Value::Keyword(_)) && matches!(second, Value::Keyword(_)) =>
+        {
+            if vertical(first) || horizontal(second) {
+                (
+                    from_edge(second, second_offset, free_width),
+                    from_edge(first, first_offset, free_height),
+                )
+            } else {
+                (
+                    from_edge(first, first_offset, free_width),
+                    from_edge(second, second_offset, free_height),
+                )
+            }
+        }
+        [first, second] if vertical(first) || horizontal(second) => (
+            offset(Some(second), free_width),
+            offset(Some(first), free_height),
+        ),
+        [first, second] => (
+            offset(Some(first), free_width),
+            offset(Some(second), free_height),
+        ),
+        [first] if vertical(first) => (free_width / 2.0, offset(Some(first), free_height)),
+        [first] => (offset(Some(first), free_width), free_height / 2.0),
+        _ => (free_width / 2.0, free_height / 2.0),
+    }
+}
+
+/// The text decorations a box sets with `text-decoration-line`, `text-decoration-style` and
+/// `text-decoration-color`, or with the `text-decoration` shorthand. Lines are in the color of
+/// the box's text unless set otherwise.
+///
+/// http://www.w3.org/TR/css-text-decor-3/#line-decoration
+fn text_decorations(layout_box: &LayoutBox) -> Vec<Decoration> {
+    let style = match style_node(layout_box) {
+        Some(style) => style,
+        None => return Vec::new(),
+    };
+    // The shorthand holds keywords for the lines and the style, and maybe a color.
+    let (mut shorthand, mut shorthand_color) = (Vec::new(), None);
+    let parts = match style.value("text-decoration") {
+        Some(Value::Sequence(values)) => values,
+        Some(value) => vec![value],
+        None => Vec::new(),
+    };
+    for part in parts {
+        match part {
+            Value::Keyword(keyword) => shorthand.push(keyword),
+            Value::Color(color) => shorthand_color = Some(color),
+            _ => {}
+        }
+    }
+    let keywords = |name| match style.value(name) {
+        Some(value @ (Value::Keyword(_) | Value::Sequence(_))) => {
+            value.keywords().into_iter().map(str::to_string).collect()
+        }
+        _ => shorthand.clone(),
+    };
+    let decoration_style = keywords("text-decoration-style")
+        .iter()
+        .find_map(|keyword| match &**keyword {
+            "solid" => Some(DecorationStyle::Solid),
+            "double" => Some(DecorationStyle::Double),
+            "dotted" => Some(DecorationStyle::Dotted),
+            "dashed" => Some(DecorationStyle::Dashed),
+            "wavy" => Some(DecorationStyle::Wavy),
+            _ => None,
+        })
+        .unwrap_or(DecorationStyle::Solid);
+    let color = get_color(layout_box, "text-decoration-color")
+        .or(shorthand_color)
+        .or_else(|| get_color(layout_box, "color"))
+        .unwrap_or(Color {
+            red: 0,
+            green: 0,
+            blue: 0,
+            alpha: 255,
+        });
+    keywords("text-decoration-line")
+        .iter()
+        .filter_map(|keyword| match &**keyword {
+            "underline" => Some(DecorationLine::Underline),
+            "overline" => Some(DecorationLine::Overline),
+            "line-through" => Some(DecorationLine::LineThrough),
+            _ => None,
+        })
+        .map(|line| Decoration {
+            line,
+            style: decoration_style,
+            color: color.clone(),
+        })
+        .collect()
+}
+
+/// Paint text decoration lines across a text run, placed with the metrics of its font.
+fn render_decorations(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox,
+    decorations: Vec<&Decoration>,
+) {
+    let (style, glyphs) = match layout_box.box_type {
+        BoxType::TextRun(style, _, ref glyphs) => (style, glyphs),
+        _ => return,
+    };
+    let first = match glyphs.first() {
+        Some(glyph) => glyph,
+        None => return,
+    };
+    let content = layout_box.dimensions.content;
+    // Glyphs are set on the baseline of the run.
+    let baseline = content.y + first.y;
+    let metrics = first.font.metrics(font_size(style));
+    for decoration in decorations {
+        let (y, thickness) = match decoration.line {
+            DecorationLine::Underline => (baseline + metrics.underline.0, metrics.underline.1),
+            DecorationLine::Overline => (baseline - metrics.ascent, metrics.underline.1),
+            DecorationLine::LineThrough => (baseline - metrics.strikeout.0, metrics.strikeout.1),
+        };
+        list.push(DisplayCommand::Decoration(
+            decoration.style,
+            decoration.color.clone(),
+            Rect {
+                x: content.x,
+                y,
+                width: content.width,
+                height: thickness,
+            },
+        ));
+    }
+}
+
+fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
+    match style_node(layout_box)?.value(name) {
+        Some(Value::Color(color)) => Some(color),
+        _ => None,
     }
 }
 
 fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
-    let color = match get_color(layout_box, "border-color") {
-        Some(color) => color,
-        _ => return, // bail out if no border-color is specified
+    let [top, right, bottom, left] = match layout_box.border_colors {
+        Some(ref colors) => colors.clone(),
+        None => match get_color(layout_box, "border-color") {
+            Some(color) => [
+                Some(color.clone()),
+                Some(color.clone()),
+                Some(color.clone()),
+                Some(color),
+            ],
+            _ => return, // bail out if no border-color is specified
+        },
     };
 
     let d = &layout_box.dimensions;
     let border_box = (*d).border_box();
 
     // Left border
-    list.push(DisplayCommand::SolidColor(
-        color.clone(),
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: d.border.left,
-            height: border_box.height,
-        },
-    ));
+    if let Some(color) = left {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y,
+                width: d.border.left,
+                height: border_box.height,
+            },
+        ));
+    }
 
     // Right border
-    list.push(DisplayCommand::SolidColor(
-        color.clone(),
-        Rect {
-            x: border_box.x + border_box.width - d.border.right,
-            y: border_box.y,
-            width: d.border.right,
-            height: border_box.height,
-        },
-    ));
+    if let Some(color) = right {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x + border_box.width - d.border.right,
+                y: border_box.y,
+                width: d.border.right,
+                height: border_box.height,
+            },
+        ));
+    }
 
     // Top border
-    list.push(DisplayCommand::SolidColor(
-        color.clone(),
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: border_box.width,
-            height: d.border.top,
-        },
-    ));
+    if let Some(color) = top {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y,
+                width: border_box.width,
+                height: d.border.top,
+            },
+        ));
+    }
 
     // Bottom border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y + border_box.height - d.border.bottom,
-            width: border_box.width,
-            height: d.border.bottom,
-        },
-    ));
+    if let Some(color) = bottom {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y + border_box.height - d.border.bottom,
+                width: border_box.width,
+                height: d.border.bottom,
+            },
+        ));
+    }
 }
 
 #[derive(Debug)]
@@ -110,6 +696,8 @@ pub struct Canvas {
     pub pixels: Vec<Color>,
     pub width: usize,
     pub height: usize,
+    /// The clips pushed so far, each inside the one before.
+    clips: Vec<Rect>,
 }
 
 impl Canvas {
@@ -124,25 +712,495 @@ impl Canvas {
             pixels: vec![white; width * height],
             width,
             height,
+            clips: Vec::new(),
         }
     }
 
+    /// The area painting is clipped to: the canvas, inside the last clip pushed.
+    fn clip(&self) -> Rect {
+        let canvas = Rect {
+            x: 0.0,
+            y: 0.0,
+            width: self.width as f32,
+            height: self.height as f32,
+        };
+        self.clips
+            .last()
+            .map_or(canvas, |&clip| clip.intersection(canvas))
+    }
+
     fn paint_item(&mut self, item: &DisplayCommand) {
         match item {
             DisplayCommand::SolidColor(color, rect) => {
-                // Clip the rectangle to the canvas boundaries.
-                let x0 = rect.x.clamp(0.0, self.width as f32) as usize;
-                let y0 = rect.y.clamp(0.0, self.height as f32) as usize;
-                let x1 = (rect.x + rect.width).clamp(0.0, self.width as f32) as usize;
-                let y1 = (rect.y + rect.height).clamp(0.0, self.height as f32) as usize;
+                // Clip the rectangle to the canvas boundaries and the current clip.
+                let clip = self.clip();
+                let (right, bottom) = (clip.x + clip.width, clip.y + clip.height);
+                let x0 = rect.x.clamp(clip.x, right) as usize;
+                let y0 = rect.y.clamp(clip.y, bottom) as usize;
+                let x1 = (rect.x + rect.width).clamp(clip.x, right) as usize;
+                let y1 = (rect.y + rect.height).clamp(clip.y, bottom) as usize;
 
                 for y in y0..y1 {
                     for x in x0..x1 {
-                        // TODO: alpha compositing with existing pixel
-                        self.pixels[y * self.width + x] = color.clone();
+                        if color.alpha == 255 {
+                            self.pixels[y * self.width + x] = color.clone();
+                        } else {
+                            self.blend(x as i32, y as i32, color, 1.0);
+                        }
+                    }
+                }
+            }
+            DisplayCommand::Text(run) => {
+                for glyph in &run.glyphs {
+                    let (x, y) = (run.x + glyph.x, run.y + glyph.y);
+                    glyph
+                        .font
+                        .rasterize(glyph.id, run.size, x, y, |x, y, coverage| {
+                            self.blend(x, y, &run.color, coverage)
+                        });
+                }
+            }
+            DisplayCommand::Decoration(style, color, rect) => {
+                // Lines are at least a pixel thick, and snapped to whole pixels.
+                let thickness = rect.height.round().max(1.0);
+                let t = thickness as i32;
+                let (x0, x1) = (rect.x.round() as i32, (rect.x + rect.width).round() as i32);
+                let y = rect.y.round() as i32;
+                for x in x0..x1 {
+                    let i = x - x0;
+                    let rows = match style {
+                        DecorationStyle::Solid => vec![y],
+                        DecorationStyle::Double => vec![y, y + 2 * t],
+                        DecorationStyle::Dotted if (i / t) % 2 == 0 => vec![y],
+                        DecorationStyle::Dashed if (i / (3 * t)) % 2 == 0 => vec![y],
+                        DecorationStyle::Dotted | DecorationStyle::Dashed => vec![],
+                        DecorationStyle::Wavy => {
+                            let phase = i as f32 / (4.0 * thickness) * std::f32::consts::TAU;
+                            vec![y + (phase.sin() * thickness).round() as i32]
+                        }
+                    };
+                    for top in rows {
+                        for y in top..top + t {
+                            self.blend(x, y, color, 1.0);
+                        }
+                    }
+                }
+            }
+            DisplayCommand::Image(image, rect, filter) => {
+                // Every pixel whose center is in the rectangle takes a sample of the image
+                // around the point it covers there.
+                let clip = self.clip().intersection(*rect);
+                let (scale_x, scale_y) = (
+                    rect.width / image.width() as f32,
+                    rect.height / image.height() as f32,
+                );
+                let (x0, x1) = (clip.x.floor() as i32, (clip.x + clip.width).ceil() as i32);
+                let (y0, y1) = (clip.y.floor() as i32, (clip.y + clip.height).ceil() as i32);
+                for y in y0..y1 {
+                    let v = (y as f32 + 0.5 - rect.y) / scale_y;
+                    for x in x0..x1 {
+                        let u = (x as f32 + 0.5 - rect.x) / scale_x;
+                        if u < 0.0 || v < 0.0 {
+                            continue;
+                        }
+                        let color = match filter {
+                            ImageFilter::Nearest => image.pixel(
+                                (u as u32).min(image.width() - 1),
+                                (v as u32).min(image.height() - 1),
+                            ),
+                            ImageFilter::Smooth => sample(image, u, v, (scale_x, scale_y)),
+                        };
+                        self.blend(x, y, &color, 1.0);
+                    }
+                }
+            }
+            DisplayCommand::Gradient(gradient, rect) => {
+                // Every pixel whose center is in the rectangle takes the color there.
+                let fill = GradientFill::new(gradient, *rect);
+                let clip = self.clip().intersection(*rect);
+                let (x0, x1) = (clip.x.floor() as i32, (clip.x + clip.width).ceil() as i32);
+                let (y0, y1) = (clip.y.floor() as i32, (clip.y + clip.height).ceil() as i32);
+                for y in y0..y1 {
+                    for x in x0..x1 {
+                        let (center_x, center_y) = (x as f32 + 0.5, y as f32 + 0.5);
+                        if center_x < rect.x
+                            || center_y < rect.y
+                            || center_x >= rect.x + rect.width
+                            || center_y >= rect.y + rect.height
+                        {
+                            continue;
+                        }
+                        self.blend(x, y, &fill.color_at(center_x, center_y), 1.0);
                     }
                 }
             }
+            DisplayCommand::PushClip(rect) => {
+                let clip = self
+                    .clips
+                    .last()
+                    .map_or(*rect, |clip| clip.intersection(*rect));
+                self.clips.push(clip);
+            }
+            DisplayCommand::PopClip => {
+                self.clips.pop();
+            }
+            DisplayCommand::Opacity(opacity, items) => {
+                // The canvas is opaque, so painting the group over a copy of it and mixing that
+                // back in is the same as compositing the group on its own.
+                let backdrop = self.pixels.clone();
+                for item in items {
+                    self.paint_item(item);
+                }
+                let mix = |under: u8, over: u8| {
+                    (under as f32 * (1.0 - opacity) + over as f32 * opacity).round() as u8
+                };
+                for (pixel, under) in self.pixels.iter_mut().zip(backdrop) {
+                    pixel.red = mix(under.red, pixel.red);
+                    pixel.green = mix(under.green, pixel.green);
+                    pixel.blue = mix(under.blue, pixel.blue);
+                }
+            }
+        }
+    }
+
+    /// Composite `color` over the pixel at (`x`, `y`), scaling its alpha by `coverage`. Pixels
+    /// outside the canvas or the current clip, by their centers, are ignored.
+    fn blend(&mut self, x: i32, y: i32, color: &Color, coverage: f32) {
+        let clip = self.clip();
+        let (center_x, center_y) = (x as f32 + 0.5, y as f32 + 0.5);
+        if center_x < clip.x
+            || center_y < clip.y
+            || center_x >= clip.x + clip.width
+            || center_y >= clip.y + clip.height
+        {
+            return;
+        }
+        let alpha = coverage.clamp(0.0, 1.0) * color.alpha as f32 / 255.0;
+        let pixel = &mut self.pixels[y as usize * self.width + x as usize];
+        let mix = |under: u8, over: u8| {
+            (under as f32 * (1.0 - alpha) + over as f32 * alpha).round() as u8
+        };
+        pixel.red = mix(pixel.red, color.red);
+        pixel.green = mix(pixel.green, color.green);
+        pixel.blue = mix(pixel.blue, color.blue);
+        pixel.alpha = mix(pixel.alpha, 255);
+    }
+}
+
+/// The color of an image around the point (`u`, `v`) in its pixels, drawn `scale` times its
+/// size. Scaled up, the four nearest pixels are interpolated; scaled down, the pixels the
+/// destination pixel covers are averaged. Colors are mixed premultiplied by their alpha, so that
+/// transparent pixels do not darken their neighbours.
+fn sample(image: &Image, u: f32, v: f32, (scale_x, scale_y): (f32, f32)) -> Color {
+    let (width, height) = (image.width() as i32, image.height() as i32);
+    // The pixels to mix, with their weights.
+    let mut weights = Vec::new();
+    if scale_x >= 1.0 && scale_y >= 1.0 {
+        let (u, v) = (u - 0.5, v - 0.5);
+        let (x, y) = (u.floor(), v.floor());
+        let (s, t) = (u - x, v - y);
+        for (dx, dy, weight) in [
+            (0, 0, (1.0 - s) * (1.0 - t)),
+            (1, 0, s * (1.0 - t)),
+            (0, 1, (1.0 - s) * t),
+            (1, 1, s * t),
+        ] {
+            weights.push((x as i32 + dx, y as i32 + dy, weight));
+        }
+    } else {
+        let (reach_x, reach_y) = (0.5 / scale_x.min(1.0), 0.5 / scale_y.min(1.0));
+        let (x0, x1) = ((u - reach_x).round() as i32, (u + reach_x).round() as i32);
+        let (y0, y1) = ((v - reach_y).round() as i32, (v + reach_y).round() as i32);
+        for y in y0..y1.max(y0 + 1) {
+            for x in x0..x1.max(x0 + 1) {
+                weights.push((x, y, 1.0));
+            }
+        }
+    }
+    let (mut red, mut green, mut blue, mut alpha, mut total) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (x, y, weight) in weights {
+        let pixel = image.pixel(x.clamp(0, width - 1) as u32, y.clamp(0, height - 1) as u32);
+        let a = pixel.alpha as f32 * weight;
+        red += pixel.red as f32 * a;
+        green += pixel.green as f32 * a;
+        blue += pixel.blue as f32 * a;
+        alpha += a;
+        total += weight;
+    }
+    if alpha == 0.0 {
+        return Color {
+            red: 0,
+            green: 0,
+            blue: 0,
+            alpha: 0,
+        };
+    }
+    Color {
+        red: (red / alpha).round() as u8,
+        green: (green / alpha).round() as u8,
+        blue: (blue / alpha).round() as u8,
+        alpha: (alpha / total).round() as u8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::Page;
+    use super::*;
+
+    /// Render `html` on a 200x50 canvas.
+    fn render(html: &str, css: &str) -> Canvas {
+        let page = Page {
+            html,
+            css,
+            height: 50.0,
+            ..Default::default()
+        };
+        page.paint()
+    }
+
+    /// Total coverage of non-white pixels, counting a pixel of full ink as 1.
+    fn ink(canvas: &Canvas) -> f32 {
+        canvas
+            .pixels
+            .iter()
+            .map(|p| (765 - p.red as u32 - p.green as u32 - p.blue as u32) as f32 / 765.0)
+            .sum()
+    }
+
+    #[test]
+    fn test_paint_text() {
+        let canvas = render("<div>Hello</div>", "div { color: #ff0000; }");
+        assert!(ink(&canvas) > 0.0);
+        for pixel in &canvas.pixels {
+            // Anti-aliased edges blend red into the white background.
+            assert_eq!(pixel.red, 255);
+            assert_eq!(pixel.green, pixel.blue);
+        }
+        let inked_rows: Vec<usize> = (0..canvas.height)
+            .filter(|y| {
+                canvas.pixels[y * canvas.width..(y + 1) * canvas.width]
+                    .iter()
+                    .any(|p| p.green < 255)
+            })
+            .collect();
+        // Glyphs sit inside the line, which is about 19px tall at 16px.
+        assert!(*inked_rows.first().unwrap() > 0);
+        assert!(*inked_rows.last().unwrap() < 19);
+    }
+
+    #[test]
+    fn test_paint_list_marker() {
+        // The item's own text is white, so only the marker inks the canvas.
+        let canvas = render(
+            "<ul><li>Item</li></ul>",
+            "ul { margin-top: 0px; } li { color: #ffffff; } li::marker { color: #0000ff; }",
+        );
+        assert!(ink(&canvas) > 0.0);
+        for y in 0..canvas.height {
+            for x in 0..canvas.width {
+                let (red, green, blue) = pixel(&canvas, x, y);
+                if (red, green, blue) != (255, 255, 255) {
+                    assert!(x < 40 && y < 19);
+                    assert_eq!((red, blue), (green, 255));
+                }
+            }
         }
     }
+
+    #[test]
+    fn test_paint_inline_block() {
+        let canvas = render(
+            "<div>Hi <p class=\"ib\"></p></div>",
+            ".ib { display: inline-block; width: 10px; height: 10px; margin-top: 0px; \
+             margin-bottom: 0px; background: #0000ff; }",
+        );
+        let blue: Vec<_> = (0..canvas.height)
+            .flat_map(|y| (0..canvas.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| pixel(&canvas, x, y) == (0, 0, 255))
+            .collect();
+        assert_eq!(blue.len(), 100);
+        // It follows the text on the line, and sits on its baseline.
+        let (left, top) = blue[0];
+        assert!(left > 10);
+        assert!(top > 0 && top + 10 < 19);
+    }
+
+    #[test]
+    fn test_paint_text_decoration() {
+        // Rows painted across the text in the color of the decoration, where the edges of the
+        // white glyphs do not touch them.
+        let lines = |css: &str| {
+            let canvas = render("<div><span>Hello</span></div>", css);
+            (0..canvas.height)
+                .filter(|&y| {
+                    let blue = (0..30).filter(|&x| pixel(&canvas, x, y) == (0, 0, 255));
+                    blue.count() > 25
+                })
+                .collect::<Vec<_>>()
+        };
+        let css = "div { color: #ffffff; } span { text-decoration: ";
+        let underline = lines(&format!("{}underline #0000ff; }}", css));
+        assert_eq!(underline.len(), 1);
+        let overline = lines(&format!("{}overline #0000ff; }}", css));
+        let through = lines(&format!("{}line-through #0000ff; }}", css));
+        assert!(overline[0] < through[0] && through[0] < underline[0]);
+        // Decorations of a block are drawn across the text in it.
+        let block = "div { color: #ffffff; text-decoration-line: underline; \
+                     text-decoration-color: #0000ff; }";
+        assert_eq!(lines(block), underline);
+        let double = lines(&format!("{}underline double #0000ff; }}", css));
+        assert_eq!(double.len(), 2);
+        assert!(lines(&format!("{}underline dashed #0000ff; }}", css)).is_empty());
+        // Lines are in the color of the text by default.
+        assert_eq!(
+            lines("div { color: #0000ff; } span { text-decoration: underline; }"),
+            underline
+        );
+    }
+
+    #[test]
+    fn test_font_weight_and_style() {
+        let regular = ink(&render("<div>Hello</div>", ""));
+        let bold = ink(&render("<div>Hello</div>", "div { font-weight: bold; }"));
+        let heavy = ink(&render("<div>Hello</div>", "div { font-weight: 700; }"));
+        assert!(bold > regular * 1.2);
+        assert_eq!(bold, heavy);
+
+        let upright = render("<div>l</div>", "");
+        let italic = render("<div>l</div>", "div { font-style: italic; }");
+        let leftmost = |canvas: &Canvas, y: usize| {
+            (0..canvas.width).find(|&x| canvas.pixels[y * canvas.width + x].red < 128)
+        };
+        // The stem of an upright l is vertical, an oblique one leans to the right.
+        assert_eq!(leftmost(&upright, 5), leftmost(&upright, 14));
+        assert!(leftmost(&italic, 5) > leftmost(&italic, 14));
+    }
+
+    #[test]
+    fn test_font_size() {
+        let small = ink(&render("<div>Hello</div>", "div { font-size: 10px; }"));
+        let large = ink(&render("<div>Hello</div>", "div { font-size: 20px; }"));
+        assert!(large > small * 3.0);
+    }
+
+    fn pixel(canvas: &Canvas, x: usize, y: usize) -> (u8, u8, u8) {
+        let pixel = &canvas.pixels[y * canvas.width + x];
+        (pixel.red, pixel.green, pixel.blue)
+    }
+
+    #[test]
+    fn test_stacking_order() {
+        let html = "<div class=\"a\"></div><div class=\"b\"></div><div class=\"c\"></div>";
+        let css = "div { width: 20px; height: 20px; } \
+                   .a, .b { position: absolute; top: 0px; left: 0px; } \
+                   .a { background: #ff0000; } .b { background: #0000ff; } \
+                   .c { background: #00ff00; }";
+        // Positioned boxes paint over the flow, and each other in tree order.
+        assert_eq!(pixel(&render(html, css), 15, 15), (0, 0, 255));
+        let raised = format!("{} .a {{ z-index: 1; }}", css);
+        assert_eq!(pixel(&render(html, &raised), 15, 15), (255, 0, 0));
+        // Negative `z-index` goes below the blocks in the flow.
+        let lowered = format!("{} .a, .b {{ z-index: -1; }}", css);
+        assert_eq!(pixel(&render(html, &lowered), 15, 15), (0, 255, 0));
+    }
+
+    #[test]
+    fn test_floats_paint_over_blocks() {
+        let html = "<div class=\"float\"></div><div class=\"block\"></div>";
+        let css = "div { width: 20px; height: 20px; } \
+                   .float { float: left; background: #ff0000; } \
+                   .block { background: #0000ff; }";
+        // The block comes later in the tree, but floats paint over block backgrounds.
+        assert_eq!(pixel(&render(html, css), 10, 10), (255, 0, 0));
+    }
+
+    #[test]
+    fn test_opacity() {
+        let html = "<div class=\"a\"><div class=\"b\"></div></div>";
+        let css = "div { width: 20px; height: 20px; } \
+                   .a { opacity: 0.5; background: #0000ff; } .b { background: #ff0000; }";
+        // The group is composited as a whole, so nothing of the blue shows under the red.
+        assert_eq!(pixel(&render(html, css), 10, 10), (255, 128, 128));
+    }
+
+    #[test]
+    fn test_paint_collapsed_borders() {
+        let html = "<table><tr><td class=\"a\"></td><td class=\"b\"></td></tr></table>";
+        let css = "table { border-collapse: collapse; } \
+                   td { width: 10px; height: 10px; border-width: 4px; } \
+                   .a { border-color: #ff0000; } .b { border-color: #0000ff; }";
+        let canvas = render(html, css);
+        // The border between the cells is as wide as one of theirs, and the first cell's wins
+        // it, so it is red on both sides.
+        assert_eq!(pixel(&canvas, 17, 5), (255, 0, 0));
+        assert_eq!(pixel(&canvas, 19, 5), (255, 0, 0));
+        assert_eq!(pixel(&canvas, 34, 5), (0, 0, 255));
+    }
+
+    #[test]
+    fn test_overflow_clip() {
+        let html = "<div class=\"clip\"><div class=\"a\"></div><div class=\"b\"></div></div>";
+        let css = "div { width: 40px; height: 40px; } \
+                   .clip { width: 20px; height: 20px; overflow: hidden; } \
+                   .a { background: #ff0000; } \
+                   .b { position: absolute; top: 0px; left: 30px; background: #0000ff; }";
+        let canvas = render(html, css);
+        assert_eq!(pixel(&canvas, 10, 10), (255, 0, 0));
+        // The child is cut off at the edge of the clipping box.
+        assert_eq!(pixel(&canvas, 25, 10), (255, 255, 255));
+        assert_eq!(pixel(&canvas, 10, 25), (255, 255, 255));
+        // The absolute box is contained by the viewport, not the clipping box, so it escapes.
+        assert_eq!(pixel(&canvas, 35, 10), (0, 0, 255));
+        let relative = format!("{} .clip {{ position: relative; }}", css);
+        assert_eq!(pixel(&render(html, &relative), 35, 10), (255, 255, 255));
+        let visible = format!("{} .clip {{ overflow: visible; }}", css);
+        assert_eq!(pixel(&render(html, &visible), 25, 10), (255, 0, 0));
+    }
+
+    #[test]
+    fn test_paint_image() {
+        // A red pixel beside a blue one.
+        let path =
+            std::env::temp_dir().join(format!("ponyrender-paint-{}.png", std::process::id()));
+        image::RgbaImage::from_fn(2, 1, |x, _| match x {
+            0 => image::Rgba([255, 0, 0, 255]),
+            _ => image::Rgba([0, 0, 255, 255]),
+        })
+        .save(&path)
+        .unwrap();
+        let html = format!("<div><img src=\"{}\"></div>", path.display());
+        let paint = |css: &str| {
+            render(
+                &html,
+                &format!("img {{ width: 40px; height: 40px; }} {}", css),
+            )
+        };
+
+        // By default the image is stretched over the content box.
+        let canvas = paint("img { image-rendering: pixelated; }");
+        assert_eq!(pixel(&canvas, 5, 5), (255, 0, 0));
+        assert_eq!(pixel(&canvas, 35, 35), (0, 0, 255));
+        assert_eq!(pixel(&canvas, 20, 20), (0, 0, 255));
+        // Smooth scaling blends neighbouring pixels.
+        let (red, green, blue) = pixel(&paint(""), 20, 20);
+        assert!(red > 100 && blue > 100 && green == 0);
+
+        let canvas = paint("img { object-fit: contain; }");
+        assert_eq!(pixel(&canvas, 5, 5), (255, 255, 255));
+        assert_eq!(pixel(&canvas, 5, 20), (255, 0, 0));
+        let canvas = paint("img { object-fit: contain; object-position: top; }");
+        assert_eq!(pixel(&canvas, 5, 5), (255, 0, 0));
+        assert_eq!(pixel(&canvas, 5, 30), (255, 255, 255));
+        // Covering the box overflows it, and what overflows is clipped.
+        let canvas = paint("img { object-fit: cover; image-rendering: pixelated; }");
+        assert_eq!(pixel(&canvas, 5, 20), (255, 0, 0));
+        assert_eq!(pixel(&canvas, 35, 20), (0, 0, 255));
+        assert_eq!(pixel(&canvas, 45, 20), (255, 255, 255));
+        let canvas = paint("img { object-fit: none; object-position: right 0px bottom 0px; }");
+        assert_eq!(pixel(&canvas, 38, 39), (255, 0, 0));
+        assert_eq!(pixel(&canvas, 39, 39), (0, 0, 255));
+        assert_eq!(pixel(&canvas, 39, 38), (255, 255, 255));
+        std::fs::remove_file(path).unwrap();
+    }
 }
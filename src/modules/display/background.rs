@@ -0,0 +1,560 @@
+//! Backgrounds: a color under layers of images, each sized, positioned and repeated in one area of
+//! the box and clipped to another.
+//!
+//! http://www.w3.org/TR/css-backgrounds-3/#backgrounds
+
+use super::super::css::{Color, Gradient, Unit, Value};
+use super::super::images::{Image, ImageCache};
+use super::super::layout::{LayoutBox, Rect};
+use super::{image_filter, position, style_node, tokens, DisplayCommand, DisplayList, ImageFilter};
+
+/// The longhands of `background` that take a value for each layer, in the order `Layer` keeps
+/// their values in.
+const LAYER_PROPERTIES: [&str; 6] = [
+    "background-image",
+    "background-position",
+    "background-size",
+    "background-repeat",
+    "background-origin",
+    "background-clip",
+];
+const IMAGE: usize = 0;
+const POSITION: usize = 1;
+const SIZE: usize = 2;
+const REPEAT: usize = 3;
+const ORIGIN: usize = 4;
+const CLIP: usize = 5;
+
+/// One layer of a background: the values of `LAYER_PROPERTIES` for it, each split into tokens.
+type Layer = [Vec<Value>; 6];
+
+/// An area of a box, from `background-origin` and `background-clip`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BoxArea {
+    Border,
+    Padding,
+    Content,
+}
+
+impl BoxArea {
+    fn of(tokens: &[Value], default: BoxArea) -> BoxArea {
+        match tokens.first() {
+            Some(Value::Keyword(area)) if area == "border-box" => BoxArea::Border,
+            Some(Value::Keyword(area)) if area == "padding-box" => BoxArea::Padding,
+            Some(Value::Keyword(area)) if area == "content-box" => BoxArea::Content,
+            _ => default,
+        }
+    }
+
+    fn rect(self, layout_box: &LayoutBox) -> Rect {
+        match self {
+            BoxArea::Border => layout_box.dimensions.border_box(),
+            BoxArea::Padding => layout_box.dimensions.padding_box(),
+            BoxArea::Content => layout_box.dimensions.content,
+        }
+    }
+}
+
+/// How an image repeats along one axis, from `background-repeat`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RepeatStyle {
+    Repeat,
+    /// Repeat as often as the image fits whole, spreading the images out to fill the area.
+    Space,
+    /// Repeat a whole number of times, scaling the image to fill the area.
+    Round,
+    NoRepeat,
+}
+
+impl RepeatStyle {
+    fn of(keyword: &str) -> Option<RepeatStyle> {
+        match keyword {
+            "repeat" => Some(RepeatStyle::Repeat),
+            "space" => Some(RepeatStyle::Space),
+            "round" => Some(RepeatStyle::Round),
+            "no-repeat" => Some(RepeatStyle::NoRepeat),
+            _ => None,
+        }
+    }
+
+    /// How an image repeats horizontally and vertically.
+    fn axes(tokens: &[Value]) -> (RepeatStyle, RepeatStyle) {
+        let keywords: Vec<&str> = tokens
+            .iter()
+            .filter_map(|token| match token {
+                Value::Keyword(keyword) => Some(&**keyword),
+                _ => None,
+            })
+            .collect();
+        match keywords.as_slice() {
+            ["repeat-x"] => (RepeatStyle::Repeat, RepeatStyle::NoRepeat),
+            ["repeat-y"] => (RepeatStyle::NoRepeat, RepeatStyle::Repeat),
+            [x, y] => (
+                RepeatStyle::of(x).unwrap_or(RepeatStyle::Repeat),
+                RepeatStyle::of(y).unwrap_or(RepeatStyle::Repeat),
+            ),
+            [both] => {
+                let both = RepeatStyle::of(both).unwrap_or(RepeatStyle::Repeat);
+                (both, both)
+            }
+            _ => (RepeatStyle::Repeat, RepeatStyle::Repeat),
+        }
+    }
+}
+
+/// The image of a background layer: a decoded image, or a gradient, which has no size of its own.
+enum LayerImage<'a> {
+    Raster(Image),
+    Gradient(&'a Gradient),
+}
+
+/// Paint the background of a box: its `background-color`, and over it the layers of
+/// `background-image` from the last to the first. The color is clipped like the last layer.
+pub(super) fn render_background(
+    list: &mut DisplayList,
+    layout_box: &LayoutBox,
+    images: &ImageCache,
+) {
+    let (layers, color) = layers(layout_box);
+    if let (Some(color), Some(last)) = (color, layers.last()) {
+        let clip = BoxArea::of(&last[CLIP], BoxArea::Border).rect(layout_box);
+        list.push(DisplayCommand::SolidColor(color, clip));
+    }
+    for layer in layers.iter().rev() {
+        let image = match layer[IMAGE].first() {
+            Some(Value::Url(url)) => images.get(url).map(LayerImage::Raster),
+            Some(Value::Gradient(gradient)) => Some(LayerImage::Gradient(gradient)),
+            _ => None,
+        };
+        if let Some(image) = image {
+            render_layer(list, layout_box, layer, &image);
+        }
+    }
+}
+
+/// The layers of a box's background and its color. Each longhand that is set gives the value of
+/// its property for every layer, repeating its list of values as needed, and the `background`
+/// shorthand gives the rest. There are as many layers as images, and at least one.
+fn layers(layout_box: &LayoutBox) -> (Vec<Layer>, Option<Color>) {
+    let style = match style_node(layout_box) {
+        Some(style) => style,
+        None => return (Vec::new(), None),
+    };
+    let (shorthand, colors): (Vec<Layer>, Vec<Option<Color>>) = style
+        .value("background")
+        .map(split_layers)
+        .unwrap_or_default()
+        .into_iter()
+        .map(split_shorthand)
+        .unzip();
+    let longhands = LAYER_PROPERTIES.map(|name| style.value(name).map(split_layers));
+    let count = match longhands[IMAGE] {
+        Some(ref images) => images.len(),
+        None => shorthand.len(),
+    }
+    .max(1);
+    let layers = (0..count)
+        .map(|i| {
+            let mut layer = Layer::default();
+            for (property, longhand) in longhands.iter().enumerate() {
+                layer[property] = match longhand {
+                    Some(values) if !values.is_empty() => values[i % values.len()].clone(),
+                    _ => shorthand
+                        .get(i)
+                        .map(|layer| layer[property].clone())
+                        .unwrap_or_default(),
+                };
+            }
+            layer
+        })
+        .collect();
+    // Only the last layer of the shorthand can have a color.
+    let color = match style.value("background-color") {
+        Some(Value::Color(color)) => Some(color),
+        Some(_) => None,
+        None => colors.last().cloned().flatten(),
+    };
+    (layers, color)
+}
+
+/// The tokens of each comma-separated layer of a value.
+fn split_layers(value: Value) -> Vec<Vec<Value>> {
+    match value {
+        Value::List(layers) => layers
+            .into_iter()
+            .map(|layer| tokens(Some(layer)))
+            .collect(),
+        value => vec![tokens(Some(value))],
+    }
+}
+
+/// Sort the tokens of one layer of the `background` shorthand into the longhands they set, and
+/// take out its color. The size follows the position after a `/`, and a first box sets both the
+/// origin and the clip, while a second sets the clip.
+fn split_shorthand(tokens: Vec<Value>) -> (Layer, Option<Color>) {
+    let mut layer = Layer::default();
+    let (mut color, mut in_size) = (None, false);
+    for token in tokens {
+        let property = match token {
+            Value::Color(value) => {
+                color = Some(value);
+                continue;
+            }
+            Value::Keyword(ref keyword) if keyword == "/" => {
+                in_size = true;
+                continue;
+            }
+            Value::Url(_) | Value::Gradient(_) => IMAGE,
+            Value::Keyword(ref keyword) if keyword == "none" => IMAGE,
+            Value::Keyword(ref keyword)
+                if keyword == "repeat-x"
+                    || keyword == "repeat-y"
+                    || RepeatStyle::of(keyword).is_some() =>
+            {
+                REPEAT
+            }
+            Value::Keyword(ref keyword) if keyword.ends_with("-box") => {
+                if layer[ORIGIN].is_empty() {
+                    layer[ORIGIN].push(token.clone());
+                } else {
+                    layer[CLIP].clear();
+                }
+                CLIP
+            }
+            _ if in_size => SIZE,
+            _ => POSITION,
+        };
+        layer[property].push(token);
+    }
+    (layer, color)
+}
+
+/// Paint one layer of images, tiled over the area it is clipped to.
+fn render_layer(list: &mut DisplayList, layout_box: &LayoutBox, layer: &Layer, image: &LayerImage) {
+    let area = BoxArea::of(&layer[ORIGIN], BoxArea::Padding).rect(layout_box);
+    let clip = BoxArea::of(&layer[CLIP], BoxArea::Border).rect(layout_box);
+    let (repeat_x, repeat_y) = RepeatStyle::axes(&layer[REPEAT]);
+    let (mut width, mut height) = image_size(&layer[SIZE], image, area);
+    if width <= 0.0 || height <= 0.0 {
+        return;
+    }
+    // Rounded images are scaled to fit a whole number of times, and keep their aspect ratio if
+    // the other axis is sized automatically.
+    let auto = |axis: usize| {
+        !matches!(layer[SIZE].get(axis), Some(token) if !is_auto(token))
+            && !layer[SIZE].iter().any(is_cover_or_contain)
+    };
+    let round = |size: f32, space: f32| space / (space / size).round().max(1.0);
+    if repeat_x == RepeatStyle::Round {
+        let rounded = round(width, area.width);
+        if repeat_y != RepeatStyle::Round && auto(1) {
+            height *= rounded / width;
+        }
+        width = rounded;
+    }
+    if repeat_y == RepeatStyle::Round {
+        let rounded = round(height, area.height);
+        if repeat_x != RepeatStyle::Round && auto(0) {
+            width *= rounded / height;
+        }
+        height = rounded;
+    }
+    // Images start at the top left of the area by default, rather than in its center.
+    let origin = [Value::Length(0.0, Unit::Px), Value::Length(0.0, Unit::Px)];
+    let tokens = match layer[POSITION].as_slice() {
+        [] => &origin[..],
+        tokens => tokens,
+    };
+    let (x, y) = position(tokens, (area.width - width, area.height - height));
+    let columns = tiles(
+        repeat_x,
+        area.x,
+        area.width,
+        area.x + x,
+        width,
+        (clip.x, clip.width),
+    );
+    let rows = tiles(
+        repeat_y,
+        area.y,
+        area.height,
+        area.y + y,
+        height,
+        (clip.y, clip.height),
+    );
+    let filter = style_node(layout_box).map_or(ImageFilter::Smooth, image_filter);
+    list.push(DisplayCommand::PushClip(clip));
+    for &y in &rows {
+        for &x in &columns {
+            let rect = Rect {
+                x,
+                y,
+                width,
+                height,
+            };
+            list.push(match image {
+                LayerImage::Raster(image) => DisplayCommand::Image(image.clone(), rect, filter),
+                LayerImage::Gradient(gradient) => {
+                    DisplayCommand::Gradient((*gradient).clone(), rect)
+                }
+            });
+        }
+    }
+    list.push(DisplayCommand::PopClip);
+}
+
+fn is_auto(token: &Value) -> bool {
+    matches!(token, Value::Keyword(keyword) if keyword == "auto")
+}
+
+fn is_cover_or_contain(token: &Value) -> bool {
+    matches!(token, Value::Keyword(keyword) if keyword == "cover" || keyword == "contain")
+}
+
+/// The size of an image in a positioning area by `background-size`: scaled to `cover` or
+/// `contain` it, or a width and height with percentages of the area, where `auto` keeps the
+/// aspect ratio of the image, or its own size if both are `auto`. Gradients have neither, and
+/// fill the area where they are not sized otherwise.
+///
+/// http://www.w3.org/TR/css-backgrounds-3/#the-background-size
+fn image_size(size: &[Value], image: &LayerImage, area: Rect) -> (f32, f32) {
+    let length = |axis: usize, reference: f32| match size.get(axis) {
+        Some(token) if !is_auto(token) && !is_cover_or_contain(token) => {
+            Some(token.resolve(reference))
+        }
+        _ => None,
+    };
+    let (width, height) = match image {
+        LayerImage::Raster(image) => (image.width() as f32, image.height() as f32),
+        LayerImage::Gradient(_) => {
+            return (
+                length(0, area.width).unwrap_or(area.width),
+                length(1, area.height).unwrap_or(area.height),
+            )
+        }
+    };
+    if width == 0.0 || height == 0.0 {
+        return (0.0, 0.0);
+    }
+    match size {
+        [Value::Keyword(keyword), ..] if keyword == "cover" || keyword == "contain" => {
+            let (x, y) = (area.width / width, area.height / height);
+            let scale = if keyword == "cover" {
+                x.max(y)
+            } else {
+                x.min(y)
+            };
+            (width * scale, height * scale)
+        }
+        _ => match (length(0, area.width), length(1, area.height)) {
+            (Some(x), Some(y)) => (x, y),
+            (Some(x), None) => (x, x * height / width),
+            (None, Some(y)) => (y * width / height, y),
+            (None, None) => (width, height),
+        },
+    }
+}
+
+/// Where the tiles of an image `size` long go along one axis of a positioning area, from the one
+/// at `start`, to cover the clip `(clip_start, clip_size)`.
+fn tiles(
+    repeat: RepeatStyle,
+    area_start: f32,
+    area_size: f32,
+    start: f32,
+    size: f32,
+    (clip_start, clip_size): (f32, f32),
+) -> Vec<f32> {
+    let (start, step) = match repeat {
+        RepeatStyle::NoRepeat => return vec![start],
+        RepeatStyle::Repeat | RepeatStyle::Round => (start, size),
+        RepeatStyle::Space => {
+            // As many images as fit, the first and last at the edges of the area.
+            let count = (area_size / size).floor();
+            if count < 2.0 {
+                return vec![start];
+            }
+            (
+                area_start,
+                size + (area_size - count * size) / (count - 1.0),
+            )
+        }
+    };
+    let first = start - ((start - clip_start) / step).ceil() * step;
+    (0..)
+        .map(|i| first + i as f32 * step)
+        .take_while(|&x| x < clip_start + clip_size)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::parser;
+    use super::super::super::testing::Page;
+    use super::super::Canvas;
+    use super::*;
+    use std::{env, fs};
+
+    /// Render `html` on a 100x50 canvas, with images loaded from `dir`.
+    fn render(html: &str, css: &str, images: &ImageCache) -> Canvas {
+        let page = Page {
+            html,
+            css,
+            width: 100.0,
+            height: 50.0,
+            user_agent: false,
+            images: Some(images),
+            ..Default::default()
+        };
+        page.paint()
+    }
+
+    fn pixel(canvas: &Canvas, x: usize, y: usize) -> (u8, u8, u8) {
+        let pixel = &canvas.pixels[y * canvas.width + x];
+        (pixel.red, pixel.green, pixel.blue)
+    }
+
+    /// The tokens of a `background-position` value.
+    fn position_tokens(value: &str) -> Vec<Value> {
+        let root = parser::parse_html("<div></div>".to_string());
+        let css = format!("div {{ background-position: {}; }}", value);
+        let stylesheet = parser::parse_css(css, &root);
+        stylesheet.rules[0].declarations[0].value.parts().to_vec()
+    }
+
+    const RED: (u8, u8, u8) = (255, 0, 0);
+    const BLUE: (u8, u8, u8) = (0, 0, 255);
+    const GREEN: (u8, u8, u8) = (0, 255, 0);
+    const WHITE: (u8, u8, u8) = (255, 255, 255);
+
+    #[test]
+    fn test_background_images() {
+        let dir = env::temp_dir().join(format!("ponyrender-background-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // A 10x10 red square, and a 2x2 one with a transparent bottom right pixel.
+        image::RgbaImage::from_pixel(10, 10, image::Rgba([255, 0, 0, 255]))
+            .save(dir.join("red.png"))
+            .unwrap();
+        image::RgbaImage::from_fn(2, 2, |x, y| match (x, y) {
+            (1, 1) => image::Rgba([0, 0, 0, 0]),
+            _ => image::Rgba([0, 0, 255, 255]),
+        })
+        .save(dir.join("blue.png"))
+        .unwrap();
+        let images = ImageCache::new(&dir);
+        let html = "<div></div>";
+        let paint = |css: &str| {
+            render(
+                html,
+                &format!("div {{ width: 40px; height: 20px; padding: 5px; {} }}", css),
+                &images,
+            )
+        };
+
+        // Images repeat from the top left of the padding box over the border box by default.
+        let canvas = paint("background: url(red.png) #00ff00;");
+        assert_eq!(pixel(&canvas, 0, 0), RED);
+        assert_eq!(pixel(&canvas, 49, 29), RED);
+        assert_eq!(pixel(&canvas, 60, 10), WHITE);
+
+        let canvas = paint("background: url(red.png) no-repeat center #00ff00;");
+        assert_eq!(pixel(&canvas, 25, 15), RED);
+        assert_eq!(pixel(&canvas, 5, 5), GREEN);
+        let canvas = paint("background: #00ff00 url(red.png) repeat-x right 0px bottom 0px;");
+        assert_eq!(pixel(&canvas, 1, 29), RED);
+        assert_eq!(pixel(&canvas, 1, 15), GREEN);
+
+        // Sizes, with percentages of the positioning area.
+        let canvas = paint("background: url(red.png) no-repeat 0px 0px / 50% auto #00ff00;");
+        assert_eq!(pixel(&canvas, 24, 24), RED);
+        assert_eq!(pixel(&canvas, 26, 10), GREEN);
+        let canvas = paint("background: url(red.png) no-repeat #00ff00; background-size: contain;");
+        assert_eq!(pixel(&canvas, 29, 29), RED);
+        assert_eq!(pixel(&canvas, 31, 10), GREEN);
+        let canvas = paint("background: url(red.png) no-repeat #00ff00; background-size: cover;");
+        assert_eq!(pixel(&canvas, 49, 29), RED);
+        // Rounded, five of them fit across the padding box and three down it, each 10px square.
+        let canvas = paint(
+            "background: url(blue.png) #00ff00; background-repeat: round; \
+             background-size: 11px 11px; image-rendering: pixelated;",
+        );
+        assert_eq!(pixel(&canvas, 4, 4), BLUE);
+        assert_eq!(pixel(&canvas, 9, 9), GREEN);
+        assert_eq!(pixel(&canvas, 14, 14), BLUE);
+
+        // The origin and the clip.
+        let canvas = paint("background: url(red.png) no-repeat content-box #00ff00;");
+        assert_eq!(pixel(&canvas, 2, 2), WHITE);
+        assert_eq!(pixel(&canvas, 12, 12), RED);
+        assert_eq!(pixel(&canvas, 20, 20), GREEN);
+        let canvas = paint(
+            "border-width: 5px; background: url(red.png) no-repeat #00ff00; \
+             background-origin: border-box; \
+             background-clip: padding-box;",
+        );
+        assert_eq!(pixel(&canvas, 1, 1), WHITE);
+        assert_eq!(pixel(&canvas, 7, 7), RED);
+        assert_eq!(pixel(&canvas, 12, 12), GREEN);
+
+        // The first layer paints over the others, whose images show through it.
+        let canvas = paint(
+            "background: url(blue.png) no-repeat 0px 0px / 20px 20px, url(red.png) #00ff00; \
+             image-rendering: pixelated;",
+        );
+        assert_eq!(pixel(&canvas, 5, 5), BLUE);
+        assert_eq!(pixel(&canvas, 15, 15), RED);
+        assert_eq!(pixel(&canvas, 25, 25), RED);
+        let canvas = paint(
+            "background-image: url(blue.png), url(red.png); background-repeat: no-repeat; \
+             background-position: 0px 0px, 20px 0px; background-size: 10px 10px; \
+             background-color: #00ff00;",
+        );
+        assert_eq!(pixel(&canvas, 2, 2), BLUE);
+        assert_eq!(pixel(&canvas, 25, 5), RED);
+        assert_eq!(pixel(&canvas, 15, 15), GREEN);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_position() {
+        // In an area 100px wider and 50px taller than the image.
+        let at = |value: &str| position(&position_tokens(value), (100.0, 50.0));
+        assert_eq!(at("right bottom"), (100.0, 50.0));
+        assert_eq!(at("bottom left"), (0.0, 50.0));
+        assert_eq!(at("center"), (50.0, 25.0));
+        assert_eq!(at("top"), (50.0, 0.0));
+        assert_eq!(at("10px"), (10.0, 25.0));
+        // Percentages line up that point of the image with that point of the area.
+        assert_eq!(at("25% 75%"), (25.0, 37.5));
+        // Offsets from the right and bottom edges, in either order.
+        assert_eq!(at("right 10px bottom 5px"), (90.0, 45.0));
+        assert_eq!(at("bottom 5px right 10%"), (90.0, 45.0));
+    }
+
+    #[test]
+    fn test_tiles() {
+        let clip = (0.0, 60.0);
+        // Repeated tiles reach back from the positioned one to cover the clip.
+        assert_eq!(
+            tiles(RepeatStyle::Repeat, 0.0, 60.0, 25.0, 10.0, clip),
+            [-5.0, 5.0, 15.0, 25.0, 35.0, 45.0, 55.0]
+        );
+        assert_eq!(
+            tiles(RepeatStyle::NoRepeat, 0.0, 60.0, 25.0, 10.0, clip),
+            [25.0]
+        );
+        // Spaced tiles leave out the position: as many as fit go from one edge of the area to
+        // the other, with equal gaps.
+        let spaced = tiles(RepeatStyle::Space, 5.0, 45.0, 20.0, 10.0, (5.0, 45.0));
+        let expected = [5.0, 16.667, 28.333, 40.0];
+        assert_eq!(spaced.len(), expected.len());
+        for (x, expected) in spaced.iter().zip(expected) {
+            assert!((x - expected).abs() < 1e-3);
+        }
+        // With room for only one, it goes where it is positioned.
+        assert_eq!(
+            tiles(RepeatStyle::Space, 0.0, 15.0, 3.0, 10.0, (0.0, 15.0)),
+            [3.0]
+        );
+    }
+}
@@ -0,0 +1,443 @@
+//! Gradients: colors that change smoothly along a line, out from a center or around it, drawn to
+//! fill a rectangle.
+//!
+//! http://www.w3.org/TR/css-images-3/#gradients
+
+use super::super::css::{
+    Color, ColorStop, Gradient, GradientLine, GradientShape, RadialSize, Unit, Value,
+};
+use super::super::layout::Rect;
+use super::position;
+
+/// A gradient laid out over a rectangle, with its stops placed, ready to give the color at any
+/// point.
+pub(super) struct GradientFill {
+    geometry: Geometry,
+    stops: Vec<Stop>,
+    repeating: bool,
+}
+
+/// Where a point is along a gradient: how far along its line or ray, in px, or how far around its
+/// center, in degrees.
+enum Geometry {
+    Linear {
+        center: (f32, f32),
+        /// The direction of the line, one px long.
+        direction: (f32, f32),
+        length: f32,
+    },
+    Radial {
+        center: (f32, f32),
+        radii: (f32, f32),
+    },
+    Conic {
+        center: (f32, f32),
+        from: f32,
+    },
+}
+
+/// A color stop at its place along a gradient.
+struct Stop {
+    offset: f32,
+    color: Color,
+    /// Where the colors of the stop before and this one mix half and half.
+    hint: Option<f32>,
+}
+
+impl GradientFill {
+    pub(super) fn new(gradient: &Gradient, rect: Rect) -> GradientFill {
+        let (width, height) = (rect.width, rect.height);
+        let center = |parts: &[Value]| {
+            let (x, y) = position(parts, (width, height));
+            (rect.x + x, rect.y + y)
+        };
+        let (geometry, length) = match gradient.shape {
+            GradientShape::Linear(ref line) => {
+                let angle = match *line {
+                    GradientLine::Angle(angle) => angle.to_radians(),
+                    // The line is at right angles to the diagonal between the other two corners,
+                    // so those are halfway along it.
+                    GradientLine::Corner(x, y) => (x * height).atan2(-y * width),
+                };
+                let (sin, cos) = angle.sin_cos();
+                let length = (width * sin).abs() + (height * cos).abs();
+                let geometry = Geometry::Linear {
+                    center: (rect.x + width / 2.0, rect.y + height / 2.0),
+                    direction: (sin, -cos),
+                    length,
+                };
+                (geometry, length)
+            }
+            GradientShape::Radial {
+                circle,
+                ref size,
+                ref position,
+            } => {
+                let (x, y) = center(position);
+                let sides = (
+                    (x - rect.x, rect.x + width - x),
+                    (y - rect.y, rect.y + height - y),
+                );
+                let radii = radii(circle, size, sides, (width, height));
+                // A shape with no width or height is drawn as a very thin one.
+                let radii = (radii.0.max(1e-3), radii.1.max(1e-3));
+                let geometry = Geometry::Radial {
+                    center: (x, y),
+                    radii,
+                };
+                (geometry, radii.0)
+            }
+            GradientShape::Conic { from, ref position } => {
+                let geometry = Geometry::Conic {
+                    center: center(position),
+                    from,
+                };
+                (geometry, 360.0)
+            }
+        };
+        GradientFill {
+            geometry,
+            stops: place_stops(&gradient.stops, length),
+            repeating: gradient.repeating,
+        }
+    }
+
+    /// The color of the gradient at the point (`x`, `y`).
+    pub(super) fn color_at(&self, x: f32, y: f32) -> Color {
+        let offset = match self.geometry {
+            Geometry::Linear {
+                center,
+                direction,
+                length,
+            } => (x - center.0) * direction.0 + (y - center.1) * direction.1 + length / 2.0,
+            Geometry::Radial { center, radii } => {
+                let (dx, dy) = ((x - center.0) / radii.0, (y - center.1) / radii.1);
+                dx.hypot(dy) * radii.0
+            }
+            Geometry::Conic { center, from } => {
+                // Clockwise from pointing up.
+                let angle = (x - center.0).atan2(center.1 - y).to_degrees();
+                (angle - from).rem_euclid(360.0)
+            }
+        };
+        self.color_at_offset(offset)
+    }
+
+    fn color_at_offset(&self, mut offset: f32) -> Color {
+        let (first, last) = (&self.stops[0], &self.stops[self.stops.len() - 1]);
+        if self.repeating {
+            let period = last.offset - first.offset;
+            if period <= 0.0 {
+                return self.average();
+            }
+            offset = first.offset + (offset - first.offset).rem_euclid(period);
+        }
+        if offset < first.offset {
+            return first.color.clone();
+        }
+        // The stops on either side, skipping over the hard transitions between stops in the
+        // same place.
+        let i = match self
+            .stops
+            .windows(2)
+            .position(|pair| offset < pair[1].offset)
+        {
+            Some(i) => i,
+            None => return last.color.clone(),
+        };
+        let (before, after) = (&self.stops[i], &self.stops[i + 1]);
+        let span = after.offset - before.offset;
+        let mut progress = (offset - before.offset) / span;
+        if let Some(hint) = after.hint {
+            // The curve through the hint that the spec gives.
+            let hint = (hint - before.offset) / span;
+            progress = if hint <= 0.0 {
+                1.0
+            } else if hint >= 1.0 {
+                0.0
+            } else {
+                progress.powf(0.5f32.ln() / hint.ln())
+            };
+        }
+        mix(&before.color, &after.color, progress)
+    }
+
+    /// The color a repeating gradient whose stops are all in one place is drawn in.
+    fn average(&self) -> Color {
+        let mut average = self.stops[0].color.clone();
+        for (i, stop) in self.stops.iter().enumerate().skip(1) {
+            // Mixing in each stop by its share of those so far averages them all.
+            average = mix(&average, &stop.color, 1.0 / (i + 1) as f32);
+        }
+        average
+    }
+}
+
+/// The radii of the ending shape of a radial gradient from its center, which is `sides` from the
+/// left and right, and the top and bottom, of an area `size` large.
+fn radii(
+    circle: bool,
+    size: &RadialSize,
+    ((left, right), (top, bottom)): ((f32, f32), (f32, f32)),
+    (width, height): (f32, f32),
+) -> (f32, f32) {
+    let closest = (left.abs().min(right.abs()), top.abs().min(bottom.abs()));
+    let farthest = (left.abs().max(right.abs()), top.abs().max(bottom.abs()));
+    match *size {
+        RadialSize::ClosestSide | RadialSize::FarthestSide => {
+            let (x, y) = match *size {
+                RadialSize::ClosestSide => closest,
+                _ => farthest,
+            };
+            if !circle {
+                (x, y)
+            } else if *size == RadialSize::ClosestSide {
+                (x.min(y), x.min(y))
+            } else {
+                (x.max(y), x.max(y))
+            }
+        }
+        RadialSize::ClosestCorner | RadialSize::FarthestCorner => {
+            let (x, y) = match *size {
+                RadialSize::ClosestCorner => closest,
+                _ => farthest,
+            };
+            if circle {
+                (x.hypot(y), x.hypot(y))
+            } else {
+                // The ellipse through the corner with the aspect ratio it would have to the sides.
+                (x * std::f32::consts::SQRT_2, y * std::f32::consts::SQRT_2)
+            }
+        }
+        RadialSize::Radii(ref radii) => match radii.as_slice() {
+            [x, y] => (x.resolve(width), y.resolve(height)),
+            [radius] => (radius.to_px(), radius.to_px()),
+            _ => (0.0, 0.0),
+        },
+    }
+}
+
+/// Place color stops along a gradient `length` long: stops without a position are spread out
+/// evenly between those around them, the first and last at the ends, and no stop comes before
+/// one ahead of it.
+///
+/// http://www.w3.org/TR/css-images-3/#color-stop-fixup
+fn place_stops(stops: &[ColorStop], length: f32) -> Vec<Stop> {
+    let offset = |value: &Value| match *value {
+        Value::Length(percentage, Unit::Percent) => percentage / 100.0 * length,
+        Value::Length(degrees, Unit::Deg) => degrees,
+        ref value => value.to_px(),
+    };
+    let mut offsets: Vec<Option<f32>> = stops
+        .iter()
+        .map(|stop| stop.position.as_ref().map(offset))
+        .collect();
+    let last = offsets.len() - 1;
+    offsets[0] = offsets[0].or(Some(0.0));
+    offsets[last] = offsets[last].or(Some(length));
+    let mut furthest = f32::MIN;
+    for offset in offsets.iter_mut().flatten() {
+        *offset = offset.max(furthest);
+        furthest = *offset;
+    }
+    let mut i = 1;
+    while i < last {
+        if offsets[i].is_none() {
+            let end = (i..).find(|&end| offsets[end].is_some()).unwrap();
+            let (from, to) = (offsets[i - 1].unwrap(), offsets[end].unwrap());
+            for (j, offset) in offsets.iter_mut().enumerate().take(end).skip(i) {
+                let share = (j - i + 1) as f32 / (end - i + 1) as f32;
+                *offset = Some(from + (to - from) * share);
+            }
+            i = end;
+        }
+        i += 1;
+    }
+    let offsets: Vec<f32> = offsets.into_iter().map(Option::unwrap).collect();
+    stops
+        .iter()
+        .enumerate()
+        .map(|(i, stop)| Stop {
+            offset: offsets[i],
+            color: stop.color.clone(),
+            hint: match stop.hint {
+                Some(ref hint) if i > 0 => Some(offset(hint).clamp(offsets[i - 1], offsets[i])),
+                _ => None,
+            },
+        })
+        .collect()
+}
+
+/// The color `progress` of the way from `from` to `to`. Colors are mixed premultiplied by their
+/// alpha, so that fading to a transparent color does not fade through its hidden color.
+fn mix(from: &Color, to: &Color, progress: f32) -> Color {
+    let (from_alpha, to_alpha) = (
+        from.alpha as f32 * (1.0 - progress),
+        to.alpha as f32 * progress,
+    );
+    let alpha = from_alpha + to_alpha;
+    if alpha == 0.0 {
+        return Color {
+            red: 0,
+            green: 0,
+            blue: 0,
+            alpha: 0,
+        };
+    }
+    let channel = |from: u8, to: u8| {
+        ((from as f32 * from_alpha + to as f32 * to_alpha) / alpha).round() as u8
+    };
+    Color {
+        red: channel(from.red, to.red),
+        green: channel(from.green, to.green),
+        blue: channel(from.blue, to.blue),
+        alpha: alpha.round() as u8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::font::FontDatabase;
+    use super::super::super::images::ImageCache;
+    use super::super::super::layout::{self, Dimensions};
+    use super::super::super::{parser, style};
+    use super::super::paint;
+
+    const WIDTH: usize = 100;
+    const HEIGHT: usize = 50;
+
+    /// Paint `gradient` as the background of a 100x50 box on white, and check every pixel against
+    /// a reference image: `reference` gives the color over white at the center of each pixel, or
+    /// `None` where a hard edge runs too close to the center to tell.
+    fn assert_matches_reference(
+        gradient: &str,
+        reference: impl Fn(f32, f32) -> Option<(f32, f32, f32)>,
+    ) {
+        let root = parser::parse_html("<div></div>".to_string());
+        let css = format!(
+            "div {{ display: block; width: {}px; height: {}px; background: {}; }}",
+            WIDTH, HEIGHT, gradient
+        );
+        let stylesheets = [parser::parse_css(css, &root)];
+        let styled = style::style_tree(&root, &stylesheets);
+        let viewport = Dimensions {
+            content: layout::Rect {
+                width: WIDTH as f32,
+                height: HEIGHT as f32,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let images = ImageCache::default();
+        let layout_root = layout::layout_tree(&styled, viewport, &FontDatabase::new(), &images);
+        let canvas = paint(&layout_root, viewport.content, &images);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let (red, green, blue) = match reference(x as f32 + 0.5, y as f32 + 0.5) {
+                    Some(color) => color,
+                    None => continue,
+                };
+                let pixel = &canvas.pixels[y * WIDTH + x];
+                let close = |actual: u8, expected: f32| (actual as f32 - expected).abs() <= 1.5;
+                assert!(
+                    close(pixel.red, red) && close(pixel.green, green) && close(pixel.blue, blue),
+                    "{} at ({}, {}) is {:?}, not ({}, {}, {})",
+                    gradient,
+                    x,
+                    y,
+                    pixel,
+                    red,
+                    green,
+                    blue
+                );
+            }
+        }
+    }
+
+    /// The gray of `shade` from black to white.
+    fn gray(shade: f32) -> Option<(f32, f32, f32)> {
+        Some((255.0 * shade, 255.0 * shade, 255.0 * shade))
+    }
+
+    #[test]
+    fn test_linear_gradient() {
+        // Red fading out from 20px to 80px. Premultiplied, it stays red as it fades, rather than
+        // turning into the blue of the transparent color.
+        assert_matches_reference(
+            "linear-gradient(90deg, #ff0000 20%, #0000ff00 80%)",
+            |x, _| {
+                let faded = ((x - 20.0) / 60.0).clamp(0.0, 1.0);
+                Some((255.0, 255.0 * faded, 255.0 * faded))
+            },
+        );
+        // Toward a corner, the other two corners are halfway along the line.
+        assert_matches_reference(
+            "linear-gradient(to bottom right, #000000, #ffffff)",
+            |x, y| gray((x / WIDTH as f32 + y / HEIGHT as f32) / 2.0),
+        );
+    }
+
+    #[test]
+    fn test_repeating_linear_gradient() {
+        assert_matches_reference(
+            "repeating-linear-gradient(#000000, #ffffff 10px)",
+            |_, y| gray((y % 10.0) / 10.0),
+        );
+    }
+
+    #[test]
+    fn test_radial_gradient() {
+        // An ellipse to the sides of the box, with a hint a quarter of the way out.
+        assert_matches_reference(
+            "radial-gradient(closest-side, #00ff00, 25%, #ffffff)",
+            |x, y| {
+                let distance = ((x - 50.0) / 50.0).hypot((y - 25.0) / 25.0).min(1.0);
+                let white = distance.sqrt();
+                Some((255.0 * white, 255.0, 255.0 * white))
+            },
+        );
+    }
+
+    #[test]
+    fn test_repeating_radial_gradient() {
+        // Rings 5px wide around the top left corner, with hard edges between them.
+        assert_matches_reference(
+            "repeating-radial-gradient(circle at 0% 0%, #000000 0px 5px, #ffffff 5px 10px)",
+            |x, y| {
+                let ring = x.hypot(y) % 10.0;
+                if (ring % 5.0).min(5.0 - ring % 5.0) < 0.01 {
+                    return None;
+                }
+                gray(if ring < 5.0 { 0.0 } else { 1.0 })
+            },
+        );
+    }
+
+    #[test]
+    fn test_conic_gradient() {
+        // From red pointing right, clockwise around the center to blue.
+        assert_matches_reference(
+            "conic-gradient(from 90deg at 50% 50%, #ff0000, #0000ff)",
+            |x, y| {
+                let angle = (x - 50.0).atan2(25.0 - y).to_degrees();
+                let turned = (angle - 90.0).rem_euclid(360.0) / 360.0;
+                Some((255.0 * (1.0 - turned), 0.0, 255.0 * turned))
+            },
+        );
+    }
+
+    #[test]
+    fn test_repeating_conic_gradient() {
+        // Wedges an eighth of a turn wide.
+        assert_matches_reference(
+            "repeating-conic-gradient(#000000 0deg 45deg, #ffffff 45deg 90deg)",
+            |x, y| {
+                let angle = (x - 50.0).atan2(25.0 - y).to_degrees().rem_euclid(90.0);
+                if (angle % 45.0).min(45.0 - angle % 45.0) < 0.1 {
+                    return None;
+                }
+                gray(if angle < 45.0 { 0.0 } else { 1.0 })
+            },
+        );
+    }
+}
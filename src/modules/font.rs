@@ -0,0 +1,206 @@
+use super::style::StyledNode;
+use ab_glyph::{point, Font as _, FontRef, GlyphId, PxScale};
+use self_cell::self_cell;
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+mod database;
+mod shaping;
+
+pub use database::FontDatabase;
+
+/// The DejaVu Sans family, embedded so text can be measured and drawn without relying on fonts
+/// installed on the system.
+static DEJAVU_SANS: &[u8] = include_bytes!("../../fonts/DejaVuSans.ttf");
+static DEJAVU_SANS_BOLD: &[u8] = include_bytes!("../../fonts/DejaVuSans-Bold.ttf");
+static DEJAVU_SANS_OBLIQUE: &[u8] = include_bytes!("../../fonts/DejaVuSans-Oblique.ttf");
+static DEJAVU_SANS_BOLD_OBLIQUE: &[u8] = include_bytes!("../../fonts/DejaVuSans-BoldOblique.ttf");
+
+/// A parsed font face. Sizes passed to its methods are font sizes (the em square) in px.
+///
+/// Cloning a font is cheap, and a font only compares equal to its clones.
+#[derive(Clone)]
+pub struct Font(Arc<FontData>);
+
+struct FontData {
+    name: String,
+    faces: Faces,
+}
+
+/// A font file parsed for rasterizing with ab_glyph and for shaping with rustybuzz.
+struct FaceRefs<'a> {
+    outlines: FontRef<'a>,
+    shaper: rustybuzz::Face<'a>,
+}
+
+self_cell!(
+    struct Faces {
+        owner: Cow<'static, [u8]>,
+
+        #[covariant]
+        dependent: FaceRefs,
+    }
+);
+
+/// Vertical metrics of a font at a given size, in px. Both `ascent` and `descent` are positive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FontMetrics {
+    pub ascent: f32,
+    pub descent: f32,
+    pub line_gap: f32,
+    /// The height of lowercase letters, from the font's OS/2 table or half an em without one.
+    pub x_height: f32,
+    /// Where an underline goes: the distance from the baseline down to its top, and its
+    /// thickness.
+    pub underline: (f32, f32),
+    /// Where a line through the text goes: the distance from the baseline up to its top, and its
+    /// thickness.
+    pub strikeout: (f32, f32),
+}
+
+/// A glyph set in a run of text. Its origin is `x` px from the start of the run, on the baseline
+/// when the text is set; layout moves `y` down to the baseline of the text run box it ends up in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Glyph {
+    pub font: Font,
+    pub id: GlyphId,
+    pub x: f32,
+    pub y: f32,
+    /// How far the glyph moves the pen, which can differ from the distance to the next glyph when
+    /// marks are attached to it.
+    pub advance: f32,
+    /// Byte offset in the text of the run of the first character the glyph was set from.
+    /// Ligatures cover several characters.
+    pub cluster: usize,
+}
+
+impl Font {
+    /// Parse the face at `index` in a font file, if it is a valid font.
+    fn new(name: String, data: Cow<'static, [u8]>, index: u32) -> Option<Font> {
+        let faces = Faces::try_new(data, |data| {
+            Ok::<_, ()>(FaceRefs {
+                outlines: FontRef::try_from_slice_and_index(data, index).map_err(|_| ())?,
+                shaper: rustybuzz::Face::from_slice(data, index).ok_or(())?,
+            })
+        })
+        .ok()?;
+        Some(Font(Arc::new(FontData { name, faces })))
+    }
+
+    /// The ab_glyph face, for outlines and metrics.
+    fn outlines(&self) -> &FontRef<'_> {
+        &self.0.faces.borrow_dependent().outlines
+    }
+
+    /// The faces embedded in the crate: DejaVu Sans in regular, bold, oblique and bold oblique.
+    fn embedded() -> &'static [Font; 4] {
+        static FACES: OnceLock<[Font; 4]> = OnceLock::new();
+        FACES.get_or_init(|| {
+            let load = |name: &str, data| {
+                Font::new(name.to_string(), Cow::Borrowed(data), 0)
+                    .expect("Embedded font is invalid")
+            };
+            [
+                load("DejaVu Sans", DEJAVU_SANS),
+                load("DejaVu Sans Bold", DEJAVU_SANS_BOLD),
+                load("DejaVu Sans Oblique", DEJAVU_SANS_OBLIQUE),
+                load("DejaVu Sans Bold Oblique", DEJAVU_SANS_BOLD_OBLIQUE),
+            ]
+        })
+    }
+
+    /// The embedded regular face.
+    pub fn default_font() -> &'static Font {
+        &Font::embedded()[0]
+    }
+
+    /// The full name of the face, or the family name of the `@font-face` rule it was loaded from.
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// Whether the font has a glyph for a character.
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.outlines().glyph_id(c).0 != 0
+    }
+
+    /// Factor from font units to px at the given font size.
+    fn scale(&self, size: f32) -> f32 {
+        size / self.outlines().units_per_em().unwrap_or(1000.0)
+    }
+
+    pub fn metrics(&self, size: f32) -> FontMetrics {
+        let scale = self.scale(size);
+        let face = &self.0.faces.borrow_dependent().shaper;
+        let x_height = face.x_height().map_or(size / 2.0, |x| f32::from(x) * scale);
+        // Fonts without line metrics get lines a 14th of an em thick.
+        let line = |metrics: Option<ttf_parser::LineMetrics>, position: f32| {
+            metrics.map_or((position, size / 14.0), |line| {
+                (
+                    f32::from(line.position) * scale,
+                    f32::from(line.thickness) * scale,
+                )
+            })
+        };
+        let underline = line(face.underline_metrics(), -size / 10.0);
+        FontMetrics {
+            ascent: self.outlines().ascent_unscaled() * scale,
+            descent: -self.outlines().descent_unscaled() * scale,
+            line_gap: self.outlines().line_gap_unscaled() * scale,
+            x_height,
+            underline: (-underline.0, underline.1),
+            strikeout: line(face.strikeout_metrics(), x_height / 2.0 + size / 28.0),
+        }
+    }
+
+    /// Rasterize a glyph with its origin at (`x`, `y`). Calls `coverage` with the pixel position
+    /// and how much of that pixel the glyph covers, from 0 to 1.
+    pub fn rasterize(
+        &self,
+        id: GlyphId,
+        size: f32,
+        x: f32,
+        y: f32,
+        mut coverage: impl FnMut(i32, i32, f32),
+    ) {
+        let face = self.outlines();
+        // ab_glyph scales by the font's height rather than its em square.
+        let scale = PxScale::from(face.height_unscaled() * self.scale(size));
+        let positioned = id.with_scale_and_position(scale, point(x, y));
+        if let Some(outline) = face.outline_glyph(positioned) {
+            let bounds = outline.px_bounds();
+            outline.draw(|px, py, c| {
+                coverage(
+                    bounds.min.x as i32 + px as i32,
+                    bounds.min.y as i32 + py as i32,
+                    c,
+                )
+            });
+        }
+    }
+}
+
+impl PartialEq for Font {
+    fn eq(&self, other: &Font) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Font {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Font").field(&self.0.name).finish()
+    }
+}
+
+/// The font size in px of a style node. `style::style_tree` computes it for every node.
+pub fn font_size(style: &StyledNode) -> f32 {
+    style.value("font-size").map_or(16.0, |size| size.to_px())
+}
+
+impl FontMetrics {
+    /// The used value of `line-height: normal`.
+    pub fn normal_line_height(&self) -> f32 {
+        self.ascent + self.descent + self.line_gap
+    }
+}
@@ -0,0 +1,655 @@
+use super::super::css::{Stylesheet, Unit, Value};
+use super::super::style::StyledNode;
+use super::shaping::feature_settings;
+use super::{font_size, Font, Glyph};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use ttf_parser::name_id;
+
+/// The family used when no family in `font-family` has a face, and for characters none of those
+/// faces have a glyph for.
+const DEFAULT_FAMILY: &str = "DejaVu Sans";
+
+/// Families tried for each generic family, before falling back to the default family.
+const GENERIC_FAMILIES: [(&str, &[&str]); 3] = [
+    (
+        "serif",
+        &["DejaVu Serif", "Times New Roman", "Liberation Serif"],
+    ),
+    ("sans-serif", &["DejaVu Sans", "Arial", "Liberation Sans"]),
+    (
+        "monospace",
+        &["DejaVu Sans Mono", "Courier New", "Liberation Mono"],
+    ),
+];
+
+/// `font-stretch` keywords and the percentages they stand for.
+const STRETCH_KEYWORDS: [(&str, f32); 9] = [
+    ("ultra-condensed", 50.0),
+    ("extra-condensed", 62.5),
+    ("condensed", 75.0),
+    ("semi-condensed", 87.5),
+    ("normal", 100.0),
+    ("semi-expanded", 112.5),
+    ("expanded", 125.0),
+    ("extra-expanded", 150.0),
+    ("ultra-expanded", 200.0),
+];
+
+/// A `font-style`, or the style a face was designed in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum FontStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+/// The family, weight, style and stretch a face is registered under, from its `@font-face` rule
+/// or read from the font file.
+#[derive(Clone, Debug, PartialEq)]
+struct FaceDescriptor {
+    family: String,
+    weight: f32,
+    style: FontStyle,
+    /// Width as a percentage of normal.
+    stretch: f32,
+}
+
+/// A face in the database. Faces are only read from disk the first time they are matched.
+struct FaceEntry {
+    descriptor: FaceDescriptor,
+    /// Whether the face comes from an `@font-face` rule. Those hide any other faces of the same
+    /// family.
+    font_face_rule: bool,
+    name: String,
+    /// Files to load the face from, tried in order, with the index of the face in each.
+    sources: Vec<(PathBuf, u32)>,
+    font: OnceLock<Option<Font>>,
+}
+
+/// The font properties of a style node, resolved to what the database matches on.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct FontQuery {
+    /// Lowercase family names in order of preference, with generic families expanded.
+    families: Vec<String>,
+    weight: u32,
+    style: FontStyle,
+    /// Bits of the `f32` percentage, so that the query can be hashed.
+    stretch: u32,
+}
+
+/// The fonts text can be set in: those embedded in the crate, those found in local font
+/// directories, and those loaded by `@font-face` rules.
+pub struct FontDatabase {
+    faces: Vec<FaceEntry>,
+    /// Fallback lists already matched, since inline layout asks for the same style many times.
+    matched: Mutex<HashMap<FontQuery, Vec<Font>>>,
+}
+
+impl FaceEntry {
+    /// The face, loaded from the first of its sources that holds a valid font.
+    fn load(&self) -> Option<&Font> {
+        self.font
+            .get_or_init(|| {
+                self.sources.iter().find_map(|(path, index)| {
+                    let data = fs::read(path).ok()?;
+                    Font::new(self.name.clone(), Cow::Owned(data), *index)
+                })
+            })
+            .as_ref()
+    }
+}
+
+impl FontDatabase {
+    /// A database holding the fonts embedded in the crate.
+    pub fn new() -> FontDatabase {
+        let embedded = Font::embedded();
+        let styles = [
+            (400.0, FontStyle::Normal),
+            (700.0, FontStyle::Normal),
+            (400.0, FontStyle::Oblique),
+            (700.0, FontStyle::Oblique),
+        ];
+        let faces = embedded
+            .iter()
+            .zip(styles)
+            .map(|(font, (weight, style))| FaceEntry {
+                descriptor: FaceDescriptor {
+                    family: DEFAULT_FAMILY.to_string(),
+                    weight,
+                    style,
+                    stretch: 100.0,
+                },
+                font_face_rule: false,
+                name: font.name().to_string(),
+                sources: Vec::new(),
+                font: OnceLock::from(Some(font.clone())),
+            })
+            .collect();
+        FontDatabase {
+            faces,
+            matched: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Register the TrueType and OpenType fonts in a directory and its subdirectories. Returns
+    /// the number of faces found. Files that are not valid fonts are skipped.
+    pub fn load_directory(&mut self, dir: &Path) -> io::Result<usize> {
+        let mut count = 0;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                count += self.load_directory(&path)?;
+                continue;
+            }
+            let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+            if !["ttf", "otf", "ttc", "otc"]
+                .iter()
+                .any(|e| extension.eq_ignore_ascii_case(e))
+            {
+                continue;
+            }
+            let data = match fs::read(&path) {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+            for index in 0..ttf_parser::fonts_in_collection(&data).unwrap_or(1) {
+                if let Ok(face) = ttf_parser::Face::parse(&data, index) {
+                    if let Some((descriptor, name)) = describe(&face) {
+                        self.faces.push(FaceEntry {
+                            descriptor,
+                            font_face_rule: false,
+                            name,
+                            sources: vec![(path.clone(), index)],
+                            font: OnceLock::new(),
+                        });
+                        count += 1;
+                    }
+                }
+            }
+        }
+        self.matched.lock().unwrap().clear();
+        Ok(count)
+    }
+
+    /// Register the faces described by the `@font-face` rules of some stylesheets. `url()`s in
+    /// `src` are resolved against `base`; other sources are ignored. Rules without a family or
+    /// a usable source are skipped.
+    ///
+    /// http://www.w3.org/TR/css-fonts-4/#font-face-rule
+    pub fn add_font_faces(&mut self, stylesheets: &[Stylesheet], base: &Path) {
+        for rule in stylesheets.iter().flat_map(|sheet| &sheet.font_faces) {
+            let descriptor = |name: &str| {
+                rule.declarations
+                    .iter()
+                    .rev()
+                    .find(|declaration| declaration.name == name)
+                    .map(|declaration| &declaration.value)
+            };
+            let family = match descriptor("font-family").and_then(family_name) {
+                Some(family) => family,
+                None => continue,
+            };
+            let sources: Vec<(PathBuf, u32)> = match descriptor("src") {
+                Some(Value::List(values)) => values.iter().collect(),
+                Some(value) => vec![value],
+                None => Vec::new(),
+            }
+            .into_iter()
+            .filter_map(|value| match value {
+                Value::Url(url) => Some((base.join(url), 0)),
+                // Format hints after the URL are not checked; fonts that fail to load are skipped.
+                Value::Sequence(values) => match values.first() {
+                    Some(Value::Url(url)) => Some((base.join(url), 0)),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+            if sources.is_empty() {
+                continue;
+            }
+            self.faces.push(FaceEntry {
+                descriptor: FaceDescriptor {
+                    family: family.clone(),
+                    weight: match descriptor("font-weight") {
+                        Some(&Value::Number(weight)) => weight,
+                        Some(Value::Keyword(keyword)) if keyword == "bold" => 700.0,
+                        _ => 400.0,
+                    },
+                    style: font_style(descriptor("font-style")),
+                    stretch: font_stretch(descriptor("font-stretch")),
+                },
+                font_face_rule: true,
+                name: family,
+                sources,
+                font: OnceLock::new(),
+            });
+        }
+        self.matched.lock().unwrap().clear();
+    }
+
+    /// The fonts to set the text of a style node in, in order of preference: the best match in
+    /// each family of `font-family` that has one, then the best match in the default family.
+    pub fn fonts(&self, style: &StyledNode) -> Vec<Font> {
+        let query = FontQuery::of(style);
+        if let Some(fonts) = self.matched.lock().unwrap().get(&query) {
+            return fonts.clone();
+        }
+        let mut fonts: Vec<Font> = Vec::new();
+        let default_family = DEFAULT_FAMILY.to_lowercase();
+        for family in query.families.iter().chain([&default_family]) {
+            if let Some(font) = self.match_family(family, &query) {
+                if !fonts.contains(&font) {
+                    fonts.push(font);
+                }
+            }
+        }
+        self.matched.lock().unwrap().insert(query, fonts.clone());
+        fonts
+    }
+
+    /// The first available font of a style node, whose metrics size its inline boxes.
+    pub fn primary_font(&self, style: &StyledNode) -> Font {
+        self.fonts(style).swap_remove(0)
+    }
+
+    /// Shape a string of text on a single line in the fonts of a style node, with its
+    /// `font-feature-settings`. Each character uses the first font that has a glyph for it.
+    /// Right-to-left text comes out in visual order. Returns the glyphs along with the advance
+    /// width of the whole run.
+    pub fn glyphs(&self, style: &StyledNode, text: &str, rtl: bool) -> (Vec<Glyph>, f32) {
+        let fonts = self.fonts(style);
+        let size = font_size(style);
+        let features = feature_settings(style.value("font-feature-settings"));
+        let font_for = |c: char| {
+            fonts
+                .iter()
+                .find(|font| font.has_glyph(c))
+                .unwrap_or(&fonts[0])
+        };
+
+        // Shape each run of characters that use the same font in one go.
+        let mut runs: Vec<(usize, usize, &Font)> = Vec::new();
+        for (i, c) in text.char_indices() {
+            let end = i + c.len_utf8();
+            // Marks stay in the font of their base, so that they can be attached to it.
+            let font = match runs.last_mut() {
+                Some(run) if is_mark(c) || font_for(c) == run.2 => {
+                    run.1 = end;
+                    continue;
+                }
+                _ => font_for(c),
+            };
+            runs.push((i, end, font));
+        }
+        if rtl {
+            runs.reverse();
+        }
+
+        let mut glyphs = Vec::new();
+        let mut x = 0.0;
+        for (start, end, font) in runs {
+            let (run, width) = font.shape(&text[start..end], size, rtl, &features);
+            glyphs.extend(run.into_iter().map(|glyph| Glyph {
+                x: glyph.x + x,
+                cluster: glyph.cluster + start,
+                ..glyph
+            }));
+            x += width;
+        }
+        (glyphs, x)
+    }
+
+    /// The best matching face of a family that can be loaded, if the family has any.
+    fn match_family(&self, family: &str, query: &FontQuery) -> Option<Font> {
+        let mut candidates: Vec<&FaceEntry> = self
+            .faces
+            .iter()
+            .filter(|face| face.descriptor.family.eq_ignore_ascii_case(family))
+            .collect();
+        if candidates.iter().any(|face| face.font_face_rule) {
+            candidates.retain(|face| face.font_face_rule);
+        }
+        while let Some(best) = best_match(&candidates, query) {
+            if let Some(font) = best.load() {
+                return Some(font.clone());
+            }
+            candidates.retain(|face| !std::ptr::eq(*face, best));
+        }
+        None
+    }
+}
+
+impl Default for FontDatabase {
+    fn default() -> FontDatabase {
+        FontDatabase::new()
+    }
+}
+
+impl FontQuery {
+    fn of(style: &StyledNode) -> FontQuery {
+        let values = match style.value("font-family") {
+            Some(Value::List(values)) => values,
+            Some(value) => vec![value],
+            None => Vec::new(),
+        };
+        let mut families = Vec::new();
+        for value in values {
+            match value {
+                Value::Keyword(family) => {
+                    match GENERIC_FAMILIES
+                        .iter()
+                        .find(|(generic, _)| *generic == family)
+                    {
+                        Some((_, expanded)) => {
+                            families.extend(expanded.iter().map(|family| family.to_lowercase()))
+                        }
+                        None => families.push(family.to_lowercase()),
+                    }
+                }
+                value => families.extend(family_name(&value).map(|family| family.to_lowercase())),
+            }
+        }
+        let weight = match style.value("font-weight") {
+            Some(Value::Number(weight)) => weight,
+            _ => 400.0,
+        };
+        FontQuery {
+            families,
+            weight: weight.to_bits(),
+            style: font_style(style.value("font-style").as_ref()),
+            stretch: font_stretch(style.value("font-stretch").as_ref()).to_bits(),
+        }
+    }
+}
+
+/// The name of a family in `font-family`: a string, or identifiers like `Times New Roman`.
+fn family_name(value: &Value) -> Option<String> {
+    match value {
+        Value::String(family) | Value::Keyword(family) => Some(family.clone()),
+        Value::Sequence(parts) if value.keywords().len() == parts.len() => {
+            Some(value.keywords().join(" "))
+        }
+        _ => None,
+    }
+}
+
+/// Whether a character is a combining mark, which is drawn over or under the previous one.
+fn is_mark(c: char) -> bool {
+    matches!(c as u32, 0x0300..=0x036F | 0x0610..=0x061A | 0x064B..=0x065F | 0x0670
+        | 0x06D6..=0x06ED | 0x0900..=0x0903 | 0x093A..=0x094F | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF | 0x20D0..=0x20FF | 0xFE20..=0xFE2F)
+}
+
+fn font_style(value: Option<&Value>) -> FontStyle {
+    match value {
+        Some(Value::Keyword(keyword)) if keyword == "italic" => FontStyle::Italic,
+        Some(Value::Keyword(keyword)) if keyword == "oblique" => FontStyle::Oblique,
+        _ => FontStyle::Normal,
+    }
+}
+
+/// The percentage a `font-stretch` keyword stands for. Faces are only registered under keyword
+/// widths, so a percentage is rounded to that of the nearest keyword.
+fn font_stretch(value: Option<&Value>) -> f32 {
+    match value {
+        Some(Value::Keyword(keyword)) => STRETCH_KEYWORDS
+            .iter()
+            .find(|(name, _)| name == keyword)
+            .map_or(100.0, |&(_, stretch)| stretch),
+        Some(&Value::Length(percent, Unit::Percent)) if percent >= 0.0 => STRETCH_KEYWORDS
+            .iter()
+            .map(|&(_, stretch)| stretch)
+            .min_by(|a, b| (a - percent).abs().total_cmp(&(b - percent).abs()))
+            .unwrap(),
+        _ => 100.0,
+    }
+}
+
+/// Read the descriptor and full name of a face from its `name` and `OS/2` tables.
+fn describe(face: &ttf_parser::Face) -> Option<(FaceDescriptor, String)> {
+    let name = |id| {
+        face.names()
+            .into_iter()
+            .filter(|name| name.name_id == id)
+            .find_map(|name| name.to_string())
+    };
+    let family = name(name_id::TYPOGRAPHIC_FAMILY).or_else(|| name(name_id::FAMILY))?;
+    let full_name = name(name_id::FULL_NAME).unwrap_or_else(|| family.clone());
+    let style = if face.is_italic() {
+        FontStyle::Italic
+    } else if face.is_oblique() {
+        FontStyle::Oblique
+    } else {
+        FontStyle::Normal
+    };
+    let width = face.width().to_number().clamp(1, 9) as usize;
+    let descriptor = FaceDescriptor {
+        family,
+        weight: face.weight().to_number() as f32,
+        style,
+        stretch: STRETCH_KEYWORDS[width - 1].1,
+    };
+    Some((descriptor, full_name))
+}
+
+/// Pick the face of a family closest to a query, narrowing the candidates by stretch, then by
+/// style, then by weight.
+///
+/// http://www.w3.org/TR/css-fonts-4/#font-style-matching
+fn best_match<'d>(candidates: &[&'d FaceEntry], query: &FontQuery) -> Option<&'d FaceEntry> {
+    let stretch = f32::from_bits(query.stretch);
+    let weight = f32::from_bits(query.weight);
+    let mut candidates = candidates.to_vec();
+
+    // Narrower widths are preferred for condensed text, wider ones for expanded text.
+    retain_best(&mut candidates, |face| {
+        directional_distance(face.descriptor.stretch, stretch, stretch <= 100.0)
+    });
+
+    let style_order = match query.style {
+        FontStyle::Normal => [FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic],
+        FontStyle::Italic => [FontStyle::Italic, FontStyle::Oblique, FontStyle::Normal],
+        FontStyle::Oblique => [FontStyle::Oblique, FontStyle::Italic, FontStyle::Normal],
+    };
+    retain_best(&mut candidates, |face| {
+        let rank = style_order
+            .iter()
+            .position(|&style| style == face.descriptor.style)
+            .unwrap();
+        (rank as u8, 0.0)
+    });
+
+    retain_best(&mut candidates, |face| {
+        let w = face.descriptor.weight;
+        if (400.0..=500.0).contains(&weight) {
+            // Weights up to 500 are tried first, then lighter ones, then heavier ones.
+            if w >= weight && w <= 500.0 {
+                (0, w - weight)
+            } else if w < weight {
+                (1, weight - w)
+            } else {
+                (2, w - weight)
+            }
+        } else {
+            directional_distance(w, weight, weight < 400.0)
+        }
+    });
+
+    candidates.first().copied()
+}
+
+/// How far `value` is from `desired`, where values on the preferred side (below `desired` if
+/// `prefer_lower`, above it otherwise) come before all values on the other side.
+fn directional_distance(value: f32, desired: f32, prefer_lower: bool) -> (u8, f32) {
+    let preferred = if prefer_lower {
+        value <= desired
+    } else {
+        value >= desired
+    };
+    ((!preferred) as u8, (value - desired).abs())
+}
+
+/// Keep only the candidates with the smallest key.
+fn retain_best(candidates: &mut Vec<&FaceEntry>, key: impl Fn(&FaceEntry) -> (u8, f32)) {
+    let best = candidates
+        .iter()
+        .map(|face| key(face))
+        .min_by(|a, b| a.partial_cmp(b).unwrap());
+    if let Some(best) = best {
+        candidates.retain(|face| key(face) == best);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{parser, style};
+    use super::*;
+
+    fn face(weight: f32, style: FontStyle, stretch: f32) -> FaceEntry {
+        FaceEntry {
+            descriptor: FaceDescriptor {
+                family: "Test".to_string(),
+                weight,
+                style,
+                stretch,
+            },
+            font_face_rule: false,
+            name: String::new(),
+            sources: Vec::new(),
+            font: OnceLock::new(),
+        }
+    }
+
+    fn query(weight: f32, style: FontStyle, stretch: f32) -> FontQuery {
+        FontQuery {
+            families: vec!["test".to_string()],
+            weight: weight.to_bits(),
+            style,
+            stretch: stretch.to_bits(),
+        }
+    }
+
+    fn best(faces: &[FaceEntry], query: FontQuery) -> FaceDescriptor {
+        let candidates: Vec<&FaceEntry> = faces.iter().collect();
+        best_match(&candidates, &query).unwrap().descriptor.clone()
+    }
+
+    /// Style `html` and pass its first text node to `check`, with a database holding the
+    /// `@font-face` rules of `css` resolved against the `fonts` directory.
+    fn with_text(html: &str, css: &str, check: impl FnOnce(&FontDatabase, &StyledNode)) {
+        let root = parser::parse_html(html.to_string());
+        let stylesheets = [parser::parse_css(css.to_string(), &root)];
+        let mut fonts = FontDatabase::new();
+        fonts.add_font_faces(
+            &stylesheets,
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("fonts"),
+        );
+        let styled = style::style_tree(&root, &stylesheets);
+        check(&fonts, &styled.children[0]);
+    }
+
+    #[test]
+    fn test_match_weight() {
+        let faces: Vec<FaceEntry> = [300.0, 400.0, 600.0, 700.0]
+            .iter()
+            .map(|&weight| face(weight, FontStyle::Normal, 100.0))
+            .collect();
+        let weight = |desired| best(&faces, query(desired, FontStyle::Normal, 100.0)).weight;
+        assert_eq!(weight(400.0), 400.0);
+        // Between 400 and 500, lighter weights are tried before heavier ones.
+        assert_eq!(weight(500.0), 400.0);
+        assert_eq!(weight(450.0), 400.0);
+        assert_eq!(weight(350.0), 300.0);
+        assert_eq!(weight(100.0), 300.0);
+        assert_eq!(weight(550.0), 600.0);
+        assert_eq!(weight(900.0), 700.0);
+    }
+
+    #[test]
+    fn test_match_style_and_stretch() {
+        let faces = [
+            face(400.0, FontStyle::Normal, 100.0),
+            face(400.0, FontStyle::Oblique, 100.0),
+            face(700.0, FontStyle::Italic, 75.0),
+            face(400.0, FontStyle::Normal, 125.0),
+        ];
+        assert_eq!(
+            best(&faces, query(400.0, FontStyle::Italic, 100.0)).style,
+            FontStyle::Oblique
+        );
+        assert_eq!(
+            best(&faces, query(400.0, FontStyle::Oblique, 100.0)).style,
+            FontStyle::Oblique
+        );
+        // Stretch is matched before style and weight.
+        assert_eq!(
+            best(&faces, query(400.0, FontStyle::Normal, 87.5)),
+            faces[2].descriptor
+        );
+        assert_eq!(
+            best(&faces, query(400.0, FontStyle::Normal, 112.5)),
+            faces[3].descriptor
+        );
+    }
+
+    #[test]
+    fn test_font_stretch_percentages() {
+        let stretch = |percent| font_stretch(Some(&Value::Length(percent, Unit::Percent)));
+        assert_eq!(stretch(75.0), 75.0);
+        assert_eq!(stretch(90.0), 87.5);
+        assert_eq!(stretch(140.0), 150.0);
+        assert_eq!(stretch(300.0), 200.0);
+        assert_eq!(stretch(-10.0), 100.0);
+    }
+
+    #[test]
+    fn test_embedded_fonts() {
+        let css = "p { font-family: \"No Such Font\", serif; font-weight: bold; }";
+        with_text("<p>text</p>", css, |fonts, text| {
+            assert_eq!(fonts.primary_font(text).name(), "DejaVu Sans Bold");
+        });
+    }
+
+    #[test]
+    fn test_font_face() {
+        let css = "@font-face { font-family: Pony; src: url(missing.ttf), url(DejaVuSans-Oblique.ttf) format(\"truetype\"); } \
+                   p { font-family: Pony, sans-serif; }";
+        with_text("<p>aب</p>", css, |fonts, text| {
+            assert_eq!(fonts.primary_font(text).name(), "Pony");
+            // The oblique face has no Arabic, so that glyph comes from the default family.
+            let (glyphs, _) = fonts.glyphs(text, "aب", false);
+            assert_eq!(glyphs[0].font.name(), "Pony");
+            assert_eq!(glyphs[1].font.name(), "DejaVu Sans");
+            assert_eq!(glyphs[1].cluster, 1);
+            assert!(glyphs[1].x > 0.0);
+        });
+        // Family names can be written as several identifiers.
+        let css = "@font-face { font-family: Pony  Sans; src: url(DejaVuSans-Oblique.ttf); } \
+                   p { font-family: Pony Sans, sans-serif; }";
+        with_text("<p>a</p>", css, |fonts, text| {
+            assert_eq!(fonts.primary_font(text).name(), "Pony Sans");
+        });
+    }
+
+    #[test]
+    fn test_load_directory() {
+        let mut fonts = FontDatabase::new();
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fonts");
+        assert_eq!(fonts.load_directory(&dir).unwrap(), 4);
+        let bold_oblique = fonts
+            .faces
+            .iter()
+            .find(|face| face.name == "DejaVu Sans Bold Oblique" && !face.sources.is_empty())
+            .unwrap();
+        assert_eq!(bold_oblique.descriptor.family, "DejaVu Sans");
+        assert_eq!(bold_oblique.descriptor.weight, 700.0);
+        assert_ne!(bold_oblique.descriptor.style, FontStyle::Normal);
+        assert!(bold_oblique.load().is_some());
+    }
+}
@@ -0,0 +1,166 @@
+use super::super::css::Value;
+use super::{Font, Glyph};
+use ab_glyph::GlyphId;
+use rustybuzz::ttf_parser::Tag;
+use rustybuzz::{Direction, Feature, UnicodeBuffer};
+
+impl Font {
+    /// Shape text in this font: apply its OpenType substitutions, like ligatures and Arabic
+    /// joining forms, and its positioning, like kerning and mark attachment, along with
+    /// `features`. Right-to-left text comes out in visual order, so its first glyph is for the last
+    /// character. Returns the glyphs, positioned from 0, along with the advance width of the whole
+    /// run.
+    pub fn shape(
+        &self,
+        text: &str,
+        size: f32,
+        rtl: bool,
+        features: &[Feature],
+    ) -> (Vec<Glyph>, f32) {
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(text);
+        buffer.guess_segment_properties();
+        buffer.set_direction(if rtl {
+            Direction::RightToLeft
+        } else {
+            Direction::LeftToRight
+        });
+        let shaped = rustybuzz::shape(&self.0.faces.borrow_dependent().shaper, features, buffer);
+
+        let scale = self.scale(size);
+        let mut glyphs = Vec::with_capacity(shaped.len());
+        let mut x = 0.0;
+        for (info, position) in shaped.glyph_infos().iter().zip(shaped.glyph_positions()) {
+            let advance = position.x_advance as f32 * scale;
+            glyphs.push(Glyph {
+                font: self.clone(),
+                id: GlyphId(info.glyph_id as u16),
+                x: x + position.x_offset as f32 * scale,
+                // OpenType offsets point up, while y grows downwards.
+                y: -position.y_offset as f32 * scale,
+                advance,
+                cluster: info.cluster as usize,
+            });
+            x += advance;
+        }
+        (glyphs, x)
+    }
+
+    /// The advance width of left-to-right text set on a single line in this font.
+    pub fn advance(&self, text: &str, size: f32) -> f32 {
+        self.shape(text, size, false, &[]).1
+    }
+}
+
+/// The OpenType features turned on or off by a `font-feature-settings` value, like
+/// `"liga" 0, "smcp"`. Tags that are not four characters long are ignored.
+///
+/// http://www.w3.org/TR/css-fonts-4/#font-feature-settings-prop
+pub fn feature_settings(value: Option<Value>) -> Vec<Feature> {
+    let settings = match value {
+        Some(Value::List(values)) => values,
+        Some(value) => vec![value],
+        None => Vec::new(),
+    };
+    settings
+        .iter()
+        .filter_map(|setting| {
+            let (tag, value) = match setting {
+                Value::String(tag) => (tag, 1),
+                Value::Sequence(values) => match values.as_slice() {
+                    [Value::String(tag), Value::Number(value)] => (tag, *value as u32),
+                    [Value::String(tag), Value::Keyword(keyword)] if keyword == "on" => (tag, 1),
+                    [Value::String(tag), Value::Keyword(keyword)] if keyword == "off" => (tag, 0),
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            let tag: [u8; 4] = tag.as_bytes().try_into().ok()?;
+            Some(Feature::new(Tag::from_bytes(&tag), value, ..))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(glyphs: &[Glyph]) -> Vec<u16> {
+        glyphs.iter().map(|glyph| glyph.id.0).collect()
+    }
+
+    fn nominal(font: &Font, text: &str) -> Vec<u16> {
+        use ab_glyph::Font as _;
+        text.chars()
+            .map(|c| font.outlines().glyph_id(c).0)
+            .collect()
+    }
+
+    #[test]
+    fn test_ligatures() {
+        let font = Font::default_font();
+        let (glyphs, width) = font.shape("fi", 16.0, false, &[]);
+        assert_eq!(glyphs.len(), 1);
+        assert_ne!(ids(&glyphs), nominal(font, "f"));
+        assert_eq!(glyphs[0].cluster, 0);
+        assert_eq!(width, glyphs[0].advance);
+
+        let no_ligatures = feature_settings(Some(Value::Sequence(vec![
+            Value::String("liga".to_string()),
+            Value::Number(0.0),
+        ])));
+        let (glyphs, _) = font.shape("fi", 16.0, false, &no_ligatures);
+        assert_eq!(ids(&glyphs), nominal(font, "fi"));
+        assert_eq!(glyphs[1].cluster, 1);
+    }
+
+    #[test]
+    fn test_kerning() {
+        let font = Font::default_font();
+        let (kerned, _) = font.shape("AV", 20.0, false, &[]);
+        assert_eq!(ids(&kerned), nominal(font, "AV"));
+        assert_eq!(kerned[0].x, 0.0);
+        assert!(kerned[1].x < font.advance("A", 20.0));
+        assert_eq!(kerned[1].x, kerned[0].advance);
+
+        let no_kerning = feature_settings(Some(Value::Sequence(vec![
+            Value::String("kern".to_string()),
+            Value::Keyword("off".to_string()),
+        ])));
+        let (unkerned, _) = font.shape("AV", 20.0, false, &no_kerning);
+        assert_eq!(unkerned[1].x, font.advance("A", 20.0));
+    }
+
+    #[test]
+    fn test_arabic_joining() {
+        let font = Font::default_font();
+        let (glyphs, width) = font.shape("ببب", 16.0, true, &[]);
+        // Initial, medial and final forms replace the isolated form, laid out right to left.
+        let isolated = nominal(font, "ب")[0];
+        assert_eq!(glyphs.len(), 3);
+        assert!(glyphs.iter().all(|glyph| glyph.id.0 != isolated));
+        assert_eq!(
+            ids(&glyphs)
+                .iter()
+                .collect::<std::collections::HashSet<_>>()
+                .len(),
+            3
+        );
+        let clusters: Vec<usize> = glyphs.iter().map(|glyph| glyph.cluster).collect();
+        assert_eq!(clusters, vec![4, 2, 0]);
+        assert!(glyphs[0].x < glyphs[2].x);
+        assert!((glyphs[2].x + glyphs[2].advance - width).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_mark_positioning() {
+        let font = Font::default_font();
+        // There is no precomposed glyph for x with a combining dot below, so the mark is
+        // positioned under the base.
+        let (glyphs, width) = font.shape("x\u{323}", 16.0, false, &[]);
+        assert_eq!(glyphs.len(), 2);
+        assert_eq!(glyphs[1].advance, 0.0);
+        assert!(glyphs[1].x < glyphs[0].advance);
+        assert_eq!(width, glyphs[0].advance);
+    }
+}
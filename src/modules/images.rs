@@ -0,0 +1,117 @@
+use super::css::Color;
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// A decoded raster image, with 8-bit RGBA pixels that are not premultiplied by their alpha.
+///
+/// Cloning an image is cheap, and an image only compares equal to its clones.
+#[derive(Clone)]
+pub struct Image(Arc<RgbaImage>);
+
+impl Image {
+    pub fn new(pixels: RgbaImage) -> Image {
+        Image(Arc::new(pixels))
+    }
+
+    /// The intrinsic width of the image in px: one px per pixel.
+    pub fn width(&self) -> u32 {
+        self.0.width()
+    }
+
+    /// The intrinsic height of the image in px.
+    pub fn height(&self) -> u32 {
+        self.0.height()
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Color {
+        let [red, green, blue, alpha] = self.0.get_pixel(x, y).0;
+        Color {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+}
+
+impl PartialEq for Image {
+    fn eq(&self, other: &Image) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Image {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Image({}x{})", self.width(), self.height())
+    }
+}
+
+/// The images a document refers to, like the `src` of `<img>`, loaded from local files on first
+/// use in any of the formats the `image` crate decodes.
+pub struct ImageCache {
+    base: PathBuf,
+    /// Images already loaded by their URL, or `None` for those that could not be.
+    loaded: Mutex<HashMap<String, Option<Image>>>,
+}
+
+impl ImageCache {
+    /// A cache that resolves relative URLs against the directory `base`.
+    pub fn new(base: &Path) -> ImageCache {
+        ImageCache {
+            base: base.to_path_buf(),
+            loaded: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The image at `url`, or `None` if the file cannot be read or decoded.
+    pub fn get(&self, url: &str) -> Option<Image> {
+        let mut loaded = self.loaded.lock().unwrap();
+        loaded
+            .entry(url.to_string())
+            .or_insert_with(|| {
+                let path = self.base.join(url.strip_prefix("file://").unwrap_or(url));
+                let pixels = image::open(path).ok()?.into_rgba8();
+                Some(Image::new(pixels))
+            })
+            .clone()
+    }
+}
+
+impl Default for ImageCache {
+    /// A cache that resolves relative URLs against the working directory.
+    fn default() -> ImageCache {
+        ImageCache::new(Path::new(""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_load_image() {
+        let dir = env::temp_dir().join(format!("ponyrender-images-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pixels =
+            RgbaImage::from_fn(3, 2, |x, _| Rgba([255, 0, 0, if x == 0 { 255 } else { 0 }]));
+        pixels.save(dir.join("red.png")).unwrap();
+        fs::write(dir.join("broken.png"), b"not a png").unwrap();
+
+        let images = ImageCache::new(&dir);
+        let image = images.get("red.png").unwrap();
+        assert_eq!((image.width(), image.height()), (3, 2));
+        assert_eq!(image.pixel(0, 1).alpha, 255);
+        assert_eq!(image.pixel(2, 0).alpha, 0);
+        // Images are decoded once.
+        assert_eq!(images.get("red.png"), Some(image));
+        assert_eq!(images.get("broken.png"), None);
+        assert_eq!(images.get("missing.png"), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,27 @@
-use super::css::Unit::Px;
-use super::css::Value::{Keyword, Length};
+use super::css::Color;
+use super::css::Unit::{Percent, Px};
+use super::css::Value::{self, Keyword, Length, Number};
+use super::dom::NodeType;
+use super::font::{FontDatabase, Glyph};
+use super::images::{Image, ImageCache};
 use super::style::StyledNode;
+use float::{Clear, FloatContext};
+
+pub use float::FloatSide;
+pub use position::Position;
+pub use table::TablePart;
+
+mod align;
+mod bidi;
+mod flex;
+mod float;
+mod grid;
+mod inline;
+mod list;
+mod position;
+mod replaced;
+mod scroll;
+mod table;
 
 #[derive(Clone, Copy, Default, Debug, PartialEq)]
 pub struct Dimensions {
@@ -13,7 +34,7 @@ pub struct Dimensions {
 
 impl Dimensions {
     // The area covered by the content area plus its padding.
-    fn padding_box(self) -> Rect {
+    pub fn padding_box(self) -> Rect {
         self.content.expanded_by(self.padding)
     }
     // The area covered by the content area plus padding and borders.
@@ -43,6 +64,18 @@ impl Rect {
             height: self.height + edge.top + edge.bottom,
         }
     }
+
+    /// The area covered by both rectangles, empty if they do not overlap.
+    pub fn intersection(self, other: Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        Rect {
+            x,
+            y,
+            width: ((self.x + self.width).min(other.x + other.width) - x).max(0.0),
+            height: ((self.y + self.height).min(other.y + other.height) - y).max(0.0),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Default, Debug, PartialEq)]
@@ -53,11 +86,46 @@ pub struct EdgeSizes {
     pub left: f32,
 }
 
+/// Adjoining vertical margins, which collapse into a single margin: the largest positive margin
+/// plus the most negative one.
+///
+/// http://www.w3.org/TR/CSS2/box.html#collapsing-margins
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+struct CollapsibleMargin {
+    positive: f32,
+    negative: f32,
+}
+
+impl CollapsibleMargin {
+    fn new(margin: f32) -> CollapsibleMargin {
+        CollapsibleMargin::default().adjoin(margin)
+    }
+
+    fn adjoin(self, margin: f32) -> CollapsibleMargin {
+        CollapsibleMargin {
+            positive: self.positive.max(margin),
+            negative: self.negative.min(margin),
+        }
+    }
+
+    fn resolve(self) -> f32 {
+        self.positive + self.negative
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct LayoutBox<'a> {
     pub dimensions: Dimensions,
     pub box_type: BoxType<'a>,
     pub children: Vec<LayoutBox<'a>>,
+    /// The colors of the top, right, bottom and left borders when they do not come from the box's
+    /// own `border-color`, as with collapsed table borders, which take the color of the border
+    /// that wins.
+    pub border_colors: Option<[Option<Color>; 4]>,
+    /// The image a replaced element, like an `<img>`, shows in its content box.
+    pub image: Option<Image>,
+    /// How far the content of a scroll container is scrolled, see `scroll_to`.
+    scroll_offset: (f32, f32),
 }
 
 impl<'a> LayoutBox<'a> {
@@ -66,66 +134,424 @@ impl<'a> LayoutBox<'a> {
             box_type,
             dimensions: Default::default(),
             children: Vec::new(),
+            border_colors: None,
+            image: None,
+            scroll_offset: (0.0, 0.0),
         }
     }
 
     fn get_style_node(&self) -> &'a StyledNode<'a> {
         match self.box_type {
-            BoxType::BlockNode(node) | BoxType::InlineNode(node) => node,
+            BoxType::BlockNode(node)
+            | BoxType::InlineNode(node)
+            | BoxType::Marker(node)
+            | BoxType::TextRun(node, ..) => node,
             BoxType::AnonymousBlock => panic!("Anonymous block box has no style node"),
+            BoxType::AnonymousTablePart(..) => panic!("Anonymous table box has no style node"),
+            BoxType::LineBox => panic!("Line box has no style node"),
+        }
+    }
+
+    /// The style of the box, or for an anonymous table box, of the element it is generated in,
+    /// which it inherits from.
+    fn inherited_style(&self) -> &'a StyledNode<'a> {
+        match self.box_type {
+            BoxType::AnonymousTablePart(_, style) => style,
+            _ => self.get_style_node(),
         }
     }
 }
 
 impl<'a> LayoutBox<'a> {
-    /// Lay out a box and its descendants.
-    fn layout(&mut self, containing_block: Dimensions) {
+    /// Lay out a box and its descendants. `containing_height` is the height of the containing
+    /// block, for resolving percentage heights.
+    fn layout(
+        &mut self,
+        containing_block: Dimensions,
+        containing_height: f32,
+        fonts: &FontDatabase,
+    ) {
         match self.box_type {
-            BoxType::BlockNode(_) => self.layout_block(containing_block),
-            BoxType::InlineNode(_) | BoxType::AnonymousBlock => {} // TODO
+            // The margins of the root box do not collapse.
+            BoxType::BlockNode(_) => {
+                let mut floats = FloatContext::default();
+                self.layout_block(
+                    containing_block,
+                    Some(containing_height),
+                    None,
+                    &mut floats,
+                    fonts,
+                );
+                self.offset_relative(containing_block, Some(containing_height));
+            }
+            // Only the root box is laid out on its own, and `layout_scrolled_tree` always makes it
+            // a block. Other boxes are laid out by their parent, see `layout_block_children`.
+            BoxType::InlineNode(_)
+            | BoxType::AnonymousBlock
+            | BoxType::AnonymousTablePart(..)
+            | BoxType::Marker(_)
+            | BoxType::LineBox
+            | BoxType::TextRun(..) => unreachable!("Only block boxes are laid out on their own"),
         }
     }
 
     /// Lay out a block-level element and its descendants.
-    fn layout_block(&mut self, containing_block: Dimensions) {
+    ///
+    /// `containing_height` is the height of the containing block if it does not depend on its
+    /// content, for resolving percentage heights. `margin` holds the margins above the box that its
+    /// top margin collapses with, or is `None` for boxes whose margins do not collapse at all, like
+    /// the root and floats. `floats` are the floats of the block formatting context the box is in.
+    /// Returns the margins below the box, which collapse with whatever comes next, and whether the
+    /// box is empty so that its top and bottom margins collapse through it.
+    ///
+    /// Floats are laid out where their containing block's content currently ends; `place_float`
+    /// moves them to their place.
+    fn layout_block(
+        &mut self,
+        containing_block: Dimensions,
+        containing_height: Option<f32>,
+        margin: Option<CollapsibleMargin>,
+        floats: &mut FloatContext,
+        fonts: &FontDatabase,
+    ) -> (CollapsibleMargin, bool) {
+        let style = self.get_style_node();
+        // The contents of a new block formatting context contain their floats, and their margins
+        // do not collapse with the box's.
+        let new_context = margin.is_none() || establishes_formatting_context(style);
+
         // Child width can depend on parent width, so we need to calculate this box's width before
         // laying out its children.
-        self.calculate_block_width(containing_block);
+        self.calculate_block_width(containing_block, containing_height, fonts);
 
         // Determine where the box is located within its container.
         self.calculate_block_position(containing_block);
 
+        // The top margin collapses with the first child's unless border or padding separate them.
+        let d = self.dimensions;
+        let above = margin.unwrap_or_default().adjoin(d.margin.top);
+        let mut collapse_top = !new_context && d.border.top == 0.0 && d.padding.top == 0.0;
+        let mut children_margin = CollapsibleMargin::default();
+        let border_top = d.border_box().y + above.resolve();
+        match Clear::of(style).and_then(|clear| floats.clearance(clear)) {
+            // Clearance puts the top border edge right below the floats it clears, and keeps the
+            // margins above from collapsing with the box's own.
+            Some(bottom) if margin.is_some() && border_top < bottom => {
+                self.dimensions.content.y += bottom - d.border_box().y;
+                collapse_top = false;
+            }
+            _ if collapse_top => children_margin = above,
+            _ => self.dimensions.content.y += above.resolve(),
+        }
+
+        // The border box of a new block formatting context in the flow goes beside floats.
+        if new_context && margin.is_some() {
+            let left = containing_block.content.x;
+            let right = left + containing_block.content.width;
+            let y = self.dimensions.border_box().y;
+            let (free_left, free_right) = floats.available(y, 0.0, left, right);
+            if (free_left, free_right) != (left, right) {
+                let mut beside = containing_block;
+                beside.content.x = free_left;
+                beside.content.width = free_right - free_left;
+                self.calculate_block_width(beside, containing_height, fonts);
+                let d = &mut self.dimensions;
+                d.content.x = free_left + d.margin.left + d.border.left + d.padding.left;
+            }
+        }
+
+        // Children resolve their percentage heights against this one, if it is known up front.
+        let height = self.definite_height(containing_block, containing_height);
+
+        // The bottom margin collapses with the last child's if the height depends on it.
+        let d = self.dimensions;
+        let bottom_adjoins = !new_context && d.border.bottom == 0.0 && d.padding.bottom == 0.0;
+        let collapse_bottom = bottom_adjoins && height.is_none();
+
         // Recursively lay out the children of this box.
-        self.layout_block_children();
+        let mut own_floats = FloatContext::default();
+        let context = if new_context { &mut own_floats } else { floats };
+        let (mut below, has_content) = if flex::is_container(style) {
+            self.layout_flex_children(height, fonts);
+            (CollapsibleMargin::default(), true)
+        } else if grid::is_container(style) {
+            self.layout_grid_children(height, fonts);
+            (CollapsibleMargin::default(), true)
+        } else if self.table_part() == Some(TablePart::Table) {
+            self.layout_table_children(height, fonts);
+            (CollapsibleMargin::default(), true)
+        } else {
+            self.layout_block_children(height, children_margin, collapse_top, context, fonts)
+        };
+
+        // Without content, the margins above the box run on to the margins of its children.
+        let empty = !has_content && collapse_top;
+        if !empty && !collapse_bottom {
+            self.dimensions.content.height += below.resolve();
+        }
+        if let Some(bottom) = own_floats.bottom() {
+            let d = &mut self.dimensions;
+            d.content.height = d.content.height.max(bottom - d.content.y);
+        }
 
         // Parent height can depend on child height, so `calculate_height` must be called after the
         // children are laid out.
-        self.calculate_block_height();
+        self.calculate_block_height(containing_block, containing_height);
+
+        // Margins collapse through an empty box whose height is zero or `auto`, and stays zero.
+        let collapse_through = empty
+            && bottom_adjoins
+            && height.is_none_or(|height| height == 0.0)
+            && self.dimensions.content.height == 0.0;
+        if empty && !collapse_through {
+            // The margins above end at the bottom edge of the box, so they go above it.
+            self.dimensions.content.y += below.resolve();
+            below = CollapsibleMargin::default();
+        }
+
+        // Now that the box has its size, lay out the absolutely positioned boxes it is the
+        // containing block of.
+        if Position::of(style) != Position::Static {
+            self.layout_absolute_descendants(self.padding_box_as_containing_block(), fonts);
+        }
+        below = if collapse_bottom || collapse_through {
+            below.adjoin(d.margin.bottom)
+        } else {
+            CollapsibleMargin::new(d.margin.bottom)
+        };
+        (below, collapse_through)
     }
 
-    /// Calculate the width of a block-level non-replaced element in normal flow.
+    /// Move a float that has been laid out to its place: as high as it fits, but not above `y`
+    /// or the floats it clears, and as far to its side of the containing block as it goes.
     ///
-    /// http://www.w3.org/TR/CSS2/visudet.html#blockwidth
+    /// http://www.w3.org/TR/CSS2/visuren.html#float-position
+    fn place_float(&mut self, containing_block: Dimensions, y: f32, floats: &mut FloatContext) {
+        let style = self.get_style_node();
+        let side = FloatSide::of(style).unwrap_or(FloatSide::Left);
+        let y = match Clear::of(style).and_then(|clear| floats.clearance(clear)) {
+            Some(bottom) => y.max(bottom),
+            None => y,
+        };
+        let margin_box = self.dimensions.margin_box();
+        let left = containing_block.content.x;
+        let (x, y) = floats.place(
+            side,
+            margin_box.width,
+            margin_box.height,
+            y,
+            left,
+            left + containing_block.content.width,
+        );
+        self.translate(x - margin_box.x, y - margin_box.y);
+        self.offset_relative(containing_block, None);
+    }
+
+    /// Move a relatively positioned box from its place in the flow, with everything inside.
+    fn offset_relative(&mut self, containing_block: Dimensions, containing_height: Option<f32>) {
+        let style = self.get_style_node();
+        if Position::of(style) == Position::Relative {
+            let (dx, dy) = position::relative_offset(style, containing_block, containing_height);
+            self.translate(dx, dy);
+        }
+    }
+
+    /// Move a box and its descendants.
+    fn translate(&mut self, dx: f32, dy: f32) {
+        self.dimensions.content.x += dx;
+        self.dimensions.content.y += dy;
+        for child in &mut self.children {
+            child.translate(dx, dy);
+        }
+    }
+
+    /// Calculate the width of a block-level element, within its `min-width` and `max-width`.
+    /// `containing_height` is the height of the containing block if it is known, which the width
+    /// of a replaced element can depend on.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#min-max-widths
     ///
     /// Sets the horizontal margin/padding/border dimensions, and the `width`.
-    fn calculate_block_width(&mut self, containing_block: Dimensions) {
+    fn calculate_block_width(
+        &mut self,
+        containing_block: Dimensions,
+        containing_height: Option<f32>,
+        fonts: &FontDatabase,
+    ) {
+        let style = self.get_style_node();
+        let reference = Some(containing_block.content.width);
+        let edges = self.box_sizing_edges(["left", "right"], containing_block);
+        let size = |name| content_size(style.value(name), reference, edges);
+        // Inline blocks shrink to fit like floats, and like inline replaced elements, their auto
+        // margins are zero.
+        let floated = FloatSide::of(style).is_some()
+            || matches!(style.display(), Display::InlineBlock | Display::Inline);
+        let absolute = Position::of(style).is_out_of_flow();
+        if TablePart::of(style) == Some(TablePart::Table) && !floated && !absolute {
+            self.solve_table_width(containing_block, fonts);
+            return;
+        }
+        let solve = |layout_box: &mut LayoutBox, width| {
+            if absolute {
+                layout_box.solve_absolute_width(containing_block, width, fonts);
+            } else if floated {
+                layout_box.solve_float_width(containing_block, width, fonts);
+            } else {
+                layout_box.solve_block_width(containing_block, width);
+            }
+        };
+
+        // Replaced elements have their width worked out already, limits included.
+        if self.image.is_some() {
+            let width =
+                self.replaced_width(Some(containing_block.content.width), containing_height);
+            solve(self, Some(width));
+            return;
+        }
+
+        solve(self, size("width"));
+        // The width algorithm runs again with a width outside the limits replaced by the limit.
+        if let Some(max_width) = size("max-width") {
+            if self.dimensions.content.width > max_width {
+                solve(self, Some(max_width));
+            }
+        }
+        if let Some(min_width) = size("min-width") {
+            if self.dimensions.content.width < min_width {
+                solve(self, Some(min_width));
+            }
+        }
+    }
+
+    /// Calculate the horizontal dimensions of a float with the given content width, or `None` for
+    /// `auto`, which shrinks the float to fit its content.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#float-width
+    fn solve_float_width(
+        &mut self,
+        containing_block: Dimensions,
+        width: Option<f32>,
+        fonts: &FontDatabase,
+    ) {
+        let style = self.get_style_node();
+        // Auto margins are zero, and percentages refer to the width of the containing block.
+        let zero = Length(0.0, Px);
+        let length = |name: &str| {
+            style
+                .lookup(name, &zero)
+                .resolve(containing_block.content.width)
+        };
+
+        let d = &mut self.dimensions;
+        d.margin.left = length("margin-left");
+        d.margin.right = length("margin-right");
+        d.border.left = length("border-left-width");
+        d.border.right = length("border-right-width");
+        d.padding.left = length("padding-left");
+        d.padding.right = length("padding-right");
+
+        let edges = sum([
+            d.margin.left,
+            d.margin.right,
+            d.border.left,
+            d.border.right,
+            d.padding.left,
+            d.padding.right,
+        ]
+        .into_iter());
+        self.dimensions.content.width = width.unwrap_or_else(|| {
+            let (min_width, max_width) = self.intrinsic_widths(fonts);
+            (containing_block.content.width - edges)
+                .max(min_width)
+                .min(max_width)
+        });
+    }
+
+    /// The narrowest and the widest the content of a block box can be laid out in: with every line
+    /// break opportunity taken, and with none of them taken.
+    pub(super) fn intrinsic_widths(&self, fonts: &FontDatabase) -> (f32, f32) {
+        if self.image.is_some() {
+            let width = self.replaced_width(None, None);
+            return (width, width);
+        }
+        if self.table_part() == Some(TablePart::Table) {
+            return self.table_intrinsic_widths(fonts);
+        }
+        if let BoxType::BlockNode(style) = self.box_type {
+            if flex::is_row_container(style) {
+                return self.flex_intrinsic_widths(fonts);
+            }
+            if grid::is_container(style) {
+                return self.grid_intrinsic_widths(fonts);
+            }
+        }
+        let style = self.inherited_style();
+        self.children
+            .iter()
+            .filter(|child| !child.is_absolutely_positioned())
+            .map(|child| match child.box_type {
+                BoxType::AnonymousBlock => inline::intrinsic_widths(&child.children, style, fonts),
+                // Outside markers hang out of the box.
+                BoxType::Marker(_) => (0.0, 0.0),
+                _ => child.outer_intrinsic_widths(fonts),
+            })
+            .fold((0.0, 0.0), |(min, max), (child_min, child_max)| {
+                (f32::max(min, child_min), f32::max(max, child_max))
+            })
+    }
+
+    /// Like `intrinsic_widths`, for the margin box of a block box with its specified width.
+    pub(super) fn outer_intrinsic_widths(&self, fonts: &FontDatabase) -> (f32, f32) {
+        // Anonymous table boxes have no edges or width of their own.
+        let style = match self.box_type {
+            BoxType::BlockNode(style) => style,
+            _ => return self.intrinsic_widths(fonts),
+        };
+        let zero = Length(0.0, Px);
+        let edges = sum(["left", "right"].iter().map(|side| {
+            style.lookup(&format!("margin-{}", side), &zero).to_px()
+                + style
+                    .lookup(&format!("border-{}-width", side), &zero)
+                    .to_px()
+                + style.lookup(&format!("padding-{}", side), &zero).to_px()
+        }));
+        let box_sizing = self.box_sizing_edges(["left", "right"], Dimensions::default());
+        match content_size(style.value("width"), None, box_sizing) {
+            Some(width) => (width + edges, width + edges),
+            None => {
+                let (min, max) = self.intrinsic_widths(fonts);
+                (min + edges, max + edges)
+            }
+        }
+    }
+
+    /// Calculate the horizontal dimensions of a block-level non-replaced element in normal flow
+    /// with the given content width, or `None` for `auto`.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#blockwidth
+    fn solve_block_width(&mut self, containing_block: Dimensions, width: Option<f32>) {
         let style = self.get_style_node();
 
-        // `width` has initial value `auto`.
         let auto = Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or_else(|| auto.clone());
+        let mut width = width.map_or_else(|| auto.clone(), |width| Length(width, Px));
 
-        // margin, border, and padding have initial value 0.
+        // margin, border, and padding have initial value 0, and percentages refer to the width of
+        // the containing block.
         let zero = Length(0.0, Px);
+        let length = |name: &str| match style.lookup(name, &zero) {
+            percentage @ Length(_, Percent) => {
+                Length(percentage.resolve(containing_block.content.width), Px)
+            }
+            value => value,
+        };
 
-        let mut margin_left = style.lookup("margin-left", "margin", &zero);
-        let mut margin_right = style.lookup("margin-right", "margin", &zero);
+        let mut margin_left = length("margin-left");
+        let mut margin_right = length("margin-right");
 
-        let border_left = style.lookup("border-left-width", "border-width", &zero);
-        let border_right = style.lookup("border-right-width", "border-width", &zero);
+        let border_left = length("border-left-width");
+        let border_right = length("border-right-width");
 
-        let padding_left = style.lookup("padding-left", "padding", &zero);
-        let padding_right = style.lookup("padding-right", "padding", &zero);
+        let padding_left = length("padding-left");
+        let padding_right = length("padding-right");
 
         let total = sum([
             &margin_left,
@@ -211,7 +637,8 @@ impl<'a> LayoutBox<'a> {
     ///
     /// http://www.w3.org/TR/CSS2/visudet.html#normal-block
     ///
-    /// Sets the vertical margin/padding/border dimensions, and the `x`, `y` values.
+    /// Sets the vertical margin/padding/border dimensions, and the `x`, `y` values. The top margin
+    /// is left out of `y`, since it may collapse; see `layout_block`.
     fn calculate_block_position(&mut self, containing_block: Dimensions) {
         let style = self.get_style_node();
         let d = &mut self.dimensions;
@@ -219,56 +646,336 @@ impl<'a> LayoutBox<'a> {
         // margin, border, and padding have initial value 0.
         let zero = Length(0.0, Px);
 
-        // If margin-top or margin-bottom is `auto`, the used value is zero.
-        d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-        d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+        // If margin-top or margin-bottom is `auto`, the used value is zero. Percentages refer to
+        // the width of the containing block.
+        let width = containing_block.content.width;
+        d.margin.top = style.lookup("margin-top", &zero).resolve(width);
+        d.margin.bottom = style.lookup("margin-bottom", &zero).resolve(width);
 
-        d.border.top = style
-            .lookup("border-top-width", "border-width", &zero)
-            .to_px();
-        d.border.bottom = style
-            .lookup("border-bottom-width", "border-width", &zero)
-            .to_px();
+        d.border.top = style.lookup("border-top-width", &zero).to_px();
+        d.border.bottom = style.lookup("border-bottom-width", &zero).to_px();
 
-        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-        d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+        d.padding.top = style.lookup("padding-top", &zero).resolve(width);
+        d.padding.bottom = style.lookup("padding-bottom", &zero).resolve(width);
 
         d.content.x = containing_block.content.x + d.margin.left + d.border.left + d.padding.left;
 
         // Position the box below all the previous boxes in the container.
         d.content.y = containing_block.content.height
             + containing_block.content.y
-            + d.margin.top
             + d.border.top
             + d.padding.top;
     }
 
-    /// Lay out the block's children within its content area.
+    /// Lay out the block's children within its content area, collapsing the vertical margins
+    /// between them.
     ///
-    /// Sets `self.dimensions.height` to the total content height.
-    fn layout_block_children(&mut self) {
+    /// `height` is the content height of this box if it is known up front, for resolving the
+    /// percentage heights of children. `margin` holds the margins that collapse with the top
+    /// margin of the first child. If
+    /// `collapse_top` is set they include this box's own top margin, and the content area moves
+    /// down to the first child with content instead of growing.
+    ///
+    /// Sets `self.dimensions.height` to the total content height, up to the bottom border edge of
+    /// the last child with content. Returns the margins after it, and whether any child had
+    /// content.
+    fn layout_block_children(
+        &mut self,
+        height: Option<f32>,
+        mut margin: CollapsibleMargin,
+        collapse_top: bool,
+        floats: &mut FloatContext,
+        fonts: &FontDatabase,
+    ) -> (CollapsibleMargin, bool) {
+        let style = self.inherited_style();
         let d = &mut self.dimensions;
+        let mut has_content = false;
         for child in &mut self.children {
-            child.layout(*d);
-            // Increment the height so each child is laid out below the previous one.
-            d.content.height += child.dimensions.margin_box().height;
+            // Margins above inline content and floats end at their top.
+            let mut container = *d;
+            container.content.height += margin.resolve();
+            let (below, collapse_through) = match child.box_type {
+                BoxType::BlockNode(_) if child.is_absolutely_positioned() => {
+                    // Absolutely positioned boxes are laid out once their containing block is.
+                    // Until then they only keep their static position, where they would have
+                    // gone in the flow.
+                    child.dimensions = Dimensions::default();
+                    child.dimensions.content.x = d.content.x;
+                    child.dimensions.content.y = container.content.y + container.content.height;
+                    (margin, true)
+                }
+                BoxType::BlockNode(child_style) if FloatSide::of(child_style).is_some() => {
+                    // Floats are out of the flow, so the margins run on past them.
+                    child.layout_block(container, height, None, floats, fonts);
+                    child.place_float(*d, container.content.y + container.content.height, floats);
+                    (margin, true)
+                }
+                BoxType::AnonymousBlock => {
+                    // Inline content inherits its font from this block.
+                    child.layout_inline(container, style, floats, fonts, !has_content);
+                    let has_lines = child
+                        .children
+                        .iter()
+                        .any(|line| line.box_type == BoxType::LineBox);
+                    if has_lines {
+                        (CollapsibleMargin::default(), false)
+                    } else {
+                        (margin, true)
+                    }
+                }
+                BoxType::AnonymousTablePart(..) => {
+                    child.layout_anonymous_table(container, fonts);
+                    (CollapsibleMargin::default(), false)
+                }
+                // Markers are placed beside the first line once it is laid out.
+                BoxType::Marker(_) => (margin, true),
+                _ => child.layout_block(*d, height, Some(margin), floats, fonts),
+            };
+            margin = below;
+            if !collapse_through {
+                let border_box = child.dimensions.border_box();
+                if collapse_top && !has_content {
+                    d.content.y = border_box.y;
+                }
+                has_content = true;
+                // Increment the height so each child is laid out below the previous one.
+                d.content.height = border_box.y + border_box.height - d.content.y;
+            }
+            // The boxes after a relatively positioned box go where they would without its
+            // offset. Floats get theirs when they are placed.
+            if let BoxType::BlockNode(child_style) = child.box_type {
+                if FloatSide::of(child_style).is_none() {
+                    child.offset_relative(*d, height);
+                }
+            }
+        }
+        self.place_marker(fonts);
+        (margin, has_content)
+    }
+
+    /// Lay out the children of a box whose contents are a formatting context of their own, with
+    /// the content height if it is known up front, and set the content height to fit them.
+    fn layout_contents(&mut self, height: Option<f32>, fonts: &FontDatabase) {
+        if self.table_part() == Some(TablePart::Table) {
+            self.layout_table_children(height, fonts);
+            return;
+        }
+        if let BoxType::BlockNode(style) = self.box_type {
+            if flex::is_container(style) {
+                self.layout_flex_children(height, fonts);
+                return;
+            }
+            if grid::is_container(style) {
+                self.layout_grid_children(height, fonts);
+                return;
+            }
+        }
+        let mut floats = FloatContext::default();
+        let (below, _) = self.layout_block_children(
+            height,
+            CollapsibleMargin::default(),
+            false,
+            &mut floats,
+            fonts,
+        );
+        self.dimensions.content.height += below.resolve();
+        if let Some(bottom) = floats.bottom() {
+            let d = &mut self.dimensions;
+            d.content.height = d.content.height.max(bottom - d.content.y);
+        }
+    }
+
+    /// Lay out a flex or grid item at the start of its container with the given content width,
+    /// and content height if it is fixed. Anonymous items lay out their inline content in the
+    /// font of the container.
+    fn layout_item(
+        &mut self,
+        container: Dimensions,
+        container_height: Option<f32>,
+        width: f32,
+        height: Option<f32>,
+        container_style: &'a StyledNode<'a>,
+        fonts: &FontDatabase,
+    ) {
+        if let BoxType::AnonymousBlock = self.box_type {
+            let mut containing_block = container;
+            containing_block.content.width = width;
+            let mut floats = Default::default();
+            self.layout_inline(containing_block, container_style, &mut floats, fonts, true);
+            if let Some(height) = height {
+                self.dimensions.content.height = height;
+            }
+            return;
+        }
+        self.solve_float_width(container, Some(width), fonts);
+        self.calculate_block_position(container);
+        self.dimensions.content.y += self.dimensions.margin.top;
+        self.dimensions.content.height = 0.0;
+        let definite = height.or_else(|| self.definite_height(container, container_height));
+        self.layout_contents(definite, fonts);
+        match height {
+            Some(height) => self.dimensions.content.height = height,
+            None => self.calculate_block_height(container, container_height),
+        }
+        if Position::of(self.get_style_node()) != Position::Static {
+            self.layout_absolute_descendants(self.padding_box_as_containing_block(), fonts);
         }
     }
 
+    /// The content width of a flex or grid item that does not stretch: its shrink-to-fit width
+    /// in the container, within its limits.
+    fn fit_content_width(
+        &mut self,
+        container: Dimensions,
+        container_style: &StyledNode,
+        fonts: &FontDatabase,
+    ) -> f32 {
+        if let BoxType::AnonymousBlock = self.box_type {
+            let (min, max) = inline::intrinsic_widths(&self.children, container_style, fonts);
+            return container.content.width.max(min).min(max);
+        }
+        let style = self.get_style_node();
+        let edges = self.box_sizing_edges(["left", "right"], container);
+        let width = content_size(style.value("width"), Some(container.content.width), edges);
+        self.solve_float_width(container, width, fonts);
+        let width = self.dimensions.content.width;
+        self.clamp_size(width, true, container, None)
+    }
+
+    /// Limit a content width, or height, to the item's `min-*` and `max-*`.
+    fn clamp_size(
+        &self,
+        size: f32,
+        width: bool,
+        container: Dimensions,
+        container_height: Option<f32>,
+    ) -> f32 {
+        let style = match self.box_type {
+            BoxType::BlockNode(style) => style,
+            _ => return size.max(0.0),
+        };
+        if !width {
+            return self.clamp_height(size.max(0.0), container, container_height);
+        }
+        let edges = self.box_sizing_edges(["left", "right"], container);
+        let limit = |name| content_size(style.value(name), Some(container.content.width), edges);
+        let size = limit("max-width").map_or(size, |max| size.min(max));
+        limit("min-width")
+            .map_or(size, |min| size.max(min))
+            .max(0.0)
+    }
+
     /// Height of a block-level non-replaced element in normal flow with overflow visible.
-    fn calculate_block_height(&mut self) {
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#min-max-heights
+    fn calculate_block_height(
+        &mut self,
+        containing_block: Dimensions,
+        containing_height: Option<f32>,
+    ) {
         // If the height is set to an explicit length, use that exact length.
-        // Otherwise, just keep the value set by `layout_block_children`.
-        if let Some(Length(h, Px)) = self.get_style_node().value("height") {
-            self.dimensions.content.height = h;
+        // Otherwise, keep the value set by `layout_block_children` within the limits.
+        self.dimensions.content.height =
+            match self.definite_height(containing_block, containing_height) {
+                // Tables grow to fit their rows.
+                Some(height) if self.table_part() == Some(TablePart::Table) => {
+                    height.max(self.dimensions.content.height)
+                }
+                Some(height) => height,
+                None => self.clamp_height(
+                    self.dimensions.content.height,
+                    containing_block,
+                    containing_height,
+                ),
+            };
+    }
+
+    /// The content height of the box if it does not depend on its content: `height` is a length,
+    /// or a percentage of a containing block height that does not depend on its content either,
+    /// or an absolutely positioned box stretches between `top` and `bottom`. Replaced elements
+    /// get theirs from their width.
+    fn definite_height(
+        &self,
+        containing_block: Dimensions,
+        containing_height: Option<f32>,
+    ) -> Option<f32> {
+        if self.image.is_some() {
+            return Some(self.replaced_height(containing_block, containing_height));
+        }
+        let edges = self.box_sizing_edges(["top", "bottom"], containing_block);
+        let height = content_size(
+            self.get_style_node().value("height"),
+            containing_height,
+            edges,
+        )
+        .or_else(|| self.absolute_stretched_height(containing_block, containing_height))?;
+        Some(self.clamp_height(height, containing_block, containing_height))
+    }
+
+    /// Limit a content height to `min-height` and `max-height`, where `min-height` wins.
+    fn clamp_height(
+        &self,
+        height: f32,
+        containing_block: Dimensions,
+        containing_height: Option<f32>,
+    ) -> f32 {
+        let style = self.get_style_node();
+        let edges = self.box_sizing_edges(["top", "bottom"], containing_block);
+        let size = |name| content_size(style.value(name), containing_height, edges);
+        let height = size("max-height").map_or(height, |max_height| height.min(max_height));
+        size("min-height").map_or(height, |min_height| height.max(min_height))
+    }
+
+    /// The padding and border on two sides of the box, which a size includes with
+    /// `box-sizing: border-box`; zero for `content-box`.
+    fn box_sizing_edges(&self, sides: [&str; 2], containing_block: Dimensions) -> f32 {
+        let style = self.get_style_node();
+        if style.value("box-sizing") != Some(Keyword("border-box".to_string())) {
+            return 0.0;
+        }
+        let zero = Length(0.0, Px);
+        sum(sides.iter().map(|side| {
+            style
+                .lookup(&format!("padding-{}", side), &zero)
+                .resolve(containing_block.content.width)
+                + style
+                    .lookup(&format!("border-{}-width", side), &zero)
+                    .to_px()
+        }))
+    }
+
+    /// Whether the box is absolutely or fixed positioned, and so out of the flow.
+    fn is_absolutely_positioned(&self) -> bool {
+        matches!(self.box_type, BoxType::BlockNode(style) if Position::of(style).is_out_of_flow())
+    }
+
+    /// Where a new float or absolutely positioned box should go: among the inline content before
+    /// it if there is some, so that it is placed where it comes in the text, and among the block
+    /// children otherwise.
+    fn get_out_of_flow_container(&mut self) -> &mut LayoutBox<'a> {
+        let after_inline_content = matches!(
+            self.children.last(),
+            Some(&LayoutBox {
+                box_type: BoxType::AnonymousBlock,
+                ..
+            })
+        );
+        if matches!(self.box_type, BoxType::BlockNode(_)) && after_inline_content {
+            self.children.last_mut().unwrap()
+        } else {
+            self
         }
     }
 
     /// Where a new inline child should go.
     fn get_inline_container(&mut self) -> &mut LayoutBox<'a> {
         match self.box_type {
-            BoxType::InlineNode(_) | BoxType::AnonymousBlock => self,
-            BoxType::BlockNode(_) => {
+            BoxType::InlineNode(_)
+            | BoxType::AnonymousBlock
+            | BoxType::Marker(_)
+            | BoxType::LineBox
+            | BoxType::TextRun(..) => self,
+            BoxType::BlockNode(_) | BoxType::AnonymousTablePart(..) => {
                 // If we've just generated an anonymous block box, keep using it.
                 // Otherwise, create a new one.
                 match self.children.last() {
@@ -289,51 +996,1049 @@ pub enum BoxType<'a> {
     BlockNode(&'a StyledNode<'a>),
     InlineNode(&'a StyledNode<'a>),
     AnonymousBlock,
+    /// A table, row or cell generated around table parts or content that lack one, with the
+    /// style of the element it is generated in, which it inherits from.
+    AnonymousTablePart(TablePart, &'a StyledNode<'a>),
+    /// One line of an inline formatting context. Line boxes replace the inline content of an
+    /// anonymous block once it is laid out; their children are fragments of that content.
+    LineBox,
+    /// The marker of a list item outside its box, with the style of its `::marker`. It holds the
+    /// marker's text, which is laid out as a line of its own beside the item's first line.
+    Marker(&'a StyledNode<'a>),
+    /// The part of a text node that falls on one line, with white space processed, and the glyphs
+    /// it is set in.
+    TextRun(&'a StyledNode<'a>, String, Vec<Glyph>),
 }
 
 pub enum Display {
     Block,
     Inline,
+    /// An atomic inline-level box, like `inline-block`: laid out like a block inside, and as one
+    /// unbreakable piece on its line outside.
+    InlineBlock,
     None,
 }
 
+/// Lay out a style tree, setting its text in fonts from `fonts` and loading the images of
+/// replaced elements from `images`.
 pub fn layout_tree<'a>(
+    node: &'a StyledNode<'a>,
+    containing_block: Dimensions,
+    fonts: &FontDatabase,
+    images: &ImageCache,
+) -> LayoutBox<'a> {
+    layout_scrolled_tree(node, containing_block, (0.0, 0.0), fonts, images)
+}
+
+/// Lay out a document like `layout_tree`, with the viewport scrolled right and down by `scroll`,
+/// as far as the document reaches. Everything but fixed positioned boxes moves up and left, and
+/// sticky positioned boxes stick to the viewport where it is scrolled to.
+pub fn layout_scrolled_tree<'a>(
     node: &'a StyledNode<'a>,
     mut containing_block: Dimensions,
+    scroll: (f32, f32),
+    fonts: &FontDatabase,
+    images: &ImageCache,
 ) -> LayoutBox<'a> {
-    // The layout algorithm expects the container height to start at 0.
-    // TODO: Save the initial containing block height, for calculating percent heights.
+    // The layout algorithm expects the container height to start at 0. The initial containing
+    // block height is kept for calculating percent heights.
+    let height = containing_block.content.height;
     containing_block.content.height = 0.0;
 
-    let mut root_box = build_layout_tree(node);
-    root_box.layout(containing_block);
+    // The root element is block-level whatever its display.
+    //
+    // https://www.w3.org/TR/css-display-3/#transformations
+    assert!(
+        !matches!(node.display(), Display::None),
+        "Root node with none display"
+    );
+    let mut root_box = build_box(
+        BoxType::BlockNode(node),
+        &mut list::Counters::default(),
+        images,
+    );
+    root_box.layout(containing_block, height, fonts);
+
+    // Absolutely positioned boxes outside any positioned box, and fixed positioned boxes, go in
+    // the initial containing block.
+    containing_block.content.height = height;
+    if let BoxType::BlockNode(style) = root_box.box_type {
+        if Position::of(style) == Position::Static {
+            root_box.layout_absolute_descendants(containing_block, fonts);
+        }
+    }
+    root_box.scroll_viewport(containing_block.content, scroll);
+    root_box.layout_fixed_descendants(containing_block, fonts);
     root_box
 }
 
-fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
-    let mut root = LayoutBox::new(match style_node.display() {
-        Display::Block => BoxType::BlockNode(style_node),
-        Display::Inline => BoxType::InlineNode(style_node),
-        Display::None => panic!("Root node with none display"),
-    });
+fn build_layout_tree<'a>(
+    style_node: &'a StyledNode<'a>,
+    counters: &mut list::Counters,
+    images: &ImageCache,
+) -> LayoutBox<'a> {
+    build_box(
+        match style_node.display() {
+            Display::Block | Display::InlineBlock => BoxType::BlockNode(style_node),
+            // Floats and absolutely positioned boxes are laid out as blocks whatever their display.
+            Display::Inline if is_out_of_flow(style_node) => BoxType::BlockNode(style_node),
+            // Inline replaced elements are atomic inlines.
+            Display::Inline if replaced::replaced_image(style_node, images).is_some() => {
+                BoxType::BlockNode(style_node)
+            }
+            Display::Inline => BoxType::InlineNode(style_node),
+            Display::None => panic!("Root node with none display"),
+        },
+        counters,
+        images,
+    )
+}
+
+/// Build the box of a style node with the boxes of its children and its list marker, updating
+/// the counters in document order on the way.
+fn build_box<'a>(
+    box_type: BoxType<'a>,
+    counters: &mut list::Counters,
+    images: &ImageCache,
+) -> LayoutBox<'a> {
+    let mut root = LayoutBox::new(box_type);
+    let style_node = root.get_style_node();
+    root.image = replaced::replaced_image(style_node, images);
+    counters.update(style_node);
+    let marker = list::marker_box(style_node, counters, images);
+    counters.enter();
+    let mut root = build_children(root, counters, images);
+    counters.leave();
+    if let Some(marker) = marker {
+        root.insert_marker(marker);
+    }
+    root
+}
+
+/// Build the boxes of the children of a style node under its box. An `<img>` that is not
+/// replaced gets its `alt` text instead.
+fn build_children<'a>(
+    mut root: LayoutBox<'a>,
+    counters: &mut list::Counters,
+    images: &ImageCache,
+) -> LayoutBox<'a> {
+    let style_node = root.get_style_node();
+    if root.image.is_some() {
+        return root;
+    }
+    if let Some(alt) = replaced::alt_text(style_node) {
+        root.get_inline_container()
+            .children
+            .push(LayoutBox::new(BoxType::TextRun(
+                style_node,
+                alt,
+                Vec::new(),
+            )));
+        return root;
+    }
+    if flex::is_container(style_node) || grid::is_container(style_node) {
+        // Every child element of a flex or grid container is a block-level item, and runs of text
+        // are wrapped in anonymous ones.
+        for child in &style_node.children {
+            match (child.display(), &child.node().node_type) {
+                (Display::None, _) | (_, NodeType::Comment(_)) => {}
+                (_, NodeType::Text(_)) => root
+                    .get_inline_container()
+                    .children
+                    .push(build_layout_tree(child, counters, images)),
+                _ => root
+                    .children
+                    .push(build_box(BoxType::BlockNode(child), counters, images)),
+            }
+        }
+        return root;
+    }
 
     for child in &style_node.children {
         match child.display() {
-            Display::Block => root.children.push(build_layout_tree(child)),
-            Display::Inline => root
+            Display::Block | Display::Inline | Display::InlineBlock if is_out_of_flow(child) => {
+                root.get_out_of_flow_container()
+                    .children
+                    .push(build_layout_tree(child, counters, images))
+            }
+            Display::Block => root
+                .children
+                .push(build_layout_tree(child, counters, images)),
+            Display::Inline | Display::InlineBlock => root
                 .get_inline_container()
                 .children
-                .push(build_layout_tree(child)),
+                .push(build_layout_tree(child, counters, images)),
             Display::None => {}
         }
     }
+    table::wrap_table_parts(&mut root);
 
     root
 }
 
+/// Whether a box is floated or absolutely positioned, which takes it out of the flow.
+fn is_out_of_flow(style: &StyledNode) -> bool {
+    FloatSide::of(style).is_some() || Position::of(style).is_out_of_flow()
+}
+
+/// Whether a block box lays out its contents in a block formatting context of its own.
+///
+/// http://www.w3.org/TR/CSS2/visuren.html#block-formatting
+fn establishes_formatting_context(style: &StyledNode) -> bool {
+    let overflow = style.value("overflow");
+    FloatSide::of(style).is_some()
+        || overflow.is_some_and(|overflow| overflow != Keyword("visible".to_string()))
+        || style.value("display") == Some(Keyword("flow-root".to_string()))
+        || flex::is_container(style)
+        || grid::is_container(style)
+        || matches!(
+            TablePart::of(style),
+            Some(TablePart::Table | TablePart::Caption | TablePart::Cell)
+        )
+}
+
+/// The content size in px that a `width`, `height`, `min-*` or `max-*` value stands for, given
+/// the size percentages refer to and the padding and border that `box-sizing` puts in the size.
+/// `None` stands for `auto` and `none`, and for percentages of an unknown size.
+fn content_size(value: Option<Value>, reference: Option<f32>, edges: f32) -> Option<f32> {
+    let size = match value? {
+        Length(percentage, Percent) => percentage / 100.0 * reference?,
+        Length(size, Px) => size,
+        _ => return None,
+    };
+    Some((size - edges).max(0.0))
+}
+
+/// The `order` of a flex or grid item; anonymous items have the initial `0`.
+fn order(layout_box: &LayoutBox) -> i32 {
+    match layout_box.box_type {
+        BoxType::BlockNode(style) => match style.value("order") {
+            Some(Number(order)) => order as i32,
+            _ => 0,
+        },
+        _ => 0,
+    }
+}
+
+/// The margins, borders and padding on two sides of a box.
+fn sum_edges(d: &Dimensions, sides: [&str; 2]) -> f32 {
+    sides
+        .iter()
+        .map(|&side| {
+            let (margin, border, padding) = match side {
+                "left" => (d.margin.left, d.border.left, d.padding.left),
+                "right" => (d.margin.right, d.border.right, d.padding.right),
+                "top" => (d.margin.top, d.border.top, d.padding.top),
+                _ => (d.margin.bottom, d.border.bottom, d.padding.bottom),
+            };
+            margin + border + padding
+        })
+        .sum()
+}
+
 fn sum<I>(iter: I) -> f32
 where
     I: Iterator<Item = f32>,
 {
     iter.fold(0., |a, b| a + b)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::font::Font;
+    use super::super::testing::Page;
+    use super::*;
+
+    /// Lay out `html` in a 200px by 100px viewport, with every element a block, and pass the root
+    /// box to `check`.
+    fn with_layout(html: &str, css: &str, check: impl FnOnce(&LayoutBox)) {
+        let page = Page {
+            html,
+            css,
+            height: 100.0,
+            user_agent: false,
+            ..Default::default()
+        };
+        page.layout(|root| check(root));
+    }
+
+    fn top(layout_box: &LayoutBox) -> f32 {
+        layout_box.dimensions.border_box().y
+    }
+
+    fn bottom(layout_box: &LayoutBox) -> f32 {
+        let border_box = layout_box.dimensions.border_box();
+        border_box.y + border_box.height
+    }
+
+    #[test]
+    fn test_collapse_siblings() {
+        let html = "<div><p class=\"a\"></p><p class=\"b\"></p></div>";
+        let gap = |css: &str| {
+            let mut gap = 0.0;
+            with_layout(html, &format!("p {{ height: 10px; }} {}", css), |root| {
+                gap = top(&root.children[1]) - bottom(&root.children[0]);
+            });
+            gap
+        };
+        assert_eq!(
+            gap(".a { margin-bottom: 20px; } .b { margin-top: 30px; }"),
+            30.0
+        );
+        assert_eq!(
+            gap(".a { margin-bottom: 20px; } .b { margin-top: -5px; }"),
+            15.0
+        );
+        assert_eq!(
+            gap(".a { margin-bottom: -10px; } .b { margin-top: -5px; }"),
+            -10.0
+        );
+    }
+
+    #[test]
+    fn test_collapse_parent_and_child() {
+        let html =
+            "<div><p class=\"parent\"><p class=\"child\"></p></p><p class=\"next\"></p></div>";
+        let css = "p { height: 10px; } .parent { height: auto; margin-top: 10px; \
+                   margin-bottom: 10px; } .child { margin-top: 25px; margin-bottom: 20px; }";
+        with_layout(html, css, |root| {
+            let parent = &root.children[0];
+            let child = &parent.children[0];
+            assert_eq!(top(parent), 25.0);
+            assert_eq!(top(child), top(parent));
+            assert_eq!(parent.dimensions.content.height, 10.0);
+            assert_eq!(top(&root.children[1]) - bottom(parent), 20.0);
+        });
+        // Padding keeps the margins apart.
+        let css = format!("{} .parent {{ padding-top: 1px; }}", css);
+        with_layout(html, &css, |root| {
+            let parent = &root.children[0];
+            assert_eq!(top(parent), 10.0);
+            assert_eq!(top(&parent.children[0]), 36.0);
+        });
+    }
+
+    #[test]
+    fn test_collapse_through_empty_block() {
+        let html = "<div><p class=\"a\"></p><p class=\"empty\"></p><p class=\"b\"></p></div>";
+        let css = ".a, .b { height: 10px; } .a { margin-bottom: 10px; } \
+                   .empty { margin-top: 30px; margin-bottom: 5px; } .b { margin-top: 20px; }";
+        with_layout(html, css, |root| {
+            assert_eq!(top(&root.children[2]) - bottom(&root.children[0]), 30.0);
+            assert_eq!(root.dimensions.content.height, 50.0);
+        });
+        // A height of zero lets margins collapse through too, unless a minimum height keeps the
+        // box open.
+        let zero = format!("{} .empty {{ height: 0px; }}", css);
+        with_layout(html, &zero, |root| {
+            assert_eq!(top(&root.children[2]) - bottom(&root.children[0]), 30.0);
+        });
+        let open = format!("{} .empty {{ height: 0px; min-height: 1px; }}", css);
+        with_layout(html, &open, |root| {
+            assert_eq!(top(&root.children[2]) - bottom(&root.children[0]), 51.0);
+        });
+    }
+
+    #[test]
+    fn test_box_sizing() {
+        let html = "<div><p class=\"a\"></p></div>";
+        let css = ".a { width: 100px; height: 50px; padding: 10px; border-width: 5px; }";
+        with_layout(html, css, |root| {
+            let content = root.children[0].dimensions.content;
+            assert_eq!((content.width, content.height), (100.0, 50.0));
+        });
+        let css = format!("{} .a {{ box-sizing: border-box; }}", css);
+        with_layout(html, &css, |root| {
+            let d = root.children[0].dimensions;
+            assert_eq!((d.content.width, d.content.height), (70.0, 20.0));
+            assert_eq!(d.border_box().width, 100.0);
+        });
+    }
+
+    #[test]
+    fn test_min_max_width() {
+        let html = "<div><p class=\"a\"></p><p class=\"b\"></p><p class=\"c\"></p></div>";
+        let css = ".a { width: 300px; max-width: 150px; } .b { max-width: 50%; } \
+                   .c { width: 10px; min-width: 40px; max-width: 20px; }";
+        with_layout(html, css, |root| {
+            let a = root.children[0].dimensions;
+            assert_eq!(a.content.width, 150.0);
+            assert_eq!(a.margin.right, 50.0);
+            // The width algorithm runs again, so the auto width leaves room in the margin.
+            let b = root.children[1].dimensions;
+            assert_eq!((b.content.width, b.margin.right), (100.0, 100.0));
+            // `min-width` wins over `max-width`.
+            assert_eq!(root.children[2].dimensions.content.width, 40.0);
+        });
+    }
+
+    #[test]
+    fn test_min_max_height() {
+        let html = "<div><p class=\"a\"></p><p class=\"b\"><p class=\"c\"></p></p></div>";
+        let css = ".a { min-height: 30px; } .b { max-height: 5px; } .c { height: 10px; }";
+        with_layout(html, css, |root| {
+            assert_eq!(root.children[0].dimensions.content.height, 30.0);
+            assert_eq!(root.children[1].dimensions.content.height, 5.0);
+        });
+    }
+
+    #[test]
+    fn test_percentage_height() {
+        let html =
+            "<div class=\"root\"><p class=\"a\"><p class=\"b\"></p></p><p class=\"c\"></p></div>";
+        let css = ".root { height: 100%; } .a { height: 50%; } .b { height: 50%; } \
+                   .c { height: 10%; }";
+        with_layout(html, css, |root| {
+            assert_eq!(root.dimensions.content.height, 100.0);
+            let a = &root.children[0];
+            assert_eq!(a.dimensions.content.height, 50.0);
+            assert_eq!(a.children[0].dimensions.content.height, 25.0);
+            assert_eq!(root.children[1].dimensions.content.height, 10.0);
+        });
+        // Percentages of a height that depends on the content behave as `auto`.
+        let css = ".a { height: 50%; } .b { height: 50%; } .c { height: 10%; }";
+        with_layout(html, css, |root| {
+            assert_eq!(root.children[0].dimensions.content.height, 0.0);
+            assert_eq!(root.dimensions.content.height, 0.0);
+        });
+    }
+
+    #[test]
+    fn test_float_placement() {
+        let html = "<div><p class=\"l\"></p><p class=\"r\"></p><p class=\"l2\"></p><p class=\"c\"></p></div>";
+        let css = ".l { float: left; width: 150px; height: 20px; } \
+                   .r { float: right; width: 30px; height: 40px; } \
+                   .l2 { float: left; width: 40px; height: 10px; } \
+                   .c { clear: left; height: 10px; margin-top: 5px; }";
+        with_layout(html, css, |root| {
+            let position = |i: usize| {
+                let content = root.children[i].dimensions.content;
+                (content.x, content.y)
+            };
+            assert_eq!(position(0), (0.0, 0.0));
+            assert_eq!(position(1), (170.0, 0.0));
+            // No room between the first two, so the third goes below the first.
+            assert_eq!(position(2), (0.0, 20.0));
+            // Clearance puts the block below the left floats, margin included.
+            assert_eq!(position(3), (0.0, 30.0));
+            // The root is a block formatting context, so its height takes in the right float.
+            assert_eq!(root.dimensions.content.height, 40.0);
+        });
+    }
+
+    #[test]
+    fn test_lines_around_floats() {
+        let html = "<div><p class=\"f\"></p>some text</div>";
+        let css = ".f { float: left; width: 50px; height: 100px; }";
+        with_layout(html, css, |root| {
+            let line = &root.children[1].children[0];
+            assert_eq!(line.box_type, BoxType::LineBox);
+            assert_eq!(line.dimensions.content.x, 50.0);
+            assert_eq!(line.dimensions.content.width, 150.0);
+            assert_eq!(line.children[0].dimensions.content.x, 50.0);
+        });
+        // A float in the middle of a line goes beside it if it fits.
+        let html = "<div>aaa<span class=\"f\"></span> bbb</div>";
+        let css = "span { display: inline; } .f { float: right; width: 20px; height: 20px; }";
+        with_layout(html, css, |root| {
+            let block = &root.children[0];
+            let float = &block.children[0];
+            assert_eq!(float.box_type, BoxType::BlockNode(float.get_style_node()));
+            assert_eq!(
+                (float.dimensions.content.x, float.dimensions.content.y),
+                (180.0, 0.0)
+            );
+            let line = &block.children[1];
+            assert_eq!(line.dimensions.content.y, 0.0);
+            assert_eq!(line.dimensions.content.width, 180.0);
+        });
+    }
+
+    #[test]
+    fn test_float_shrink_to_fit() {
+        let html =
+            "<div><p class=\"f\">ab</p><p class=\"g\">aaaa aaaa aaaa aaaa aaaa aaaa</p></div>";
+        let css = "p { float: left; clear: left; }";
+        with_layout(html, css, |root| {
+            let f = root.children[0].dimensions.content;
+            let advance = Font::default_font().advance("ab", 16.0);
+            assert!((f.width - advance).abs() < 1e-3);
+            // Content wider than the containing block wraps at its width.
+            assert_eq!(root.children[1].dimensions.content.width, 200.0);
+        });
+    }
+
+    #[test]
+    fn test_formatting_context_contains_floats() {
+        let html = "<div><p class=\"f\"></p><p class=\"bfc\"><p class=\"f\"></p></p></div>";
+        let css = ".f { float: left; width: 50px; height: 30px; }";
+        with_layout(html, css, |root| {
+            assert_eq!(root.children[1].dimensions.content.height, 0.0);
+            // The root contains the floats.
+            assert_eq!(root.dimensions.content.height, 30.0);
+        });
+        for bfc in ["overflow: hidden", "display: flow-root"] {
+            let css = format!("{} .bfc {{ {}; }}", css, bfc);
+            with_layout(html, &css, |root| {
+                let d = root.children[1].dimensions;
+                assert_eq!(d.content.height, 30.0);
+                // It also goes beside the float before it rather than under it.
+                assert_eq!((d.content.x, d.content.width), (50.0, 150.0));
+            });
+        }
+    }
+
+    #[test]
+    fn test_relative_position() {
+        let html = "<div><p class=\"a\"><p class=\"b\"></p></p><p class=\"c\"></p></div>";
+        let css = "p { height: 10px; } .a { position: relative; top: 5px; left: 10%; } \
+                   .c { position: relative; bottom: 3px; right: 4px; }";
+        with_layout(html, css, |root| {
+            let a = &root.children[0];
+            assert_eq!((a.dimensions.content.x, top(a)), (20.0, 5.0));
+            assert_eq!(top(&a.children[0]), 5.0);
+            // The box after it goes where it would without the offset.
+            let c = &root.children[1];
+            assert_eq!((c.dimensions.content.x, top(c)), (-4.0, 7.0));
+        });
+    }
+
+    #[test]
+    fn test_absolute_position() {
+        let html = "<div><p class=\"before\"></p><p class=\"cb\"><p class=\"a\">ab</p>\
+                    <p class=\"b\"></p><p class=\"static\"></p></p></div>";
+        let css = ".before { height: 10px; } \
+                   .cb { position: relative; height: 50px; padding: 5px; margin-left: 20px; } \
+                   .a { position: absolute; right: 10px; bottom: 0px; } \
+                   .b { position: absolute; top: 0px; bottom: 0px; left: 0px; width: 50px; \
+                        margin-left: auto; margin-right: auto; right: 0px; } \
+                   .static { position: absolute; width: 10px; height: 10px; }";
+        with_layout(html, css, |root| {
+            let cb = &root.children[1];
+            // The padding box of the containing block is 180px by 60px at (20, 10).
+            let a = cb.children[0].dimensions;
+            let advance = Font::default_font().advance("ab", 16.0);
+            assert!((a.content.width - advance).abs() < 1e-3);
+            assert!((a.content.x + a.content.width - 190.0).abs() < 1e-3);
+            assert_eq!(a.content.y + a.content.height, 70.0);
+            // Stretched between `top` and `bottom`, and centered between `left` and `right`.
+            let b = cb.children[1].dimensions;
+            assert_eq!((b.content.y, b.content.height), (10.0, 60.0));
+            assert_eq!(b.content.x, 85.0);
+            // With no insets set it stays where it would be in the flow.
+            let position = cb.children[2].dimensions.content;
+            assert_eq!((position.x, position.y), (25.0, 15.0));
+            // None of them take up space.
+            assert_eq!(cb.dimensions.content.height, 50.0);
+        });
+        // Without a positioned ancestor the initial containing block is used.
+        let css = ".a { position: absolute; bottom: 10px; left: 5px; width: 10px; height: 10px; }";
+        with_layout("<div><p class=\"a\"></p></div>", css, |root| {
+            let a = root.children[0].dimensions.content;
+            assert_eq!((a.x, a.y), (5.0, 80.0));
+        });
+    }
+
+    #[test]
+    fn test_fixed_and_sticky_position() {
+        let html = "<div><p class=\"cb\"><p class=\"fixed\"></p></p><p class=\"tall\">\
+                    <p class=\"spacer\"></p><p class=\"sticky\"></p></p></div>";
+        let css = ".cb { position: relative; height: 30px; margin-left: 30px; } \
+                   .fixed { position: fixed; top: 0px; right: 0px; width: 10px; height: 10px; } \
+                   .tall { height: 300px; } .spacer { height: 150px; } \
+                   .sticky { position: sticky; height: 20px; bottom: 5px; }";
+        with_layout(html, css, |root| {
+            let fixed = root.children[0].children[0].dimensions.content;
+            assert_eq!((fixed.x, fixed.y), (190.0, 0.0));
+            // Below the viewport, so it moves up to show at its bottom.
+            let sticky = &root.children[1].children[1];
+            assert_eq!(bottom(sticky), 95.0);
+        });
+    }
+
+    #[test]
+    fn test_flex_row() {
+        let html =
+            "<div class=\"flex\"><p class=\"a\"></p><p class=\"b\"></p><p class=\"c\"></p></div>";
+        let css = ".flex { display: flex; height: 50px; column-gap: 10px; } \
+                   .a { width: 20px; flex-grow: 1; } .b { flex: 2; } \
+                   .c { width: 30px; height: 10px; align-self: center; order: -1; }";
+        with_layout(html, css, |root| {
+            let position = |i: usize| {
+                let content = root.children[i].dimensions.content;
+                (content.x, content.y, content.width, content.height)
+            };
+            // `order` puts the last item first. The rest share 130px of free space by their
+            // factors, on top of their bases of 20px and 0px.
+            assert_eq!(position(0), (0.0, 20.0, 30.0, 10.0));
+            let (x, y, width, height) = position(1);
+            assert!((x - 40.0).abs() < 1e-3 && (width - 63.333).abs() < 1e-3);
+            // Items without a height stretch across the line.
+            assert_eq!((y, height), (0.0, 50.0));
+            let (x, _, width, _) = position(2);
+            assert!((x - 113.333).abs() < 1e-3 && (width - 86.667).abs() < 1e-3);
+        });
+    }
+
+    #[test]
+    fn test_flex_wrap_and_alignment() {
+        let html = "<div class=\"flex\"><p></p><p></p><p></p></div>";
+        let css = ".flex { display: flex; flex-wrap: wrap; justify-content: space-between; \
+                   row-gap: 5px; } p { width: 80px; height: 10px; }";
+        with_layout(html, css, |root| {
+            let position = |i: usize| {
+                let content = root.children[i].dimensions.content;
+                (content.x, content.y)
+            };
+            assert_eq!(position(0), (0.0, 0.0));
+            assert_eq!(position(1), (120.0, 0.0));
+            // The third item does not fit, so it goes on a line of its own.
+            assert_eq!(position(2), (0.0, 15.0));
+            assert_eq!(root.dimensions.content.height, 25.0);
+        });
+        let css = ".flex { display: flex; flex-direction: row-reverse; } \
+                   p { width: 20px; height: 10px; } .auto { margin-right: auto; }";
+        with_layout(
+            "<div class=\"flex\"><p></p><p class=\"auto\"></p></div>",
+            css,
+            |root| {
+                assert_eq!(root.children[0].dimensions.content.x, 180.0);
+                // An auto margin takes up the free space.
+                assert_eq!(root.children[1].dimensions.content.x, 0.0);
+            },
+        );
+    }
+
+    #[test]
+    fn test_flex_column() {
+        let html = "<div class=\"flex\"><p class=\"a\">text</p><p class=\"b\"></p></div>";
+        let css = ".flex { display: flex; flex-direction: column; height: 100px; \
+                   align-items: flex-end; } .a { flex-shrink: 0; } \
+                   .b { flex-grow: 1; width: 50px; min-height: 10px; }";
+        with_layout(html, css, |root| {
+            let a = root.children[0].dimensions.content;
+            let advance = Font::default_font().advance("text", 16.0);
+            // Not stretched, so it fits its content at the end of the cross axis.
+            assert!((a.width - advance).abs() < 1e-3);
+            assert!((a.x + a.width - 200.0).abs() < 1e-3);
+            let b = root.children[1].dimensions.content;
+            assert_eq!((b.x, b.width), (150.0, 50.0));
+            assert_eq!(b.y + b.height, 100.0);
+            assert_eq!(b.y, a.y + a.height);
+        });
+    }
+
+    #[test]
+    fn test_grid_tracks() {
+        let html = "<div class=\"grid\"><p></p><p></p><p></p><p class=\"tall\"></p></div>";
+        let css = ".grid { display: grid; grid-template-columns: 50px 1fr 2fr; gap: 5px 10px; } \
+                   p { height: 10px; } .tall { height: 30px; }";
+        with_layout(html, css, |root| {
+            let position = |i: usize| {
+                let content = root.children[i].dimensions.content;
+                (content.x, content.y, content.width)
+            };
+            assert_eq!(position(0), (0.0, 0.0, 50.0));
+            // The flexible columns share the 130px left by their factors.
+            let (x, _, width) = position(1);
+            assert!((x - 60.0).abs() < 1e-3 && (width - 43.333).abs() < 1e-3);
+            let (x, _, width) = position(2);
+            assert!((x - 113.333).abs() < 1e-3 && (width - 86.667).abs() < 1e-3);
+            // The fourth item goes on a new row, which fits its height.
+            assert_eq!(position(3), (0.0, 15.0, 50.0));
+            assert_eq!(root.dimensions.content.height, 45.0);
+        });
+        // A shrink-to-fit grid is as wide as its columns fit its items.
+        let html = "<div><p class=\"grid\"><p class=\"a\"></p><p class=\"b\"></p></p></div>";
+        let css = ".grid { float: left; display: grid; grid-template-columns: auto auto; } \
+                   .a { width: 30px; } .b { width: 40px; }";
+        with_layout(html, css, |root| {
+            let grid = &root.children[0];
+            assert_eq!(grid.dimensions.content.width, 70.0);
+            assert_eq!(grid.children[1].dimensions.content.x, 30.0);
+        });
+    }
+
+    #[test]
+    fn test_grid_placement() {
+        let html = "<div class=\"grid\"><p class=\"main\"></p><p class=\"head\"></p>\
+                    <p class=\"x\"></p><p class=\"y\"></p></div>";
+        let css = ".grid { display: grid; grid-template-columns: [left] 100px [mid] 100px; \
+                   grid-template-rows: 20px 20px; grid-template-areas: \"head head\" \"side main\"; \
+                   grid-auto-rows: 10px; } \
+                   .head { grid-area: head; } .main { grid-area: main; } \
+                   .x { grid-column: mid / span 1; } .y { grid-row: -1; }";
+        with_layout(html, css, |root| {
+            let position = |i: usize| {
+                let content = root.children[i].dimensions.content;
+                (content.x, content.y, content.width, content.height)
+            };
+            assert_eq!(position(0), (100.0, 20.0, 100.0, 20.0));
+            assert_eq!(position(1), (0.0, 0.0, 200.0, 20.0));
+            // The item locked to a row goes first, in an implicit row after the explicit grid.
+            assert_eq!(position(3), (0.0, 40.0, 100.0, 10.0));
+            assert_eq!(position(2), (100.0, 40.0, 100.0, 10.0));
+            assert_eq!(root.dimensions.content.height, 50.0);
+        });
+    }
+
+    #[test]
+    fn test_grid_auto_flow() {
+        let html = "<div class=\"grid\"><p></p><p class=\"tall\"></p><p></p></div>";
+        let css = ".grid { display: grid; grid-template-rows: 10px 10px; grid-auto-columns: 30px; \
+                   grid-auto-flow: column dense; } .tall { grid-row: span 2; }";
+        with_layout(html, css, |root| {
+            let position = |i: usize| {
+                let content = root.children[i].dimensions.content;
+                (content.x, content.y)
+            };
+            assert_eq!(position(1), (30.0, 0.0));
+            // Dense placement fills the hole the tall item left in the first column.
+            assert_eq!(position(2), (0.0, 10.0));
+        });
+    }
+
+    #[test]
+    fn test_grid_alignment() {
+        let html = "<div class=\"grid\"><p class=\"a\"></p><p class=\"b\"></p></div>";
+        let css = ".grid { display: grid; grid-template-columns: repeat(2, 60px); \
+                   grid-auto-rows: 40px; height: 100px; justify-content: center; \
+                   align-content: center; justify-items: center; align-items: end; } \
+                   .a { width: 20px; height: 10px; } \
+                   .b { justify-self: stretch; align-self: stretch; }";
+        with_layout(html, css, |root| {
+            let a = root.children[0].dimensions.content;
+            assert_eq!((a.x, a.y), (60.0, 60.0));
+            let b = root.children[1].dimensions.content;
+            assert_eq!((b.x, b.y, b.width, b.height), (100.0, 30.0, 60.0, 40.0));
+        });
+    }
+
+    const TABLE_CSS: &str =
+        "table { display: table; } tr { display: table-row; } td { display: table-cell; }";
+
+    #[test]
+    fn test_table_auto_layout() {
+        let html = "<table><tr><td class=\"a\"></td><td class=\"b\"></td></tr>\
+                    <tr><td class=\"c\"></td></tr></table>";
+        let css = format!(
+            "{} table {{ border-spacing: 2px 4px; }} .a {{ width: 30px; height: 20px; }} \
+             .b {{ width: 50px; padding-left: 5px; }} .c {{ height: 10px; }}",
+            TABLE_CSS
+        );
+        with_layout(html, &css, |table| {
+            // The table shrinks to its columns and the spacing around them.
+            let d = table.dimensions.content;
+            assert_eq!((d.width, d.height), (91.0, 42.0));
+            let position = |row: usize, cell: usize| {
+                let content = table.children[row].children[cell].dimensions.content;
+                (content.x, content.y, content.width, content.height)
+            };
+            assert_eq!(position(0, 0), (2.0, 4.0, 30.0, 20.0));
+            // Cells are as tall as their row.
+            assert_eq!(position(0, 1), (39.0, 4.0, 50.0, 20.0));
+            assert_eq!(position(1, 0), (2.0, 28.0, 30.0, 10.0));
+            let row = table.children[0].dimensions.content;
+            assert_eq!(
+                (row.x, row.y, row.width, row.height),
+                (2.0, 4.0, 87.0, 20.0)
+            );
+        });
+    }
+
+    #[test]
+    fn test_table_spans() {
+        let html = "<table><tr><td class=\"wide\" colspan=\"2\"></td></tr>\
+                    <tr><td class=\"tall\" rowspan=\"2\"></td><td></td></tr>\
+                    <tr><td class=\"last\"></td></tr></table>";
+        let css = format!(
+            "{} td {{ width: 20px; height: 10px; vertical-align: top; }} \
+             .wide {{ width: 100px; }} .tall {{ height: 50px; }}",
+            TABLE_CSS
+        );
+        with_layout(html, &css, |table| {
+            let position = |row: usize, cell: usize| {
+                let content = table.children[row].children[cell].dimensions.content;
+                (content.x, content.y, content.width, content.height)
+            };
+            // The spanning cell widens both columns, and the one spanning rows makes the last of
+            // them taller.
+            assert_eq!(position(0, 0), (0.0, 0.0, 100.0, 10.0));
+            assert_eq!(position(1, 0), (0.0, 10.0, 50.0, 50.0));
+            assert_eq!(position(1, 1), (50.0, 10.0, 50.0, 10.0));
+            // The last row's cell goes in the slot left free beside the spanning cell.
+            assert_eq!(position(2, 0), (50.0, 20.0, 50.0, 40.0));
+            assert_eq!(table.dimensions.content.height, 60.0);
+        });
+    }
+
+    #[test]
+    fn test_table_fixed_layout() {
+        let html = "<table><tr><td class=\"a\"></td><td></td><td></td></tr>\
+                    <tr><td class=\"b\"></td></tr></table>";
+        let css = format!(
+            "{} table {{ table-layout: fixed; width: 100px; border-spacing: 5px; }} \
+             .a {{ width: 25px; }} .b {{ width: 80px; }}",
+            TABLE_CSS
+        );
+        with_layout(html, &css, |table| {
+            let position = |row: usize, cell: usize| {
+                let content = table.children[row].children[cell].dimensions.content;
+                (content.x, content.width)
+            };
+            // Only the first row counts, and the columns without a width share the rest.
+            assert_eq!(position(0, 0), (5.0, 25.0));
+            assert_eq!(position(0, 1), (35.0, 27.5));
+            assert_eq!(position(0, 2), (67.5, 27.5));
+            assert_eq!(position(1, 0), (5.0, 25.0));
+            assert_eq!(table.dimensions.content.width, 100.0);
+        });
+    }
+
+    #[test]
+    fn test_anonymous_table_boxes() {
+        let html =
+            "<div><p class=\"cell\">a</p><p class=\"cell\">b</p><p class=\"after\"></p></div>";
+        with_layout(html, ".cell { display: table-cell; }", |root| {
+            let table = &root.children[0];
+            assert!(matches!(
+                table.box_type,
+                BoxType::AnonymousTablePart(TablePart::Table, _)
+            ));
+            let row = &table.children[0];
+            assert!(matches!(
+                row.box_type,
+                BoxType::AnonymousTablePart(TablePart::Row, _)
+            ));
+            // The cells go side by side, and the table is as wide as they are.
+            let (a, b) = (&row.children[0].dimensions, &row.children[1].dimensions);
+            assert_eq!(a.content.y, b.content.y);
+            assert_eq!(b.border_box().x, a.border_box().x + a.border_box().width);
+            let width = table.dimensions.content.width;
+            assert_eq!(width, b.border_box().x + b.border_box().width);
+            assert!(width < 200.0);
+            assert_eq!(top(&root.children[1]), bottom(table));
+        });
+    }
+
+    #[test]
+    fn test_collapsed_borders() {
+        let html = "<table><tr><td class=\"a\"></td><td class=\"b\"></td></tr></table>";
+        let css = format!(
+            "{} table {{ border-collapse: collapse; border-width: 4px; padding-left: 3px; \
+             border-color: #000000; }} \
+             td {{ width: 10px; height: 10px; border-width: 2px; border-color: #ff0000; }} \
+             .a {{ border-width: 6px; }}",
+            TABLE_CSS
+        );
+        with_layout(html, &css, |table| {
+            // The borders belong to the cells, and the table has no padding.
+            assert_eq!(table.dimensions.border, EdgeSizes::default());
+            assert_eq!(table.dimensions.padding, EdgeSizes::default());
+            let d = table.dimensions.content;
+            assert_eq!((d.width, d.height), (36.0, 22.0));
+            let (a, b) = (
+                &table.children[0].children[0],
+                &table.children[0].children[1],
+            );
+            // Cells share the border between them, and have the whole of the table's.
+            let edges = |top, right, bottom, left| EdgeSizes {
+                top,
+                right,
+                bottom,
+                left,
+            };
+            assert_eq!(a.dimensions.border, edges(6.0, 3.0, 6.0, 6.0));
+            assert_eq!(b.dimensions.border, edges(4.0, 4.0, 4.0, 3.0));
+            assert_eq!(b.dimensions.content.x, 22.0);
+            // Each border takes the color of the one that wins.
+            let colors = b.border_colors.clone().unwrap();
+            let color = |red, alpha| {
+                Some(Color {
+                    red,
+                    green: 0,
+                    blue: 0,
+                    alpha,
+                })
+            };
+            assert_eq!(colors[0], color(0, 255));
+            assert_eq!(colors[3], color(255, 255));
+        });
+    }
+
+    #[test]
+    fn test_cell_vertical_align() {
+        let html = "<table><tr><td class=\"tall\"></td><td class=\"middle\"><p></p></td>\
+                    <td class=\"bottom\"><p></p></td></tr></table>";
+        let css = format!(
+            "{} td {{ vertical-align: top; }} .tall {{ height: 40px; }} p {{ height: 10px; }} \
+             .middle {{ vertical-align: middle; }} .bottom {{ vertical-align: bottom; }}",
+            TABLE_CSS
+        );
+        with_layout(html, &css, |table| {
+            let row = &table.children[0];
+            assert_eq!(row.children[1].dimensions.content.height, 40.0);
+            assert_eq!(top(&row.children[1].children[0]), 15.0);
+            assert_eq!(top(&row.children[2].children[0]), 30.0);
+        });
+        // Cells aligned on their baselines line up their first lines.
+        let html = "<table><tr><td class=\"big\">A</td><td>a</td></tr></table>";
+        let css = format!("{} .big {{ font-size: 32px; }}", TABLE_CSS);
+        with_layout(html, &css, |table| {
+            let fonts = FontDatabase::new();
+            let row = &table.children[0];
+            let big = inline::first_baseline(&row.children[0], &fonts).unwrap();
+            let small = inline::first_baseline(&row.children[1], &fonts).unwrap();
+            assert!((big - small).abs() < 1e-3);
+            assert!(top(&row.children[1]) < small - 16.0);
+        });
+    }
+
+    /// The text of the line boxes in a box, like the marker of a list item.
+    fn line_text(layout_box: &LayoutBox) -> String {
+        match layout_box.box_type {
+            BoxType::TextRun(_, ref text, _) => text.clone(),
+            _ => layout_box.children.iter().map(line_text).collect(),
+        }
+    }
+
+    #[test]
+    fn test_list_markers() {
+        let html = "<ol start=\"3\"><li>a</li><li value=\"10\">b</li><li>c</li>\
+                    <li class=\"inside\">d</li></ol>";
+        let css = "ol { counter-reset: list-item; list-style-type: upper-roman; \
+                   padding-left: 40px; } li { display: list-item; } \
+                   .inside { list-style-position: inside; }";
+        with_layout(html, css, |ol| {
+            let fonts = FontDatabase::new();
+            let markers: Vec<_> = ol.children[..3]
+                .iter()
+                .map(|li| {
+                    assert!(matches!(li.children[0].box_type, BoxType::Marker(_)));
+                    line_text(&li.children[0]).trim_end().to_string()
+                })
+                .collect();
+            assert_eq!(markers, vec!["III.", "X.", "XI."]);
+
+            // Outside markers end at the item's border box and sit on its first baseline.
+            let li = &ol.children[0];
+            let marker = &li.children[0];
+            let line = &marker.children[0];
+            let right = line
+                .children
+                .iter()
+                .map(|fragment| fragment.dimensions.margin_box())
+                .fold(0.0, |right: f32, rect| right.max(rect.x + rect.width));
+            assert!((right - 40.0).abs() < 1e-3);
+            let item_baseline = inline::first_baseline(&li.children[1], &fonts).unwrap();
+            let marker_baseline = inline::first_baseline(marker, &fonts).unwrap();
+            assert!((item_baseline - marker_baseline).abs() < 1e-3);
+
+            // Inside markers start the item's first line.
+            let inside = &ol.children[3];
+            assert_eq!(inside.children[0].box_type, BoxType::AnonymousBlock);
+            assert!(line_text(inside).starts_with("XII."));
+            assert!(
+                inside.children[0].children[0].children[0]
+                    .dimensions
+                    .content
+                    .x
+                    >= 40.0
+            );
+        });
+    }
+
+    #[test]
+    fn test_counters() {
+        let html = "<ul><li>a<ul><li>b</li><li>c<ul><li>d</li></ul></li></ul></li>\
+                    <li class=\"skip\">e</li><li>f</li></ul>";
+        let css = "ul { counter-reset: list-item; } li { display: list-item; } \
+                   li::marker { content: counters(list-item, \".\") \" \"; } \
+                   .skip { counter-increment: list-item 2; } \
+                   .skip::marker { content: \"(\" counter(list-item, lower-alpha) \")\"; }";
+        with_layout(html, css, |ul| {
+            let mut markers = Vec::new();
+            fn collect(layout_box: &LayoutBox, markers: &mut Vec<String>) {
+                if let BoxType::Marker(_) = layout_box.box_type {
+                    markers.push(line_text(layout_box).trim_end().to_string());
+                }
+                for child in &layout_box.children {
+                    collect(child, markers);
+                }
+            }
+            collect(ul, &mut markers);
+            assert_eq!(markers, vec!["1", "1.1", "1.2", "1.2.1", "(c)", "4"]);
+        });
+    }
+
+    #[test]
+    fn test_inline_block() {
+        let html = "<div>ab <p class=\"ib\">cd</p> ef</div>";
+        let css = ".ib { display: inline-block; padding-left: 5px; padding-right: 5px; }";
+        with_layout(html, css, |div| {
+            let fonts = FontDatabase::new();
+            let block = &div.children[0];
+            assert_eq!(block.children.len(), 1);
+            let line = &block.children[0];
+            assert_eq!(line.children.len(), 3);
+            let (ab, ib, ef) = (&line.children[0], &line.children[1], &line.children[2]);
+            // The inline block shrinks to its text and sits between the runs around it.
+            let text = &ib.children[0].children[0].children[0];
+            assert!((ib.dimensions.content.width - text.dimensions.content.width).abs() < 1e-3);
+            let ab_end = ab.dimensions.content.x + ab.dimensions.content.width;
+            assert!((ib.dimensions.border_box().x - ab_end).abs() < 1e-3);
+            assert!(ef.dimensions.content.x >= ib.dimensions.border_box().x + 10.0);
+            // Its last line is on the baseline of the surrounding text.
+            let outer = inline::first_baseline(line, &fonts).unwrap();
+            let inner = inline::last_baseline(ib, &fonts).unwrap();
+            assert!((outer - inner).abs() < 1e-3);
+        });
+
+        // With more than one line inside, it is not the first one that is on the baseline.
+        let tall = "<div>ab <p class=\"ib\"><p>cd</p><p>ef</p></p> gh</div>";
+        with_layout(tall, ".ib { display: inline-block; }", |div| {
+            let fonts = FontDatabase::new();
+            let line = &div.children[0].children[0];
+            let ib = &line.children[1];
+            let outer = inline::first_baseline(line, &fonts).unwrap();
+            let first = inline::first_baseline(ib, &fonts).unwrap();
+            let last = inline::last_baseline(ib, &fonts).unwrap();
+            assert!(last > first);
+            assert!((outer - last).abs() < 1e-3);
+        });
+
+        // Lines break around inline blocks that do not fit.
+        let css = ".ib { display: inline-block; width: 190px; }";
+        with_layout(html, css, |div| {
+            let lines = &div.children[0].children;
+            assert_eq!(lines.len(), 3);
+            assert!(matches!(
+                lines[1].children[0].box_type,
+                BoxType::BlockNode(_)
+            ));
+        });
+    }
+
+    #[test]
+    fn test_atomic_inline_baselines() {
+        // An inline block without lines sits on the baseline with its bottom margin edge, and
+        // makes the line tall enough to hold it.
+        let html = "<div>x<p class=\"empty\"></p><p class=\"flex\"><p>y</p><p>z</p></p></div>";
+        let css = ".empty { display: inline-block; width: 10px; height: 30px; \
+                   margin-bottom: 2px; } .flex { display: inline-flex; } \
+                   .flex p { width: 10px; }";
+        with_layout(html, css, |div| {
+            let fonts = FontDatabase::new();
+            let line = &div.children[0].children[0];
+            let baseline = inline::first_baseline(line, &fonts).unwrap();
+            let empty = &line.children[1];
+            assert!((bottom(empty) + 2.0 - baseline).abs() < 1e-3);
+            assert!(line.dimensions.content.height > 32.0);
+            // An inline flex container shrinks to its items and is aligned on its first line.
+            let flex = &line.children[2];
+            assert_eq!(flex.dimensions.content.width, 20.0);
+            let flex_baseline = inline::first_baseline(&flex.children[0], &fonts).unwrap();
+            assert!((flex_baseline - baseline).abs() < 1e-3);
+        });
+    }
+}
@@ -0,0 +1,72 @@
+use super::super::css::Unit::Percent;
+use super::super::css::Value::{Keyword, Length, Sequence};
+use super::super::style::StyledNode;
+
+/// Where boxes go along an axis, from `justify-content`, `align-content`, `justify-items`,
+/// `align-items`, `justify-self` and `align-self`. Baseline alignment is treated as start
+/// alignment.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(super) enum Align {
+    Start,
+    End,
+    Center,
+    SpaceBetween,
+    SpaceAround,
+    SpaceEvenly,
+    #[default]
+    Stretch,
+}
+
+impl Align {
+    /// The alignment a property asks for, or `None` for `normal` and `auto`.
+    pub(super) fn of(style: &StyledNode, name: &str) -> Option<Align> {
+        match style.value(name) {
+            Some(Keyword(keyword)) => match &*keyword {
+                "flex-start" | "start" | "self-start" | "left" | "baseline" => Some(Align::Start),
+                "flex-end" | "end" | "self-end" | "right" => Some(Align::End),
+                "center" => Some(Align::Center),
+                "space-between" => Some(Align::SpaceBetween),
+                "space-around" => Some(Align::SpaceAround),
+                "space-evenly" => Some(Align::SpaceEvenly),
+                "stretch" => Some(Align::Stretch),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The space before the first of `count` boxes and between each two, sharing out `free`
+    /// space. Distributed alignment falls back to start or center alignment without free space.
+    pub(super) fn distribute(self, free: f32, count: usize) -> (f32, f32) {
+        let count = count as f32;
+        match self {
+            Align::Start | Align::Stretch => (0.0, 0.0),
+            Align::End => (free, 0.0),
+            Align::Center => (free / 2.0, 0.0),
+            Align::SpaceBetween if free > 0.0 && count > 1.0 => (0.0, free / (count - 1.0)),
+            Align::SpaceBetween => (0.0, 0.0),
+            Align::SpaceAround if free > 0.0 => (free / count / 2.0, free / count),
+            Align::SpaceEvenly if free > 0.0 => (free / (count + 1.0), free / (count + 1.0)),
+            Align::SpaceAround | Align::SpaceEvenly => (free / 2.0, 0.0),
+        }
+    }
+}
+
+/// The `row-gap` and `column-gap` of a container, from the longhands or the `gap` shorthand.
+/// Percentages of an unknown height are zero.
+pub(super) fn gaps(style: &StyledNode, width: f32, height: Option<f32>) -> (f32, f32) {
+    let gap = |name, index: usize, reference: Option<f32>| {
+        let value = style.value(name).or_else(|| match style.value("gap")? {
+            Sequence(values) => values.get(index).cloned(),
+            value => Some(value),
+        });
+        match value {
+            Some(percentage @ Length(_, Percent)) => {
+                reference.map_or(0.0, |reference| percentage.resolve(reference))
+            }
+            Some(value) => value.to_px(),
+            None => 0.0,
+        }
+    };
+    (gap("row-gap", 0, height), gap("column-gap", 1, Some(width)))
+}
@@ -0,0 +1,134 @@
+use super::super::css::Value;
+use super::super::style::StyledNode;
+use unicode_bidi::{BidiInfo, Level};
+
+/// The `unicode-bidi` property, which controls how an element takes part in bidi resolution.
+#[derive(Clone, Copy, PartialEq)]
+enum UnicodeBidi {
+    Normal,
+    Embed,
+    Isolate,
+    BidiOverride,
+    IsolateOverride,
+    Plaintext,
+}
+
+impl UnicodeBidi {
+    fn of(style: &StyledNode) -> UnicodeBidi {
+        match style.value("unicode-bidi") {
+            Some(Value::Keyword(keyword)) => match &*keyword {
+                "embed" => UnicodeBidi::Embed,
+                "isolate" => UnicodeBidi::Isolate,
+                "bidi-override" => UnicodeBidi::BidiOverride,
+                "isolate-override" => UnicodeBidi::IsolateOverride,
+                "plaintext" => UnicodeBidi::Plaintext,
+                _ => UnicodeBidi::Normal,
+            },
+            _ => UnicodeBidi::Normal,
+        }
+    }
+}
+
+/// Whether the `direction` of a style node is right-to-left.
+fn is_rtl(style: &StyledNode) -> bool {
+    style.value("direction") == Some(Value::Keyword("rtl".to_string()))
+}
+
+/// The paragraph embedding level of a block container, or `None` if it is found from the text
+/// itself, as for `unicode-bidi: plaintext`.
+pub fn paragraph_level(style: &StyledNode) -> Option<Level> {
+    if UnicodeBidi::of(style) == UnicodeBidi::Plaintext {
+        None
+    } else if is_rtl(style) {
+        Some(Level::rtl())
+    } else {
+        Some(Level::ltr())
+    }
+}
+
+/// The bidi control characters an inline element adds to the text of its paragraph at its start
+/// and at its end.
+///
+/// http://www.w3.org/TR/css-writing-modes-3/#bidi-control-codes-injection-table
+pub fn controls(style: &StyledNode) -> (&'static str, &'static str) {
+    let rtl = is_rtl(style);
+    match UnicodeBidi::of(style) {
+        UnicodeBidi::Normal => ("", ""),
+        UnicodeBidi::Embed if rtl => ("\u{202B}", "\u{202C}"),
+        UnicodeBidi::Embed => ("\u{202A}", "\u{202C}"),
+        UnicodeBidi::Isolate if rtl => ("\u{2067}", "\u{2069}"),
+        UnicodeBidi::Isolate => ("\u{2066}", "\u{2069}"),
+        UnicodeBidi::BidiOverride if rtl => ("\u{202E}", "\u{202C}"),
+        UnicodeBidi::BidiOverride => ("\u{202D}", "\u{202C}"),
+        UnicodeBidi::IsolateOverride if rtl => ("\u{2067}\u{202E}", "\u{202C}\u{2069}"),
+        UnicodeBidi::IsolateOverride => ("\u{2066}\u{202D}", "\u{202C}\u{2069}"),
+        UnicodeBidi::Plaintext => ("\u{2068}", "\u{2069}"),
+    }
+}
+
+/// Resolve the embedding level of every byte of some text, split into paragraphs at newlines.
+///
+/// http://www.unicode.org/reports/tr9/
+pub fn levels(text: &str, paragraph_level: Option<Level>) -> Vec<u8> {
+    BidiInfo::new(text, paragraph_level)
+        .levels
+        .iter()
+        .map(|level| level.number())
+        .collect()
+}
+
+/// The visual order of items with the given embedding levels: from the highest level down to the
+/// lowest odd one, every run of items at that level or higher is reversed (rule L2).
+pub fn visual_order(levels: &[u8]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+    let highest = levels.iter().copied().max().unwrap_or(0);
+    let lowest_odd = match levels.iter().copied().filter(|level| level % 2 == 1).min() {
+        Some(level) => level,
+        None => return order,
+    };
+    for level in (lowest_odd..=highest).rev() {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < level {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < order.len() && levels[order[i]] >= level {
+                i += 1;
+            }
+            order[start..i].reverse();
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visual_order() {
+        assert_eq!(visual_order(&[0, 0, 0]), vec![0, 1, 2]);
+        assert_eq!(visual_order(&[1, 1, 1]), vec![2, 1, 0]);
+        // Left-to-right text embedded in a right-to-left paragraph keeps its own order.
+        assert_eq!(visual_order(&[1, 2, 2, 1]), vec![3, 1, 2, 0]);
+        assert_eq!(visual_order(&[0, 1, 1, 0, 1]), vec![0, 2, 1, 3, 4]);
+    }
+
+    #[test]
+    fn test_levels() {
+        // "abc", then Hebrew alef and bet.
+        let text = "abc \u{5D0}\u{5D1}";
+        assert_eq!(
+            levels(text, Some(Level::ltr())),
+            vec![0, 0, 0, 0, 1, 1, 1, 1]
+        );
+        assert_eq!(
+            levels(text, Some(Level::rtl())),
+            vec![2, 2, 2, 1, 1, 1, 1, 1]
+        );
+        // Without a paragraph level, the first strong character decides.
+        assert_eq!(levels("\u{5D0} a", None)[3], 2);
+    }
+}
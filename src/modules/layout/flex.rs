@@ -0,0 +1,764 @@
+use super::super::css::Unit::Px;
+use super::super::css::Value::{self, Keyword, Length, Number, Sequence};
+use super::super::font::FontDatabase;
+use super::super::style::StyledNode;
+use super::align::{gaps, Align};
+use super::{content_size, inline, order, sum_edges, BoxType, Dimensions, LayoutBox};
+use std::ops::Range;
+
+/// Whether a box lays out its children as flex items.
+pub fn is_container(style: &StyledNode) -> bool {
+    matches!(style.value("display"), Some(Keyword(keyword)) if keyword == "flex" || keyword == "inline-flex")
+}
+
+/// The axes of a flex container, from `flex-direction`, `flex-wrap` and `direction`.
+#[derive(Clone, Copy, Debug)]
+struct Flow {
+    /// Whether the main axis is horizontal.
+    row: bool,
+    /// Whether items go from the right or bottom along the main axis.
+    reverse: bool,
+    /// Whether items may go on more than one line.
+    wrap: bool,
+    /// Whether lines go from the bottom or right along the cross axis.
+    wrap_reverse: bool,
+}
+
+impl Flow {
+    fn of(style: &StyledNode) -> Flow {
+        let keyword = |name| match style.value(name) {
+            Some(Keyword(keyword)) => keyword,
+            _ => String::new(),
+        };
+        let direction = keyword("flex-direction");
+        let row = !direction.starts_with("column");
+        let rtl = keyword("direction") == "rtl";
+        let wrap = keyword("flex-wrap");
+        Flow {
+            row,
+            reverse: direction.ends_with("-reverse") != (row && rtl),
+            wrap: wrap == "wrap" || wrap == "wrap-reverse",
+            wrap_reverse: wrap == "wrap-reverse",
+        }
+    }
+}
+
+/// A flex item, with its sizes along the main axis as content sizes.
+#[derive(Clone, Debug, Default)]
+struct FlexItem {
+    /// The index of the item among the children of the container.
+    index: usize,
+    grow: f32,
+    shrink: f32,
+    /// The flex base size.
+    base: f32,
+    min: f32,
+    max: f32,
+    /// Margins, borders and padding along the main axis, with `auto` margins as zero.
+    main_edges: f32,
+    /// The main size, once flexible lengths are resolved.
+    target: f32,
+    frozen: bool,
+    /// The content size along the cross axis.
+    cross: f32,
+    /// Margins, borders and padding along the cross axis, with `auto` margins as zero.
+    cross_edges: f32,
+    align: Align,
+    /// Whether the cross size stretches to fill the line.
+    stretch: bool,
+}
+
+impl FlexItem {
+    fn hypothetical(&self) -> f32 {
+        self.base.min(self.max).max(self.min)
+    }
+}
+
+/// The `flex-grow`, `flex-shrink` and `flex-basis` of an item, from the longhands or the `flex`
+/// shorthand. Anonymous items have the initial values.
+fn flex_factors(style: Option<&StyledNode>) -> (f32, f32, Value) {
+    let auto = Keyword("auto".to_string());
+    let style = match style {
+        Some(style) => style,
+        None => return (0.0, 1.0, auto),
+    };
+    let (grow, shrink, basis) = match style.value("flex") {
+        Some(Number(grow)) => (grow, 1.0, Length(0.0, Px)),
+        Some(Keyword(keyword)) if keyword == "none" => (0.0, 0.0, auto),
+        Some(Keyword(keyword)) if keyword == "auto" => (1.0, 1.0, auto),
+        Some(basis @ Length(..)) => (1.0, 1.0, basis),
+        Some(Sequence(values)) => {
+            let mut numbers = values.iter().filter_map(|value| match value {
+                Number(number) => Some(*number),
+                _ => None,
+            });
+            let (grow, shrink) = (numbers.next(), numbers.next());
+            let basis = values.iter().find(|value| !matches!(value, Number(_)));
+            (
+                grow.unwrap_or(1.0),
+                shrink.unwrap_or(1.0),
+                basis.cloned().unwrap_or(Length(0.0, Px)),
+            )
+        }
+        _ => (0.0, 1.0, auto),
+    };
+    let number = |name, default| match style.value(name) {
+        Some(Number(number)) => number.max(0.0),
+        _ => default,
+    };
+    (
+        number("flex-grow", grow),
+        number("flex-shrink", shrink),
+        style.value("flex-basis").unwrap_or(basis),
+    )
+}
+
+/// Size the items of one line along the main axis so that they fill `available` space, growing
+/// or shrinking them by their flex factors within their limits.
+///
+/// https://www.w3.org/TR/css-flexbox-1/#resolve-flexible-lengths
+fn resolve_flexible_lengths(items: &mut [FlexItem], available: f32) {
+    let hypothetical: f32 = items
+        .iter()
+        .map(|item| item.hypothetical() + item.main_edges)
+        .sum();
+    let growing = hypothetical < available;
+    let factor = |item: &FlexItem| if growing { item.grow } else { item.shrink };
+
+    // Items that cannot flex keep their hypothetical size.
+    for item in items.iter_mut() {
+        item.target = item.hypothetical();
+        item.frozen = factor(item) == 0.0
+            || (growing && item.base > item.hypothetical())
+            || (!growing && item.base < item.hypothetical());
+    }
+    let free_space = |items: &[FlexItem]| {
+        available
+            - items
+                .iter()
+                .map(|item| item.main_edges + if item.frozen { item.target } else { item.base })
+                .sum::<f32>()
+    };
+    let initial_free_space = free_space(items);
+
+    while items.iter().any(|item| !item.frozen) {
+        let mut remaining = free_space(items);
+        let factors: f32 = items.iter().filter(|item| !item.frozen).map(factor).sum();
+        // Factors adding up to less than one only take that share of the free space.
+        if factors < 1.0 && (initial_free_space * factors).abs() < remaining.abs() {
+            remaining = initial_free_space * factors;
+        }
+        // Shrinking takes more from bigger items.
+        let scaled_shrink: f32 = items
+            .iter()
+            .filter(|item| !item.frozen)
+            .map(|item| item.shrink * item.base)
+            .sum();
+        for item in items.iter_mut().filter(|item| !item.frozen) {
+            item.target = item.base
+                + if growing {
+                    remaining * item.grow / factors
+                } else if scaled_shrink > 0.0 {
+                    remaining * item.shrink * item.base / scaled_shrink
+                } else {
+                    0.0
+                };
+        }
+
+        // Clamp to the limits, and freeze the items whose limits are in the way of the rest.
+        let violations: Vec<f32> = items
+            .iter_mut()
+            .map(|item| {
+                if item.frozen {
+                    return 0.0;
+                }
+                let clamped = item.target.min(item.max).max(item.min).max(0.0);
+                let violation = clamped - item.target;
+                item.target = clamped;
+                violation
+            })
+            .collect();
+        let total: f32 = violations.iter().sum();
+        for (item, violation) in items.iter_mut().zip(violations) {
+            item.frozen |= total == 0.0
+                || (total > 0.0 && violation > 0.0)
+                || (total < 0.0 && violation < 0.0);
+        }
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Lay out the children of a flex container as flex items: sized along the main axis by
+    /// their flex factors, broken into lines, and aligned on both axes. `height` is the content
+    /// height of the container if it is known up front; otherwise it is set to fit the lines.
+    ///
+    /// Items are laid out from a copy when they need measuring first, since laying out inline
+    /// content replaces it with lines.
+    ///
+    /// https://www.w3.org/TR/css-flexbox-1/#layout-algorithm
+    pub(super) fn layout_flex_children(&mut self, height: Option<f32>, fonts: &FontDatabase) {
+        let style = self.get_style_node();
+        let flow = Flow::of(style);
+        // Items are laid out, and painted, in their `order`.
+        self.children.sort_by_key(order);
+        let pristine = self.children.clone();
+
+        let d = self.dimensions;
+        let mut container = d;
+        container.content.height = 0.0;
+        let (row_gap, column_gap) = gaps(style, d.content.width, height);
+        let (main_gap, cross_gap) = if flow.row {
+            (column_gap, row_gap)
+        } else {
+            (row_gap, column_gap)
+        };
+        let available_main = if flow.row {
+            Some(d.content.width)
+        } else {
+            height
+        };
+        let definite_cross = if flow.row {
+            height
+        } else {
+            Some(d.content.width)
+        };
+        let align_items = Align::of(style, "align-items").unwrap_or(Align::Stretch);
+
+        let mut items = Vec::new();
+        for (index, child) in self.children.iter_mut().enumerate() {
+            if child.is_absolutely_positioned() {
+                // Absolutely positioned children start at the start of the content box.
+                child.dimensions = Dimensions::default();
+                child.dimensions.content.x = d.content.x;
+                child.dimensions.content.y = d.content.y;
+                continue;
+            }
+            items.push(child.flex_item(index, container, height, flow, align_items, style, fonts));
+        }
+
+        // Collect the items into lines.
+        let mut lines: Vec<Range<usize>> = Vec::new();
+        match available_main {
+            Some(available) if flow.wrap => {
+                let mut start = 0;
+                let mut used = 0.0;
+                for (i, item) in items.iter().enumerate() {
+                    let size = item.hypothetical() + item.main_edges;
+                    if i > start && used + main_gap + size > available {
+                        lines.push(start..i);
+                        start = i;
+                        used = 0.0;
+                    }
+                    used += if i > start { main_gap + size } else { size };
+                }
+                lines.push(start..items.len());
+            }
+            _ => lines.push(0..items.len()),
+        }
+
+        // Resolve the main sizes, and lay the items out at them to find their cross sizes.
+        for line in &lines {
+            let gaps = main_gap * line.len().saturating_sub(1) as f32;
+            match available_main {
+                Some(available) => {
+                    resolve_flexible_lengths(&mut items[line.clone()], available - gaps)
+                }
+                None => {
+                    for item in &mut items[line.clone()] {
+                        item.target = item.hypothetical();
+                    }
+                }
+            }
+        }
+        for item in &mut items {
+            let child = &mut self.children[item.index];
+            *child = pristine[item.index].clone();
+            if flow.row {
+                child.layout_item(container, height, item.target, None, style, fonts);
+                item.cross = child.dimensions.content.height;
+            } else {
+                child.layout_item(
+                    container,
+                    height,
+                    item.cross,
+                    Some(item.target),
+                    style,
+                    fonts,
+                );
+            }
+        }
+
+        // Size the lines along the cross axis; a single line fills a container of known size.
+        let mut line_cross: Vec<f32> = lines
+            .iter()
+            .map(|line| {
+                items[line.clone()]
+                    .iter()
+                    .map(|item| item.cross + item.cross_edges)
+                    .fold(0.0, f32::max)
+            })
+            .collect();
+        if let (Some(cross), false) = (definite_cross, flow.wrap) {
+            line_cross[0] = cross;
+        }
+        let cross_gaps = cross_gap * (lines.len() - 1) as f32;
+        let lines_cross = line_cross.iter().sum::<f32>() + cross_gaps;
+        let container_cross = definite_cross.unwrap_or(lines_cross);
+
+        // Share out the free space along the cross axis between the lines.
+        let mut free = container_cross - lines_cross;
+        let align_content = Align::of(style, "align-content").unwrap_or(Align::Stretch);
+        if !flow.wrap {
+            free = 0.0;
+        } else if align_content == Align::Stretch && free > 0.0 {
+            for cross in &mut line_cross {
+                *cross += free / lines.len() as f32;
+            }
+            free = 0.0;
+        }
+        let (mut line_start, between_lines) = align_content.distribute(free, lines.len());
+
+        let justify = Align::of(style, "justify-content").unwrap_or(Align::Start);
+        let main_size = available_main.unwrap_or_else(|| {
+            lines
+                .iter()
+                .map(|line| outer_main(&items[line.clone()], main_gap))
+                .fold(0.0, f32::max)
+        });
+        for (line, &cross_size) in lines.iter().zip(&line_cross) {
+            // Stretched items fill the line.
+            for item in &mut items[line.clone()] {
+                if !item.stretch {
+                    continue;
+                }
+                let child = &mut self.children[item.index];
+                let stretched =
+                    child.clamp_size(cross_size - item.cross_edges, !flow.row, container, height);
+                if stretched != item.cross {
+                    item.cross = stretched;
+                    *child = pristine[item.index].clone();
+                    let (width, item_height) = if flow.row {
+                        (item.target, stretched)
+                    } else {
+                        (stretched, item.target)
+                    };
+                    child.layout_item(container, height, width, Some(item_height), style, fonts);
+                }
+            }
+
+            // Auto margins along the main axis take the free space first, then `justify-content`
+            // shares out what is left.
+            let line_items = &items[line.clone()];
+            let mut free = main_size - outer_main(line_items, main_gap);
+            let auto_margins: usize = line_items
+                .iter()
+                .map(|item| self.children[item.index].auto_margins(flow.row).len())
+                .sum();
+            if free > 0.0 && auto_margins > 0 {
+                let share = free / auto_margins as f32;
+                for item in line_items {
+                    let child = &mut self.children[item.index];
+                    for side in child.auto_margins(flow.row) {
+                        *side_mut(&mut child.dimensions, side) = share;
+                    }
+                }
+                free = 0.0;
+            }
+            let (mut main_position, between) = justify.distribute(free, line_items.len());
+
+            for item in line_items {
+                let child = &mut self.children[item.index];
+                // Auto margins along the cross axis take the free space in the line, and
+                // `align-self` places the item otherwise.
+                let outer_cross = item.cross + item.cross_edges;
+                let free = cross_size - outer_cross;
+                let auto_cross = child.auto_margins(!flow.row);
+                let cross_offset = if auto_cross.is_empty() {
+                    match item.align {
+                        Align::End => free,
+                        Align::Center => free / 2.0,
+                        _ => 0.0,
+                    }
+                } else {
+                    for &side in &auto_cross {
+                        *side_mut(&mut child.dimensions, side) =
+                            free.max(0.0) / auto_cross.len() as f32;
+                    }
+                    0.0
+                };
+
+                let margin_box = child.dimensions.margin_box();
+                let (outer_main, outer_cross) = if flow.row {
+                    (margin_box.width, margin_box.height)
+                } else {
+                    (margin_box.height, margin_box.width)
+                };
+                let mut main = main_position;
+                if flow.reverse {
+                    main = main_size - main - outer_main;
+                }
+                let mut cross = line_start + cross_offset;
+                if flow.wrap_reverse {
+                    cross = container_cross - cross - outer_cross;
+                }
+                let (x, y) = if flow.row {
+                    (main, cross)
+                } else {
+                    (cross, main)
+                };
+                child.translate(
+                    d.content.x + x - margin_box.x,
+                    d.content.y + y - margin_box.y,
+                );
+                child.offset_relative(container, height);
+                main_position += outer_main + main_gap + between;
+            }
+            line_start += cross_size + cross_gap + between_lines;
+        }
+
+        self.dimensions.content.height = if flow.row { container_cross } else { main_size };
+    }
+
+    /// Set up a child of a flex container as a flex item: find its edges, alignment and flex
+    /// base size, and its cross size for column containers, where it is needed to measure the
+    /// content height.
+    ///
+    /// https://www.w3.org/TR/css-flexbox-1/#algo-main-item
+    #[allow(clippy::too_many_arguments)]
+    fn flex_item(
+        &mut self,
+        index: usize,
+        container: Dimensions,
+        container_height: Option<f32>,
+        flow: Flow,
+        align_items: Align,
+        container_style: &StyledNode,
+        fonts: &FontDatabase,
+    ) -> FlexItem {
+        let style = match self.box_type {
+            BoxType::BlockNode(style) => Some(style),
+            _ => None,
+        };
+        let (grow, shrink, basis) = flex_factors(style);
+        let align = style
+            .and_then(|style| Align::of(style, "align-self"))
+            .unwrap_or(align_items);
+        // Percentage widths refer to the container's width, and heights to its definite height.
+        let width = Some(container.content.width);
+        let (main_name, cross_name, main_reference) = if flow.row {
+            ("width", "height", width)
+        } else {
+            ("height", "width", container_height)
+        };
+
+        // Edges, with percentages of the container's width and `auto` margins as zero.
+        if style.is_some() {
+            self.solve_float_width(container, Some(0.0), fonts);
+            self.calculate_block_position(container);
+        }
+        let d = self.dimensions;
+        let horizontal = sum_edges(&d, ["left", "right"]);
+        let vertical = sum_edges(&d, ["top", "bottom"]);
+        let (main_edges, cross_edges) = if flow.row {
+            (horizontal, vertical)
+        } else {
+            (vertical, horizontal)
+        };
+        let main_sides = if flow.row {
+            ["left", "right"]
+        } else {
+            ["top", "bottom"]
+        };
+        let box_sizing = match style {
+            Some(_) => self.box_sizing_edges(main_sides, container),
+            None => 0.0,
+        };
+        let size = |name: &str, reference: Option<f32>| {
+            content_size(
+                style.and_then(|style| style.value(name)),
+                reference,
+                box_sizing,
+            )
+        };
+
+        let stretch = align == Align::Stretch
+            && size(cross_name, if flow.row { container_height } else { width }).is_none()
+            && self.auto_margins(!flow.row).is_empty();
+        let mut item = FlexItem {
+            index,
+            grow,
+            shrink,
+            main_edges,
+            cross_edges,
+            align,
+            stretch,
+            max: size(&format!("max-{}", main_name), main_reference).unwrap_or(f32::INFINITY),
+            ..Default::default()
+        };
+
+        // Columns are measured at their cross size, which fits the content unless it stretches.
+        if !flow.row {
+            item.cross = if stretch {
+                self.clamp_size(
+                    container.content.width - cross_edges,
+                    true,
+                    container,
+                    container_height,
+                )
+            } else {
+                self.fit_content_width(container, container_style, fonts)
+            };
+        }
+        let mut content_main = None;
+        let mut measure = |item: &FlexItem, min_content: bool| -> f32 {
+            if flow.row {
+                let (min, max) = match self.box_type {
+                    BoxType::AnonymousBlock => {
+                        inline::intrinsic_widths(&self.children, container_style, fonts)
+                    }
+                    _ => self.intrinsic_widths(fonts),
+                };
+                if min_content {
+                    min
+                } else {
+                    max
+                }
+            } else {
+                *content_main.get_or_insert_with(|| {
+                    let mut probe = self.clone();
+                    probe.layout_item(
+                        container,
+                        container_height,
+                        item.cross,
+                        None,
+                        container_style,
+                        fonts,
+                    );
+                    probe.dimensions.content.height
+                })
+            }
+        };
+
+        let specified = size(main_name, main_reference);
+        item.base = match basis {
+            Keyword(keyword) if keyword == "auto" => specified,
+            Keyword(_) => None,
+            basis => content_size(Some(basis), main_reference, box_sizing),
+        }
+        .unwrap_or_else(|| measure(&item, false));
+
+        // An `auto` minimum keeps items from shrinking below their content.
+        let min_name = format!("min-{}", main_name);
+        let overflow_visible = style.is_none_or(|style| {
+            style
+                .value("overflow")
+                .is_none_or(|overflow| overflow == Keyword("visible".to_string()))
+        });
+        item.min = match style.and_then(|style| style.value(&min_name)) {
+            None | Some(Keyword(_)) if overflow_visible => {
+                let content = measure(&item, true);
+                specified
+                    .map_or(content, |specified| specified.min(content))
+                    .min(item.max)
+            }
+            value => content_size(value, main_reference, box_sizing).unwrap_or(0.0),
+        };
+        item
+    }
+
+    /// The `auto` margins of an item along an axis, by side.
+    fn auto_margins(&self, horizontal: bool) -> Vec<&'static str> {
+        let style = match self.box_type {
+            BoxType::BlockNode(style) => style,
+            _ => return Vec::new(),
+        };
+        let sides = if horizontal {
+            ["left", "right"]
+        } else {
+            ["top", "bottom"]
+        };
+        sides
+            .into_iter()
+            .filter(|side| {
+                let margin = style.lookup(&format!("margin-{}", side), &Length(0.0, Px));
+                matches!(margin, Keyword(keyword) if keyword == "auto")
+            })
+            .collect()
+    }
+
+    /// The narrowest and the widest the items of a row flex container can be laid out in: side
+    /// by side, or for a wrapping container, with the narrowest on a line of its own.
+    pub(super) fn flex_intrinsic_widths(&self, fonts: &FontDatabase) -> (f32, f32) {
+        let style = self.get_style_node();
+        let (_, gap) = gaps(style, 0.0, None);
+        let contributions: Vec<(f32, f32)> = self
+            .children
+            .iter()
+            .filter(|child| !child.is_absolutely_positioned())
+            .map(|child| match child.box_type {
+                BoxType::AnonymousBlock => inline::intrinsic_widths(&child.children, style, fonts),
+                _ => child.outer_intrinsic_widths(fonts),
+            })
+            .collect();
+        let gaps = gap * contributions.len().saturating_sub(1) as f32;
+        let max = contributions.iter().map(|&(_, max)| max).sum::<f32>() + gaps;
+        let min = if Flow::of(style).wrap {
+            contributions
+                .iter()
+                .map(|&(min, _)| min)
+                .fold(0.0, f32::max)
+        } else {
+            contributions.iter().map(|&(min, _)| min).sum::<f32>() + gaps
+        };
+        (min, max)
+    }
+}
+
+/// Whether a flex container lays its items out in rows, so that its intrinsic widths add up
+/// theirs.
+pub fn is_row_container(style: &StyledNode) -> bool {
+    is_container(style) && Flow::of(style).row
+}
+
+/// The outer main size of the items on a line, with the gaps between them.
+fn outer_main(items: &[FlexItem], gap: f32) -> f32 {
+    items
+        .iter()
+        .map(|item| item.target + item.main_edges)
+        .sum::<f32>()
+        + gap * items.len().saturating_sub(1) as f32
+}
+
+fn side_mut<'d>(d: &'d mut Dimensions, side: &str) -> &'d mut f32 {
+    match side {
+        "left" => &mut d.margin.left,
+        "right" => &mut d.margin.right,
+        "top" => &mut d.margin.top,
+        _ => &mut d.margin.bottom,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::font::Font;
+    use super::super::super::testing::Page;
+    use super::*;
+
+    fn item(grow: f32, shrink: f32, base: f32) -> FlexItem {
+        FlexItem {
+            grow,
+            shrink,
+            base,
+            max: f32::INFINITY,
+            ..Default::default()
+        }
+    }
+
+    fn targets(items: &[FlexItem]) -> Vec<f32> {
+        items.iter().map(|item| item.target).collect()
+    }
+
+    #[test]
+    fn test_resolve_flexible_lengths() {
+        // Growing shares the free space by `flex-grow`.
+        let mut items = [
+            item(1.0, 1.0, 10.0),
+            item(3.0, 1.0, 10.0),
+            item(0.0, 1.0, 20.0),
+        ];
+        resolve_flexible_lengths(&mut items, 80.0);
+        assert_eq!(targets(&items), [20.0, 40.0, 20.0]);
+
+        // Shrinking takes more from bigger items.
+        let mut items = [item(0.0, 1.0, 100.0), item(0.0, 1.0, 50.0)];
+        resolve_flexible_lengths(&mut items, 120.0);
+        assert_eq!(targets(&items), [80.0, 40.0]);
+
+        // An item that hits its maximum is frozen, and the others take the rest.
+        let mut items = [item(1.0, 1.0, 0.0), item(1.0, 1.0, 0.0)];
+        items[0].max = 10.0;
+        resolve_flexible_lengths(&mut items, 100.0);
+        assert_eq!(targets(&items), [10.0, 90.0]);
+
+        // Factors below one take only part of the free space.
+        let mut items = [item(0.5, 1.0, 0.0)];
+        resolve_flexible_lengths(&mut items, 100.0);
+        assert_eq!(targets(&items), [50.0]);
+    }
+
+    /// Lay out `html` with every element a block, in a viewport 100px wide, and pass the
+    /// positions and sizes of the children of the root box to `check`.
+    fn with_items(html: &str, css: &str, check: impl FnOnce(Vec<(f32, f32, f32)>)) {
+        let page = Page {
+            html,
+            css,
+            width: 100.0,
+            user_agent: false,
+            ..Default::default()
+        };
+        page.layout(|root| {
+            let items = root.children.iter().map(|item| {
+                let content = item.dimensions.content;
+                (content.x, content.y, content.width)
+            });
+            check(items.collect())
+        });
+    }
+
+    #[test]
+    fn test_wrap() {
+        let html = "<div><p></p><p></p><p></p></div>";
+        let items = "p { width: 40px; height: 10px; flex-grow: 1; }";
+        // Items that do not fit go on the next line, and each line shares out its own free space.
+        let css = format!("div {{ display: flex; flex-wrap: wrap; }} {}", items);
+        with_items(html, &css, |items| {
+            assert_eq!(
+                items,
+                [(0.0, 0.0, 50.0), (50.0, 0.0, 50.0), (0.0, 10.0, 100.0)]
+            );
+        });
+        // Lines stack from the bottom up.
+        let css = format!(
+            "div {{ display: flex; flex-wrap: wrap-reverse; }} {}",
+            items
+        );
+        with_items(html, &css, |items| {
+            assert_eq!(
+                items,
+                [(0.0, 10.0, 50.0), (50.0, 10.0, 50.0), (0.0, 0.0, 100.0)]
+            );
+        });
+        // Without wrapping, the items shrink to fit on one line.
+        let css = format!("div {{ display: flex; }} {}", items);
+        with_items(html, &css, |items| {
+            for (i, &(x, y, width)) in items.iter().enumerate() {
+                assert!((x - i as f32 * 100.0 / 3.0).abs() < 1e-3 && y == 0.0);
+                assert!((width - 100.0 / 3.0).abs() < 1e-3);
+            }
+        });
+    }
+
+    #[test]
+    fn test_shrink_below_min_content() {
+        let html = "<div><p class=\"text\">unbreakable</p><p></p></div>";
+        let advance = Font::default_font().advance("unbreakable", 16.0);
+        assert!(advance > 50.0 && advance < 100.0);
+        // Sharing the overflow evenly would leave the text 50px, but an `auto` minimum stops it
+        // at its longest word, and the other item shrinks the rest of the way.
+        let css = "div { display: flex; } p { width: 100px; height: 10px; }";
+        with_items(html, css, |items| {
+            assert!((items[0].2 - advance).abs() < 1e-3);
+            assert!((items[1].2 - (100.0 - advance)).abs() < 1e-3);
+        });
+        // A zero minimum, or overflow other than visible, lets it shrink below its content.
+        for text in ["min-width: 0px", "overflow: hidden"] {
+            let css = format!(
+                "div {{ display: flex; }} p {{ width: 100px; height: 10px; }} .text {{ {}; }}",
+                text
+            );
+            with_items(html, &css, |items| {
+                assert_eq!((items[0].2, items[1].2), (50.0, 50.0), "{}", text);
+            });
+        }
+    }
+}
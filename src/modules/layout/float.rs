@@ -0,0 +1,221 @@
+use super::super::css::Value;
+use super::super::style::StyledNode;
+use super::position::Position;
+use super::Rect;
+
+/// The side a box floats to, from the `float` property.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FloatSide {
+    Left,
+    Right,
+}
+
+impl FloatSide {
+    /// The side a style node floats to, or `None` if it does not float. Absolutely positioned
+    /// boxes do not float.
+    pub fn of(style: &StyledNode) -> Option<FloatSide> {
+        if Position::of(style).is_out_of_flow() {
+            return None;
+        }
+        match style.value("float") {
+            Some(Value::Keyword(keyword)) => match &*keyword {
+                "left" => Some(FloatSide::Left),
+                "right" => Some(FloatSide::Right),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// The sides of earlier floats a box must be placed below, from the `clear` property.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Clear {
+    Left,
+    Right,
+    Both,
+}
+
+impl Clear {
+    /// What a style node clears, or `None` if it clears nothing.
+    pub fn of(style: &StyledNode) -> Option<Clear> {
+        match style.value("clear") {
+            Some(Value::Keyword(keyword)) => match &*keyword {
+                "left" => Some(Clear::Left),
+                "right" => Some(Clear::Right),
+                "both" => Some(Clear::Both),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn clears(self, side: FloatSide) -> bool {
+        matches!(
+            (self, side),
+            (Clear::Both, _) | (Clear::Left, FloatSide::Left) | (Clear::Right, FloatSide::Right)
+        )
+    }
+}
+
+/// The floats placed so far in a block formatting context, as margin boxes.
+///
+/// http://www.w3.org/TR/CSS2/visuren.html#floats
+#[derive(Default, Debug)]
+pub struct FloatContext {
+    floats: Vec<(FloatSide, Rect)>,
+}
+
+impl FloatContext {
+    /// Floats that overlap the horizontal band from `y` down `height` px. A band without height
+    /// overlaps the floats next to its top.
+    fn overlapping(&self, y: f32, height: f32) -> impl Iterator<Item = &(FloatSide, Rect)> {
+        self.floats.iter().filter(move |(_, rect)| {
+            (rect.y < y + height || rect.y <= y) && rect.y + rect.height > y
+        })
+    }
+
+    /// The part of the space between `left` and `right` that floats leave free over a band.
+    pub fn available(&self, y: f32, height: f32, left: f32, right: f32) -> (f32, f32) {
+        self.overlapping(y, height)
+            .fold((left, right), |(left, right), &(side, rect)| match side {
+                FloatSide::Left => (left.max(rect.x + rect.width), right),
+                FloatSide::Right => (left, right.min(rect.x)),
+            })
+    }
+
+    /// The highest float bottom below `y`, which is the next place where more space may open up.
+    pub fn next_bottom(&self, y: f32) -> Option<f32> {
+        self.floats
+            .iter()
+            .map(|(_, rect)| rect.y + rect.height)
+            .filter(|&bottom| bottom > y)
+            .min_by(f32::total_cmp)
+    }
+
+    /// The bottom of the lowest float, if there are any.
+    pub fn bottom(&self) -> Option<f32> {
+        self.floats
+            .iter()
+            .map(|(_, rect)| rect.y + rect.height)
+            .max_by(f32::total_cmp)
+    }
+
+    /// The bottom of the lowest float on the sides that `clear` clears, if there are any.
+    pub fn clearance(&self, clear: Clear) -> Option<f32> {
+        self.floats
+            .iter()
+            .filter(|(side, _)| clear.clears(*side))
+            .map(|(_, rect)| rect.y + rect.height)
+            .max_by(f32::total_cmp)
+    }
+
+    /// Place a float's margin box of the given size as high as it fits, but no higher than `y` or
+    /// any earlier float, and as far to its side as it goes between `left` and `right`. Returns
+    /// the position of the margin box.
+    pub fn place(
+        &mut self,
+        side: FloatSide,
+        width: f32,
+        height: f32,
+        y: f32,
+        left: f32,
+        right: f32,
+    ) -> (f32, f32) {
+        let mut y = self.floats.iter().map(|(_, rect)| rect.y).fold(y, f32::max);
+        let (left, right) = loop {
+            let (free_left, free_right) = self.available(y, height, left, right);
+            // A float wider than the containing block goes where no floats narrow it.
+            let unobstructed = free_left == left && free_right == right;
+            match self.next_bottom(y) {
+                Some(bottom) if free_right - free_left < width && !unobstructed => y = bottom,
+                _ => break (free_left, free_right),
+            }
+        };
+        let x = match side {
+            FloatSide::Left => left,
+            FloatSide::Right => right - width,
+        };
+        self.floats.push((
+            side,
+            Rect {
+                x,
+                y,
+                width,
+                height,
+            },
+        ));
+        (x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::testing::Page;
+    use super::super::LayoutBox;
+    use super::*;
+
+    #[test]
+    fn test_place() {
+        let mut floats = FloatContext::default();
+        assert_eq!(
+            floats.place(FloatSide::Left, 50.0, 20.0, 0.0, 0.0, 100.0),
+            (0.0, 0.0)
+        );
+        assert_eq!(
+            floats.place(FloatSide::Right, 30.0, 40.0, 0.0, 0.0, 100.0),
+            (70.0, 0.0)
+        );
+        assert_eq!(floats.available(10.0, 5.0, 0.0, 100.0), (50.0, 70.0));
+        // Too wide for the space between the first two, so it goes below the left one.
+        assert_eq!(
+            floats.place(FloatSide::Left, 40.0, 10.0, 0.0, 0.0, 100.0),
+            (0.0, 20.0)
+        );
+        // Never above an earlier float.
+        assert_eq!(
+            floats.place(FloatSide::Left, 10.0, 10.0, 0.0, 0.0, 100.0),
+            (40.0, 20.0)
+        );
+        assert_eq!(floats.clearance(Clear::Left), Some(30.0));
+        assert_eq!(floats.clearance(Clear::Both), Some(40.0));
+        assert_eq!(floats.available(40.0, 10.0, 0.0, 100.0), (0.0, 100.0));
+    }
+
+    /// The top of the border box of the box at `path` from the root, in a document laid out with
+    /// every element a block.
+    fn top(html: &str, css: &str, path: &[usize]) -> f32 {
+        let mut top = 0.0;
+        let page = Page {
+            html,
+            css,
+            user_agent: false,
+            ..Default::default()
+        };
+        page.layout(|root| {
+            let layout_box = path
+                .iter()
+                .fold(&*root, |parent: &LayoutBox, &i| &parent.children[i]);
+            top = layout_box.dimensions.border_box().y;
+        });
+        top
+    }
+
+    #[test]
+    fn test_clearance_across_formatting_contexts() {
+        let css =
+            ".f { float: left; width: 50px; height: 30px; } .c { clear: both; height: 10px; } \
+                   .bfc { overflow: hidden; }";
+        // Clearance reaches floats in the same formatting context through plain blocks.
+        let html = "<div><p class=\"f\"></p><p><p class=\"c\"></p></p></div>";
+        assert_eq!(top(html, css, &[1, 0]), 30.0);
+        // A new formatting context is not cleared past the floats outside it.
+        let html = "<div><p class=\"f\"></p><p class=\"bfc\"><p class=\"c\"></p></p></div>";
+        assert_eq!(top(html, css, &[1, 0]), 0.0);
+        // Floats inside a formatting context are not cleared from outside it, even when they
+        // overflow it.
+        let html = "<div><p class=\"bfc\"><p class=\"f\"></p></p><p class=\"c\"></p></div>";
+        let short = format!("{} .bfc {{ height: 10px; }}", css);
+        assert_eq!(top(html, &short, &[1]), 10.0);
+    }
+}
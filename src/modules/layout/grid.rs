@@ -0,0 +1,1339 @@
+use super::super::css::Value::{self, Keyword, Sequence};
+use super::super::font::FontDatabase;
+use super::super::style::StyledNode;
+use super::align::{gaps, Align};
+use super::{content_size, inline, order, sum_edges, BoxType, Dimensions, LayoutBox};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// Whether a box lays out its children as grid items.
+pub fn is_container(style: &StyledNode) -> bool {
+    matches!(style.value("display"), Some(Keyword(keyword)) if keyword == "grid" || keyword == "inline-grid")
+}
+
+/// A part of the value of a grid property: line names in brackets, a function with the text of
+/// its arguments, or a word like `1fr`, `span` or `/`.
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Names(Vec<String>),
+    Function(String, String),
+    Word(String),
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() || c == ',' => {
+                chars.next();
+            }
+            '[' => {
+                chars.next();
+                let names: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                tokens.push(Token::Names(
+                    names.split_whitespace().map(str::to_string).collect(),
+                ));
+            }
+            '/' => {
+                chars.next();
+                tokens.push(Token::Word("/".to_string()));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '[' | '(' | ',' | '/') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                if chars.peek() != Some(&'(') {
+                    tokens.push(Token::Word(word));
+                    continue;
+                }
+                chars.next();
+                let mut depth = 0;
+                let mut arguments = String::new();
+                for c in chars.by_ref() {
+                    match c {
+                        '(' => depth += 1,
+                        ')' if depth == 0 => break,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    arguments.push(c);
+                }
+                tokens.push(Token::Function(word, arguments));
+            }
+        }
+    }
+    tokens
+}
+
+/// Split the arguments of a function at the commas outside nested functions.
+fn split_arguments(text: &str) -> Vec<String> {
+    let mut arguments = vec![String::new()];
+    let mut depth = 0;
+    for c in text.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                arguments.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        arguments.last_mut().unwrap().push(c);
+    }
+    arguments
+        .iter()
+        .map(|argument| argument.trim().to_string())
+        .collect()
+}
+
+/// One of the two sizing functions of a track.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Sizing {
+    Fixed(f32),
+    Percent(f32),
+    /// A share of the free space, only as a maximum.
+    Fr(f32),
+    MinContent,
+    MaxContent,
+    Auto,
+    /// The max-content size, but no more than the given size, only as a maximum.
+    FitContent(f32),
+}
+
+impl Sizing {
+    fn parse(word: &str) -> Option<Sizing> {
+        match word {
+            "auto" => Some(Sizing::Auto),
+            "min-content" => Some(Sizing::MinContent),
+            "max-content" => Some(Sizing::MaxContent),
+            _ => {
+                let split = word
+                    .find(|c: char| !matches!(c, '0'..='9' | '.' | '-' | '+'))
+                    .unwrap_or(word.len());
+                let number: f32 = word[..split].parse().ok()?;
+                match &word[split..] {
+                    // A unitless number can only be zero.
+                    "px" | "" => Some(Sizing::Fixed(number)),
+                    "%" => Some(Sizing::Percent(number)),
+                    "fr" => Some(Sizing::Fr(number.max(0.0))),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// The size in px, if it does not depend on the content, with percentages of `available`.
+    fn definite(self, available: Option<f32>) -> Option<f32> {
+        match self {
+            Sizing::Fixed(size) => Some(size),
+            Sizing::Percent(percentage) => {
+                available.map(|available| percentage / 100.0 * available)
+            }
+            _ => None,
+        }
+    }
+
+    fn is_intrinsic(self) -> bool {
+        matches!(
+            self,
+            Sizing::MinContent | Sizing::MaxContent | Sizing::Auto | Sizing::FitContent(_)
+        )
+    }
+}
+
+/// The minimum and maximum sizing functions of a track.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TrackSize {
+    min: Sizing,
+    max: Sizing,
+}
+
+impl TrackSize {
+    const AUTO: TrackSize = TrackSize {
+        min: Sizing::Auto,
+        max: Sizing::Auto,
+    };
+
+    fn parse(token: &Token) -> Option<TrackSize> {
+        match token {
+            Token::Word(word) => Some(match Sizing::parse(word)? {
+                // `1fr` stands for `minmax(auto, 1fr)`.
+                fr @ Sizing::Fr(_) => TrackSize {
+                    min: Sizing::Auto,
+                    max: fr,
+                },
+                sizing => TrackSize {
+                    min: sizing,
+                    max: sizing,
+                },
+            }),
+            Token::Function(name, arguments) => match (&**name, &*split_arguments(arguments)) {
+                ("minmax", [min, max]) => Some(TrackSize {
+                    min: match Sizing::parse(min)? {
+                        Sizing::Fr(_) => Sizing::Auto,
+                        min => min,
+                    },
+                    max: Sizing::parse(max)?,
+                }),
+                ("fit-content", [limit]) => match Sizing::parse(limit)? {
+                    Sizing::Fixed(limit) => Some(TrackSize {
+                        min: Sizing::Auto,
+                        max: Sizing::FitContent(limit),
+                    }),
+                    _ => None,
+                },
+                _ => None,
+            },
+            Token::Names(_) => None,
+        }
+    }
+}
+
+/// A part of a track list, with `repeat()` by a number of times already expanded.
+#[derive(Clone, Debug)]
+enum Entry {
+    Names(Vec<String>),
+    Track(TrackSize),
+    AutoRepeat { fit: bool, entries: Vec<Entry> },
+}
+
+fn parse_entries(text: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for token in tokenize(text) {
+        match token {
+            Token::Names(names) => entries.push(Entry::Names(names)),
+            Token::Function(ref name, ref arguments) if name == "repeat" => {
+                let arguments = split_arguments(arguments);
+                let (count, repeated) = match arguments.split_first() {
+                    Some(split) => split,
+                    None => continue,
+                };
+                let repeated = parse_entries(&repeated.join(" "));
+                match count.as_str() {
+                    "auto-fill" | "auto-fit" => entries.push(Entry::AutoRepeat {
+                        fit: count == "auto-fit",
+                        entries: repeated,
+                    }),
+                    count => {
+                        for _ in 0..count.parse::<usize>().unwrap_or(0) {
+                            entries.extend(repeated.iter().cloned());
+                        }
+                    }
+                }
+            }
+            token => entries.extend(TrackSize::parse(&token).map(Entry::Track)),
+        }
+    }
+    entries
+}
+
+/// The tracks of the explicit grid along one axis, with the names of the lines before each track
+/// and after the last.
+#[derive(Debug, Default)]
+struct Tracks {
+    sizes: Vec<TrackSize>,
+    names: Vec<Vec<String>>,
+    /// The tracks repeated by `auto-fit`, which collapse when no item is placed in them.
+    auto_fit: Option<Range<usize>>,
+}
+
+impl Tracks {
+    /// The tracks of a `grid-template-columns` or `grid-template-rows` value, with `auto-fill`
+    /// and `auto-fit` repeated as often as they fit in `available` space, or once if it is not
+    /// known.
+    ///
+    /// https://www.w3.org/TR/css-grid-1/#auto-repeat
+    fn of(value: Option<Value>, available: Option<f32>, gap: f32) -> Tracks {
+        let entries = match value {
+            Some(Keyword(keyword)) if keyword == "none" => Vec::new(),
+            Some(value) => parse_entries(&value.to_string()),
+            None => Vec::new(),
+        };
+        // Tracks count at their maximum if it is fixed, and their minimum otherwise.
+        let outer = |entries: &[Entry]| -> f32 {
+            entries
+                .iter()
+                .map(|entry| match entry {
+                    Entry::Track(size) => {
+                        let max = size.max.definite(available);
+                        max.or_else(|| size.min.definite(available)).unwrap_or(0.0) + gap
+                    }
+                    _ => 0.0,
+                })
+                .sum()
+        };
+        let repeated = entries.iter().find_map(|entry| match entry {
+            Entry::AutoRepeat { entries, .. } => Some(entries),
+            _ => None,
+        });
+        let count = match (repeated.map(|repeated| outer(repeated)), available) {
+            (Some(repetition), Some(available)) if repetition > 0.0 => {
+                ((available + gap - outer(&entries)) / repetition)
+                    .floor()
+                    .max(1.0) as usize
+            }
+            _ => 1,
+        };
+
+        let mut tracks = Tracks {
+            names: vec![Vec::new()],
+            ..Default::default()
+        };
+        tracks.extend(&entries, count);
+        tracks
+    }
+
+    fn extend(&mut self, entries: &[Entry], count: usize) {
+        for entry in entries {
+            match entry {
+                Entry::Names(names) => self.names.last_mut().unwrap().extend_from_slice(names),
+                Entry::Track(size) => {
+                    self.sizes.push(*size);
+                    self.names.push(Vec::new());
+                }
+                Entry::AutoRepeat { fit, entries } => {
+                    let start = self.sizes.len();
+                    for _ in 0..count {
+                        self.extend(entries, count);
+                    }
+                    if *fit {
+                        self.auto_fit = Some(start..self.sizes.len());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The named areas of `grid-template-areas`, with the rows and columns they cover, and the
+/// number of rows and columns of the template.
+#[derive(Debug, Default)]
+struct Areas {
+    named: HashMap<String, (Range<usize>, Range<usize>)>,
+    rows: usize,
+    columns: usize,
+}
+
+impl Areas {
+    fn of(style: &StyledNode) -> Areas {
+        let rows: Vec<String> = match style.value("grid-template-areas") {
+            Some(Value::String(row)) => vec![row],
+            Some(Sequence(values)) => values
+                .into_iter()
+                .filter_map(|value| match value {
+                    Value::String(row) => Some(row),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let mut areas = Areas {
+            rows: rows.len(),
+            ..Default::default()
+        };
+        for (row, cells) in rows.iter().enumerate() {
+            let cells: Vec<&str> = cells.split_whitespace().collect();
+            areas.columns = areas.columns.max(cells.len());
+            for (column, name) in cells.into_iter().enumerate() {
+                // Runs of dots are cells outside any area.
+                if name.chars().all(|c| c == '.') {
+                    continue;
+                }
+                let (rows, columns) = areas
+                    .named
+                    .entry(name.to_string())
+                    .or_insert((row..row + 1, column..column + 1));
+                rows.end = rows.end.max(row + 1);
+                columns.end = columns.end.max(column + 1);
+            }
+        }
+        areas
+    }
+
+    /// The names of the lines of the explicit grid along an axis, given the names from the track
+    /// list, with the `-start` and `-end` lines of the areas added.
+    fn line_names(&self, mut names: Vec<Vec<String>>, row: bool) -> Vec<Vec<String>> {
+        let count = if row { self.rows } else { self.columns };
+        if names.len() < count + 1 {
+            names.resize(count + 1, Vec::new());
+        }
+        for (name, (rows, columns)) in &self.named {
+            let range = if row { rows } else { columns };
+            names[range.start].push(format!("{}-start", name));
+            names[range.end].push(format!("{}-end", name));
+        }
+        names
+    }
+}
+
+/// A value of `grid-row-start`, `grid-column-end` and the like.
+#[derive(Clone, Debug, PartialEq)]
+enum GridLine {
+    Auto,
+    /// A line by number, counting from the end of the explicit grid if negative.
+    Line(i32),
+    /// A line by name, and which of the lines with the name, if given.
+    Named(String, Option<i32>),
+    Span(usize),
+}
+
+impl GridLine {
+    fn parse(tokens: &[Token]) -> GridLine {
+        let words: Vec<&str> = tokens
+            .iter()
+            .filter_map(|token| match token {
+                Token::Word(word) => Some(word.as_str()),
+                _ => None,
+            })
+            .collect();
+        let integer = words.iter().find_map(|word| word.parse::<i32>().ok());
+        let name = words
+            .iter()
+            .find(|word| word.parse::<i32>().is_err() && !matches!(**word, "span" | "auto"));
+        if words.contains(&"span") {
+            // Spans to a line by name are treated as spans of one track.
+            return GridLine::Span(integer.unwrap_or(1).max(1) as usize);
+        }
+        match (integer, name) {
+            (Some(0), None) | (None, None) => GridLine::Auto,
+            (Some(line), None) => GridLine::Line(line),
+            (nth, Some(name)) => GridLine::Named(name.to_string(), nth),
+        }
+    }
+
+    fn is_custom_ident(tokens: &[Token]) -> bool {
+        matches!(GridLine::parse(tokens), GridLine::Named(_, None))
+    }
+
+    /// The index of the line among the lines of the explicit grid, counting from zero, given
+    /// their names and whether this is a start or end line, or `None` for `auto`, spans and
+    /// names no line has.
+    ///
+    /// https://www.w3.org/TR/css-grid-1/#line-placement
+    fn resolve(&self, names: &[Vec<String>], side: &str) -> Option<i32> {
+        let named = |name: &str| -> Vec<i32> {
+            (0..names.len())
+                .filter(|&line| names[line].iter().any(|line_name| line_name == name))
+                .map(|line| line as i32)
+                .collect()
+        };
+        match self {
+            GridLine::Line(line) if *line > 0 => Some(line - 1),
+            GridLine::Line(line) => Some(names.len() as i32 + line),
+            // A lone name refers to the start or end line of an area of that name first.
+            GridLine::Named(name, None) => named(&format!("{}-{}", name, side))
+                .first()
+                .or(named(name).first())
+                .copied(),
+            GridLine::Named(name, Some(nth)) => {
+                let lines = named(name);
+                let index = if *nth > 0 {
+                    nth - 1
+                } else {
+                    lines.len() as i32 + nth
+                };
+                usize::try_from(index)
+                    .ok()
+                    .and_then(|index| lines.get(index))
+                    .copied()
+            }
+            GridLine::Auto | GridLine::Span(_) => None,
+        }
+    }
+}
+
+/// The start and end lines of an item along an axis, `"row"` or `"column"`, from the longhands
+/// or the `grid-row`, `grid-column` and `grid-area` shorthands. A missing line that would pair
+/// with a line name is that name too, as in `grid-area: header`.
+fn item_lines(style: &StyledNode, axis: &str) -> (GridLine, GridLine) {
+    let parts = |name: &str| -> Option<Vec<Vec<Token>>> {
+        let tokens = tokenize(&style.value(name)?.to_string());
+        Some(
+            tokens
+                .split(|token| *token == Token::Word("/".to_string()))
+                .map(<[Token]>::to_vec)
+                .collect(),
+        )
+    };
+    let (mut start, mut end) = (None, None);
+    if let Some(area) = parts("grid-area") {
+        let (start_index, end_index) = if axis == "row" { (0, 2) } else { (1, 3) };
+        start = area.get(start_index).cloned().or_else(|| {
+            area.first()
+                .filter(|row| GridLine::is_custom_ident(row))
+                .cloned()
+        });
+        end = area.get(end_index).cloned();
+    }
+    if let Some(lines) = parts(&format!("grid-{}", axis)) {
+        start = lines.first().cloned();
+        end = lines.get(1).cloned();
+    }
+    let longhand = |side| {
+        let value = style.value(&format!("grid-{}-{}", axis, side))?;
+        Some(tokenize(&value.to_string()))
+    };
+    let start = longhand("start").or(start).unwrap_or_default();
+    let end = longhand("end").or(end).unwrap_or_else(|| {
+        if GridLine::is_custom_ident(&start) {
+            start.clone()
+        } else {
+            Vec::new()
+        }
+    });
+    (GridLine::parse(&start), GridLine::parse(&end))
+}
+
+/// The start track of an item along an axis if its position is definite, and the number of
+/// tracks it spans. Lines before the start of the explicit grid are taken as its start.
+fn resolve_placement((start, end): &(GridLine, GridLine), names: &[Vec<String>]) -> Placement {
+    let span = |line: &GridLine| match line {
+        GridLine::Span(span) => *span,
+        _ => 1,
+    };
+    match (start.resolve(names, "start"), end.resolve(names, "end")) {
+        (Some(start), Some(end)) => {
+            let (start, end) = (start.min(end), start.max(end));
+            (Some(start.max(0) as usize), (end - start).max(1) as usize)
+        }
+        (Some(start), None) => (Some(start.max(0) as usize), span(end)),
+        (None, Some(end)) => (
+            Some((end - span(start) as i32).max(0) as usize),
+            span(start),
+        ),
+        (None, None) if matches!(start, GridLine::Span(_)) => (None, span(start)),
+        (None, None) => (None, span(end)),
+    }
+}
+
+/// A definite start track or `None`, and a number of tracks.
+type Placement = (Option<usize>, usize);
+
+/// The grid cells taken by items placed so far.
+#[derive(Default)]
+struct Occupied(HashSet<(usize, usize)>);
+
+impl Occupied {
+    fn fits(&self, major: &Range<usize>, minor: &Range<usize>) -> bool {
+        major
+            .clone()
+            .all(|i| minor.clone().all(|j| !self.0.contains(&(i, j))))
+    }
+
+    fn occupy(&mut self, major: Range<usize>, minor: Range<usize>) -> (Range<usize>, Range<usize>) {
+        for i in major.clone() {
+            for j in minor.clone() {
+                self.0.insert((i, j));
+            }
+        }
+        (major, minor)
+    }
+}
+
+/// Place items in the grid, each given by its placement along the axis `grid-auto-flow` moves
+/// between tracks of, rows for `row`, and its placement along the axis it fills tracks along,
+/// which has `explicit_minor` tracks in the explicit grid. Items with definite positions go first,
+/// then the rest go in the first cells they fit in after the item before them, or from the start
+/// of the grid with `dense`.
+///
+/// https://www.w3.org/TR/css-grid-1/#auto-placement-algo
+fn auto_place(
+    items: &[(Placement, Placement)],
+    explicit_minor: usize,
+    dense: bool,
+) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut occupied = Occupied::default();
+    let mut placed = vec![None; items.len()];
+    for (i, &((major, major_span), (minor, minor_span))) in items.iter().enumerate() {
+        if let (Some(major), Some(minor)) = (major, minor) {
+            placed[i] = Some(occupied.occupy(major..major + major_span, minor..minor + minor_span));
+        }
+    }
+
+    // Items locked to a track go in the first cells they fit in along it.
+    let mut minor_count = items
+        .iter()
+        .map(|&(_, (minor, span))| minor.unwrap_or(0) + span)
+        .fold(explicit_minor, usize::max);
+    let mut cursors = HashMap::new();
+    for (i, &((major, major_span), (minor, minor_span))) in items.iter().enumerate() {
+        let (Some(major), None) = (major, minor) else {
+            continue;
+        };
+        let major_range = major..major + major_span;
+        let mut minor = if dense {
+            0
+        } else {
+            cursors.get(&major).copied().unwrap_or(0)
+        };
+        while !occupied.fits(&major_range, &(minor..minor + minor_span)) {
+            minor += 1;
+        }
+        cursors.insert(major, minor + minor_span);
+        minor_count = minor_count.max(minor + minor_span);
+        placed[i] = Some(occupied.occupy(major_range, minor..minor + minor_span));
+    }
+
+    // The rest follow the cursor, adding tracks as needed.
+    let mut cursor = (0, 0);
+    for (i, &((major, major_span), (minor, minor_span))) in items.iter().enumerate() {
+        if major.is_some() {
+            continue;
+        }
+        if dense {
+            cursor = (0, 0);
+        }
+        cursor = match minor {
+            Some(minor) => {
+                let mut major = cursor.0;
+                if minor < cursor.1 {
+                    major += 1;
+                }
+                while !occupied.fits(&(major..major + major_span), &(minor..minor + minor_span)) {
+                    major += 1;
+                }
+                (major, minor)
+            }
+            None => loop {
+                let (major, minor) = cursor;
+                if minor + minor_span > minor_count {
+                    cursor = (major + 1, 0);
+                } else if occupied.fits(&(major..major + major_span), &(minor..minor + minor_span))
+                {
+                    break cursor;
+                } else {
+                    cursor.1 += 1;
+                }
+            },
+        };
+        let (major, minor) = cursor;
+        placed[i] = Some(occupied.occupy(major..major + major_span, minor..minor + minor_span));
+    }
+    placed.into_iter().map(Option::unwrap).collect()
+}
+
+/// A track being sized, with its base size and growth limit.
+#[derive(Clone, Copy, Debug)]
+struct Track {
+    size: TrackSize,
+    base: f32,
+    limit: f32,
+}
+
+impl Track {
+    fn flex(&self) -> Option<f32> {
+        match self.size.max {
+            Sizing::Fr(factor) => Some(factor),
+            _ => None,
+        }
+    }
+
+    fn is_flexible(&self) -> bool {
+        self.flex().is_some()
+    }
+}
+
+/// Grow the base sizes of the tracks `pick` selects evenly, so that the tracks add up to `size`.
+fn grow_bases(tracks: &mut [Track], size: f32, pick: impl Fn(&Track) -> bool) {
+    let picked = tracks.iter().filter(|track| pick(track)).count();
+    let needed = size - tracks.iter().map(|track| track.base).sum::<f32>();
+    if picked == 0 || needed <= 0.0 {
+        return;
+    }
+    for track in tracks.iter_mut().filter(|track| pick(track)) {
+        track.base += needed / picked as f32;
+    }
+}
+
+/// Grow the growth limits of the tracks with intrinsic maximums evenly, so that the tracks add up
+/// to `size`. Tracks without a growth limit yet count at their base size.
+fn grow_limits(tracks: &mut [Track], size: f32) {
+    let current = |track: &Track| {
+        if track.limit.is_finite() {
+            track.limit
+        } else {
+            track.base
+        }
+    };
+    let picked = tracks
+        .iter()
+        .filter(|track| track.size.max.is_intrinsic())
+        .count();
+    let needed = (size - tracks.iter().map(current).sum::<f32>()).max(0.0);
+    for track in tracks
+        .iter_mut()
+        .filter(|track| track.size.max.is_intrinsic())
+    {
+        let mut limit = current(track) + needed / picked as f32;
+        if let Sizing::FitContent(max) = track.size.max {
+            limit = limit.min(max.max(track.base));
+        }
+        track.limit = limit;
+    }
+}
+
+/// The size of `1fr` with which flexible tracks fill `space` along with the rest, where flexible
+/// tracks that would be smaller than their base size are treated as inflexible.
+///
+/// https://www.w3.org/TR/css-grid-1/#algo-find-fr-size
+fn fr_size(tracks: &[Track], space: f32) -> f32 {
+    let mut inflexible: Vec<bool> = tracks.iter().map(|track| !track.is_flexible()).collect();
+    loop {
+        let mut leftover = space;
+        let mut factors = 0.0;
+        for (track, &inflexible) in tracks.iter().zip(&inflexible) {
+            match track.flex() {
+                Some(factor) if !inflexible => factors += factor,
+                _ => leftover -= track.base,
+            }
+        }
+        // Factors adding up to less than one only take that share of the space.
+        let fr = leftover.max(0.0) / f32::max(factors, 1.0);
+        let mut changed = false;
+        for (track, inflexible) in tracks.iter().zip(&mut inflexible) {
+            if !*inflexible && fr * track.flex().unwrap_or(0.0) < track.base {
+                *inflexible = true;
+                changed = true;
+            }
+        }
+        if !changed {
+            return fr;
+        }
+    }
+}
+
+/// Size the tracks along one axis to fit the outer sizes of the items in them, given as the
+/// tracks each item spans with its min-content and max-content contributions, in `available`
+/// space if it is known. With `stretch`, `auto` tracks share out the space left over.
+///
+/// https://www.w3.org/TR/css-grid-1/#algo-track-sizing
+fn size_tracks(
+    sizes: &[TrackSize],
+    available: Option<f32>,
+    gap: f32,
+    contributions: &[(Range<usize>, f32, f32)],
+    stretch: bool,
+) -> Vec<f32> {
+    // Percentages of an unknown size behave as `auto`.
+    let resolve = |sizing| match sizing {
+        Sizing::Percent(_) if available.is_none() => Sizing::Auto,
+        sizing => sizing,
+    };
+    let mut tracks: Vec<Track> = sizes
+        .iter()
+        .map(|size| {
+            let size = TrackSize {
+                min: resolve(size.min),
+                max: resolve(size.max),
+            };
+            let base = size.min.definite(available).unwrap_or(0.0);
+            let limit = size
+                .max
+                .definite(available)
+                .map_or(f32::INFINITY, |limit| limit.max(base));
+            Track { size, base, limit }
+        })
+        .collect();
+    let gaps = |count: usize| gap * count.saturating_sub(1) as f32;
+    let spans_flexible = |tracks: &[Track]| tracks.iter().any(Track::is_flexible);
+
+    // Fit the intrinsic tracks to the items that span no flexible tracks, the items that span
+    // fewer tracks first, and then the minimums of flexible tracks to the rest.
+    let mut contributions: Vec<_> = contributions.iter().collect();
+    contributions.sort_by_key(|(range, ..)| range.len());
+    for (range, min, max) in &contributions {
+        let spanned = &mut tracks[range.clone()];
+        let room = gaps(range.len());
+        if spans_flexible(spanned) {
+            continue;
+        }
+        let all_max_content = spanned
+            .iter()
+            .all(|track| track.size.min == Sizing::MaxContent);
+        let size = if all_max_content { max } else { min };
+        grow_bases(spanned, size - room, |track| track.size.min.is_intrinsic());
+        let all_min_content = spanned
+            .iter()
+            .all(|track| track.size.max == Sizing::MinContent);
+        grow_limits(spanned, if all_min_content { min } else { max } - room);
+    }
+    for (range, min, _) in &contributions {
+        let spanned = &mut tracks[range.clone()];
+        if spans_flexible(spanned) {
+            grow_bases(spanned, min - gaps(range.len()), |track| {
+                track.is_flexible() && track.size.min.is_intrinsic()
+            });
+        }
+    }
+    for track in &mut tracks {
+        if track.limit.is_infinite() || track.limit < track.base {
+            track.limit = track.base;
+        }
+    }
+
+    // Grow the tracks up to their limits, into the free space if it is known.
+    let total = |tracks: &[Track]| tracks.iter().map(|track| track.base).sum::<f32>();
+    let gaps_total = gaps(tracks.len());
+    match available {
+        Some(available) => {
+            let mut free = available - gaps_total - total(&tracks);
+            loop {
+                let growing = tracks
+                    .iter()
+                    .filter(|track| track.base < track.limit)
+                    .count();
+                if free < 0.01 || growing == 0 {
+                    break;
+                }
+                let share = free / growing as f32;
+                for track in tracks.iter_mut().filter(|track| track.base < track.limit) {
+                    let grow = share.min(track.limit - track.base);
+                    track.base += grow;
+                    free -= grow;
+                }
+            }
+        }
+        None => {
+            for track in &mut tracks {
+                track.base = track.limit;
+            }
+        }
+    }
+
+    // Flexible tracks share out the rest, or fit their items if the free space is not known.
+    if spans_flexible(&tracks) {
+        let fr = match available {
+            Some(available) => fr_size(&tracks, available - gaps_total),
+            None => {
+                let tracks_fr = tracks
+                    .iter()
+                    .filter_map(|track| Some(track.base / track.flex()?.max(1.0)));
+                let items_fr = contributions
+                    .iter()
+                    .filter(|(range, ..)| spans_flexible(&tracks[range.clone()]))
+                    .map(|(range, _, max)| {
+                        fr_size(&tracks[range.clone()], max - gaps(range.len()))
+                    });
+                tracks_fr.chain(items_fr).fold(0.0, f32::max)
+            }
+        };
+        for track in &mut tracks {
+            if let Some(factor) = track.flex() {
+                track.base = track.base.max(fr * factor);
+            }
+        }
+    }
+
+    if let (true, Some(available)) = (stretch, available) {
+        let free = available - gaps_total - total(&tracks);
+        let auto = tracks
+            .iter()
+            .filter(|track| track.size.max == Sizing::Auto)
+            .count();
+        if free > 0.0 && auto > 0 {
+            for track in tracks
+                .iter_mut()
+                .filter(|track| track.size.max == Sizing::Auto)
+            {
+                track.base += free / auto as f32;
+            }
+        }
+    }
+    tracks.into_iter().map(|track| track.base).collect()
+}
+
+/// The size of a run of tracks with the gaps between them.
+fn span_size(sizes: &[f32], range: Range<usize>, gap: f32) -> f32 {
+    gap * range.len().saturating_sub(1) as f32 + sizes[range].iter().sum::<f32>()
+}
+
+/// The offsets of the tracks from the start of the content box, `gap` apart after `start`.
+fn offsets(sizes: &[f32], gap: f32, start: f32) -> Vec<f32> {
+    let mut offset = start;
+    sizes
+        .iter()
+        .map(|size| {
+            let track = offset;
+            offset += size + gap;
+            track
+        })
+        .collect()
+}
+
+/// An item placed in the grid, by its index among the children of the container.
+#[derive(Clone, Debug)]
+struct GridItem {
+    index: usize,
+    row: Range<usize>,
+    column: Range<usize>,
+}
+
+/// The tracks of a grid along both axes, and its items.
+struct Grid {
+    rows: Vec<TrackSize>,
+    columns: Vec<TrackSize>,
+    items: Vec<GridItem>,
+}
+
+/// All the tracks along an axis: the `explicit_count` tracks of the explicit grid, then the
+/// implicit tracks the items need, sized by `grid-auto-rows` or `grid-auto-columns`, less the
+/// `auto-fit` tracks without items.
+fn axis_tracks(
+    explicit: &Tracks,
+    explicit_count: usize,
+    auto: Option<Value>,
+    items: &mut [GridItem],
+    row: bool,
+) -> Vec<TrackSize> {
+    fn range(item: &mut GridItem, row: bool) -> &mut Range<usize> {
+        if row {
+            &mut item.row
+        } else {
+            &mut item.column
+        }
+    }
+    let auto = Tracks::of(auto, None, 0.0).sizes;
+    let count = items
+        .iter_mut()
+        .map(|item| range(item, row).end)
+        .fold(explicit_count, usize::max);
+    let mut sizes: Vec<TrackSize> = (0..count)
+        .map(|track| match explicit.sizes.get(track) {
+            Some(size) => *size,
+            None if auto.is_empty() => TrackSize::AUTO,
+            None => auto[(track - explicit.sizes.len()) % auto.len()],
+        })
+        .collect();
+    if let Some(auto_fit) = &explicit.auto_fit {
+        for track in auto_fit.clone().rev() {
+            if items
+                .iter_mut()
+                .any(|item| range(item, row).contains(&track))
+            {
+                continue;
+            }
+            sizes.remove(track);
+            for item in items.iter_mut() {
+                let range = range(item, row);
+                if range.start > track {
+                    range.start -= 1;
+                    range.end -= 1;
+                }
+            }
+        }
+    }
+    sizes
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Lay out the children of a grid container as grid items: placed in the grid, with the
+    /// tracks sized to fit them, and aligned in their grid areas. `height` is the content height
+    /// of the container if it is known up front; otherwise it is set to fit the rows.
+    ///
+    /// Items are measured on copies, since laying out inline content replaces it with lines.
+    ///
+    /// https://www.w3.org/TR/css-grid-1/#layout-algorithm
+    pub(super) fn layout_grid_children(&mut self, height: Option<f32>, fonts: &FontDatabase) {
+        let style = self.get_style_node();
+        // Items are placed, and painted, in their `order`.
+        self.children.sort_by_key(order);
+        let pristine = self.children.clone();
+        let d = self.dimensions;
+        let width = d.content.width;
+        let (row_gap, column_gap) = gaps(style, width, height);
+        let grid = self.grid(Some(width), height, (row_gap, column_gap));
+        for child in &mut self.children {
+            if child.is_absolutely_positioned() {
+                // Absolutely positioned children start at the start of the content box.
+                child.dimensions = Dimensions::default();
+                child.dimensions.content.x = d.content.x;
+                child.dimensions.content.y = d.content.y;
+            }
+        }
+        let justify_content = Align::of(style, "justify-content");
+        let align_content = Align::of(style, "align-content");
+        let stretch = |align: Option<Align>| align.is_none_or(|align| align == Align::Stretch);
+
+        // Size the columns to fit the widths of the items.
+        let contributions: Vec<_> = grid
+            .items
+            .iter()
+            .map(|item| {
+                let (min, max) = self.children[item.index].grid_item_widths(style, fonts);
+                (item.column.clone(), min, max)
+            })
+            .collect();
+        let columns = size_tracks(
+            &grid.columns,
+            Some(width),
+            column_gap,
+            &contributions,
+            stretch(justify_content),
+        );
+        let free = width - span_size(&columns, 0..columns.len(), column_gap);
+        let (column_start, between) = justify_content
+            .unwrap_or_default()
+            .distribute(free, columns.len());
+        let column_gap = column_gap + between;
+        let column_offsets = offsets(&columns, column_gap, column_start);
+
+        // Size the rows to fit the heights of the items, laid out at the widths of their areas.
+        let justify_items = Align::of(style, "justify-items");
+        let mut widths = Vec::new();
+        let mut contributions = Vec::new();
+        for item in &grid.items {
+            let child = &mut self.children[item.index];
+            let mut area = Dimensions::default();
+            area.content.width = span_size(&columns, item.column.clone(), column_gap);
+            let justify = self_alignment(child, "justify-self")
+                .or(justify_items)
+                .unwrap_or_default();
+            let item_width = child.grid_item_width(area, justify, style, fonts);
+            let mut probe = pristine[item.index].clone();
+            probe.layout_item(area, None, item_width, None, style, fonts);
+            let outer_height = probe.dimensions.margin_box().height;
+            contributions.push((item.row.clone(), outer_height, outer_height));
+            widths.push((item_width, justify));
+        }
+        let rows = size_tracks(
+            &grid.rows,
+            height,
+            row_gap,
+            &contributions,
+            stretch(align_content),
+        );
+        let content_height = height.unwrap_or_else(|| span_size(&rows, 0..rows.len(), row_gap));
+        let free = content_height - span_size(&rows, 0..rows.len(), row_gap);
+        let (row_start, between) = align_content
+            .unwrap_or_default()
+            .distribute(free, rows.len());
+        let row_gap = row_gap + between;
+        let row_offsets = offsets(&rows, row_gap, row_start);
+
+        // Lay the items out in their areas, and align them there.
+        let align_items = Align::of(style, "align-items");
+        for (item, (item_width, justify)) in grid.items.iter().zip(widths) {
+            let child = &mut self.children[item.index];
+            *child = pristine[item.index].clone();
+            let mut area = Dimensions::default();
+            area.content.x = d.content.x + column_offsets[item.column.start];
+            area.content.y = d.content.y + row_offsets[item.row.start];
+            area.content.width = span_size(&columns, item.column.clone(), column_gap);
+            let area_height = span_size(&rows, item.row.clone(), row_gap);
+            let align = self_alignment(child, "align-self")
+                .or(align_items)
+                .unwrap_or_default();
+            let item_height = child.grid_item_height(area, area_height, align);
+            child.layout_item(
+                area,
+                Some(area_height),
+                item_width,
+                item_height,
+                style,
+                fonts,
+            );
+
+            let margin_box = child.dimensions.margin_box();
+            let offset = |align, free| match align {
+                Align::End => free,
+                Align::Center => free / 2.0,
+                _ => 0.0,
+            };
+            child.translate(
+                area.content.x + offset(justify, area.content.width - margin_box.width)
+                    - margin_box.x,
+                area.content.y + offset(align, area_height - margin_box.height) - margin_box.y,
+            );
+            child.offset_relative(area, Some(area_height));
+        }
+        self.dimensions.content.height = content_height;
+    }
+
+    /// Set up the grid of a grid container: its explicit tracks, with `auto-fill` and `auto-fit`
+    /// repeated to fit the content width and height if they are known, and its items placed in
+    /// it, with the implicit tracks they need.
+    ///
+    /// https://www.w3.org/TR/css-grid-1/#placement
+    fn grid(
+        &self,
+        width: Option<f32>,
+        height: Option<f32>,
+        (row_gap, column_gap): (f32, f32),
+    ) -> Grid {
+        let style = self.get_style_node();
+        let rows = Tracks::of(style.value("grid-template-rows"), height, row_gap);
+        let columns = Tracks::of(style.value("grid-template-columns"), width, column_gap);
+        let areas = Areas::of(style);
+        let row_names = areas.line_names(rows.names.clone(), true);
+        let column_names = areas.line_names(columns.names.clone(), false);
+        let flow = style.value("grid-auto-flow");
+        let flow = flow.as_ref().map(Value::keywords).unwrap_or_default();
+        let by_column = flow.contains(&"column");
+
+        let indices: Vec<usize> = (0..self.children.len())
+            .filter(|&index| !self.children[index].is_absolutely_positioned())
+            .collect();
+        let placements: Vec<(Placement, Placement)> = indices
+            .iter()
+            .map(|&index| {
+                let (row, column) = match self.children[index].box_type {
+                    BoxType::BlockNode(child) => (
+                        resolve_placement(&item_lines(child, "row"), &row_names),
+                        resolve_placement(&item_lines(child, "column"), &column_names),
+                    ),
+                    _ => ((None, 1), (None, 1)),
+                };
+                if by_column {
+                    (column, row)
+                } else {
+                    (row, column)
+                }
+            })
+            .collect();
+        let (explicit_rows, explicit_columns) = (row_names.len() - 1, column_names.len() - 1);
+        let explicit_minor = if by_column {
+            explicit_rows
+        } else {
+            explicit_columns
+        };
+        let mut items: Vec<GridItem> =
+            auto_place(&placements, explicit_minor, flow.contains(&"dense"))
+                .into_iter()
+                .zip(indices)
+                .map(|((major, minor), index)| {
+                    let (row, column) = if by_column {
+                        (minor, major)
+                    } else {
+                        (major, minor)
+                    };
+                    GridItem { index, row, column }
+                })
+                .collect();
+        Grid {
+            rows: axis_tracks(
+                &rows,
+                explicit_rows,
+                style.value("grid-auto-rows"),
+                &mut items,
+                true,
+            ),
+            columns: axis_tracks(
+                &columns,
+                explicit_columns,
+                style.value("grid-auto-columns"),
+                &mut items,
+                false,
+            ),
+            items,
+        }
+    }
+
+    /// The min-content and max-content widths of the margin box of a grid item.
+    fn grid_item_widths(&self, container_style: &StyledNode, fonts: &FontDatabase) -> (f32, f32) {
+        match self.box_type {
+            BoxType::AnonymousBlock => {
+                inline::intrinsic_widths(&self.children, container_style, fonts)
+            }
+            _ => self.outer_intrinsic_widths(fonts),
+        }
+    }
+
+    /// The content width of a grid item in its area: stretched across it if it has no width of
+    /// its own, or shrink-to-fit.
+    fn grid_item_width(
+        &mut self,
+        area: Dimensions,
+        justify: Align,
+        container_style: &StyledNode,
+        fonts: &FontDatabase,
+    ) -> f32 {
+        let style = match self.box_type {
+            BoxType::BlockNode(style) => style,
+            _ if justify == Align::Stretch => return area.content.width,
+            _ => return self.fit_content_width(area, container_style, fonts),
+        };
+        let edges = self.box_sizing_edges(["left", "right"], area);
+        let width = content_size(style.value("width"), Some(area.content.width), edges);
+        if justify != Align::Stretch || width.is_some() {
+            return self.fit_content_width(area, container_style, fonts);
+        }
+        self.solve_float_width(area, Some(0.0), fonts);
+        let edges = sum_edges(&self.dimensions, ["left", "right"]);
+        self.clamp_size(area.content.width - edges, true, area, None)
+    }
+
+    /// The content height of a grid item stretched down its area, or `None` if it fits its
+    /// content or has a height of its own.
+    fn grid_item_height(
+        &mut self,
+        area: Dimensions,
+        area_height: f32,
+        align: Align,
+    ) -> Option<f32> {
+        if align != Align::Stretch {
+            return None;
+        }
+        let style = match self.box_type {
+            BoxType::BlockNode(style) => style,
+            _ => return Some(area_height),
+        };
+        let edges = self.box_sizing_edges(["top", "bottom"], area);
+        if content_size(style.value("height"), Some(area_height), edges).is_some() {
+            return None;
+        }
+        self.calculate_block_position(area);
+        let edges = sum_edges(&self.dimensions, ["top", "bottom"]);
+        Some(self.clamp_size(area_height - edges, false, area, Some(area_height)))
+    }
+
+    /// The narrowest and the widest a grid container can be: with its columns sized to fit the
+    /// min-content widths of its items, and their max-content widths.
+    pub(super) fn grid_intrinsic_widths(&self, fonts: &FontDatabase) -> (f32, f32) {
+        let style = self.get_style_node();
+        let (_, gap) = gaps(style, 0.0, None);
+        let grid = self.grid(None, None, (0.0, gap));
+        let contributions: Vec<_> = grid
+            .items
+            .iter()
+            .map(|item| {
+                let (min, max) = self.children[item.index].grid_item_widths(style, fonts);
+                (item.column.clone(), min, max)
+            })
+            .collect();
+        let min_contributions: Vec<_> = contributions
+            .iter()
+            .map(|(range, min, _)| (range.clone(), *min, *min))
+            .collect();
+        let width = |contributions: &[(Range<usize>, f32, f32)]| {
+            let columns = size_tracks(&grid.columns, None, gap, contributions, false);
+            span_size(&columns, 0..columns.len(), gap)
+        };
+        (width(&min_contributions), width(&contributions))
+    }
+}
+
+/// The `justify-self` or `align-self` of an item, or `None` for `auto` and anonymous items.
+fn self_alignment(item: &LayoutBox, name: &str) -> Option<Align> {
+    match item.box_type {
+        BoxType::BlockNode(style) => Align::of(style, name),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixed(size: f32) -> TrackSize {
+        TrackSize {
+            min: Sizing::Fixed(size),
+            max: Sizing::Fixed(size),
+        }
+    }
+
+    fn fr(factor: f32) -> TrackSize {
+        TrackSize {
+            min: Sizing::Auto,
+            max: Sizing::Fr(factor),
+        }
+    }
+
+    #[test]
+    fn test_track_list() {
+        let value = Keyword("[a] 100px repeat(2, [b] 1fr) minmax(10px, auto) [c]".to_string());
+        let tracks = Tracks::of(Some(value), None, 0.0);
+        assert_eq!(
+            tracks.sizes,
+            [
+                fixed(100.0),
+                fr(1.0),
+                fr(1.0),
+                TrackSize {
+                    min: Sizing::Fixed(10.0),
+                    max: Sizing::Auto
+                },
+            ]
+        );
+        assert_eq!(
+            tracks.names,
+            [vec!["a"], vec!["b"], vec!["b"], vec![], vec!["c"]]
+        );
+
+        // As many repetitions as fit, with the gaps between them.
+        let value = Keyword("20px repeat(auto-fit, minmax(30px, 1fr))".to_string());
+        let tracks = Tracks::of(Some(value), Some(200.0), 10.0);
+        assert_eq!(tracks.sizes.len(), 5);
+        assert_eq!(tracks.auto_fit, Some(1..5));
+    }
+
+    #[test]
+    fn test_auto_place() {
+        // Sparse placement never goes back to fill holes; dense placement does.
+        let items = [
+            ((None, 1), (None, 2)),
+            ((None, 1), (None, 2)),
+            ((None, 1), (None, 1)),
+        ];
+        assert_eq!(
+            auto_place(&items, 3, false),
+            [(0..1, 0..2), (1..2, 0..2), (1..2, 2..3)]
+        );
+        assert_eq!(
+            auto_place(&items, 3, true),
+            [(0..1, 0..2), (1..2, 0..2), (0..1, 2..3)]
+        );
+
+        // Items with definite positions go first, then the ones locked to a row.
+        let items = [
+            ((None, 1), (None, 1)),
+            ((Some(0), 1), (None, 1)),
+            ((Some(0), 1), (Some(0), 1)),
+        ];
+        assert_eq!(
+            auto_place(&items, 2, false),
+            [(1..2, 0..1), (0..1, 1..2), (0..1, 0..1)]
+        );
+    }
+
+    #[test]
+    fn test_size_tracks() {
+        // Flexible tracks share out what the fixed and `auto` tracks leave.
+        let sizes = [fixed(50.0), TrackSize::AUTO, fr(1.0), fr(3.0)];
+        let contributions = [(1..2, 10.0, 30.0)];
+        assert_eq!(
+            size_tracks(&sizes, Some(210.0), 10.0, &contributions, true),
+            [50.0, 30.0, 25.0, 75.0]
+        );
+
+        // Without flexible tracks, `auto` tracks stretch to fill the space.
+        let sizes = [TrackSize::AUTO, TrackSize::AUTO];
+        let contributions = [(0..1, 10.0, 20.0), (1..2, 10.0, 40.0)];
+        assert_eq!(
+            size_tracks(&sizes, Some(100.0), 0.0, &contributions, true),
+            [40.0, 60.0]
+        );
+        assert_eq!(
+            size_tracks(&sizes, Some(100.0), 0.0, &contributions, false),
+            [20.0, 40.0]
+        );
+
+        // Flexible tracks do not shrink below their content, and fit it if the space is unknown.
+        let sizes = [fr(1.0), fr(1.0)];
+        let contributions = [(0..1, 80.0, 80.0), (1..2, 10.0, 20.0)];
+        assert_eq!(
+            size_tracks(&sizes, Some(100.0), 0.0, &contributions, true),
+            [80.0, 20.0]
+        );
+        assert_eq!(
+            size_tracks(&sizes, None, 0.0, &contributions, false),
+            [80.0, 80.0]
+        );
+    }
+}
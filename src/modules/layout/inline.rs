@@ -0,0 +1,1788 @@
+use super::super::css::{Unit, Value};
+use super::super::dom::NodeType;
+use super::super::font::{font_size, FontDatabase, Glyph};
+use super::super::style::StyledNode;
+use super::bidi;
+use super::float::{FloatContext, FloatSide};
+use super::position;
+use super::{flex, grid};
+use super::{BoxType, Dimensions, LayoutBox, TablePart};
+use std::ops::Range;
+use std::{mem, ptr};
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+
+/// Inline content flattened into the order it is laid out in.
+enum InlineItem<'a> {
+    /// The start of an inline element, where its left margin, border and padding go.
+    Start(&'a StyledNode<'a>),
+    /// The end of an inline element, where its right margin, border and padding go.
+    End(&'a StyledNode<'a>),
+    Text(TextPiece<'a>),
+    /// A float or an absolutely positioned box, by its index among those of the inline content.
+    OutOfFlow(usize),
+    /// An atomic inline, like an inline block, by its index among those of the inline content,
+    /// with the width of its margin box once it is laid out and its bidi embedding level.
+    Atomic {
+        index: usize,
+        width: f32,
+        level: u8,
+    },
+}
+
+/// A run of text from one text node, ending at a line break opportunity.
+#[derive(Clone)]
+struct TextPiece<'a> {
+    style: &'a StyledNode<'a>,
+    text: String,
+    glyphs: Vec<Glyph>,
+    width: f32,
+    /// Width of the collapsible white space at the end, which may hang past the end of a line.
+    trailing_space: f32,
+    /// Whether a line may or must end after this piece.
+    break_after: Option<BreakOpportunity>,
+    /// The bidi embedding level of the text, odd for right-to-left text.
+    level: u8,
+}
+
+/// How the `white-space` property treats spaces and newlines.
+#[derive(Clone, Copy, PartialEq)]
+enum WhiteSpace {
+    Normal,
+    NoWrap,
+    Pre,
+    PreWrap,
+    PreLine,
+}
+
+impl WhiteSpace {
+    fn of(style: &StyledNode) -> WhiteSpace {
+        match style.value("white-space") {
+            Some(Value::Keyword(keyword)) => match &*keyword {
+                "nowrap" => WhiteSpace::NoWrap,
+                "pre" => WhiteSpace::Pre,
+                "pre-wrap" => WhiteSpace::PreWrap,
+                "pre-line" => WhiteSpace::PreLine,
+                _ => WhiteSpace::Normal,
+            },
+            _ => WhiteSpace::Normal,
+        }
+    }
+
+    fn collapses_spaces(self) -> bool {
+        matches!(
+            self,
+            WhiteSpace::Normal | WhiteSpace::NoWrap | WhiteSpace::PreLine
+        )
+    }
+
+    fn preserves_newlines(self) -> bool {
+        matches!(
+            self,
+            WhiteSpace::Pre | WhiteSpace::PreWrap | WhiteSpace::PreLine
+        )
+    }
+
+    fn wraps(self) -> bool {
+        !matches!(self, WhiteSpace::NoWrap | WhiteSpace::Pre)
+    }
+}
+
+/// Space an inline box takes above and below the baseline of its line: its font's ascent and
+/// descent, each grown by half the leading `line-height` adds on top of them.
+///
+/// http://www.w3.org/TR/CSS2/visudet.html#leading
+fn inline_box_extent(style: &StyledNode, fonts: &FontDatabase) -> (f32, f32) {
+    let metrics = fonts.primary_font(style).metrics(font_size(style));
+    let line_height = line_height(style, fonts);
+    let half_leading = (line_height - (metrics.ascent + metrics.descent)) / 2.0;
+    (
+        metrics.ascent + half_leading,
+        metrics.descent + half_leading,
+    )
+}
+
+/// The used value of `line-height` for a box.
+fn line_height(style: &StyledNode, fonts: &FontDatabase) -> f32 {
+    let size = font_size(style);
+    match style.value("line-height") {
+        Some(Value::Number(factor)) => factor * size,
+        Some(length @ Value::Length(..)) => length.to_px(),
+        _ => fonts.primary_font(style).metrics(size).normal_line_height(),
+    }
+}
+
+/// Horizontal margin, border and padding on one side of an inline box.
+fn edge_width(style: &StyledNode, side: &str) -> (f32, f32, f32) {
+    let zero = Value::Length(0.0, Unit::Px);
+    (
+        style.lookup(&format!("margin-{}", side), &zero).to_px(),
+        style
+            .lookup(&format!("border-{}-width", side), &zero)
+            .to_px(),
+        style.lookup(&format!("padding-{}", side), &zero).to_px(),
+    )
+}
+
+impl<'a> InlineItem<'a> {
+    fn width(&self) -> f32 {
+        match self {
+            InlineItem::Start(style) => {
+                let (margin, border, padding) = edge_width(style, "left");
+                margin + border + padding
+            }
+            InlineItem::End(style) => {
+                let (margin, border, padding) = edge_width(style, "right");
+                margin + border + padding
+            }
+            InlineItem::Text(piece) => piece.width,
+            InlineItem::Atomic { width, .. } => *width,
+            InlineItem::OutOfFlow(_) => 0.0,
+        }
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Lay out an anonymous block holding inline content: break the content into line boxes
+    /// stacked in the block, replacing the inline boxes among the children. Floats in the content
+    /// are placed in `floats` as they come, and are kept as children before the lines, along with
+    /// absolutely positioned boxes at their static position.
+    ///
+    /// `style` is the block container the anonymous block belongs to. Its font and `line-height`
+    /// set the minimum height of every line (the "strut"), and its `text-align` aligns the content
+    /// of each line. Its `text-indent` indents the first line if `first_line` is set, when the
+    /// content starts the block container.
+    ///
+    /// http://www.w3.org/TR/CSS2/visuren.html#inline-formatting
+    pub(super) fn layout_inline(
+        &mut self,
+        containing_block: Dimensions,
+        style: &'a StyledNode<'a>,
+        floats: &mut FloatContext,
+        fonts: &FontDatabase,
+        first_line: bool,
+    ) {
+        let d = &mut self.dimensions;
+        d.content.x = containing_block.content.x;
+        d.content.y = containing_block.content.y + containing_block.content.height;
+        d.content.width = containing_block.content.width;
+        d.content.height = 0.0;
+
+        let (mut items, mut out_of_flow, atomics) =
+            inline_items(mem::take(&mut self.children), style, fonts);
+        let floated: Vec<_> = out_of_flow
+            .iter()
+            .map(|out_of_flow_box| !out_of_flow_box.is_absolutely_positioned())
+            .collect();
+        for (out_of_flow_box, &floated) in out_of_flow.iter_mut().zip(&floated) {
+            if floated {
+                out_of_flow_box.layout_block(containing_block, None, None, floats, fonts);
+            }
+        }
+        // Atomic inlines are laid out where the content starts, and moved onto their line once it
+        // is built.
+        let mut atomics: Vec<_> = atomics
+            .into_iter()
+            .map(|mut atomic| {
+                let mut floats = FloatContext::default();
+                atomic.layout_block(containing_block, None, None, &mut floats, fonts);
+                Some(atomic)
+            })
+            .collect();
+        for item in &mut items {
+            if let InlineItem::Atomic { index, width, .. } = item {
+                *width = atomics[*index]
+                    .as_ref()
+                    .unwrap()
+                    .dimensions
+                    .margin_box()
+                    .width;
+            }
+        }
+
+        let strut = inline_box_extent(style, fonts);
+        let base_level = bidi::paragraph_level(style).map_or(0, |level| level.number());
+        let rtl = base_level % 2 == 1;
+        let mut indent = match style.value("text-indent") {
+            Some(indent) if first_line => indent.resolve(d.content.width),
+            _ => 0.0,
+        };
+        let (left, right) = (d.content.x, d.content.x + d.content.width);
+        let mut y = d.content.y;
+        let mut placed = vec![false; out_of_flow.len()];
+        let mut open = Vec::new();
+        let mut lines = Vec::new();
+        let mut start = 0;
+        while start < items.len() {
+            let (line_left, line_right) = floats.available(y, strut.0 + strut.1, left, right);
+            let available_width = line_right - line_left - indent;
+            let end = break_line(&items, start, available_width);
+
+            // Floats go beside the line if they fit next to the content before them, and below
+            // the line otherwise. Placing one changes the space for the line, so it is broken
+            // again.
+            let mut width = 0.0;
+            let mut below = Vec::new();
+            let mut placed_beside = false;
+            for item in &items[start..end] {
+                match *item {
+                    InlineItem::OutOfFlow(i) if floated[i] && !placed[i] => {
+                        let float_width = out_of_flow[i].dimensions.margin_box().width;
+                        if width == 0.0 || width + float_width <= available_width {
+                            out_of_flow[i].place_float(containing_block, y, floats);
+                            placed[i] = true;
+                            placed_beside = true;
+                            break;
+                        }
+                        below.push(i);
+                    }
+                    ref item => width += item.width(),
+                }
+            }
+            if placed_beside {
+                continue;
+            }
+            // A line too wide for the space beside floats moves down past them, if that helps.
+            if line_width(&items[start..end]) > available_width
+                && line_right - line_left < right - left
+            {
+                if let Some(bottom) = floats.next_bottom(y) {
+                    y = bottom;
+                    continue;
+                }
+            }
+
+            // Absolutely positioned boxes in the line start at its start edge until they are laid
+            // out.
+            for item in &items[start..end] {
+                if let InlineItem::OutOfFlow(i) = *item {
+                    if !floated[i] {
+                        out_of_flow[i].dimensions = Dimensions::default();
+                        out_of_flow[i].dimensions.content.x = line_left;
+                        out_of_flow[i].dimensions.content.y = y;
+                    }
+                }
+            }
+
+            let mut line = build_line(&items, start..end, &mut atomics, &mut open, base_level);
+            // The last line of a paragraph is not justified, like lines ending in a forced break.
+            let last = end == items.len()
+                || matches!(items[end - 1], InlineItem::Text(ref piece)
+                    if piece.break_after == Some(BreakOpportunity::Mandatory));
+            start = end;
+            // Lines with nothing visible on them are treated as if they did not exist.
+            if line.children.iter().any(has_content) {
+                let line_start = if rtl { line_left } else { line_left + indent };
+                align_line(&mut line, style, available_width, rtl, last);
+                let x = line_start + line.dimensions.content.x;
+                position_fragments(&mut line.children, x);
+                if ellipsizes(style) {
+                    ellipsize(&mut line, style, (line_left, line_right), rtl, fonts);
+                }
+                indent = 0.0;
+                line.dimensions.content.x = line_left;
+                line.dimensions.content.y = y;
+                line.dimensions.content.width = line_right - line_left;
+                align_baselines(&mut line, style, fonts);
+                position::offset_relative_fragments(&mut line.children, containing_block);
+                y += line.dimensions.content.height;
+                lines.push(line);
+            }
+            for i in below {
+                out_of_flow[i].place_float(containing_block, y, floats);
+                placed[i] = true;
+            }
+        }
+        self.dimensions.content.height = y - self.dimensions.content.y;
+        self.children = out_of_flow;
+        self.children.extend(lines);
+    }
+}
+
+/// Turn inline content into items for line breaking: white space processed, split at line break
+/// opportunities into pieces at a single bidi embedding level, and shaped. Floats and absolutely
+/// positioned boxes among the content are taken out and returned separately, and so are atomic
+/// inlines.
+///
+/// `style` is the block container of the content.
+fn inline_items<'a>(
+    content: Vec<LayoutBox<'a>>,
+    style: &StyledNode,
+    fonts: &FontDatabase,
+) -> (Vec<InlineItem<'a>>, Vec<LayoutBox<'a>>, Vec<LayoutBox<'a>>) {
+    let mut items = Vec::new();
+    let mut boxes = (Vec::new(), Vec::new());
+    let mut space_before = true;
+    for child in content {
+        collect_items(child, &mut items, &mut boxes, &mut space_before);
+    }
+    let (out_of_flow, atomics) = boxes;
+    let mut items = resolve_levels(items, bidi::paragraph_level(style));
+    for item in &mut items {
+        if let InlineItem::Text(piece) = item {
+            shape_piece(piece, fonts);
+        }
+    }
+    (items, out_of_flow, atomics)
+}
+
+/// The narrowest and the widest inline content can be laid out in: the widest piece that cannot
+/// be broken, and the widest line with only forced line breaks. Floats and atomic inlines count as
+/// pieces of their own, and absolutely positioned boxes do not count.
+pub(super) fn intrinsic_widths(
+    content: &[LayoutBox],
+    style: &StyledNode,
+    fonts: &FontDatabase,
+) -> (f32, f32) {
+    let (items, out_of_flow, atomics) = inline_items(content.to_vec(), style, fonts);
+    let (mut min, mut max) = (0.0f32, 0.0f32);
+    let (mut word, mut line) = (0.0, 0.0);
+    for item in &items {
+        match item {
+            InlineItem::Text(piece) => {
+                word += piece.width;
+                line += piece.width;
+                if piece.break_after.is_some() {
+                    min = min.max(word - piece.trailing_space);
+                    word = 0.0;
+                }
+                if piece.break_after == Some(BreakOpportunity::Mandatory) {
+                    max = max.max(line - piece.trailing_space);
+                    line = 0.0;
+                }
+            }
+            InlineItem::OutOfFlow(i) if !out_of_flow[*i].is_absolutely_positioned() => {
+                let (float_min, float_max) = out_of_flow[*i].outer_intrinsic_widths(fonts);
+                min = min.max(float_min);
+                line += float_max;
+            }
+            // Lines can break before and after atomic inlines.
+            InlineItem::Atomic { index, .. } => {
+                let (atomic_min, atomic_max) = atomics[*index].outer_intrinsic_widths(fonts);
+                min = min.max(word).max(atomic_min);
+                word = 0.0;
+                line += atomic_max;
+            }
+            item => {
+                word += item.width();
+                line += item.width();
+            }
+        }
+    }
+    (min.max(word), max.max(line))
+}
+
+/// Flatten the inline boxes under `layout_box` into items, collapsing white space on the way.
+/// Floats and absolutely positioned boxes are moved to the first of `boxes`, and atomic inlines
+/// to the second.
+///
+/// `space_before` tracks whether the text so far ends in a collapsible space, so that spaces are
+/// also collapsed across element boundaries.
+fn collect_items<'a>(
+    layout_box: LayoutBox<'a>,
+    items: &mut Vec<InlineItem<'a>>,
+    boxes: &mut (Vec<LayoutBox<'a>>, Vec<LayoutBox<'a>>),
+    space_before: &mut bool,
+) {
+    let (out_of_flow, atomics) = boxes;
+    let style = match layout_box.box_type {
+        BoxType::InlineNode(style) => style,
+        BoxType::BlockNode(style)
+            if FloatSide::of(style).is_some() || position::Position::of(style).is_out_of_flow() =>
+        {
+            items.push(InlineItem::OutOfFlow(out_of_flow.len()));
+            out_of_flow.push(layout_box);
+            return;
+        }
+        // Block-level boxes inside inline boxes are laid out like inline blocks, except that they
+        // fill the line, rather than splitting the inline box around them.
+        BoxType::BlockNode(_) => {
+            items.push(InlineItem::Atomic {
+                index: atomics.len(),
+                width: 0.0,
+                level: 0,
+            });
+            atomics.push(layout_box);
+            *space_before = false;
+            return;
+        }
+        // Generated text, like the marker of a list item.
+        BoxType::TextRun(style, ref text, _) => {
+            let white_space = WhiteSpace::of(style);
+            let text = collapse_white_space(text, white_space, space_before);
+            items.extend(
+                split_text(style, &text, white_space)
+                    .into_iter()
+                    .map(InlineItem::Text),
+            );
+            return;
+        }
+        // Outside markers are laid out on their own by `place_marker`, and anonymous boxes are only
+        // generated in block containers.
+        _ => return,
+    };
+    match style.node().node_type {
+        NodeType::Text(ref text) => {
+            let white_space = WhiteSpace::of(style);
+            let word_start = *space_before;
+            let text = collapse_white_space(text, white_space, space_before);
+            let text = transform_text(&text, style, word_start);
+            items.extend(
+                split_text(style, &text, white_space)
+                    .into_iter()
+                    .map(InlineItem::Text),
+            );
+        }
+        NodeType::Element(_) => {
+            items.push(InlineItem::Start(style));
+            for child in layout_box.children {
+                collect_items(child, items, boxes, space_before);
+            }
+            items.push(InlineItem::End(style));
+        }
+        NodeType::Comment(_) => {}
+    }
+}
+
+/// Apply the white space processing rules of `white-space` to the text of a text node.
+///
+/// http://www.w3.org/TR/css-text-3/#white-space-processing
+fn collapse_white_space(text: &str, white_space: WhiteSpace, space_before: &mut bool) -> String {
+    if !white_space.collapses_spaces() {
+        *space_before = false;
+        return text.to_string();
+    }
+    let mut result = String::new();
+    for c in text.chars() {
+        if c == '\n' && white_space.preserves_newlines() {
+            // Spaces before a preserved newline are removed, and so are spaces after it below.
+            while result.ends_with(' ') {
+                result.pop();
+            }
+            result.push('\n');
+            *space_before = true;
+        } else if c.is_whitespace() {
+            if !*space_before {
+                result.push(' ');
+            }
+            *space_before = true;
+        } else {
+            result.push(c);
+            *space_before = false;
+        }
+    }
+    result
+}
+
+/// Apply `text-transform` to the text of a text node. `word_start` tells whether the text starts a
+/// new word, for `capitalize`.
+///
+/// http://www.w3.org/TR/css-text-3/#text-transform-property
+fn transform_text(text: &str, style: &StyledNode, mut word_start: bool) -> String {
+    let transform = match style.value("text-transform") {
+        Some(Value::Keyword(transform)) => transform,
+        _ => return text.to_string(),
+    };
+    match &*transform {
+        "uppercase" => text.to_uppercase(),
+        "lowercase" => text.to_lowercase(),
+        "capitalize" => {
+            let mut result = String::with_capacity(text.len());
+            for c in text.chars() {
+                if c.is_alphanumeric() {
+                    if word_start {
+                        result.extend(c.to_uppercase());
+                    } else {
+                        result.push(c);
+                    }
+                    word_start = false;
+                } else {
+                    result.push(c);
+                    word_start |= c.is_whitespace();
+                }
+            }
+            result
+        }
+        _ => text.to_string(),
+    }
+}
+
+/// Split text at its line break opportunities, as given by UAX #14.
+fn split_text<'a>(
+    style: &'a StyledNode<'a>,
+    text: &str,
+    white_space: WhiteSpace,
+) -> Vec<TextPiece<'a>> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for (end, _) in linebreaks(text) {
+        let piece = &text[start..end];
+        start = end;
+        if piece.is_empty() {
+            continue;
+        }
+        let newline = piece.ends_with('\n');
+        let piece = piece.trim_end_matches('\n');
+        let break_after = if newline && white_space.preserves_newlines() {
+            Some(BreakOpportunity::Mandatory)
+        } else if end == text.len() && !piece.ends_with(char::is_whitespace) {
+            // The text may continue in the next text node without an opportunity in between.
+            None
+        } else if white_space.wraps() {
+            Some(BreakOpportunity::Allowed)
+        } else {
+            None
+        };
+        pieces.push(TextPiece {
+            style,
+            text: piece.to_string(),
+            glyphs: Vec::new(),
+            width: 0.0,
+            trailing_space: 0.0,
+            break_after,
+            level: 0,
+        });
+    }
+    pieces
+}
+
+/// Resolve the bidi embedding levels of the text in a block container, given its paragraph level,
+/// and split text pieces where their level changes. Inline elements take part through the
+/// control characters their `unicode-bidi` and `direction` stand for, and forced line breaks end
+/// paragraphs.
+///
+/// http://www.w3.org/TR/css-writing-modes-3/#text-direction
+fn resolve_levels<'a>(
+    items: Vec<InlineItem<'a>>,
+    paragraph_level: Option<unicode_bidi::Level>,
+) -> Vec<InlineItem<'a>> {
+    let mut paragraph = String::new();
+    let mut offsets = Vec::new();
+    for item in &items {
+        match item {
+            InlineItem::Start(style) => paragraph.push_str(bidi::controls(style).0),
+            InlineItem::End(style) => paragraph.push_str(bidi::controls(style).1),
+            InlineItem::Text(piece) => {
+                offsets.push(paragraph.len());
+                paragraph.push_str(&piece.text);
+                if piece.break_after == Some(BreakOpportunity::Mandatory) {
+                    paragraph.push('\n');
+                }
+            }
+            // Floats stand in the text as neutral objects, and so do atomic inlines.
+            InlineItem::OutOfFlow(_) => paragraph.push('\u{FFFC}'),
+            InlineItem::Atomic { .. } => {
+                offsets.push(paragraph.len());
+                paragraph.push('\u{FFFC}');
+            }
+        }
+    }
+    let levels = bidi::levels(&paragraph, paragraph_level);
+
+    let mut offsets = offsets.into_iter();
+    let mut result = Vec::with_capacity(items.len());
+    for item in items {
+        let piece = match item {
+            InlineItem::Text(piece) => piece,
+            InlineItem::Atomic { index, width, .. } => {
+                let level = levels[offsets.next().unwrap()];
+                result.push(InlineItem::Atomic {
+                    index,
+                    width,
+                    level,
+                });
+                continue;
+            }
+            item => {
+                result.push(item);
+                continue;
+            }
+        };
+        let offset = offsets.next().unwrap();
+        let level_at = |i: usize| levels[offset + i];
+        let mut start = 0;
+        for (i, _) in piece.text.char_indices().skip(1) {
+            if level_at(i) != level_at(start) {
+                result.push(InlineItem::Text(TextPiece {
+                    text: piece.text[start..i].to_string(),
+                    break_after: None,
+                    level: level_at(start),
+                    ..piece.clone()
+                }));
+                start = i;
+            }
+        }
+        let level = if piece.text.is_empty() {
+            0
+        } else {
+            level_at(start)
+        };
+        result.push(InlineItem::Text(TextPiece {
+            text: piece.text[start..].to_string(),
+            level,
+            ..piece
+        }));
+    }
+    result
+}
+
+/// Shape a text piece in the fonts of its style, in the direction of its embedding level.
+fn shape_piece(piece: &mut TextPiece, fonts: &FontDatabase) {
+    let (mut glyphs, mut width) = fonts.glyphs(piece.style, &piece.text, piece.level % 2 == 1);
+    let letter_spacing = spacing(piece.style, "letter-spacing");
+    let word_spacing = spacing(piece.style, "word-spacing");
+    if letter_spacing != 0.0 || word_spacing != 0.0 {
+        width += add_spacing(&mut glyphs, &piece.text, |c| {
+            if is_word_separator(c) {
+                letter_spacing + word_spacing
+            } else {
+                letter_spacing
+            }
+        });
+    }
+    if WhiteSpace::of(piece.style) != WhiteSpace::Pre {
+        let trimmed = piece.text.trim_end_matches(' ').len();
+        piece.trailing_space = glyphs
+            .iter()
+            .filter(|glyph| glyph.cluster >= trimmed)
+            .map(|glyph| glyph.advance)
+            .sum();
+    }
+    piece.glyphs = glyphs;
+    piece.width = width;
+}
+
+/// The used value of `letter-spacing` or `word-spacing`: extra space in px, 0 for `normal`.
+/// Percentages are of the font size.
+///
+/// http://www.w3.org/TR/css-text-4/#spacing
+fn spacing(style: &StyledNode, name: &str) -> f32 {
+    match style.value(name) {
+        Some(length @ Value::Length(..)) => length.resolve(font_size(style)),
+        _ => 0.0,
+    }
+}
+
+/// Fill a line of the given width with items from `start` on, breaking only where a text piece
+/// allows it, or around atomic inlines. Returns where the line ends.
+fn break_line(items: &[InlineItem], start: usize, available_width: f32) -> usize {
+    let mut width = 0.0;
+    let mut last_break = None;
+    for (i, item) in items.iter().enumerate().skip(start) {
+        if let (InlineItem::Atomic { .. }, true) = (item, i > start) {
+            last_break = Some(i - 1);
+        }
+        let fitting_width = match item {
+            InlineItem::Text(piece) => Some(piece.width - piece.trailing_space),
+            InlineItem::Atomic { width, .. } => Some(*width),
+            _ => None,
+        };
+        if let Some(fitting_width) = fitting_width {
+            let overflows = width + fitting_width > available_width;
+            if let (true, Some(end)) = (overflows, last_break) {
+                // Ends of inline boxes right after the break stay on the line they close.
+                let mut end = end + 1;
+                while end < i && matches!(items[end], InlineItem::End(_)) {
+                    end += 1;
+                }
+                return end;
+            }
+        }
+        width += item.width();
+        match item {
+            InlineItem::Text(piece) => match piece.break_after {
+                Some(BreakOpportunity::Mandatory) => return i + 1,
+                Some(BreakOpportunity::Allowed) => last_break = Some(i),
+                None => {}
+            },
+            InlineItem::Atomic { .. } => last_break = Some(i),
+            _ => {}
+        }
+    }
+    items.len()
+}
+
+/// The width of the items on a line, without the white space that hangs at its end.
+fn line_width(items: &[InlineItem]) -> f32 {
+    let width: f32 = items.iter().map(InlineItem::width).sum();
+    let hanging = items.iter().rev().find_map(|item| match item {
+        InlineItem::Text(piece) => Some(piece.trailing_space),
+        InlineItem::Atomic { .. } => Some(0.0),
+        _ => None,
+    });
+    width - hanging.unwrap_or(0.0)
+}
+
+/// Build the line box for a range of items, with a fragment for each inline box and each run of
+/// text on the line. Inline boxes still `open` at the start of the line continue from the previous
+/// line, and those still open at its end are left in `open` for the next one.
+///
+/// Fragments are put in visual order, following the embedding levels of their text, and fragments
+/// without text take `base_level`. Their positions are left for `align_line`, `position_fragments`
+/// and `align_baselines`.
+fn build_line<'a>(
+    items: &[InlineItem<'a>],
+    range: Range<usize>,
+    atomics: &mut [Option<LayoutBox<'a>>],
+    open: &mut Vec<&'a StyledNode<'a>>,
+    base_level: u8,
+) -> LayoutBox<'a> {
+    // Collapsible spaces at the start of a line are removed, and those at its end hang.
+    let first_text = range.clone().find(|&i| match items[i] {
+        InlineItem::Text(ref piece) => !piece.text.trim_start_matches(' ').is_empty(),
+        InlineItem::Atomic { .. } => true,
+        _ => false,
+    });
+    let last_text = range
+        .clone()
+        .rev()
+        .find(|&i| matches!(items[i], InlineItem::Text(_) | InlineItem::Atomic { .. }));
+
+    // Fragments being built, each with the embedding levels of its children.
+    let mut stack = vec![(LayoutBox::new(BoxType::LineBox), Vec::new())];
+    for &style in open.iter() {
+        stack.push((LayoutBox::new(BoxType::InlineNode(style)), Vec::new()));
+    }
+
+    for i in range {
+        match items[i] {
+            InlineItem::Start(style) => {
+                let mut fragment = LayoutBox::new(BoxType::InlineNode(style));
+                let d = &mut fragment.dimensions;
+                (d.margin.left, d.border.left, d.padding.left) = edge_width(style, "left");
+                stack.push((fragment, Vec::new()));
+                open.push(style);
+            }
+            InlineItem::End(style) => {
+                let (mut fragment, levels) = stack.pop().unwrap();
+                let d = &mut fragment.dimensions;
+                (d.margin.right, d.border.right, d.padding.right) = edge_width(style, "right");
+                close_fragment(&mut stack, fragment, levels, base_level);
+                open.pop();
+            }
+            InlineItem::OutOfFlow(_) => {}
+            InlineItem::Atomic { index, level, .. } => {
+                let (parent, levels) = stack.last_mut().unwrap();
+                parent.children.push(atomics[index].take().unwrap());
+                levels.push(level);
+            }
+            InlineItem::Text(ref piece) => {
+                let collapsible = WhiteSpace::of(piece.style).collapses_spaces();
+                if collapsible && first_text.is_none_or(|first| i < first) {
+                    continue;
+                }
+                let rtl = piece.level % 2 == 1;
+                let (mut text, mut width, mut shift) = (piece.text.as_str(), piece.width, 0.0);
+                if collapsible && Some(i) == last_text {
+                    text = text.trim_end_matches(' ');
+                    width -= piece.trailing_space;
+                    // Right-to-left text starts with its trailing spaces.
+                    if rtl {
+                        shift = piece.trailing_space;
+                    }
+                }
+                let glyphs = piece
+                    .glyphs
+                    .iter()
+                    .filter(|glyph| glyph.cluster < text.len())
+                    .map(|glyph| Glyph {
+                        x: glyph.x - shift,
+                        ..glyph.clone()
+                    });
+                let (parent, levels) = stack.last_mut().unwrap();
+                // Consecutive pieces of the same text node at the same level make up a single run.
+                let continues_run = match parent.children.last() {
+                    Some(LayoutBox {
+                        box_type: BoxType::TextRun(run_style, ..),
+                        ..
+                    }) => ptr::eq(*run_style, piece.style) && levels.last() == Some(&piece.level),
+                    _ => false,
+                };
+                if continues_run {
+                    let run = parent.children.last_mut().unwrap();
+                    let run_width = run.dimensions.content.width;
+                    if let BoxType::TextRun(_, ref mut run_text, ref mut run_glyphs) = run.box_type
+                    {
+                        let offset = run_text.len();
+                        let glyphs = glyphs.map(|glyph| Glyph {
+                            cluster: glyph.cluster + offset,
+                            ..glyph
+                        });
+                        if rtl {
+                            // Right-to-left text continues to the left of the run.
+                            for glyph in run_glyphs.iter_mut() {
+                                glyph.x += width;
+                            }
+                            run_glyphs.splice(0..0, glyphs);
+                        } else {
+                            run_glyphs.extend(glyphs.map(|glyph| Glyph {
+                                x: glyph.x + run_width,
+                                ..glyph
+                            }));
+                        }
+                        run_text.push_str(text);
+                    }
+                    run.dimensions.content.width += width;
+                } else {
+                    let mut run = LayoutBox::new(BoxType::TextRun(
+                        piece.style,
+                        text.to_string(),
+                        glyphs.collect(),
+                    ));
+                    run.dimensions.content.width = width;
+                    parent.children.push(run);
+                    levels.push(piece.level);
+                }
+            }
+        }
+    }
+
+    // Close the inline boxes that continue on the next line.
+    while stack.len() > 1 {
+        let (fragment, levels) = stack.pop().unwrap();
+        close_fragment(&mut stack, fragment, levels, base_level);
+    }
+    let (mut line, levels) = stack.pop().unwrap();
+    reorder(&mut line, &levels);
+    line
+}
+
+/// Put the children of a finished fragment in visual order, and add it to its parent at the
+/// lowest level of its children.
+fn close_fragment<'a>(
+    stack: &mut [(LayoutBox<'a>, Vec<u8>)],
+    mut fragment: LayoutBox<'a>,
+    levels: Vec<u8>,
+    base_level: u8,
+) {
+    reorder(&mut fragment, &levels);
+    let (parent, parent_levels) = stack.last_mut().unwrap();
+    parent.children.push(fragment);
+    parent_levels.push(levels.into_iter().min().unwrap_or(base_level));
+}
+
+/// Reorder the children of a fragment from their logical order to their visual order.
+///
+/// http://www.unicode.org/reports/tr9/#L2
+fn reorder(fragment: &mut LayoutBox, levels: &[u8]) {
+    let mut children: Vec<Option<LayoutBox>> = fragment.children.drain(..).map(Some).collect();
+    fragment.children = bidi::visual_order(levels)
+        .into_iter()
+        .map(|i| children[i].take().unwrap())
+        .collect();
+}
+
+/// Align the content of a line in the `width` it has, following `text-align` of `style`, its block
+/// container. Sets the content x of the line to where the content starts relative to the start of
+/// the line, and stretches word separators in justified lines. Content that overflows the line
+/// starts at its start edge.
+///
+/// http://www.w3.org/TR/css-text-3/#text-align-property
+fn align_line(line: &mut LayoutBox, style: &StyledNode, width: f32, rtl: bool, last: bool) {
+    let free = width - position_fragments(&mut line.children, 0.0);
+    let align = match style.value("text-align") {
+        Some(Value::Keyword(align)) => align,
+        _ => "start".to_string(),
+    };
+    // The start edge is on the right in right-to-left lines.
+    let (start, end) = if rtl { (free, 0.0) } else { (0.0, free) };
+    let offset = match &*align {
+        "left" => 0.0,
+        "right" => free,
+        "center" => free / 2.0,
+        "end" => end,
+        "justify" if !last && free > 0.0 => {
+            let separators: usize = line.children.iter().map(word_separators).sum();
+            if separators > 0 {
+                let extra = free / separators as f32;
+                for fragment in &mut line.children {
+                    stretch_word_separators(fragment, extra);
+                }
+                0.0
+            } else {
+                start
+            }
+        }
+        _ => start,
+    };
+    line.dimensions.content.x = if free < 0.0 { start } else { offset };
+}
+
+/// Whether lines that overflow a block container end in an ellipsis: with `text-overflow:
+/// ellipsis` on a box whose `overflow` clips.
+fn ellipsizes(style: &StyledNode) -> bool {
+    style.value("text-overflow") == Some(Value::Keyword("ellipsis".to_string()))
+        && style
+            .value("overflow")
+            .is_some_and(|overflow| overflow != Value::Keyword("visible".to_string()))
+}
+
+/// Cut the content of a line that overflows the end of the space between `left` and `right`, and
+/// end it with an ellipsis in the font of `style`, its block container. Text is cut between
+/// glyphs, and atomic inlines that do not fit whole are dropped.
+///
+/// http://www.w3.org/TR/css-overflow-3/#text-overflow
+fn ellipsize<'a>(
+    line: &mut LayoutBox<'a>,
+    style: &'a StyledNode<'a>,
+    (left, right): (f32, f32),
+    rtl: bool,
+    fonts: &FontDatabase,
+) {
+    let (start, end) = match (line.children.first(), line.children.last()) {
+        (Some(first), Some(last)) => {
+            let (first, last) = (first.dimensions.margin_box(), last.dimensions.margin_box());
+            (first.x, last.x + last.width)
+        }
+        _ => return,
+    };
+    if (rtl && start >= left) || (!rtl && end <= right) {
+        return;
+    }
+    let text = "\u{2026}".to_string();
+    let (glyphs, width) = fonts.glyphs(style, &text, rtl);
+    let mut ellipsis = LayoutBox::new(BoxType::TextRun(style, text, glyphs));
+    ellipsis.dimensions.content.width = width;
+    // The ellipsis goes right after what is left of the content, which keeps its start.
+    if rtl {
+        truncate_fragments(&mut line.children, left + width, rtl);
+        line.children.insert(0, ellipsis);
+        let width = position_fragments(&mut line.children, 0.0);
+        position_fragments(&mut line.children, end - width);
+    } else {
+        truncate_fragments(&mut line.children, right - width, rtl);
+        line.children.push(ellipsis);
+        position_fragments(&mut line.children, start);
+    }
+}
+
+/// Cut fragments at `limit`, keeping what is left of it, or right of it in right-to-left lines.
+/// Text runs keep the glyphs that fit, inline boxes that start before the limit keep what fits of
+/// their content, and atomic inlines are kept only if they fit whole.
+fn truncate_fragments(fragments: &mut Vec<LayoutBox>, limit: f32, rtl: bool) {
+    let fits = |x: f32, width: f32| {
+        if rtl {
+            x >= limit - 1e-3
+        } else {
+            x + width <= limit + 1e-3
+        }
+    };
+    fragments.retain_mut(|fragment| {
+        let margin_box = fragment.dimensions.margin_box();
+        if fits(margin_box.x, margin_box.width) {
+            return true;
+        }
+        let d = &mut fragment.dimensions;
+        match fragment.box_type {
+            BoxType::TextRun(_, ref mut text, ref mut glyphs) => {
+                let x = d.content.x;
+                retain_glyphs(text, glyphs, |glyph| fits(x + glyph.x, glyph.advance));
+                let (start, end) = glyphs
+                    .iter()
+                    .fold((f32::INFINITY, 0.0f32), |(s, e), glyph| {
+                        (s.min(glyph.x), e.max(glyph.x + glyph.advance))
+                    });
+                for glyph in glyphs.iter_mut() {
+                    glyph.x -= start;
+                }
+                d.content.x += start;
+                d.content.width = end - start;
+                !glyphs.is_empty()
+            }
+            BoxType::InlineNode(_) => {
+                truncate_fragments(&mut fragment.children, limit, rtl);
+                if rtl {
+                    margin_box.x + margin_box.width > limit
+                } else {
+                    margin_box.x < limit
+                }
+            }
+            _ => false,
+        }
+    });
+}
+
+/// Keep the glyphs of a text run that `keep` says to, along with the text of their clusters.
+fn retain_glyphs(text: &mut String, glyphs: &mut Vec<Glyph>, keep: impl Fn(&Glyph) -> bool) {
+    let mut clusters: Vec<usize> = glyphs.iter().map(|glyph| glyph.cluster).collect();
+    clusters.sort_unstable();
+    clusters.dedup();
+    glyphs.retain(|glyph| keep(glyph));
+    let kept: Vec<usize> = glyphs.iter().map(|glyph| glyph.cluster).collect();
+    // Each cluster covers the text up to the next one, and moves to where its text ends up.
+    let mut kept_text = String::new();
+    let mut moved = Vec::new();
+    for (i, &cluster) in clusters.iter().enumerate() {
+        if kept.contains(&cluster) {
+            let end = clusters.get(i + 1).copied().unwrap_or(text.len());
+            moved.push((cluster, kept_text.len()));
+            kept_text.push_str(&text[cluster..end]);
+        }
+    }
+    for glyph in glyphs.iter_mut() {
+        if let Some(&(_, offset)) = moved.iter().find(|&&(cluster, _)| cluster == glyph.cluster) {
+            glyph.cluster = offset;
+        }
+    }
+    *text = kept_text;
+}
+
+/// Whether a character separates words, for `word-spacing` and justification.
+///
+/// http://www.w3.org/TR/css-text-3/#word-separator
+fn is_word_separator(c: char) -> bool {
+    matches!(c, ' ' | '\u{A0}')
+}
+
+/// The number of word separators in the text of a fragment.
+fn word_separators(fragment: &LayoutBox) -> usize {
+    match fragment.box_type {
+        BoxType::TextRun(_, ref text, _) => text.chars().filter(|&c| is_word_separator(c)).count(),
+        BoxType::InlineNode(_) => fragment.children.iter().map(word_separators).sum(),
+        _ => 0,
+    }
+}
+
+/// Add `extra` px after each word separator in the text of a fragment.
+fn stretch_word_separators(fragment: &mut LayoutBox, extra: f32) {
+    match fragment.box_type {
+        BoxType::TextRun(_, ref text, ref mut glyphs) => {
+            let added = add_spacing(
+                glyphs,
+                text,
+                |c| {
+                    if is_word_separator(c) {
+                        extra
+                    } else {
+                        0.0
+                    }
+                },
+            );
+            fragment.dimensions.content.width += added;
+        }
+        BoxType::InlineNode(_) => {
+            for child in &mut fragment.children {
+                stretch_word_separators(child, extra);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Add space after each cluster of glyphs set from `text`, as much as `extra` gives for its first
+/// character, moving the glyphs after it along. Returns the space added.
+fn add_spacing(glyphs: &mut [Glyph], text: &str, extra: impl Fn(char) -> f32) -> f32 {
+    let mut added = 0.0;
+    for i in 0..glyphs.len() {
+        glyphs[i].x += added;
+        let cluster = glyphs[i].cluster;
+        if glyphs.get(i + 1).is_none_or(|next| next.cluster != cluster) {
+            let space = text[cluster..].chars().next().map_or(0.0, &extra);
+            glyphs[i].advance += space;
+            added += space;
+        }
+    }
+    added
+}
+
+/// Set the horizontal positions of fragments laid side by side from `x`, along with the widths of
+/// inline boxes around them. Returns where the last one ends.
+fn position_fragments(fragments: &mut [LayoutBox], mut x: f32) -> f32 {
+    for fragment in fragments {
+        let d = &mut fragment.dimensions;
+        if let BoxType::TextRun(..) = fragment.box_type {
+            d.content.x = x;
+            x += d.content.width;
+            continue;
+        }
+        if let BoxType::BlockNode(_) = fragment.box_type {
+            let margin_box = d.margin_box();
+            fragment.translate(x - margin_box.x, 0.0);
+            x += margin_box.width;
+            continue;
+        }
+        x += d.margin.left + d.border.left + d.padding.left;
+        d.content.x = x;
+        x = position_fragments(&mut fragment.children, x);
+        d.content.width = x - d.content.x;
+        x += d.margin.right + d.border.right + d.padding.right;
+    }
+    x
+}
+
+/// Whether a fragment holds text or an atomic inline, or is an inline box with horizontal margin,
+/// border or padding.
+fn has_content(fragment: &LayoutBox) -> bool {
+    let d = &fragment.dimensions;
+    let edges = d.margin.left
+        + d.border.left
+        + d.padding.left
+        + d.margin.right
+        + d.border.right
+        + d.padding.right;
+    match fragment.box_type {
+        BoxType::TextRun(_, ref text, _) => !text.is_empty(),
+        BoxType::BlockNode(_) => true,
+        _ => edges != 0.0 || fragment.children.iter().any(has_content),
+    }
+}
+
+/// How a fragment is aligned vertically in its line, from its `vertical-align`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum VerticalAlign {
+    /// On the baseline of its parent, moved down by this many px.
+    Baseline(f32),
+    /// With the top of its subtree at the top of the line box.
+    Top,
+    /// With the bottom of its subtree at the bottom of the line box.
+    Bottom,
+}
+
+/// The vertical alignment of a fragment inside an inline box, or a line box, with `parent`'s
+/// style. Text runs are always on the baseline of their inline box.
+///
+/// http://www.w3.org/TR/CSS2/visudet.html#propdef-vertical-align
+fn vertical_align(
+    fragment: &LayoutBox,
+    parent: &StyledNode,
+    fonts: &FontDatabase,
+) -> VerticalAlign {
+    let style = match fragment.box_type {
+        BoxType::InlineNode(style) | BoxType::BlockNode(style) => style,
+        _ => return VerticalAlign::Baseline(0.0),
+    };
+    let parent_size = font_size(parent);
+    let parent_metrics = fonts.primary_font(parent).metrics(parent_size);
+    let (above, below) = fragment_extent(fragment, fonts);
+    let shift = match style.value("vertical-align") {
+        Some(Value::Keyword(keyword)) => match keyword.as_str() {
+            "top" => return VerticalAlign::Top,
+            "bottom" => return VerticalAlign::Bottom,
+            "sub" => parent_size / 5.0,
+            "super" => -parent_size / 3.0,
+            "text-top" => above - parent_metrics.ascent,
+            "text-bottom" => parent_metrics.descent - below,
+            // The middle of the box goes half the parent's x-height above its baseline.
+            "middle" => -parent_metrics.x_height / 2.0 - (below - above) / 2.0,
+            _ => 0.0,
+        },
+        Some(Value::Length(percentage, Unit::Percent)) => {
+            -percentage / 100.0 * line_height(style, fonts)
+        }
+        Some(length @ Value::Length(..)) => -length.to_px(),
+        _ => 0.0,
+    };
+    VerticalAlign::Baseline(shift)
+}
+
+/// Align the fragments on a line vertically, and size the line box to fit them. The line starts
+/// with a strut: an empty inline box with the font and `line-height` of `style`, the block
+/// container of the line.
+///
+/// Fragments aligned with `top` or `bottom` are aligned with the line box once its height is known
+/// from the rest, along with their descendants on their baseline. The line grows to fit them if
+/// needed.
+///
+/// http://www.w3.org/TR/CSS2/visudet.html#line-height
+fn align_baselines(line: &mut LayoutBox, style: &StyledNode, fonts: &FontDatabase) {
+    // Measure with the baseline of the strut at 0.
+    let strut = inline_box_extent(style, fonts);
+    let mut extent = (-strut.0, strut.1);
+    let mut aligned = Vec::new();
+    for fragment in &line.children {
+        measure_subtree(fragment, style, 0.0, fonts, &mut extent, &mut aligned);
+    }
+    let (mut top, mut bottom) = extent;
+    for &(align, above, below) in &aligned {
+        if align == VerticalAlign::Top {
+            bottom = bottom.max(top + above + below);
+        }
+    }
+    for &(align, above, below) in &aligned {
+        if align == VerticalAlign::Bottom {
+            top = top.min(bottom - above - below);
+        }
+    }
+
+    let y = line.dimensions.content.y;
+    line.dimensions.content.height = bottom - top;
+    let edges = (y, y + bottom - top);
+    let mut aligned = aligned.into_iter();
+    for fragment in &mut line.children {
+        place_subtree(fragment, style, y - top, edges, fonts, &mut aligned);
+    }
+}
+
+/// Grow `extent`, the top and bottom of the line relative to its baseline, to fit a fragment
+/// inside an inline box with style `parent` whose baseline is at `baseline`, and its descendants.
+/// Subtrees aligned with the line box are measured apart and pushed to `aligned` with their extent
+/// above and below their own baseline, in the order they come in the line.
+fn measure_subtree(
+    fragment: &LayoutBox,
+    parent: &StyledNode,
+    baseline: f32,
+    fonts: &FontDatabase,
+    extent: &mut (f32, f32),
+    aligned: &mut Vec<(VerticalAlign, f32, f32)>,
+) {
+    let align = vertical_align(fragment, parent, fonts);
+    if let VerticalAlign::Baseline(shift) = align {
+        measure_fragment(fragment, baseline + shift, fonts, extent, aligned);
+        return;
+    }
+    let slot = aligned.len();
+    aligned.push((align, 0.0, 0.0));
+    let mut subtree = (f32::INFINITY, f32::NEG_INFINITY);
+    measure_fragment(fragment, 0.0, fonts, &mut subtree, aligned);
+    aligned[slot] = (align, -subtree.0, subtree.1);
+}
+
+/// Grow `extent` to fit a fragment with its baseline at `baseline`, and its descendants.
+fn measure_fragment(
+    fragment: &LayoutBox,
+    baseline: f32,
+    fonts: &FontDatabase,
+    extent: &mut (f32, f32),
+    aligned: &mut Vec<(VerticalAlign, f32, f32)>,
+) {
+    let (above, below) = fragment_extent(fragment, fonts);
+    extent.0 = extent.0.min(baseline - above);
+    extent.1 = extent.1.max(baseline + below);
+    if let BoxType::InlineNode(style) = fragment.box_type {
+        for child in &fragment.children {
+            measure_subtree(child, style, baseline, fonts, extent, aligned);
+        }
+    }
+}
+
+/// Place a fragment inside an inline box with style `parent` whose baseline is at `baseline`, and
+/// its descendants, in a line box spanning `edges` vertically. Takes the extents measured for
+/// subtrees aligned with the line box from `aligned`.
+fn place_subtree(
+    fragment: &mut LayoutBox,
+    parent: &StyledNode,
+    baseline: f32,
+    edges: (f32, f32),
+    fonts: &FontDatabase,
+    aligned: &mut impl Iterator<Item = (VerticalAlign, f32, f32)>,
+) {
+    let baseline = match vertical_align(fragment, parent, fonts) {
+        VerticalAlign::Baseline(shift) => baseline + shift,
+        _ => match aligned.next() {
+            Some((VerticalAlign::Top, above, _)) => edges.0 + above,
+            Some((_, _, below)) => edges.1 - below,
+            None => baseline,
+        },
+    };
+    place_on_baseline(fragment, baseline, fonts);
+    if let BoxType::InlineNode(style) = fragment.box_type {
+        for child in &mut fragment.children {
+            place_subtree(child, style, baseline, edges, fonts, aligned);
+        }
+    }
+}
+
+/// The extent of a fragment above and below its baseline: the line height of inline boxes around
+/// their font's ascent and descent, and the margin box of atomic inlines.
+fn fragment_extent(fragment: &LayoutBox, fonts: &FontDatabase) -> (f32, f32) {
+    match fragment.box_type {
+        BoxType::InlineNode(style) | BoxType::TextRun(style, ..) => inline_box_extent(style, fonts),
+        BoxType::BlockNode(_) => {
+            let margin_box = fragment.dimensions.margin_box();
+            let baseline = atomic_baseline(fragment, fonts);
+            (
+                baseline - margin_box.y,
+                margin_box.y + margin_box.height - baseline,
+            )
+        }
+        _ => (0.0, 0.0),
+    }
+}
+
+/// Set the vertical position of a fragment's content area, which spans its font's ascent and
+/// descent around the baseline, along with its vertical border and padding. Glyphs of text runs
+/// are moved down to the baseline, and atomic inlines are moved so theirs is on it. Descendants of
+/// inline boxes are left for `place_subtree`.
+fn place_on_baseline(fragment: &mut LayoutBox, baseline: f32, fonts: &FontDatabase) {
+    if let BoxType::BlockNode(_) = fragment.box_type {
+        let dy = baseline - atomic_baseline(fragment, fonts);
+        fragment.translate(0.0, dy);
+        return;
+    }
+    if let BoxType::InlineNode(style) | BoxType::TextRun(style, ..) = fragment.box_type {
+        let metrics = fonts.primary_font(style).metrics(font_size(style));
+        let d = &mut fragment.dimensions;
+        d.content.y = baseline - metrics.ascent;
+        d.content.height = metrics.ascent + metrics.descent;
+        if let BoxType::TextRun(_, _, ref mut glyphs) = fragment.box_type {
+            for glyph in glyphs {
+                glyph.y += metrics.ascent;
+            }
+        }
+        if let BoxType::InlineNode(style) = fragment.box_type {
+            let zero = Value::Length(0.0, Unit::Px);
+            d.border.top = style.lookup("border-top-width", &zero).to_px();
+            d.border.bottom = style.lookup("border-bottom-width", &zero).to_px();
+            d.padding.top = style.lookup("padding-top", &zero).to_px();
+            d.padding.bottom = style.lookup("padding-bottom", &zero).to_px();
+        }
+    }
+}
+
+/// The baseline of the first line box in a block box, if it has one, for aligning the box with
+/// others on their baselines.
+pub(super) fn first_baseline(layout_box: &LayoutBox, fonts: &FontDatabase) -> Option<f32> {
+    match layout_box.box_type {
+        BoxType::LineBox => line_baseline(layout_box, fonts),
+        BoxType::BlockNode(style)
+            if FloatSide::of(style).is_some() || position::Position::of(style).is_out_of_flow() =>
+        {
+            None
+        }
+        _ => layout_box
+            .children
+            .iter()
+            .find_map(|child| first_baseline(child, fonts)),
+    }
+}
+
+/// The baseline of the last line box in a block box, if it has one.
+pub(super) fn last_baseline(layout_box: &LayoutBox, fonts: &FontDatabase) -> Option<f32> {
+    match layout_box.box_type {
+        BoxType::LineBox => line_baseline(layout_box, fonts),
+        BoxType::BlockNode(style)
+            if FloatSide::of(style).is_some() || position::Position::of(style).is_out_of_flow() =>
+        {
+            None
+        }
+        _ => layout_box
+            .children
+            .iter()
+            .rev()
+            .find_map(|child| last_baseline(child, fonts)),
+    }
+}
+
+/// The baseline of a line box, where its first fragment on the baseline sits. Lines without any
+/// use their first fragment instead.
+fn line_baseline(line: &LayoutBox, fonts: &FontDatabase) -> Option<f32> {
+    let on_baseline = |fragment: &&LayoutBox| match fragment.box_type {
+        BoxType::InlineNode(style) | BoxType::BlockNode(style) => style
+            .value("vertical-align")
+            .is_none_or(|align| align == Value::Keyword("baseline".to_string())),
+        _ => true,
+    };
+    let baseline = |fragment: &LayoutBox| match fragment.box_type {
+        BoxType::InlineNode(style) | BoxType::TextRun(style, ..) => {
+            let metrics = fonts.primary_font(style).metrics(font_size(style));
+            Some(fragment.dimensions.content.y + metrics.ascent)
+        }
+        BoxType::BlockNode(_) => Some(atomic_baseline(fragment, fonts)),
+        _ => None,
+    };
+    line.children
+        .iter()
+        .filter(on_baseline)
+        .find_map(baseline)
+        .or_else(|| line.children.iter().find_map(baseline))
+}
+
+/// The baseline an atomic inline is aligned on: the first baseline of flex, grid and table
+/// containers, and the last one of other inline blocks. Inline blocks without lines, or with an
+/// `overflow` other than `visible`, use their bottom margin edge instead.
+///
+/// http://www.w3.org/TR/CSS2/visudet.html#propdef-vertical-align
+fn atomic_baseline(atomic: &LayoutBox, fonts: &FontDatabase) -> f32 {
+    let style = atomic.get_style_node();
+    let baseline = if flex::is_container(style)
+        || grid::is_container(style)
+        || TablePart::of(style) == Some(TablePart::Table)
+    {
+        first_baseline(atomic, fonts)
+    } else if style
+        .value("overflow")
+        .is_none_or(|overflow| overflow == Value::Keyword("visible".to_string()))
+    {
+        last_baseline(atomic, fonts)
+    } else {
+        None
+    };
+    baseline.unwrap_or_else(|| {
+        let margin_box = atomic.dimensions.margin_box();
+        margin_box.y + margin_box.height
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::font::Font;
+    use super::super::super::testing::Page;
+    use super::*;
+
+    /// Lay out `html` in a viewport `width` px wide and pass the root layout box to `check`.
+    fn with_layout(html: &str, css: &str, width: f32, check: impl FnOnce(&LayoutBox)) {
+        let page = Page {
+            html,
+            css,
+            width,
+            ..Default::default()
+        };
+        page.layout(|root| check(root));
+    }
+
+    fn lines<'b, 'a>(block: &'b LayoutBox<'a>) -> &'b [LayoutBox<'a>] {
+        &block.children[0].children
+    }
+
+    fn text(fragment: &LayoutBox) -> String {
+        match fragment.box_type {
+            BoxType::TextRun(_, ref text, _) => text.clone(),
+            _ => fragment.children.iter().map(text).collect(),
+        }
+    }
+
+    #[test]
+    fn test_collapse_white_space() {
+        with_layout("<p>a  \n b</p>", "", 200.0, |p| {
+            let lines = lines(p);
+            assert_eq!(lines.len(), 1);
+            assert_eq!(text(&lines[0]), "a b");
+        });
+    }
+
+    #[test]
+    fn test_break_lines() {
+        let html = "<p>The quick brown fox jumps over the lazy dog</p>";
+        with_layout(html, "p { font-size: 20px; }", 150.0, |p| {
+            let lines = lines(p);
+            assert!(lines.len() > 1);
+            let words: Vec<String> = lines.iter().map(text).collect();
+            assert_eq!(
+                words.join(" "),
+                "The quick brown fox jumps over the lazy dog"
+            );
+            for line in lines {
+                assert!(line.children[0].dimensions.content.width <= 150.0);
+            }
+            let height: f32 = lines
+                .iter()
+                .map(|line| line.dimensions.content.height)
+                .sum();
+            assert_eq!(p.children[0].dimensions.content.height, height);
+            assert_eq!(p.dimensions.content.height, height);
+            assert_eq!(
+                lines[1].dimensions.content.y,
+                lines[0].dimensions.content.y + lines[0].dimensions.content.height
+            );
+        });
+    }
+
+    #[test]
+    fn test_preserve_newlines() {
+        with_layout("<pre>one\ntwo  three</pre>", "", 800.0, |pre| {
+            let lines = lines(pre);
+            assert_eq!(lines.len(), 2);
+            assert_eq!(text(&lines[1]), "two  three");
+        });
+    }
+
+    #[test]
+    fn test_split_inline_box() {
+        let html = "<p>aaaa <b>bbbb cccc</b></p>";
+        let css = "b { padding-left: 5px; padding-right: 7px; }";
+        with_layout(html, css, 60.0, |p| {
+            let lines = lines(p);
+            assert_eq!(lines.len(), 3);
+            let first = &lines[1].children[0];
+            let second = &lines[2].children[0];
+            assert_eq!(text(first), "bbbb");
+            assert_eq!(text(second), "cccc");
+            assert_eq!(first.dimensions.padding.left, 5.0);
+            assert_eq!(first.dimensions.padding.right, 0.0);
+            assert_eq!(second.dimensions.padding.left, 0.0);
+            assert_eq!(second.dimensions.padding.right, 7.0);
+            assert_eq!(
+                first.children[0].dimensions.content.x,
+                first.dimensions.content.x
+            );
+        });
+    }
+
+    #[test]
+    fn test_line_height() {
+        let css = "p { font-size: 10px; line-height: 30px; } span { font-size: 20px; }";
+        with_layout("<p>small</p>", css, 800.0, |p| {
+            assert_eq!(lines(p)[0].dimensions.content.height, 30.0);
+        });
+        with_layout("<p>small <span>big</span></p>", css, 800.0, |p| {
+            // Both inline boxes get 30px of line height around their own font, centered on the
+            // content area, but are aligned on their baselines.
+            let small = Font::default_font().metrics(10.0);
+            let big = Font::default_font().metrics(20.0);
+            let small_leading = (30.0 - small.ascent - small.descent) / 2.0;
+            let big_leading = (30.0 - big.ascent - big.descent) / 2.0;
+            let above = (small.ascent + small_leading).max(big.ascent + big_leading);
+            let below = (small.descent + small_leading).max(big.descent + big_leading);
+
+            let line = &lines(p)[0];
+            assert!((line.dimensions.content.height - (above + below)).abs() < 1e-3);
+            let baseline = line.dimensions.content.y + above;
+            let small_run = &line.children[0];
+            let big_run = &line.children[1].children[0];
+            assert!((small_run.dimensions.content.y + small.ascent - baseline).abs() < 1e-3);
+            assert!((big_run.dimensions.content.y + big.ascent - baseline).abs() < 1e-3);
+        });
+    }
+
+    #[test]
+    fn test_vertical_align_shifts() {
+        let css = "p { font-size: 10px; line-height: 20px; } .sub { vertical-align: sub; } \
+                   .up { vertical-align: 5px; } .half { vertical-align: 50%; }";
+        let metrics = Font::default_font().metrics(10.0);
+        let strut_above = metrics.ascent + (20.0 - metrics.ascent - metrics.descent) / 2.0;
+        // Shifted boxes move their text off the baseline, and the line grows to fit them.
+        for (class, shift) in [("sub", 2.0), ("up", -5.0), ("half", -10.0)] {
+            let html = format!("<p>a<span class=\"{}\">b</span></p>", class);
+            with_layout(&html, css, 800.0, |p| {
+                let line = &lines(p)[0];
+                let height = line.dimensions.content.height;
+                assert!(
+                    (height - (20.0 + f32::abs(shift))).abs() < 1e-3,
+                    "{}",
+                    class
+                );
+                let a = &line.children[0];
+                let b = &line.children[1].children[0];
+                let dy = b.dimensions.content.y - a.dimensions.content.y;
+                assert!((dy - shift).abs() < 1e-3, "{}", class);
+                let baseline = a.dimensions.content.y + metrics.ascent;
+                let top = (strut_above - shift).max(strut_above);
+                assert!((baseline - line.dimensions.content.y - top).abs() < 1e-3);
+            });
+        }
+    }
+
+    #[test]
+    fn test_block_in_inline() {
+        let css = "p { font-size: 10px; line-height: 20px; } div { height: 30px; }";
+        with_layout("<p><span>a<div>b</div>c</span></p>", css, 100.0, |p| {
+            // The block is not dropped, and takes a line of its own as wide as the paragraph.
+            let lines = lines(p);
+            assert_eq!(lines.len(), 3);
+            assert_eq!(text(&lines[0]), "a");
+            assert_eq!(text(&lines[2]), "c");
+            let div = &lines[1].children[0].children[0];
+            assert!(matches!(div.box_type, BoxType::BlockNode(_)));
+            assert_eq!(div.dimensions.content.width, 100.0);
+            assert_eq!(div.dimensions.content.height, 30.0);
+            assert_eq!(text(div), "b");
+        });
+        // An inline root is laid out as a block.
+        with_layout("<span>a</span>", css, 100.0, |span| {
+            assert_eq!(text(&lines(span)[0]), "a");
+        });
+    }
+
+    #[test]
+    fn test_vertical_align_line_edges() {
+        let css = "p { font-size: 10px; line-height: 20px; } \
+                   b { display: inline-block; width: 10px; height: 40px; } \
+                   .top { vertical-align: top; } .bottom { vertical-align: bottom; } \
+                   .text-top { vertical-align: text-top; } .middle { vertical-align: middle; }";
+        let metrics = Font::default_font().metrics(10.0);
+        let leading = (20.0 - metrics.ascent - metrics.descent) / 2.0;
+        let (strut_above, strut_below) = (metrics.ascent + leading, metrics.descent + leading);
+        let check = |class: &str, box_top: f32, baseline: f32, height: f32| {
+            let html = format!("<p>a<b class=\"{}\"></b></p>", class);
+            with_layout(&html, css, 800.0, |p| {
+                let line = &lines(p)[0];
+                let y = line.dimensions.content.y;
+                assert!(
+                    (line.dimensions.content.height - height).abs() < 1e-3,
+                    "{}",
+                    class
+                );
+                let text = &line.children[0];
+                let text_baseline = text.dimensions.content.y + metrics.ascent;
+                assert!((text_baseline - y - baseline).abs() < 1e-3, "{}", class);
+                let b = &line.children[1];
+                assert!(
+                    (b.dimensions.content.y - y - box_top).abs() < 1e-3,
+                    "{}",
+                    class
+                );
+            });
+        };
+        // A box taller than the line sets its height, and the strut sits at the other edge.
+        check("top", 0.0, strut_above, 40.0);
+        check("bottom", 0.0, 40.0 - strut_below, 40.0);
+        // On the baseline, the bottom of a box without lines is its baseline.
+        check("", 0.0, 40.0, 40.0 + strut_below);
+        // The top of the box goes at the top of the parent's font, below the strut's leading.
+        check("text-top", leading, strut_above, leading + 40.0);
+        // The middle of the box goes half an x-height above the baseline.
+        let box_above = 20.0 + metrics.x_height / 2.0;
+        check(
+            "middle",
+            0.0,
+            box_above,
+            box_above + (40.0 - box_above).max(strut_below),
+        );
+    }
+
+    #[test]
+    fn test_text_align() {
+        let html = "<p>aa bb cc dd ee ff gg hh</p>";
+        // Each line ends where its content does, plus its offset in the line.
+        let ends = |css: &str| {
+            let mut ends = Vec::new();
+            with_layout(html, css, 100.0, |p| {
+                for line in lines(p) {
+                    let first = &line.children[0].dimensions.content;
+                    let last = &line.children.last().unwrap().dimensions.content;
+                    ends.push((first.x, last.x + last.width));
+                }
+            });
+            ends
+        };
+        let left = ends("p { width: 60px; }");
+        assert!(left.len() > 1);
+        assert!(left.iter().all(|&(start, _)| start == 0.0));
+        for (align, rtl) in [("right", ""), ("end", ""), ("start", "direction: rtl;")] {
+            let css = format!("p {{ width: 60px; text-align: {}; {} }}", align, rtl);
+            for &(start, end) in &ends(&css) {
+                assert!(start > 0.0 && (end - 60.0).abs() < 1e-3, "{}", align);
+            }
+        }
+        let center = ends("p { width: 60px; text-align: center; }");
+        for (&(start, end), &(_, left_end)) in center.iter().zip(&left) {
+            assert!((start - (60.0 - left_end) / 2.0).abs() < 1e-3);
+            assert!((60.0 - end - start).abs() < 1e-3);
+        }
+        // Justified lines fill the line, except for the last one.
+        let justify = ends("p { width: 60px; text-align: justify; }");
+        let (last, lines) = justify.split_last().unwrap();
+        for &(start, end) in lines {
+            assert!(start == 0.0 && (end - 60.0).abs() < 1e-3);
+        }
+        assert_eq!(*last, *left.last().unwrap());
+    }
+
+    #[test]
+    fn test_text_indent() {
+        let html = "<p>aa bb cc dd ee ff gg hh</p>";
+        with_layout(html, "p { width: 60px; text-indent: 10px; }", 100.0, |p| {
+            let lines = lines(p);
+            assert_eq!(lines[0].children[0].dimensions.content.x, 10.0);
+            assert_eq!(lines[1].children[0].dimensions.content.x, 0.0);
+        });
+        // Percentages are of the width of the block, and the indent is on the start side.
+        let css = "p { width: 60px; text-indent: 50%; direction: rtl; }";
+        with_layout(html, css, 100.0, |p| {
+            let first = &lines(p)[0].children.last().unwrap().dimensions.content;
+            assert!((first.x + first.width - 30.0).abs() < 1e-3);
+        });
+    }
+
+    #[test]
+    fn test_letter_and_word_spacing() {
+        let width = |css: &str| {
+            let mut width = 0.0;
+            with_layout("<p>ab cd</p>", css, 800.0, |p| {
+                width = lines(p)[0].children[0].dimensions.content.width;
+            });
+            width
+        };
+        let normal = width("");
+        // Letter spacing goes after each of the five characters, word spacing after the space.
+        assert!((width("p { letter-spacing: 2px; }") - normal - 10.0).abs() < 1e-3);
+        assert!((width("p { word-spacing: 5px; }") - normal - 5.0).abs() < 1e-3);
+        assert!(
+            (width("p { letter-spacing: 1px; word-spacing: -1px; }") - normal - 4.0).abs() < 1e-3
+        );
+        with_layout("<p>ab cd</p>", "p { letter-spacing: 3px; }", 800.0, |p| {
+            let glyphs = match lines(p)[0].children[0].box_type {
+                BoxType::TextRun(_, _, ref glyphs) => glyphs.clone(),
+                _ => panic!("expected a text run"),
+            };
+            assert!((glyphs[1].x - glyphs[0].x - glyphs[0].advance).abs() < 1e-3);
+            assert!(glyphs[0].advance > 3.0);
+        });
+    }
+
+    #[test]
+    fn test_text_transform() {
+        let css = ".u { text-transform: uppercase; } .l { text-transform: lowercase; } \
+                   .c { text-transform: capitalize; }";
+        let html = "<p><span class=\"u\">straße </span><span class=\"l\">ABC </span>\
+                    <span class=\"c\">hello (big) world</span>x<span class=\"c\">yz</span></p>";
+        with_layout(html, css, 800.0, |p| {
+            assert_eq!(text(&lines(p)[0]), "STRASSE abc Hello (Big) Worldxyz");
+        });
+    }
+
+    #[test]
+    fn test_text_overflow_ellipsis() {
+        let html = "<p>aaaa bbbb cccc dddd</p>";
+        let css = "p { width: 50px; overflow: hidden; white-space: nowrap; \
+                   text-overflow: ellipsis; }";
+        with_layout(html, css, 100.0, |p| {
+            let line = &lines(p)[0];
+            let last = line.children.last().unwrap();
+            let content = text(line);
+            assert!(content.starts_with('a') && content.ends_with('\u{2026}'));
+            assert!(last.dimensions.content.x + last.dimensions.content.width <= 50.0);
+        });
+        // The ellipsis only replaces what overflows, and only in boxes that clip it.
+        with_layout(
+            html,
+            "p { white-space: nowrap; text-overflow: ellipsis; }",
+            100.0,
+            |p| {
+                assert_eq!(text(&lines(p)[0]), "aaaa bbbb cccc dddd");
+            },
+        );
+        let css = "p { width: 50px; overflow: hidden; white-space: nowrap; }";
+        with_layout(html, css, 100.0, |p| {
+            assert_eq!(text(&lines(p)[0]), "aaaa bbbb cccc dddd");
+        });
+    }
+
+    #[test]
+    fn test_bidi_reordering() {
+        // Hebrew words in separate elements form one right-to-left run in a left-to-right line.
+        let html = "<div>a <b>\u{5D0}\u{5D1} </b><i>\u{5D2}\u{5D3}</i> c</div>";
+        with_layout(html, "", 800.0, |div| {
+            let line = &lines(div)[0];
+            let order: Vec<String> = line.children.iter().map(text).collect();
+            assert_eq!(order, vec!["a ", "\u{5D2}\u{5D3}", "\u{5D0}\u{5D1} ", "c"]);
+            for pair in line.children.windows(2) {
+                assert!(pair[0].dimensions.content.x < pair[1].dimensions.content.x);
+            }
+        });
+        // A right-to-left paragraph lays its runs out from the right.
+        let html = "<div>\u{5D0}\u{5D1} <b>abc</b> \u{5D2}</div>";
+        with_layout(html, "div { direction: rtl; }", 800.0, |div| {
+            let line = &lines(div)[0];
+            let order: Vec<String> = line.children.iter().map(text).collect();
+            assert_eq!(order, vec!["\u{5D2}", "abc", "\u{5D0}\u{5D1} "]);
+        });
+    }
+
+    #[test]
+    fn test_bidi_override() {
+        let html = "<div>ab<span>cd</span></div>";
+        let css = "span { direction: rtl; unicode-bidi: bidi-override; }";
+        with_layout(html, css, 800.0, |div| {
+            let span = &lines(div)[0].children[1];
+            match span.children[0].box_type {
+                BoxType::TextRun(_, ref text, ref glyphs) => {
+                    assert_eq!(text, "cd");
+                    // The glyph for "d" comes first.
+                    assert_eq!(glyphs[0].cluster, 1);
+                    assert!(glyphs[0].x < glyphs[1].x);
+                }
+                _ => panic!("expected a text run"),
+            }
+        });
+    }
+}
@@ -0,0 +1,470 @@
+//! List items, their markers and the CSS counters that number them.
+//!
+//! http://www.w3.org/TR/css-lists-3/
+
+use super::super::css::Value;
+use super::super::dom::NodeType;
+use super::super::font::FontDatabase;
+use super::super::images::ImageCache;
+use super::super::style::StyledNode;
+use super::float::FloatContext;
+use super::inline;
+use super::{BoxType, Dimensions, LayoutBox};
+use std::collections::HashMap;
+
+/// The counters in scope while the box tree is built in document order.
+///
+/// Every name maps to its nested instances, innermost last, each with the depth in the tree of the
+/// element that created it. An instance is in scope for that element, its following siblings and
+/// their descendants, so it goes away once its parent is done.
+#[derive(Default)]
+pub(super) struct Counters {
+    instances: HashMap<String, Vec<(i32, usize)>>,
+    depth: usize,
+}
+
+impl Counters {
+    /// Apply the `counter-reset`, `counter-increment` and `counter-set` of an element, in that
+    /// order, before its marker and children are built. List items also increment `list-item`,
+    /// unless they increment it themselves, and `<ol start>` and `<li value>` are presentational
+    /// hints for the value `list-item` is reset and set to.
+    pub(super) fn update(&mut self, style: &StyledNode) {
+        let attribute = |name: &str| match style.node().node_type {
+            NodeType::Element(ref elem) => elem
+                .attributes
+                .get(name)
+                .and_then(|value| value.trim().parse::<i32>().ok()),
+            _ => None,
+        };
+
+        for (name, mut value) in counter_list(style.value("counter-reset"), 0) {
+            if name == "list-item" {
+                if let Some(start) = attribute("start") {
+                    value = start - 1;
+                }
+            }
+            self.reset(name, value);
+        }
+
+        let mut increments = counter_list(style.value("counter-increment"), 1);
+        let is_list_item = style.value("display") == Some(Value::Keyword("list-item".to_string()));
+        if is_list_item && !increments.iter().any(|(name, _)| name == "list-item") {
+            increments.push(("list-item".to_string(), 1));
+        }
+        for (name, value) in increments {
+            *self.instance(name) += value;
+        }
+
+        let mut sets = counter_list(style.value("counter-set"), 0);
+        if let (true, Some(value)) = (is_list_item, attribute("value")) {
+            sets.push(("list-item".to_string(), value));
+        }
+        for (name, value) in sets {
+            *self.instance(name) = value;
+        }
+    }
+
+    /// Go down to the children of the element last updated.
+    pub(super) fn enter(&mut self) {
+        self.depth += 1;
+    }
+
+    /// Go back up from the children of an element, dropping the counters they created.
+    pub(super) fn leave(&mut self) {
+        for instances in self.instances.values_mut() {
+            while instances
+                .last()
+                .is_some_and(|&(_, depth)| depth == self.depth)
+            {
+                instances.pop();
+            }
+        }
+        self.depth -= 1;
+    }
+
+    /// Create a counter, replacing the one a preceding sibling created.
+    fn reset(&mut self, name: String, value: i32) {
+        let depth = self.depth;
+        let instances = self.instances.entry(name).or_default();
+        match instances.last_mut() {
+            Some(last) if last.1 == depth => *last = (value, depth),
+            _ => instances.push((value, depth)),
+        }
+    }
+
+    /// The innermost counter of a name, created at zero if there is none in scope.
+    fn instance(&mut self, name: String) -> &mut i32 {
+        let depth = self.depth;
+        let instances = self.instances.entry(name).or_default();
+        if instances.is_empty() {
+            instances.push((0, depth));
+        }
+        &mut instances.last_mut().unwrap().0
+    }
+
+    /// The values of the counters of a name, outermost first, as `counters()` shows them.
+    fn values(&self, name: &str) -> Vec<i32> {
+        self.instances
+            .get(name)
+            .map(|instances| instances.iter().map(|&(value, _)| value).collect())
+            .unwrap_or_default()
+    }
+
+    /// The value of the innermost counter of a name, as `counter()` shows it.
+    fn value(&self, name: &str) -> i32 {
+        self.values(name).last().copied().unwrap_or(0)
+    }
+}
+
+/// The names and values of a `counter-reset`, `counter-increment` or `counter-set` value, like
+/// `chapter 2 section`, with `default` for names without a value.
+fn counter_list(value: Option<Value>, default: i32) -> Vec<(String, i32)> {
+    let values = match value {
+        Some(Value::Sequence(values)) => values,
+        Some(value) => vec![value],
+        None => Vec::new(),
+    };
+    let mut counters: Vec<(String, i32)> = Vec::new();
+    for value in values {
+        match value {
+            Value::Keyword(name) if name != "none" => counters.push((name, default)),
+            Value::Number(number) => {
+                if let Some(last) = counters.last_mut() {
+                    last.1 = number as i32;
+                }
+            }
+            _ => {}
+        }
+    }
+    counters
+}
+
+/// A counter value in a counter style. Values the style cannot represent, like 0 in
+/// `lower-alpha` or 4000 in `upper-roman`, are shown in `decimal`.
+///
+/// http://www.w3.org/TR/css-counter-styles-3/#predefined-counters
+fn format_counter(value: i32, style: &str) -> String {
+    match style {
+        "none" => String::new(),
+        "disc" => "•".to_string(),
+        "circle" => "◦".to_string(),
+        "square" => "▪".to_string(),
+        "lower-alpha" | "lower-latin" if value >= 1 => alphabetic(value, b'a'),
+        "upper-alpha" | "upper-latin" if value >= 1 => alphabetic(value, b'A'),
+        "lower-roman" if (1..4000).contains(&value) => roman(value).to_lowercase(),
+        "upper-roman" if (1..4000).contains(&value) => roman(value),
+        _ => value.to_string(),
+    }
+}
+
+/// A positive number in bijective base 26: a to z, then aa, ab and so on.
+fn alphabetic(mut value: i32, first: u8) -> String {
+    let mut letters = Vec::new();
+    while value > 0 {
+        value -= 1;
+        letters.push((first + (value % 26) as u8) as char);
+        value /= 26;
+    }
+    letters.iter().rev().collect()
+}
+
+/// A number from 1 to 3999 in upper case roman numerals.
+fn roman(mut value: i32) -> String {
+    const NUMERALS: [(i32, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut numeral = String::new();
+    for (step, letters) in NUMERALS {
+        while value >= step {
+            numeral.push_str(letters);
+            value -= step;
+        }
+    }
+    numeral
+}
+
+/// The marker of a list item for a `list-style-type`: the symbol of a symbolic style followed by
+/// a space, the `list-item` counter followed by a period and a space, or a string as given.
+fn list_style_marker(list_style_type: Option<Value>, counters: &Counters) -> Option<String> {
+    match list_style_type {
+        Some(Value::String(marker)) => Some(marker),
+        Some(Value::Keyword(style)) => match &*style {
+            "none" => None,
+            "disc" | "circle" | "square" => Some(format!("{} ", format_counter(0, &style))),
+            _ => Some(format!(
+                "{}. ",
+                format_counter(counters.value("list-item"), &style)
+            )),
+        },
+        _ => Some(format!("{} ", format_counter(0, "disc"))),
+    }
+}
+
+/// The text generated by a `content` value of strings, `counter()` and `counters()`.
+fn generated_content(content: Value, counters: &Counters) -> String {
+    match content {
+        Value::String(text) => text,
+        Value::Sequence(values) => values
+            .into_iter()
+            .map(|value| generated_content(value, counters))
+            .collect(),
+        // Functions are parsed into keywords holding their source text.
+        Value::Keyword(function) => match function.split_once('(') {
+            Some((name, arguments)) => {
+                let arguments = arguments.strip_suffix(')').unwrap_or(arguments);
+                counter_function(name.trim(), &split_arguments(arguments), counters)
+            }
+            None => String::new(),
+        },
+        _ => String::new(),
+    }
+}
+
+/// The comma-separated arguments of a function, with strings unquoted.
+fn split_arguments(arguments: &str) -> Vec<&str> {
+    let mut split = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    for (i, c) in arguments.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            (None, ',') => {
+                split.push(&arguments[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    split.push(&arguments[start..]);
+    split
+        .into_iter()
+        .map(|argument| argument.trim().trim_matches(|c| c == '"' || c == '\''))
+        .collect()
+}
+
+/// The text of `counter(name, style)` or `counters(name, separator, style)`.
+fn counter_function(name: &str, arguments: &[&str], counters: &Counters) -> String {
+    let counter = arguments[0];
+    match name {
+        "counter" => format_counter(
+            counters.value(counter),
+            arguments.get(1).copied().unwrap_or("decimal"),
+        ),
+        "counters" => {
+            let style = arguments.get(2).copied().unwrap_or("decimal");
+            let values = counters.values(counter);
+            let values = if values.is_empty() { vec![0] } else { values };
+            values
+                .into_iter()
+                .map(|value| format_counter(value, style))
+                .collect::<Vec<_>>()
+                .join(arguments.get(1).copied().unwrap_or(""))
+        }
+        _ => String::new(),
+    }
+}
+
+/// Build the marker box of a list item, with the counters as they are at the item: an outside
+/// marker box, or with `list-style-position: inside`, a text run or an image to put at the start
+/// of the item's inline content.
+pub(super) fn marker_box<'a>(
+    style: &'a StyledNode<'a>,
+    counters: &Counters,
+    images: &ImageCache,
+) -> Option<LayoutBox<'a>> {
+    let marker_style = style.marker()?;
+    let text = match marker_style.value("content") {
+        Some(Value::Keyword(keyword)) if keyword == "normal" => None,
+        Some(Value::Keyword(keyword)) if keyword == "none" => return None,
+        Some(content) => Some(generated_content(content, counters)),
+        None => None,
+    };
+    // The marker is an inline replaced element showing the `list-style-image`, or if that is not
+    // available, the text of the `list-style-type`.
+    let image = match marker_style.value("list-style-image") {
+        Some(Value::Url(url)) if text.is_none() => images.get(&url),
+        _ => None,
+    };
+    let run = match (text, image) {
+        (Some(text), _) => LayoutBox::new(BoxType::TextRun(marker_style, text, Vec::new())),
+        (None, Some(image)) => {
+            let mut replaced = LayoutBox::new(BoxType::BlockNode(marker_style));
+            replaced.image = Some(image);
+            replaced
+        }
+        (None, None) => {
+            let text = list_style_marker(marker_style.value("list-style-type"), counters)?;
+            LayoutBox::new(BoxType::TextRun(marker_style, text, Vec::new()))
+        }
+    };
+    match marker_style.value("list-style-position") {
+        Some(Value::Keyword(position)) if position == "inside" => Some(run),
+        _ => {
+            let mut marker = LayoutBox::new(BoxType::Marker(marker_style));
+            marker.children.push(run);
+            Some(marker)
+        }
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Add the marker from `marker_box` to a list item once its children are built.
+    pub(super) fn insert_marker(&mut self, marker: LayoutBox<'a>) {
+        if let BoxType::Marker(_) = marker.box_type {
+            self.children.insert(0, marker);
+            return;
+        }
+        match self.children.first_mut() {
+            Some(LayoutBox {
+                box_type: BoxType::AnonymousBlock,
+                children,
+                ..
+            }) => children.insert(0, marker),
+            _ => {
+                let mut block = LayoutBox::new(BoxType::AnonymousBlock);
+                block.children.push(marker);
+                self.children.insert(0, block);
+            }
+        }
+    }
+
+    /// Place the outside marker of a list item once the item's children are laid out: on the
+    /// baseline of the item's first line, or at its top if it has none, and just outside its
+    /// border box on the start side.
+    pub(super) fn place_marker(&mut self, fonts: &FontDatabase) {
+        let baseline = inline::first_baseline(self, fonts);
+        let d = self.dimensions;
+        let marker = match self.children.first_mut() {
+            Some(marker) if matches!(marker.box_type, BoxType::Marker(_)) => marker,
+            _ => return,
+        };
+        let style = marker.get_style_node();
+
+        let mut container = Dimensions::default();
+        container.content.width = d.content.width;
+        marker.layout_inline(container, style, &mut FloatContext::default(), fonts, false);
+
+        // The marker's line can be aligned within the width it is laid out in, so its ends are
+        // where its fragments are.
+        let fragments = marker
+            .children
+            .iter()
+            .flat_map(|line| &line.children)
+            .map(|fragment| fragment.dimensions.margin_box());
+        let (left, right) = fragments.fold((f32::INFINITY, f32::NEG_INFINITY), |(l, r), rect| {
+            (l.min(rect.x), r.max(rect.x + rect.width))
+        });
+        if left > right {
+            return;
+        }
+        let border_box = d.border_box();
+        let dx = match style.value("direction") {
+            Some(Value::Keyword(direction)) if direction == "rtl" => {
+                border_box.x + border_box.width - left
+            }
+            _ => border_box.x - right,
+        };
+        let dy = match (baseline, inline::first_baseline(marker, fonts)) {
+            (Some(baseline), Some(marker_baseline)) => baseline - marker_baseline,
+            _ => d.content.y,
+        };
+        marker.translate(dx, dy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::{parser, style};
+    use super::*;
+
+    #[test]
+    fn test_format_counter() {
+        assert_eq!(format_counter(3, "decimal"), "3");
+        assert_eq!(format_counter(-2, "decimal"), "-2");
+        assert_eq!(format_counter(1, "lower-alpha"), "a");
+        assert_eq!(format_counter(26, "lower-alpha"), "z");
+        assert_eq!(format_counter(28, "upper-alpha"), "AB");
+        assert_eq!(format_counter(0, "upper-alpha"), "0");
+        assert_eq!(format_counter(1994, "upper-roman"), "MCMXCIV");
+        assert_eq!(format_counter(49, "lower-roman"), "xlix");
+        assert_eq!(format_counter(4000, "lower-roman"), "4000");
+        assert_eq!(format_counter(7, "square"), "▪");
+    }
+
+    #[test]
+    fn test_counter_list() {
+        let sequence = Value::Sequence(vec![
+            Value::Keyword("chapter".to_string()),
+            Value::Number(-2.0),
+            Value::Keyword("section".to_string()),
+            Value::Keyword("figure".to_string()),
+        ]);
+        assert_eq!(
+            counter_list(Some(sequence), 1),
+            vec![
+                ("chapter".to_string(), -2),
+                ("section".to_string(), 1),
+                ("figure".to_string(), 1),
+            ]
+        );
+        assert_eq!(
+            counter_list(Some(Value::Keyword("none".to_string())), 0),
+            vec![]
+        );
+    }
+
+    /// Walk a styled tree the way the box tree is built, and record the values of the `s`
+    /// counters at every `h` element.
+    fn record(style: &StyledNode, counters: &mut Counters, values: &mut Vec<Vec<i32>>) {
+        counters.update(style);
+        if let NodeType::Element(ref elem) = style.node().node_type {
+            if elem.tag_name == "h" {
+                values.push(counters.values("s"));
+            }
+        }
+        counters.enter();
+        for child in &style.children {
+            record(child, counters, values);
+        }
+        counters.leave();
+    }
+
+    #[test]
+    fn test_nested_counters() {
+        let html = "<div><h></h><section><h></h><h></h><section><h></h></section>\
+                    </section><h></h><h></h></div>";
+        let css = "div, section { counter-reset: s; } h { counter-increment: s; }";
+        let root = parser::parse_html(html.to_string());
+        let stylesheets = [parser::parse_css(css.to_string(), &root)];
+        let styled = style::style_tree(&root, &stylesheets);
+        let mut values = Vec::new();
+        record(&styled, &mut Counters::default(), &mut values);
+        // Each reset nests a new counter inside those in scope. The counter a section resets is
+        // also in scope for the siblings after it, so the last two headings count on from the outer
+        // section's, while the innermost counter goes away with its section's parent.
+        assert_eq!(
+            values,
+            vec![
+                vec![1],
+                vec![1, 1],
+                vec![1, 2],
+                vec![1, 2, 1],
+                vec![1, 3],
+                vec![1, 4],
+            ]
+        );
+    }
+}
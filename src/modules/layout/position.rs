@@ -0,0 +1,378 @@
+use super::super::css::Unit::{Percent, Px};
+use super::super::css::Value::{Keyword, Length};
+use super::super::font::FontDatabase;
+use super::super::style::StyledNode;
+use super::{BoxType, Dimensions, LayoutBox, Rect};
+
+/// The `position` property, which takes a box out of the normal flow or offsets it from there.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Position {
+    Static,
+    Relative,
+    Absolute,
+    Fixed,
+    Sticky,
+}
+
+impl Position {
+    pub fn of(style: &StyledNode) -> Position {
+        match style.value("position") {
+            Some(Keyword(keyword)) => match &*keyword {
+                "relative" => Position::Relative,
+                "absolute" => Position::Absolute,
+                "fixed" => Position::Fixed,
+                "sticky" => Position::Sticky,
+                _ => Position::Static,
+            },
+            _ => Position::Static,
+        }
+    }
+
+    /// Whether the box is absolutely positioned, and so out of the normal flow.
+    pub fn is_out_of_flow(self) -> bool {
+        matches!(self, Position::Absolute | Position::Fixed)
+    }
+}
+
+/// The value of `top`, `right`, `bottom` or `left` in px, or `None` for `auto`. Percentages refer
+/// to `reference`, and are `auto` if it is unknown.
+fn inset(style: &StyledNode, side: &str, reference: Option<f32>) -> Option<f32> {
+    match style.value(side)? {
+        Length(length, Px) => Some(length),
+        value @ Length(_, Percent) => reference.map(|reference| value.resolve(reference)),
+        _ => None,
+    }
+}
+
+/// A margin in px, or `None` for `auto`. Percentages refer to the containing block width.
+fn margin(style: &StyledNode, side: &str, containing_width: f32) -> Option<f32> {
+    match style.lookup(&format!("margin-{}", side), &Length(0.0, Px)) {
+        Keyword(_) => None,
+        value => Some(value.resolve(containing_width)),
+    }
+}
+
+/// How far one side of a box moves from where it is: to `start` if it is before it, and back to
+/// `end` if it goes past it. `start` wins if both apply.
+fn inset_shift(position: f32, size: f32, start: Option<f32>, end: Option<f32>) -> f32 {
+    match (start, end) {
+        (Some(start), _) => start - position,
+        (None, Some(end)) => end - (position + size),
+        (None, None) => 0.0,
+    }
+}
+
+/// How far a relatively positioned box moves from its place in the flow.
+///
+/// http://www.w3.org/TR/CSS2/visuren.html#relative-positioning
+pub fn relative_offset(
+    style: &StyledNode,
+    containing_block: Dimensions,
+    containing_height: Option<f32>,
+) -> (f32, f32) {
+    let width = Some(containing_block.content.width);
+    let dx = match (inset(style, "left", width), inset(style, "right", width)) {
+        (Some(left), _) => left,
+        (None, Some(right)) => -right,
+        (None, None) => 0.0,
+    };
+    let dy = match (
+        inset(style, "top", containing_height),
+        inset(style, "bottom", containing_height),
+    ) {
+        (Some(top), _) => top,
+        (None, Some(bottom)) => -bottom,
+        (None, None) => 0.0,
+    };
+    (dx, dy)
+}
+
+/// Move the relatively positioned inline boxes and atomic inlines among the fragments of a line,
+/// with what is in them.
+pub(super) fn offset_relative_fragments(fragments: &mut [LayoutBox], containing_block: Dimensions) {
+    for fragment in fragments {
+        match fragment.box_type {
+            BoxType::InlineNode(style) | BoxType::BlockNode(style)
+                if Position::of(style) == Position::Relative =>
+            {
+                let (dx, dy) = relative_offset(style, containing_block, None);
+                fragment.translate(dx, dy);
+                continue;
+            }
+            // Atomic inlines offset their own descendants.
+            BoxType::BlockNode(_) => continue,
+            _ => {}
+        }
+        offset_relative_fragments(&mut fragment.children, containing_block);
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// Lay out an absolutely positioned box in its containing block, the padding box of its
+    /// nearest positioned ancestor. Its position in the flow, which `auto` insets fall back on,
+    /// must already be in `self.dimensions`.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#abs-non-replaced-width
+    /// http://www.w3.org/TR/CSS2/visudet.html#abs-non-replaced-height
+    pub(super) fn layout_absolute(&mut self, containing_block: Dimensions, fonts: &FontDatabase) {
+        let style = self.get_style_node();
+        let static_position = (self.dimensions.content.x, self.dimensions.content.y);
+        let containing_height = containing_block.content.height;
+
+        self.calculate_block_width(containing_block, Some(containing_height), fonts);
+        self.solve_absolute_x(containing_block, static_position.0);
+
+        // Lay the box out at the top of its containing block, and move it into place once its
+        // height is known.
+        let mut top = containing_block;
+        top.content.height = 0.0;
+        let x = self.dimensions.content.x;
+        self.calculate_block_position(top);
+        self.dimensions.content.x = x;
+
+        let height = self.definite_height(containing_block, Some(containing_height));
+        self.layout_contents(height, fonts);
+        self.calculate_block_height(containing_block, Some(containing_height));
+        self.layout_absolute_descendants(self.padding_box_as_containing_block(), fonts);
+
+        // Solve the vertical equation of 10.6.4 for the position.
+        let width = containing_block.content.width;
+        let d = &mut self.dimensions;
+        let edges = d.border.top + d.padding.top + d.padding.bottom + d.border.bottom;
+        let (margin_top, margin_bottom) =
+            (margin(style, "top", width), margin(style, "bottom", width));
+        let top = inset(style, "top", Some(containing_height));
+        let bottom = inset(style, "bottom", Some(containing_height));
+        let (top, margin_top, margin_bottom) = match (top, bottom) {
+            (Some(top), Some(bottom)) => {
+                let free = containing_height - top - bottom - d.content.height - edges;
+                match (margin_top, margin_bottom) {
+                    (None, None) => (top, free / 2.0, free / 2.0),
+                    (None, Some(margin_bottom)) => (top, free - margin_bottom, margin_bottom),
+                    (Some(margin_top), None) => (top, margin_top, free - margin_top),
+                    // Over-constrained, so `bottom` is ignored.
+                    (Some(margin_top), Some(margin_bottom)) => (top, margin_top, margin_bottom),
+                }
+            }
+            (None, Some(bottom)) => {
+                let (margin_top, margin_bottom) =
+                    (margin_top.unwrap_or(0.0), margin_bottom.unwrap_or(0.0));
+                let top = containing_height
+                    - bottom
+                    - margin_bottom
+                    - edges
+                    - d.content.height
+                    - margin_top;
+                (top, margin_top, margin_bottom)
+            }
+            (top, None) => (
+                top.unwrap_or(static_position.1 - containing_block.content.y),
+                margin_top.unwrap_or(0.0),
+                margin_bottom.unwrap_or(0.0),
+            ),
+        };
+        d.margin.top = margin_top;
+        d.margin.bottom = margin_bottom;
+        let y = containing_block.content.y + top + margin_top + d.border.top + d.padding.top;
+        let dy = y - d.content.y;
+        self.translate(0.0, dy);
+    }
+
+    /// Solve the horizontal equation of 10.3.7 for the margins and the position, given the width
+    /// from `calculate_block_width`.
+    fn solve_absolute_x(&mut self, containing_block: Dimensions, static_x: f32) {
+        let style = self.get_style_node();
+        let width = containing_block.content.width;
+        let d = &mut self.dimensions;
+        let edges = d.border.left + d.padding.left + d.padding.right + d.border.right;
+        let (margin_left, margin_right) =
+            (margin(style, "left", width), margin(style, "right", width));
+        let left = inset(style, "left", Some(width));
+        let right = inset(style, "right", Some(width));
+        let (left, margin_left, margin_right) = match (left, right) {
+            (Some(left), Some(right)) => {
+                let free = width - left - right - d.content.width - edges;
+                match (margin_left, margin_right) {
+                    // Equal margins, unless that makes them negative.
+                    (None, None) if free >= 0.0 => (left, free / 2.0, free / 2.0),
+                    (None, None) => (left, 0.0, free),
+                    (None, Some(margin_right)) => (left, free - margin_right, margin_right),
+                    (Some(margin_left), None) => (left, margin_left, free - margin_left),
+                    // Over-constrained, so `right` is ignored.
+                    (Some(margin_left), Some(margin_right)) => (left, margin_left, margin_right),
+                }
+            }
+            (None, Some(right)) => {
+                let (margin_left, margin_right) =
+                    (margin_left.unwrap_or(0.0), margin_right.unwrap_or(0.0));
+                let left = width - right - margin_right - edges - d.content.width - margin_left;
+                (left, margin_left, margin_right)
+            }
+            (left, _) => (
+                left.unwrap_or(static_x - containing_block.content.x),
+                margin_left.unwrap_or(0.0),
+                margin_right.unwrap_or(0.0),
+            ),
+        };
+        d.margin.left = margin_left;
+        d.margin.right = margin_right;
+        d.content.x =
+            containing_block.content.x + left + margin_left + d.border.left + d.padding.left;
+    }
+
+    /// Calculate the horizontal borders and padding of an absolutely positioned box, and its
+    /// content width given the width, or `None` for `auto`: what is left between the insets if
+    /// both are set, and shrink-to-fit otherwise. The margins follow in `solve_absolute_x`.
+    pub(super) fn solve_absolute_width(
+        &mut self,
+        containing_block: Dimensions,
+        width: Option<f32>,
+        fonts: &FontDatabase,
+    ) {
+        let style = self.get_style_node();
+        let containing_width = containing_block.content.width;
+        let zero = Length(0.0, Px);
+        let length = |name: &str| style.lookup(name, &zero).resolve(containing_width);
+
+        let d = &mut self.dimensions;
+        d.border.left = length("border-left-width");
+        d.border.right = length("border-right-width");
+        d.padding.left = length("padding-left");
+        d.padding.right = length("padding-right");
+        let edges = d.border.left + d.border.right + d.padding.left + d.padding.right;
+
+        self.dimensions.content.width = width.unwrap_or_else(|| {
+            let left = inset(style, "left", Some(containing_width));
+            let right = inset(style, "right", Some(containing_width));
+            let margins = margin(style, "left", containing_width).unwrap_or(0.0)
+                + margin(style, "right", containing_width).unwrap_or(0.0);
+            let available =
+                containing_width - left.unwrap_or(0.0) - right.unwrap_or(0.0) - margins - edges;
+            if left.is_some() && right.is_some() {
+                return available.max(0.0);
+            }
+            let (min_width, max_width) = self.intrinsic_widths(fonts);
+            available.max(min_width).min(max_width)
+        });
+    }
+
+    /// The content height of an absolutely positioned box with `height: auto` and both `top` and
+    /// `bottom` set, which stretches between them; `None` otherwise.
+    pub(super) fn absolute_stretched_height(
+        &self,
+        containing_block: Dimensions,
+        containing_height: Option<f32>,
+    ) -> Option<f32> {
+        let style = self.get_style_node();
+        if !Position::of(style).is_out_of_flow() {
+            return None;
+        }
+        let top = inset(style, "top", containing_height)?;
+        let bottom = inset(style, "bottom", containing_height)?;
+        let width = containing_block.content.width;
+        let d = self.dimensions;
+        let margins = margin(style, "top", width).unwrap_or(0.0)
+            + margin(style, "bottom", width).unwrap_or(0.0);
+        let edges = d.border.top + d.padding.top + d.padding.bottom + d.border.bottom;
+        Some((containing_height? - top - bottom - margins - edges).max(0.0))
+    }
+
+    /// The padding box, which is the containing block this box gives its absolutely positioned
+    /// descendants.
+    pub(super) fn padding_box_as_containing_block(&self) -> Dimensions {
+        Dimensions {
+            content: self.dimensions.padding_box(),
+            ..Default::default()
+        }
+    }
+
+    /// Lay out the absolutely positioned descendants that have `containing_block` as theirs:
+    /// those not inside another positioned box.
+    pub(super) fn layout_absolute_descendants(
+        &mut self,
+        containing_block: Dimensions,
+        fonts: &FontDatabase,
+    ) {
+        for child in &mut self.children {
+            if let BoxType::BlockNode(style) = child.box_type {
+                match Position::of(style) {
+                    Position::Absolute => {
+                        child.layout_absolute(containing_block, fonts);
+                        continue;
+                    }
+                    Position::Static => {}
+                    // Positioned boxes lay out their own absolutely positioned descendants.
+                    _ => continue,
+                }
+            }
+            child.layout_absolute_descendants(containing_block, fonts);
+        }
+    }
+
+    /// Lay out the fixed positioned descendants in the viewport, and shift sticky positioned
+    /// ones to stay inside it, as the page is shown before any scrolling. This comes last, since
+    /// fixed boxes keep their place whatever their ancestors do.
+    ///
+    /// http://www.w3.org/TR/css-position-3/#stickypos-insets
+    pub(super) fn layout_fixed_descendants(&mut self, viewport: Dimensions, fonts: &FontDatabase) {
+        let container = self.dimensions.content;
+        for child in &mut self.children {
+            if let BoxType::BlockNode(style) = child.box_type {
+                match Position::of(style) {
+                    Position::Fixed => child.layout_absolute(viewport, fonts),
+                    Position::Sticky => child.stick(viewport.content, container),
+                    _ => {}
+                }
+            }
+            child.layout_fixed_descendants(viewport, fonts);
+        }
+    }
+
+    /// Shift a sticky positioned box by as much as its insets ask to keep it inside the
+    /// viewport, but not out of the content box of its parent.
+    fn stick(&mut self, viewport: Rect, container: Rect) {
+        let style = self.get_style_node();
+        let margin_box = self.dimensions.margin_box();
+        let border_box = self.dimensions.border_box();
+        let axis = |position: f32,
+                    size: f32,
+                    start: &str,
+                    end: &str,
+                    view_start: f32,
+                    view_size: f32,
+                    limits: (f32, f32)| {
+            let start = inset(style, start, Some(view_size)).map(|inset| view_start + inset);
+            let end =
+                inset(style, end, Some(view_size)).map(|inset| view_start + view_size - inset);
+            // Only insets the box does not already respect move it.
+            let start = start.filter(|&start| position < start);
+            let end = end.filter(|&end| position + size > end);
+            inset_shift(position, size, start, end).clamp(limits.0, limits.1.max(limits.0))
+        };
+        let dx = axis(
+            border_box.x,
+            border_box.width,
+            "left",
+            "right",
+            viewport.x,
+            viewport.width,
+            (
+                container.x - margin_box.x,
+                container.x + container.width - (margin_box.x + margin_box.width),
+            ),
+        );
+        let dy = axis(
+            border_box.y,
+            border_box.height,
+            "top",
+            "bottom",
+            viewport.y,
+            viewport.height,
+            (
+                container.y - margin_box.y,
+                container.y + container.height - (margin_box.y + margin_box.height),
+            ),
+        );
+        self.translate(dx, dy);
+    }
+}
@@ -0,0 +1,334 @@
+//! Replaced elements, like `<img>`, whose content is an image outside the box tree. They are
+//! sized from the intrinsic dimensions of the image, keeping its aspect ratio for a `width` or
+//! `height` left `auto`.
+//!
+//! http://www.w3.org/TR/CSS2/visudet.html#inline-replaced-width
+//! http://www.w3.org/TR/CSS2/visudet.html#inline-replaced-height
+
+use super::super::css::Unit::{Percent, Px};
+use super::super::css::Value::{self, Keyword, Length};
+use super::super::dom::{ElementData, NodeType};
+use super::super::images::{Image, ImageCache};
+use super::super::style::StyledNode;
+use super::{content_size, Dimensions, LayoutBox};
+
+/// The element of a style node if it is an `<img>`.
+fn img_element<'a>(style: &StyledNode<'a>) -> Option<&'a ElementData> {
+    match style.node().node_type {
+        NodeType::Element(ref element) if element.tag_name.eq_ignore_ascii_case("img") => {
+            Some(element)
+        }
+        _ => None,
+    }
+}
+
+/// The image an element is replaced with: the `src` of an `<img>` that can be loaded. An `<img>`
+/// whose image cannot be loaded is not replaced, and shows its `alt` text instead.
+pub(super) fn replaced_image(style: &StyledNode, images: &ImageCache) -> Option<Image> {
+    images.get(img_element(style)?.attributes.get("src")?)
+}
+
+/// The `alt` text an `<img>` shows when it is not replaced.
+pub(super) fn alt_text(style: &StyledNode) -> Option<String> {
+    let alt = img_element(style)?.attributes.get("alt")?;
+    Some(alt.clone()).filter(|alt| !alt.is_empty())
+}
+
+/// The `width` or `height` of a replaced element, or if it is `auto`, the `<img>` attribute of
+/// the same name, which HTML maps to the property: a number of px or a percentage.
+///
+/// https://html.spec.whatwg.org/multipage/rendering.html#dimRendering
+fn size_value(style: &StyledNode, name: &str) -> Option<Value> {
+    match style.value(name) {
+        Some(Keyword(ref keyword)) if keyword == "auto" => {}
+        None => {}
+        value => return value,
+    }
+    let attribute = img_element(style)?.attributes.get(name)?.trim();
+    match attribute.strip_suffix('%') {
+        Some(percentage) => Some(Length(percentage.parse().ok()?, Percent)),
+        None => Some(Length(attribute.parse().ok()?, Px)),
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// The used content width of a replaced element, given the width of its containing block
+    /// and its height if it is known, for resolving percentages. A `width` of `auto` follows
+    /// from the height and the image's aspect ratio, and from the image alone if the height is
+    /// `auto` too, within `min-width`, `max-width`, `min-height` and `max-height`.
+    ///
+    /// http://www.w3.org/TR/CSS2/visudet.html#min-max-widths
+    pub(super) fn replaced_width(
+        &self,
+        containing_width: Option<f32>,
+        containing_height: Option<f32>,
+    ) -> f32 {
+        let (intrinsic_width, intrinsic_height) = self.intrinsic_size();
+        let style = self.get_style_node();
+        let containing_block = Dimensions {
+            content: super::Rect {
+                width: containing_width.unwrap_or(0.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let horizontal = self.box_sizing_edges(["left", "right"], containing_block);
+        let vertical = self.box_sizing_edges(["top", "bottom"], containing_block);
+        let width_limit = |name| content_size(style.value(name), containing_width, horizontal);
+        let height_limit = |name| content_size(style.value(name), containing_height, vertical);
+        let min_width = width_limit("min-width").unwrap_or(0.0);
+        let max_width = width_limit("max-width")
+            .unwrap_or(f32::INFINITY)
+            .max(min_width);
+        let min_height = height_limit("min-height").unwrap_or(0.0);
+        let max_height = height_limit("max-height")
+            .unwrap_or(f32::INFINITY)
+            .max(min_height);
+
+        let width = content_size(size_value(style, "width"), containing_width, horizontal);
+        let height = content_size(size_value(style, "height"), containing_height, vertical);
+        match (width, height) {
+            (Some(width), _) => width.clamp(min_width, max_width),
+            (None, Some(height)) => {
+                let height = height.clamp(min_height, max_height);
+                (height * self.aspect_ratio()).clamp(min_width, max_width)
+            }
+            (None, None) => {
+                constrain(
+                    (intrinsic_width, intrinsic_height),
+                    (min_width, max_width),
+                    (min_height, max_height),
+                )
+                .0
+            }
+        }
+    }
+
+    /// The used content height of a replaced element whose used width is already set: its
+    /// `height`, or the height that keeps the image's aspect ratio at that width, within
+    /// `min-height` and `max-height`.
+    pub(super) fn replaced_height(
+        &self,
+        containing_block: Dimensions,
+        containing_height: Option<f32>,
+    ) -> f32 {
+        let style = self.get_style_node();
+        let edges = self.box_sizing_edges(["top", "bottom"], containing_block);
+        let height = content_size(size_value(style, "height"), containing_height, edges);
+        let height = height.unwrap_or_else(|| {
+            let ratio = self.aspect_ratio();
+            if ratio > 0.0 {
+                self.dimensions.content.width / ratio
+            } else {
+                self.intrinsic_size().1
+            }
+        });
+        self.clamp_height(height, containing_block, containing_height)
+    }
+
+    /// The size of the image of a replaced element in px.
+    fn intrinsic_size(&self) -> (f32, f32) {
+        match self.image {
+            Some(ref image) => (image.width() as f32, image.height() as f32),
+            None => (0.0, 0.0),
+        }
+    }
+
+    /// The width of the image of a replaced element divided by its height, or zero for an empty
+    /// image.
+    fn aspect_ratio(&self) -> f32 {
+        match self.intrinsic_size() {
+            (width, height) if height > 0.0 => width / height,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Fit an intrinsic `width` and `height` within limits on both, keeping their ratio where the
+/// limits allow, following the table of CSS 2.1 section 10.4. The maximums are at least the
+/// minimums.
+fn constrain(
+    (width, height): (f32, f32),
+    (min_width, max_width): (f32, f32),
+    (min_height, max_height): (f32, f32),
+) -> (f32, f32) {
+    if width <= 0.0 || height <= 0.0 {
+        return (
+            width.clamp(min_width, max_width),
+            height.clamp(min_height, max_height),
+        );
+    }
+    let (too_wide, too_narrow) = (width > max_width, width < min_width);
+    let (too_tall, too_short) = (height > max_height, height < min_height);
+    match (too_wide, too_narrow, too_tall, too_short) {
+        (true, _, true, _) if max_width / width <= max_height / height => {
+            (max_width, (max_width * height / width).max(min_height))
+        }
+        (true, _, true, _) => ((max_height * width / height).max(min_width), max_height),
+        (_, true, _, true) if min_width / width <= min_height / height => {
+            ((min_height * width / height).min(max_width), min_height)
+        }
+        (_, true, _, true) => (min_width, (min_width * height / width).min(max_height)),
+        (_, true, true, _) => (min_width, max_height),
+        (true, _, _, true) => (max_width, min_height),
+        (true, ..) => (max_width, (max_width * height / width).max(min_height)),
+        (_, true, ..) => (min_width, (min_width * height / width).min(max_height)),
+        (_, _, true, _) => ((max_height * width / height).max(min_width), max_height),
+        (_, _, _, true) => ((min_height * width / height).min(max_width), min_height),
+        _ => (width, height),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::font::{font_size, FontDatabase};
+    use super::super::super::testing::Page;
+    use super::super::BoxType;
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::{env, fs};
+
+    /// Lay out `html` in a viewport 200px wide with the user agent stylesheet, where `src`
+    /// attributes can name a 40px by 20px image as `wide.png`, and pass the root box to `check`.
+    /// Each call writes the image to a directory of its own, since tests run in parallel.
+    fn with_layout(html: &str, css: &str, check: impl FnOnce(&LayoutBox)) {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let call = CALLS.fetch_add(1, Ordering::Relaxed);
+        let dir = env::temp_dir().join(format!(
+            "ponyrender-replaced-{}-{}",
+            std::process::id(),
+            call
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        RgbaImage::from_pixel(40, 20, Rgba([0, 0, 255, 255]))
+            .save(dir.join("wide.png"))
+            .unwrap();
+        let images = ImageCache::new(&dir);
+        let page = Page {
+            html,
+            css,
+            images: Some(&images),
+            ..Default::default()
+        };
+        page.layout(|root| {
+            fs::remove_dir_all(&dir).unwrap();
+            check(root)
+        });
+    }
+
+    /// The content size of the first image in a tree.
+    fn image_size(layout_box: &LayoutBox) -> Option<(f32, f32)> {
+        if layout_box.image.is_some() {
+            let content = layout_box.dimensions.content;
+            return Some((content.width, content.height));
+        }
+        layout_box.children.iter().find_map(image_size)
+    }
+
+    #[test]
+    fn test_replaced_size() {
+        let size = |img: &str, css: &str| {
+            let mut size = None;
+            with_layout(&format!("<div>{}</div>", img), css, |root| {
+                size = image_size(root);
+            });
+            size.unwrap()
+        };
+        let img = "<img src=\"wide.png\">";
+        assert_eq!(size(img, ""), (40.0, 20.0));
+        // A size left `auto` keeps the aspect ratio, and attributes stand in for `auto`.
+        assert_eq!(size(img, "img { width: 100px; }"), (100.0, 50.0));
+        assert_eq!(size(img, "img { height: 10px; }"), (20.0, 10.0));
+        assert_eq!(
+            size("<img src=\"wide.png\" width=\"80\">", ""),
+            (80.0, 40.0)
+        );
+        assert_eq!(
+            size("<img src=\"wide.png\" height=\"50%\">", ""),
+            (40.0, 20.0)
+        );
+        let both = "<img src=\"wide.png\" width=\"80\" height=\"10\">";
+        assert_eq!(size(both, ""), (80.0, 10.0));
+        assert_eq!(size(both, "img { width: 30px; }"), (30.0, 10.0));
+        // Percentages are of the containing block.
+        assert_eq!(size(img, "img { width: 50%; }"), (100.0, 50.0));
+        // Limits scale the image down or up as a whole.
+        assert_eq!(size(img, "img { max-width: 20px; }"), (20.0, 10.0));
+        assert_eq!(size(img, "img { max-height: 5px; }"), (10.0, 5.0));
+        assert_eq!(size(img, "img { min-height: 40px; }"), (80.0, 40.0));
+        assert_eq!(
+            size(img, "img { max-width: 20px; min-height: 30px; }"),
+            (20.0, 30.0)
+        );
+        // Block-level, floated and absolutely positioned images are sized the same way.
+        for css in [
+            "img { display: block; width: 100px; }",
+            "img { float: right; width: 100px; }",
+            "img { position: absolute; width: 100px; left: 0px; right: 0px; }",
+        ] {
+            assert_eq!(size(img, css), (100.0, 50.0), "{}", css);
+        }
+    }
+
+    #[test]
+    fn test_replaced_inline() {
+        // The image sits on the baseline of the line like an inline block, and the line grows to
+        // fit it.
+        with_layout("<p>a<img src=\"wide.png\">b</p>", "", |p| {
+            let line = &p.children[0].children[0];
+            let img = line
+                .children
+                .iter()
+                .find(|child| child.image.is_some())
+                .unwrap();
+            let text = &line.children[0];
+            let metrics = match text.box_type {
+                BoxType::TextRun(style, ..) => FontDatabase::new()
+                    .primary_font(style)
+                    .metrics(font_size(style)),
+                _ => panic!("expected a text run"),
+            };
+            let baseline = text.dimensions.content.y + metrics.ascent;
+            let content = img.dimensions.content;
+            assert!((content.y + content.height - baseline).abs() < 1e-3);
+            assert!(line.dimensions.content.height > 20.0);
+        });
+        // An image that cannot be loaded shows its `alt` text.
+        with_layout("<p><img src=\"missing.png\" alt=\"Pony\"></p>", "", |p| {
+            let line = &p.children[0].children[0];
+            assert!(image_size(p).is_none());
+            match line.children[0].children[0].box_type {
+                BoxType::TextRun(_, ref text, _) => assert_eq!(text, "Pony"),
+                _ => panic!("expected the alt text"),
+            }
+        });
+    }
+
+    #[test]
+    fn test_list_style_image() {
+        let html = "<ul><li>a</li></ul>";
+        with_layout(html, "li { list-style-image: url(wide.png); }", |ul| {
+            let marker = &ul.children[0].children[0];
+            assert!(matches!(marker.box_type, BoxType::Marker(_)));
+            assert_eq!(image_size(marker), Some((40.0, 20.0)));
+            // The marker lies outside the item.
+            let fragment = &marker.children[0].children[0];
+            assert!(fragment.dimensions.content.x < ul.children[0].dimensions.content.x);
+        });
+        let css = "li { list-style-image: url(wide.png); list-style-position: inside; }";
+        with_layout(html, css, |ul| {
+            let block = &ul.children[0].children[0];
+            assert_eq!(block.box_type, BoxType::AnonymousBlock);
+            assert_eq!(image_size(block), Some((40.0, 20.0)));
+        });
+        // Images that cannot be loaded fall back to `list-style-type`.
+        with_layout(html, "li { list-style-image: url(missing.png); }", |ul| {
+            assert_eq!(image_size(ul), None);
+            assert!(matches!(
+                ul.children[0].children[0].box_type,
+                BoxType::Marker(_)
+            ));
+        });
+    }
+}
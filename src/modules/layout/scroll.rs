@@ -0,0 +1,216 @@
+//! Scroll containers and the viewport, whose content can be scrolled.
+//!
+//! http://www.w3.org/TR/css-overflow-3/#scrollable
+
+use super::super::css::Value::Keyword;
+use super::{BoxType, LayoutBox, Position, Rect};
+
+impl<'a> LayoutBox<'a> {
+    /// Whether the box is a scroll container: a block with an `overflow` of `hidden`, `scroll` or
+    /// `auto`. Content hidden by `overflow: hidden` can still be scrolled here, as with a script.
+    ///
+    /// http://www.w3.org/TR/css-overflow-3/#scroll-container
+    pub fn is_scroll_container(&self) -> bool {
+        match self.box_type {
+            BoxType::BlockNode(style) => matches!(
+                style.value("overflow"),
+                Some(Keyword(overflow)) if matches!(&*overflow, "hidden" | "scroll" | "auto")
+            ),
+            _ => false,
+        }
+    }
+
+    /// How far the content of a scroll container is scrolled right and down, in px.
+    pub fn scroll_offset(&self) -> (f32, f32) {
+        self.scroll_offset
+    }
+
+    /// The furthest the content of a scroll container can be scrolled right and down: until the
+    /// end of its content meets the end of its padding box. Zero for other boxes.
+    pub fn max_scroll_offset(&self) -> (f32, f32) {
+        if !self.is_scroll_container() {
+            return (0.0, 0.0);
+        }
+        let padding_box = self.dimensions.padding_box();
+        let mut overflow = padding_box;
+        self.add_scrollable_overflow(&mut overflow, self.contains_absolute());
+        // The content is measured where it is scrolled to.
+        let (x, y) = self.scroll_offset;
+        (
+            (overflow.x + overflow.width + x - (padding_box.x + padding_box.width)).max(0.0),
+            (overflow.y + overflow.height + y - (padding_box.y + padding_box.height)).max(0.0),
+        )
+    }
+
+    /// Scroll the content of a scroll container to (`x`, `y`), within `max_scroll_offset`. Does
+    /// nothing to other boxes. Content is scrolled by moving it, and the box clips what is moved
+    /// out of view when it is painted.
+    ///
+    /// http://www.w3.org/TR/cssom-view-1/#scroll-an-element
+    pub fn scroll_to(&mut self, x: f32, y: f32) {
+        if !self.is_scroll_container() {
+            return;
+        }
+        let (max_x, max_y) = self.max_scroll_offset();
+        let (x, y) = (x.clamp(0.0, max_x), y.clamp(0.0, max_y));
+        let (dx, dy) = (self.scroll_offset.0 - x, self.scroll_offset.1 - y);
+        self.scroll_offset = (x, y);
+        self.scroll_content(dx, dy, self.contains_absolute());
+    }
+
+    /// Scroll the viewport over the root box of a document to (`x`, `y`), as far as the document
+    /// reaches past the viewport. Fixed positioned boxes are left where they are.
+    pub(super) fn scroll_viewport(&mut self, viewport: Rect, (x, y): (f32, f32)) {
+        let mut overflow = self.dimensions.border_box();
+        self.add_scrollable_overflow(&mut overflow, true);
+        let max_x = (overflow.x + overflow.width - (viewport.x + viewport.width)).max(0.0);
+        let max_y = (overflow.y + overflow.height - (viewport.y + viewport.height)).max(0.0);
+        let (dx, dy) = (-x.clamp(0.0, max_x), -y.clamp(0.0, max_y));
+        self.dimensions.content.x += dx;
+        self.dimensions.content.y += dy;
+        self.scroll_content(dx, dy, true);
+    }
+
+    /// Whether absolutely positioned descendants are in this box or one of its descendants,
+    /// rather than in a box it is in.
+    fn contains_absolute(&self) -> bool {
+        Position::of(self.get_style_node()) != Position::Static
+    }
+
+    /// Move the descendants that scroll with the box: all but fixed positioned ones, and
+    /// absolutely positioned ones only if the box or a box in between contains them.
+    fn scroll_content(&mut self, dx: f32, dy: f32, contains_absolute: bool) {
+        for child in &mut self.children {
+            let position = match scrolled_position(child, contains_absolute) {
+                Some(position) => position,
+                None => continue,
+            };
+            child.dimensions.content.x += dx;
+            child.dimensions.content.y += dy;
+            child.scroll_content(dx, dy, contains_absolute || position != Position::Static);
+        }
+    }
+
+    /// Grow `overflow` to the border boxes of the descendants that scroll with the box. Content
+    /// in descendants that clip it only counts up to their own padding box.
+    ///
+    /// http://www.w3.org/TR/css-overflow-3/#scrollable
+    fn add_scrollable_overflow(&self, overflow: &mut Rect, contains_absolute: bool) {
+        for child in &self.children {
+            let position = match scrolled_position(child, contains_absolute) {
+                Some(position) => position,
+                None => continue,
+            };
+            *overflow = union(*overflow, child.dimensions.border_box());
+            let clips = matches!(child.box_type, BoxType::BlockNode(style)
+                if style.value("overflow").is_some_and(|overflow| overflow != Keyword("visible".to_string())));
+            if !clips {
+                child.add_scrollable_overflow(
+                    overflow,
+                    contains_absolute || position != Position::Static,
+                );
+            }
+        }
+    }
+}
+
+/// The `position` of a box scrolled along with its parent, or `None` if it does not scroll with
+/// it: fixed positioned boxes, and absolutely positioned ones unless the scrolled box contains
+/// them.
+fn scrolled_position(layout_box: &LayoutBox, contains_absolute: bool) -> Option<Position> {
+    let position = match layout_box.box_type {
+        BoxType::BlockNode(style) => Position::of(style),
+        _ => Position::Static,
+    };
+    match position {
+        Position::Fixed => None,
+        Position::Absolute if !contains_absolute => None,
+        position => Some(position),
+    }
+}
+
+/// The smallest rectangle around both rectangles.
+fn union(a: Rect, b: Rect) -> Rect {
+    let (x, y) = (a.x.min(b.x), a.y.min(b.y));
+    Rect {
+        x,
+        y,
+        width: (a.x + a.width).max(b.x + b.width) - x,
+        height: (a.y + a.height).max(b.y + b.height) - y,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::testing::Page;
+    use super::*;
+
+    /// Lay out `html` in a 200px by 100px viewport scrolled by `scroll`, with every element a
+    /// block, and pass the root box to `check`.
+    fn with_layout(html: &str, css: &str, scroll: (f32, f32), check: impl FnOnce(&mut LayoutBox)) {
+        let page = Page {
+            html,
+            css,
+            height: 100.0,
+            scroll,
+            user_agent: false,
+            ..Default::default()
+        };
+        page.layout(check);
+    }
+
+    #[test]
+    fn test_scroll_container() {
+        let html = "<div><p class=\"a\"></p><p class=\"b\"></p><p class=\"fixed\"></p></div>";
+        let css = "div { overflow: auto; height: 50px; padding: 5px; } p { height: 40px; } \
+                   .b { position: absolute; top: 0px; } .fixed { position: fixed; top: 0px; }";
+        with_layout(html, css, (0.0, 0.0), |div| {
+            assert!(div.is_scroll_container());
+            // The static box and the padding above it fit in the padding box. The absolute box is
+            // not in the div, so it does not count.
+            assert_eq!(div.max_scroll_offset(), (0.0, 0.0));
+        });
+        let css = format!("{} .a {{ height: 70px; }}", css);
+        with_layout(html, &css, (0.0, 0.0), |div| {
+            assert_eq!(div.max_scroll_offset(), (0.0, 15.0));
+            div.scroll_to(10.0, 10.0);
+            assert_eq!(div.scroll_offset(), (0.0, 10.0));
+            assert_eq!(div.children[0].dimensions.content.y, -5.0);
+            // Boxes outside the scroll container stay where they are.
+            assert_eq!(div.children[1].dimensions.content.y, 0.0);
+            assert_eq!(div.children[2].dimensions.content.y, 0.0);
+            div.scroll_to(0.0, 100.0);
+            assert_eq!(div.scroll_offset(), (0.0, 15.0));
+            assert_eq!(div.children[0].dimensions.content.y, -10.0);
+            div.scroll_to(0.0, 0.0);
+            assert_eq!(div.children[0].dimensions.content.y, 5.0);
+        });
+        // Other boxes do not scroll.
+        with_layout(html, "p { height: 200px; }", (0.0, 0.0), |div| {
+            assert!(!div.is_scroll_container());
+            div.scroll_to(0.0, 10.0);
+            assert_eq!(div.scroll_offset(), (0.0, 0.0));
+            assert_eq!(div.children[0].dimensions.content.y, 0.0);
+        });
+    }
+
+    #[test]
+    fn test_scroll_viewport() {
+        let html = "<div><p class=\"a\"></p><p class=\"fixed\"></p><p class=\"sticky\"></p>\
+                    <p class=\"b\"></p></div>";
+        let css = "p { height: 100px; } .fixed { position: fixed; top: 10px; height: 10px; } \
+                   .sticky { position: sticky; top: 0px; height: 10px; }";
+        with_layout(html, css, (0.0, 105.0), |div| {
+            assert_eq!(div.dimensions.content.y, -105.0);
+            assert_eq!(div.children[0].dimensions.content.y, -105.0);
+            assert_eq!(div.children[1].dimensions.content.y, 10.0);
+            // The sticky box sticks to the top of the viewport.
+            assert_eq!(div.children[2].dimensions.content.y, 0.0);
+            assert_eq!(div.children[3].dimensions.content.y, 5.0);
+        });
+        // The viewport scrolls no further than the end of the document.
+        with_layout(html, css, (0.0, 1000.0), |div| {
+            assert_eq!(div.dimensions.content.y, -110.0);
+        });
+    }
+}
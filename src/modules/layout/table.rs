@@ -0,0 +1,1156 @@
+use super::super::css::Color;
+use super::super::css::Unit::Px;
+use super::super::css::Value::{self, Keyword, Length, Sequence};
+use super::super::dom::NodeType;
+use super::super::font::FontDatabase;
+use super::super::style::StyledNode;
+use super::float::FloatContext;
+use super::{
+    content_size, inline, is_out_of_flow, sum, BoxType, Dimensions, EdgeSizes, LayoutBox, Position,
+    Rect,
+};
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+/// The boxes a table is made of, from their `display`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TablePart {
+    Table,
+    Caption,
+    HeaderGroup,
+    RowGroup,
+    FooterGroup,
+    Row,
+    Cell,
+    ColumnGroup,
+    Column,
+}
+
+impl TablePart {
+    /// The part of a table a box with this style is. Floated and absolutely positioned boxes are
+    /// blocks instead, unless they are tables.
+    pub fn of(style: &StyledNode) -> Option<TablePart> {
+        let part = match style.value("display") {
+            Some(Keyword(display)) => match &*display {
+                "table" | "inline-table" => TablePart::Table,
+                "table-caption" => TablePart::Caption,
+                "table-header-group" => TablePart::HeaderGroup,
+                "table-row-group" => TablePart::RowGroup,
+                "table-footer-group" => TablePart::FooterGroup,
+                "table-row" => TablePart::Row,
+                "table-cell" => TablePart::Cell,
+                "table-column-group" => TablePart::ColumnGroup,
+                "table-column" => TablePart::Column,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        if part != TablePart::Table && is_out_of_flow(style) {
+            return None;
+        }
+        Some(part)
+    }
+
+    fn is_row_group(self) -> bool {
+        matches!(
+            self,
+            TablePart::HeaderGroup | TablePart::RowGroup | TablePart::FooterGroup
+        )
+    }
+}
+
+/// Whether a box that is the given table part, or not one, can be a child of a box that is
+/// `parent`, without anonymous table boxes in between.
+fn fits(child: Option<TablePart>, parent: Option<TablePart>) -> bool {
+    match parent {
+        Some(TablePart::Table) => {
+            child.is_some_and(|child| !matches!(child, TablePart::Table | TablePart::Cell))
+        }
+        Some(part) if part.is_row_group() => child == Some(TablePart::Row),
+        Some(TablePart::Row) => child == Some(TablePart::Cell),
+        _ => matches!(child, None | Some(TablePart::Table)),
+    }
+}
+
+/// Generate the anonymous table boxes that the children of a block box are missing: rows around
+/// the children of tables and row groups that are not rows, cells around the children of rows
+/// that are not cells, and tables around table parts outside a table. Runs of such children share
+/// one anonymous box.
+///
+/// http://www.w3.org/TR/CSS2/tables.html#anonymous-boxes
+pub(super) fn wrap_table_parts(root: &mut LayoutBox) {
+    let parent = match root.box_type {
+        BoxType::BlockNode(_) | BoxType::AnonymousTablePart(..) => root.table_part(),
+        _ => return,
+    };
+    if let Some(TablePart::Column | TablePart::ColumnGroup) = parent {
+        // Columns have no content, and column groups only hold columns.
+        root.children.retain(|child| {
+            parent == Some(TablePart::ColumnGroup) && child.table_part() == Some(TablePart::Column)
+        });
+        return;
+    }
+    let wrapper = match parent {
+        Some(TablePart::Row) => TablePart::Cell,
+        Some(part) if part == TablePart::Table || part.is_row_group() => TablePart::Row,
+        _ => TablePart::Table,
+    };
+    let style = root.inherited_style();
+    let mut wrapping = false;
+    for child in mem::take(&mut root.children) {
+        if fits(child.table_part(), parent) {
+            root.children.push(child);
+            wrapping = false;
+            continue;
+        }
+        if !wrapping {
+            let anonymous = BoxType::AnonymousTablePart(wrapper, style);
+            root.children.push(LayoutBox::new(anonymous));
+            wrapping = true;
+        }
+        root.children.last_mut().unwrap().children.push(child);
+    }
+    for child in &mut root.children {
+        if let BoxType::AnonymousTablePart(..) = child.box_type {
+            wrap_table_parts(child);
+        }
+    }
+}
+
+/// The rows and cells of a table, and the slots of its grid each cell covers. Boxes are found by
+/// the indices of the children that lead to them from the table box.
+struct Table {
+    /// The rows in the order they are laid out, with header groups first and footer groups last.
+    rows: Vec<Row>,
+    cells: Vec<Cell>,
+    columns: usize,
+    /// The `width` of columns that a column or column group box gives one.
+    column_widths: Vec<Option<f32>>,
+    captions: Vec<usize>,
+    collapse: bool,
+    /// The horizontal and vertical `border-spacing`, which collapsed borders leave out.
+    spacing: (f32, f32),
+}
+
+impl Table {
+    /// The total of the horizontal `border-spacing` between the columns and around them.
+    fn grid_spacing(&self) -> f32 {
+        if self.columns == 0 {
+            return 0.0;
+        }
+        self.spacing.0 * (self.columns + 1) as f32
+    }
+}
+
+struct Row {
+    path: Vec<usize>,
+    /// The index of the row group the row is in, or `None` for a child of the table.
+    group: Option<usize>,
+    /// The index of the row after the last one of its group, which cells span no further than.
+    group_end: usize,
+}
+
+struct Cell {
+    path: Vec<usize>,
+    row: usize,
+    column: usize,
+    rowspan: usize,
+    colspan: usize,
+    /// The widths of the borders, which a collapsed border between two cells splits between them.
+    border: EdgeSizes,
+    /// The colors of collapsed borders, taken from the border that wins each.
+    colors: Option<[Option<Color>; 4]>,
+}
+
+/// How a cell's content is aligned in the height of its rows, from `vertical-align`.
+#[derive(Clone, Copy, PartialEq)]
+enum CellAlign {
+    Baseline,
+    Top,
+    Middle,
+    Bottom,
+}
+
+impl CellAlign {
+    fn of(layout_box: &LayoutBox) -> CellAlign {
+        let style = match layout_box.box_type {
+            BoxType::BlockNode(style) => style,
+            _ => return CellAlign::Baseline,
+        };
+        match style.value("vertical-align") {
+            Some(Keyword(keyword)) => match &*keyword {
+                "top" => CellAlign::Top,
+                "middle" => CellAlign::Middle,
+                "bottom" => CellAlign::Bottom,
+                _ => CellAlign::Baseline,
+            },
+            _ => CellAlign::Baseline,
+        }
+    }
+}
+
+/// Which box a collapsing border is set on, in the order that decides conflicts between borders
+/// that are otherwise alike.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+enum BorderOwner {
+    #[default]
+    Table,
+    RowGroup,
+    Row,
+    Cell,
+}
+
+/// `border-style` values from the weakest to the strongest in border conflicts.
+const BORDER_STYLES: [&str; 10] = [
+    "none", "inset", "groove", "outset", "ridge", "dotted", "dashed", "solid", "double", "hidden",
+];
+
+/// A border that takes part in collapsing, with the weight of its style in `BORDER_STYLES`.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Border {
+    width: f32,
+    style: usize,
+    color: Option<Color>,
+    owner: BorderOwner,
+}
+
+impl Border {
+    /// The border on one side of a box, or `None` for anonymous boxes, which have none. Borders
+    /// without a `border-style` are solid, as everywhere else.
+    fn of(layout_box: &LayoutBox, side: &str, owner: BorderOwner) -> Option<Border> {
+        let style = match layout_box.box_type {
+            BoxType::BlockNode(style) => style,
+            _ => return None,
+        };
+        let zero = Length(0.0, Px);
+        let solid = Keyword("solid".to_string());
+        let border_style = match style.lookup(&format!("border-{}-style", side), &solid) {
+            Keyword(keyword) => BORDER_STYLES.iter().position(|&name| name == keyword),
+            _ => None,
+        };
+        Some(Border {
+            width: style
+                .lookup(&format!("border-{}-width", side), &zero)
+                .to_px(),
+            style: border_style.unwrap_or(7),
+            color: match style.value("border-color") {
+                Some(Value::Color(color)) => Some(color),
+                _ => None,
+            },
+            owner,
+        })
+    }
+}
+
+/// The border that wins a conflict between collapsing borders: none if one of them is `hidden`,
+/// and otherwise the widest one that is not `none`, then the one with the strongest style, then
+/// the one on the box that weighs most, then the first, which is the one above or to the left.
+///
+/// http://www.w3.org/TR/CSS2/tables.html#border-conflict-resolution
+fn resolve_conflict(borders: Vec<Option<Border>>) -> Border {
+    let mut winner = Border::default();
+    for border in borders.into_iter().flatten() {
+        if BORDER_STYLES[border.style] == "hidden" {
+            return Border::default();
+        }
+        let stronger =
+            (border.width, border.style, border.owner) > (winner.width, winner.style, winner.owner);
+        if BORDER_STYLES[border.style] != "none" && stronger {
+            winner = border;
+        }
+    }
+    winner
+}
+
+/// Share the width of a table grid out between columns with the given minimum and maximum widths:
+/// columns grow from their minimum in proportion to how much wider their maximum is, and past
+/// their maximum in proportion to it.
+fn distribute(limits: &[(f32, f32)], width: f32) -> Vec<f32> {
+    let min = sum(limits.iter().map(|&(min, _)| min));
+    let max = sum(limits.iter().map(|&(_, max)| max));
+    limits
+        .iter()
+        .map(|&(column_min, column_max)| {
+            if width >= max {
+                if max > 0.0 {
+                    column_max + (width - max) * column_max / max
+                } else {
+                    width / limits.len() as f32
+                }
+            } else if width > min {
+                column_min + (width - min) * (column_max - column_min) / (max - min)
+            } else {
+                column_min
+            }
+        })
+        .collect()
+}
+
+/// A `colspan`, `rowspan` or `span` attribute of the element of a box, if it is a valid number.
+fn span_attribute(layout_box: &LayoutBox, name: &str) -> Option<usize> {
+    let style = match layout_box.box_type {
+        BoxType::BlockNode(style) => style,
+        _ => return None,
+    };
+    match style.node().node_type {
+        NodeType::Element(ref data) => data.attributes.get(name)?.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// The `width` of a column or column group box in px, if it has one.
+fn column_width(layout_box: &LayoutBox) -> Option<f32> {
+    match layout_box.box_type {
+        BoxType::BlockNode(style) => content_size(style.value("width"), None, 0.0),
+        _ => None,
+    }
+}
+
+/// The horizontal and vertical `border-spacing`.
+fn border_spacing(style: &StyledNode) -> (f32, f32) {
+    match style.value("border-spacing") {
+        Some(Sequence(values)) if values.len() == 2 => (values[0].to_px(), values[1].to_px()),
+        Some(value) => (value.to_px(), value.to_px()),
+        None => (0.0, 0.0),
+    }
+}
+
+/// The padding of a cell, with percentages of the width of the table.
+fn cell_padding(layout_box: &LayoutBox, table_width: f32) -> EdgeSizes {
+    let style = match layout_box.box_type {
+        BoxType::BlockNode(style) => style,
+        _ => return EdgeSizes::default(),
+    };
+    let zero = Length(0.0, Px);
+    let padding = |side: &str| {
+        style
+            .lookup(&format!("padding-{}", side), &zero)
+            .resolve(table_width)
+    };
+    EdgeSizes {
+        top: padding("top"),
+        right: padding("right"),
+        bottom: padding("bottom"),
+        left: padding("left"),
+    }
+}
+
+impl<'a> LayoutBox<'a> {
+    /// The part of a table the box is, if any.
+    pub(super) fn table_part(&self) -> Option<TablePart> {
+        match self.box_type {
+            BoxType::BlockNode(style) => TablePart::of(style),
+            BoxType::AnonymousTablePart(part, _) => Some(part),
+            _ => None,
+        }
+    }
+
+    fn descendant(&self, path: &[usize]) -> &LayoutBox<'a> {
+        path.iter()
+            .fold(self, |layout_box, &i| &layout_box.children[i])
+    }
+
+    fn descendant_mut(&mut self, path: &[usize]) -> &mut LayoutBox<'a> {
+        path.iter()
+            .fold(self, |layout_box, &i| &mut layout_box.children[i])
+    }
+
+    /// Find the rows, cells and columns of a table box, and give each cell the slots it covers:
+    /// the first free one in its row, and as many more as its `colspan` and `rowspan` ask for.
+    ///
+    /// http://www.w3.org/TR/CSS2/tables.html#table-layout
+    fn table(&self) -> Table {
+        let style = self.inherited_style();
+        let collapse = style.value("border-collapse") == Some(Keyword("collapse".to_string()));
+        let mut sections: Vec<(u8, Option<usize>, Vec<Vec<usize>>)> = Vec::new();
+        let mut captions = Vec::new();
+        let mut column_widths = Vec::new();
+        for (i, child) in self.children.iter().enumerate() {
+            match child.table_part() {
+                Some(TablePart::Row) => match sections.last_mut() {
+                    Some((_, None, rows)) if i > 0 && rows.last() == Some(&vec![i - 1]) => {
+                        rows.push(vec![i])
+                    }
+                    _ => sections.push((1, None, vec![vec![i]])),
+                },
+                Some(part) if part.is_row_group() => {
+                    let order = match part {
+                        TablePart::HeaderGroup => 0,
+                        TablePart::FooterGroup => 2,
+                        _ => 1,
+                    };
+                    let rows = (0..child.children.len())
+                        .filter(|&j| child.children[j].table_part() == Some(TablePart::Row))
+                        .map(|j| vec![i, j])
+                        .collect();
+                    sections.push((order, Some(i), rows));
+                }
+                Some(TablePart::Caption) => captions.push(i),
+                Some(TablePart::Column) => {
+                    let span = span_attribute(child, "span").unwrap_or(1).max(1);
+                    column_widths.extend(vec![column_width(child); span]);
+                }
+                Some(TablePart::ColumnGroup) => {
+                    // Columns without a width take that of their group.
+                    let width = column_width(child);
+                    if child.children.is_empty() {
+                        let span = span_attribute(child, "span").unwrap_or(1).max(1);
+                        column_widths.extend(vec![width; span]);
+                    }
+                    for column in &child.children {
+                        let span = span_attribute(column, "span").unwrap_or(1).max(1);
+                        column_widths.extend(vec![column_width(column).or(width); span]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        sections.sort_by_key(|&(order, ..)| order);
+
+        let mut rows = Vec::new();
+        for (_, group, paths) in sections {
+            let group_end = rows.len() + paths.len();
+            rows.extend(paths.into_iter().map(|path| Row {
+                path,
+                group,
+                group_end,
+            }));
+        }
+
+        let mut taken = HashSet::new();
+        let mut cells = Vec::new();
+        let mut columns = column_widths.len();
+        for (r, row) in rows.iter().enumerate() {
+            let mut column = 0;
+            for (k, cell) in self.descendant(&row.path).children.iter().enumerate() {
+                while taken.contains(&(r, column)) {
+                    column += 1;
+                }
+                let colspan = span_attribute(cell, "colspan").unwrap_or(1).clamp(1, 1000);
+                // `rowspan="0"` spans the rest of the row group.
+                let rowspan = match span_attribute(cell, "rowspan") {
+                    Some(0) => row.group_end - r,
+                    Some(rowspan) => rowspan.min(row.group_end - r),
+                    None => 1,
+                }
+                .max(1);
+                for slot_row in r..r + rowspan {
+                    for slot_column in column..column + colspan {
+                        taken.insert((slot_row, slot_column));
+                    }
+                }
+                let mut path = row.path.clone();
+                path.push(k);
+                cells.push(Cell {
+                    path,
+                    row: r,
+                    column,
+                    rowspan,
+                    colspan,
+                    border: EdgeSizes::default(),
+                    colors: None,
+                });
+                column += colspan;
+                columns = columns.max(column);
+            }
+        }
+        column_widths.resize(columns, None);
+
+        let mut table = Table {
+            rows,
+            cells,
+            columns,
+            column_widths,
+            captions,
+            collapse,
+            spacing: if collapse {
+                (0.0, 0.0)
+            } else {
+                border_spacing(style)
+            },
+        };
+        self.resolve_cell_borders(&mut table);
+        table
+    }
+
+    /// Set the border widths of the cells of a table: their own with separated borders, and with
+    /// collapsed borders, the border that wins at each edge. Cells take half of the borders they
+    /// share with other cells, and the whole of those at the edge of the table.
+    ///
+    /// http://www.w3.org/TR/CSS2/tables.html#collapsing-borders
+    fn resolve_cell_borders(&self, table: &mut Table) {
+        if !table.collapse {
+            for cell in &mut table.cells {
+                let zero = Length(0.0, Px);
+                if let BoxType::BlockNode(style) = self.descendant(&cell.path).box_type {
+                    let width = |side: &str| {
+                        style
+                            .lookup(&format!("border-{}-width", side), &zero)
+                            .to_px()
+                    };
+                    cell.border = EdgeSizes {
+                        top: width("top"),
+                        right: width("right"),
+                        bottom: width("bottom"),
+                        left: width("left"),
+                    };
+                }
+            }
+            return;
+        }
+
+        let mut slots = HashMap::new();
+        for (i, cell) in table.cells.iter().enumerate() {
+            for row in cell.row..cell.row + cell.rowspan {
+                for column in cell.column..cell.column + cell.colspan {
+                    slots.insert((row, column), i);
+                }
+            }
+        }
+        let rows = &table.rows;
+        let cell_border = |row: usize, column: usize, side: &str| {
+            let cell = &table.cells[*slots.get(&(row, column))?];
+            Border::of(self.descendant(&cell.path), side, BorderOwner::Cell)
+        };
+        let row_border =
+            |r: usize, side| Border::of(self.descendant(&rows[r].path), side, BorderOwner::Row);
+        let group_border = |r: usize, side| {
+            let group = &self.children[rows[r].group?];
+            Border::of(group, side, BorderOwner::RowGroup)
+        };
+        let table_border = |side| Border::of(self, side, BorderOwner::Table);
+        // Whether a row starts a new row group, or the rows of the table.
+        let starts_group = |r: usize| r == 0 || rows[r - 1].group != rows[r].group;
+
+        let mut borders = Vec::new();
+        for cell in &table.cells {
+            let (r, c) = (cell.row, cell.column);
+            let last_row = r + cell.rowspan - 1;
+            let last_column = c + cell.colspan - 1;
+            let next_row = last_row + 1;
+
+            let mut top = vec![];
+            if r > 0 {
+                top.extend([cell_border(r - 1, c, "bottom"), cell_border(r, c, "top")]);
+                top.push(row_border(r - 1, "bottom"));
+            } else {
+                top.push(cell_border(r, c, "top"));
+            }
+            top.push(row_border(r, "top"));
+            if starts_group(r) {
+                if r > 0 {
+                    top.push(group_border(r - 1, "bottom"));
+                }
+                top.push(group_border(r, "top"));
+            }
+            if r == 0 {
+                top.push(table_border("top"));
+            }
+
+            let mut bottom = vec![cell_border(r, c, "bottom")];
+            bottom.push(row_border(last_row, "bottom"));
+            if next_row < rows.len() {
+                bottom.extend([cell_border(next_row, c, "top"), row_border(next_row, "top")]);
+                if starts_group(next_row) {
+                    bottom.push(group_border(last_row, "bottom"));
+                    bottom.push(group_border(next_row, "top"));
+                }
+            } else {
+                bottom.push(group_border(last_row, "bottom"));
+                bottom.push(table_border("bottom"));
+            }
+
+            let mut left = vec![];
+            if c > 0 {
+                left.extend([cell_border(r, c - 1, "right"), cell_border(r, c, "left")]);
+            } else {
+                left.push(cell_border(r, c, "left"));
+                left.push(row_border(r, "left"));
+                left.push(group_border(r, "left"));
+                left.push(table_border("left"));
+            }
+
+            let mut right = vec![cell_border(r, c, "right")];
+            if last_column + 1 < table.columns {
+                right.push(cell_border(r, last_column + 1, "left"));
+            } else {
+                right.push(row_border(r, "right"));
+                right.push(group_border(r, "right"));
+                right.push(table_border("right"));
+            }
+
+            let outer = [
+                r == 0,
+                last_column + 1 == table.columns,
+                next_row == rows.len(),
+                c == 0,
+            ];
+            let mut border = [top, right, bottom, left].map(resolve_conflict);
+            for (border, outer) in border.iter_mut().zip(outer) {
+                if !outer {
+                    border.width /= 2.0;
+                }
+            }
+            borders.push(border);
+        }
+
+        for (cell, [top, right, bottom, left]) in table.cells.iter_mut().zip(borders) {
+            cell.border = EdgeSizes {
+                top: top.width,
+                right: right.width,
+                bottom: bottom.width,
+                left: left.width,
+            };
+            cell.colors = Some([top.color, right.color, bottom.color, left.color]);
+        }
+    }
+
+    /// The specified width of a cell's border box, if it has one.
+    fn cell_width(&self, cell: &Cell) -> Option<f32> {
+        let cell_box = self.descendant(&cell.path);
+        let style = match cell_box.box_type {
+            BoxType::BlockNode(style) => style,
+            _ => return None,
+        };
+        let edges = cell_box.box_sizing_edges(["left", "right"], Dimensions::default());
+        let width = content_size(style.value("width"), None, edges)?;
+        let padding = cell_padding(cell_box, 0.0);
+        Some(width + padding.left + padding.right + cell.border.left + cell.border.right)
+    }
+
+    /// The narrowest and widest each column of a table can be, from the cells in it and the
+    /// widths of columns and cells. Columns with a width are as wide as that unless their cells
+    /// need more room, and cells that span several columns widen them together.
+    ///
+    /// http://www.w3.org/TR/CSS2/tables.html#auto-table-layout
+    fn column_limits(&self, table: &Table, fonts: &FontDatabase) -> Vec<(f32, f32)> {
+        let mut limits = vec![(0.0f32, 0.0f32); table.columns];
+        let mut widths = table.column_widths.clone();
+        let mut spanning = Vec::new();
+        for cell in &table.cells {
+            let cell_box = self.descendant(&cell.path);
+            let padding = cell_padding(cell_box, 0.0);
+            let edges = padding.left + padding.right + cell.border.left + cell.border.right;
+            let (min, max) = cell_box.intrinsic_widths(fonts);
+            let (min, max) = (min + edges, max + edges);
+            if cell.colspan > 1 {
+                spanning.push((cell, min, max.max(self.cell_width(cell).unwrap_or(0.0))));
+                continue;
+            }
+            let limit = &mut limits[cell.column];
+            *limit = (limit.0.max(min), limit.1.max(max));
+            if let Some(width) = self.cell_width(cell) {
+                let column = &mut widths[cell.column];
+                *column = Some(column.map_or(width, |column| column.max(width)));
+            }
+        }
+        for (limit, width) in limits.iter_mut().zip(widths) {
+            if let Some(width) = width {
+                *limit = (limit.0.max(width), limit.0.max(width));
+            }
+        }
+
+        spanning.sort_by_key(|(cell, ..)| cell.colspan);
+        for (cell, min, max) in spanning {
+            let columns = &mut limits[cell.column..cell.column + cell.colspan];
+            let inner_spacing = table.spacing.0 * (cell.colspan - 1) as f32;
+            let total_max = sum(columns.iter().map(|&(_, max)| max));
+            let share = |column_max: f32, count: usize| {
+                if total_max > 0.0 {
+                    column_max / total_max
+                } else {
+                    1.0 / count as f32
+                }
+            };
+            let count = columns.len();
+            let missing = min - inner_spacing - sum(columns.iter().map(|&(min, _)| min));
+            if missing > 0.0 {
+                for column in columns.iter_mut() {
+                    column.0 += missing * share(column.1, count);
+                }
+            }
+            let missing = max - inner_spacing - total_max;
+            if missing > 0.0 {
+                for column in columns.iter_mut() {
+                    column.1 += missing * share(column.1, count);
+                }
+            }
+            for column in columns.iter_mut() {
+                column.1 = column.1.max(column.0);
+            }
+        }
+        limits
+    }
+
+    /// The widths of the columns in the fixed table layout, which only looks at the columns and
+    /// the first row: columns are as wide as they or the cell in them say, and the rest of the
+    /// grid's `width` goes to the others, or to all of them if none are left.
+    ///
+    /// http://www.w3.org/TR/CSS2/tables.html#fixed-table-layout
+    fn fixed_columns(&self, table: &Table, width: f32) -> Vec<f32> {
+        let mut widths = table.column_widths.clone();
+        for cell in table.cells.iter().filter(|cell| cell.row == 0) {
+            if let Some(cell_width) = self.cell_width(cell) {
+                let inner_spacing = table.spacing.0 * (cell.colspan - 1) as f32;
+                let share = (cell_width - inner_spacing) / cell.colspan as f32;
+                for column in &mut widths[cell.column..cell.column + cell.colspan] {
+                    column.get_or_insert(share);
+                }
+            }
+        }
+        let known = sum(widths.iter().flatten().copied());
+        let unknown = widths.iter().filter(|width| width.is_none()).count();
+        let rest = (width - known).max(0.0);
+        widths
+            .into_iter()
+            .map(|column| match column {
+                Some(column) if unknown == 0 && known > 0.0 => column + rest * column / known,
+                Some(column) => column,
+                None => rest / unknown as f32,
+            })
+            .collect()
+    }
+
+    fn is_fixed_layout(&self) -> bool {
+        match self.box_type {
+            BoxType::BlockNode(style) => {
+                style.value("table-layout") == Some(Keyword("fixed".to_string()))
+                    && matches!(style.value("width"), Some(Length(..)))
+            }
+            _ => false,
+        }
+    }
+
+    /// The narrowest and the widest the content of a table can be: the widths of its columns and
+    /// the spacing between them, and no narrower than its captions.
+    pub(super) fn table_intrinsic_widths(&self, fonts: &FontDatabase) -> (f32, f32) {
+        let table = self.table();
+        let limits = self.column_limits(&table, fonts);
+        let spacing = table.grid_spacing();
+        let captions = table
+            .captions
+            .iter()
+            .map(|&i| self.children[i].outer_intrinsic_widths(fonts).0)
+            .fold(0.0, f32::max);
+        let min = (sum(limits.iter().map(|&(min, _)| min)) + spacing).max(captions);
+        let max = sum(limits.iter().map(|&(_, max)| max)) + spacing;
+        (min, max.max(min))
+    }
+
+    /// Calculate the horizontal dimensions of a table in the flow. Its width is its `width`, or
+    /// that of its content up to the width it would fill, but never narrower than its content.
+    /// With collapsed borders, the table's border and padding are left to the cells.
+    pub(super) fn solve_table_width(&mut self, containing_block: Dimensions, fonts: &FontDatabase) {
+        self.solve_block_width(containing_block, None);
+        let style = self.get_style_node();
+        let d = self.dimensions;
+        let edges = if self.table().collapse {
+            d.border.left + d.border.right + d.padding.left + d.padding.right
+        } else {
+            0.0
+        };
+        let box_sizing = self.box_sizing_edges(["left", "right"], containing_block);
+        let reference = Some(containing_block.content.width);
+        let specified = content_size(style.value("width"), reference, box_sizing);
+        let width = match specified {
+            Some(width) if self.is_fixed_layout() => {
+                let table = self.table();
+                let columns = sum(table.column_widths.iter().flatten().copied());
+                width.max(columns + table.grid_spacing())
+            }
+            Some(width) => width.max(self.table_intrinsic_widths(fonts).0),
+            None => {
+                let (min, max) = self.table_intrinsic_widths(fonts);
+                (d.content.width + edges).min(max).max(min)
+            }
+        };
+        self.solve_block_width(containing_block, Some(width - edges));
+    }
+
+    /// Lay out an anonymous table at the end of `container`, as wide as its content but no wider
+    /// than the container unless its content needs more room.
+    pub(super) fn layout_anonymous_table(&mut self, container: Dimensions, fonts: &FontDatabase) {
+        let (min, max) = self.table_intrinsic_widths(fonts);
+        let d = &mut self.dimensions;
+        *d = Dimensions::default();
+        d.content.x = container.content.x;
+        d.content.y = container.content.y + container.content.height;
+        d.content.width = container.content.width.min(max).max(min);
+        self.layout_table_children(None, fonts);
+    }
+
+    /// Lay out the captions, rows and cells of a table box whose width is known, with the content
+    /// height if it is known up front, which the rows grow to fill. Sets the content height to fit
+    /// them.
+    ///
+    /// Rows are as tall as their tallest cell, cells that span rows stretch the last of them, and
+    /// the content of each cell goes at the top, middle or bottom of its rows, or lines up with
+    /// the first lines of the other cells in its row.
+    ///
+    /// http://www.w3.org/TR/CSS2/tables.html#height-layout
+    pub(super) fn layout_table_children(&mut self, height: Option<f32>, fonts: &FontDatabase) {
+        let table = self.table();
+        if table.collapse {
+            // Collapsed borders belong to the cells, and the table's padding goes away with them.
+            let d = &mut self.dimensions;
+            d.content = d.border_box();
+            d.border = EdgeSizes::default();
+            d.padding = EdgeSizes::default();
+        }
+        let d = self.dimensions;
+        let (h, v) = table.spacing;
+        let grid_width = (d.content.width - table.grid_spacing()).max(0.0);
+        let widths = if self.is_fixed_layout() {
+            self.fixed_columns(&table, grid_width)
+        } else {
+            distribute(&self.column_limits(&table, fonts), grid_width)
+        };
+        let mut x = d.content.x + h;
+        let lefts: Vec<f32> = widths
+            .iter()
+            .map(|width| {
+                let left = x;
+                x += width + h;
+                left
+            })
+            .collect();
+        let grid_right = x - h;
+
+        self.dimensions.content.height = 0.0;
+        self.layout_captions(&table.captions, false, fonts);
+        let grid_top = self.dimensions.content.y + self.dimensions.content.height;
+
+        // Lay the cells out at the top of the grid first, to find how much room they need.
+        for cell in &table.cells {
+            let columns = cell.column..cell.column + cell.colspan;
+            let width = sum(widths[columns].iter().copied()) + h * (cell.colspan - 1) as f32;
+            let cell_box = self.descendant_mut(&cell.path);
+            cell_box.layout_cell(
+                cell,
+                lefts[cell.column],
+                grid_top,
+                width,
+                d.content.width,
+                fonts,
+            );
+        }
+
+        let aligns: Vec<CellAlign> = table
+            .cells
+            .iter()
+            .map(|cell| CellAlign::of(self.descendant(&cell.path)))
+            .collect();
+        // How far the baseline of each cell is from its top, or the bottom of its content if it
+        // has no lines.
+        let offsets: Vec<f32> = table
+            .cells
+            .iter()
+            .map(|cell| {
+                let cell_box = self.descendant(&cell.path);
+                let content = cell_box.dimensions.content;
+                let baseline =
+                    inline::first_baseline(cell_box, fonts).unwrap_or(content.y + content.height);
+                baseline - cell_box.dimensions.border_box().y
+            })
+            .collect();
+        let mut baselines = vec![0.0f32; table.rows.len()];
+        for (i, cell) in table.cells.iter().enumerate() {
+            if aligns[i] == CellAlign::Baseline {
+                baselines[cell.row] = baselines[cell.row].max(offsets[i]);
+            }
+        }
+
+        let mut heights: Vec<f32> = table
+            .rows
+            .iter()
+            .map(|row| match self.descendant(&row.path).box_type {
+                BoxType::BlockNode(style) => content_size(style.value("height"), None, 0.0),
+                _ => None,
+            })
+            .map(|height| height.unwrap_or(0.0))
+            .collect();
+        let mut spanning = Vec::new();
+        for (i, cell) in table.cells.iter().enumerate() {
+            let mut needed = self.descendant(&cell.path).dimensions.border_box().height;
+            if aligns[i] == CellAlign::Baseline {
+                needed += baselines[cell.row] - offsets[i];
+            }
+            if cell.rowspan > 1 {
+                spanning.push((cell, needed));
+            } else {
+                heights[cell.row] = heights[cell.row].max(needed);
+            }
+        }
+        spanning.sort_by_key(|(cell, _)| cell.rowspan);
+        for (cell, needed) in spanning {
+            let last_row = cell.row + cell.rowspan - 1;
+            let spanned = sum(heights[cell.row..=last_row].iter().copied());
+            let missing = needed - spanned - v * (cell.rowspan - 1) as f32;
+            if missing > 0.0 {
+                heights[last_row] += missing;
+            }
+        }
+        if let (Some(height), false) = (height, table.rows.is_empty()) {
+            let rows = heights.len() as f32;
+            let used = sum(heights.iter().copied()) + v * (rows + 1.0);
+            let extra = height - (grid_top - d.content.y) - used;
+            if extra > 0.0 {
+                for row_height in &mut heights {
+                    *row_height += extra / rows;
+                }
+            }
+        }
+
+        let mut y = grid_top + v;
+        let tops: Vec<f32> = heights
+            .iter()
+            .map(|height| {
+                let top = y;
+                y += height + v;
+                top
+            })
+            .collect();
+        let grid_bottom = if table.rows.is_empty() { grid_top } else { y };
+
+        let grid_left = d.content.x + h;
+        let row_rect = |first: usize, last: usize| Dimensions {
+            content: Rect {
+                x: grid_left,
+                y: tops[first],
+                width: (grid_right - grid_left).max(0.0),
+                height: tops[last] + heights[last] - tops[first],
+            },
+            ..Default::default()
+        };
+        for (r, row) in table.rows.iter().enumerate() {
+            self.descendant_mut(&row.path).dimensions = row_rect(r, r);
+            if let Some(group) = row.group {
+                let first = table.rows.iter().position(|row| row.group == Some(group));
+                self.children[group].dimensions = row_rect(first.unwrap_or(r), r);
+            }
+        }
+
+        for (i, cell) in table.cells.iter().enumerate() {
+            let top = tops[cell.row];
+            let last_row = cell.row + cell.rowspan - 1;
+            let bottom = tops[last_row] + heights[last_row];
+            let cell_box = self.descendant_mut(&cell.path);
+            let border_box = cell_box.dimensions.border_box();
+            cell_box.translate(0.0, top - border_box.y);
+            let d = cell_box.dimensions;
+            let edges = d.border.top + d.padding.top + d.padding.bottom + d.border.bottom;
+            let height = bottom - top - edges;
+            let shift = match aligns[i] {
+                CellAlign::Top => 0.0,
+                CellAlign::Middle => (height - d.content.height) / 2.0,
+                CellAlign::Bottom => height - d.content.height,
+                CellAlign::Baseline => baselines[cell.row] - offsets[i],
+            };
+            for child in &mut cell_box.children {
+                child.translate(0.0, shift);
+            }
+            cell_box.dimensions.content.height = height;
+        }
+
+        self.dimensions.content.height = grid_bottom - self.dimensions.content.y;
+        self.layout_captions(&table.captions, true, fonts);
+    }
+
+    /// Lay out the captions of a table that go above its grid, or those that go below, after what
+    /// has been laid out so far.
+    fn layout_captions(&mut self, captions: &[usize], bottom: bool, fonts: &FontDatabase) {
+        for &i in captions {
+            let side = match self.children[i].box_type {
+                BoxType::BlockNode(style) => style.value("caption-side"),
+                _ => None,
+            };
+            if (side == Some(Keyword("bottom".to_string()))) != bottom {
+                continue;
+            }
+            let container = self.dimensions;
+            let mut floats = FloatContext::default();
+            let caption = &mut self.children[i];
+            let (below, _) = caption.layout_block(container, None, None, &mut floats, fonts);
+            let border_box = caption.dimensions.border_box();
+            let d = &mut self.dimensions;
+            d.content.height = border_box.y + border_box.height + below.resolve() - d.content.y;
+        }
+    }
+
+    /// Lay out the content of a table cell with the given border box width at (`x`, `y`), and
+    /// set its height to fit the content, or its `height` if that is more.
+    fn layout_cell(
+        &mut self,
+        cell: &Cell,
+        x: f32,
+        y: f32,
+        width: f32,
+        table_width: f32,
+        fonts: &FontDatabase,
+    ) {
+        let padding = cell_padding(self, table_width);
+        let border = cell.border;
+        let d = &mut self.dimensions;
+        *d = Dimensions::default();
+        d.border = border;
+        d.padding = padding;
+        d.content.x = x + border.left + padding.left;
+        d.content.y = y + border.top + padding.top;
+        d.content.width =
+            (width - border.left - padding.left - padding.right - border.right).max(0.0);
+        self.border_colors = cell.colors.clone();
+        self.layout_contents(None, fonts);
+
+        if let BoxType::BlockNode(style) = self.box_type {
+            let edges = self.box_sizing_edges(["top", "bottom"], Dimensions::default());
+            if let Some(height) = content_size(style.value("height"), None, edges) {
+                let d = &mut self.dimensions;
+                d.content.height = d.content.height.max(height);
+            }
+            if Position::of(style) != Position::Static {
+                self.layout_absolute_descendants(self.padding_box_as_containing_block(), fonts);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::testing::Page;
+    use super::*;
+
+    fn border(width: f32, style: &str, owner: BorderOwner) -> Option<Border> {
+        Some(Border {
+            width,
+            style: BORDER_STYLES
+                .iter()
+                .position(|&name| name == style)
+                .unwrap(),
+            color: None,
+            owner,
+        })
+    }
+
+    #[test]
+    fn test_resolve_conflict() {
+        let resolve = |borders| {
+            let winner = resolve_conflict(borders);
+            (winner.width, BORDER_STYLES[winner.style], winner.owner)
+        };
+        // Wider borders win, then stronger styles, then borders on cells over rows and tables.
+        assert_eq!(
+            resolve(vec![
+                border(1.0, "solid", BorderOwner::Cell),
+                border(3.0, "dotted", BorderOwner::Table),
+            ]),
+            (3.0, "dotted", BorderOwner::Table)
+        );
+        assert_eq!(
+            resolve(vec![
+                border(2.0, "dashed", BorderOwner::Cell),
+                border(2.0, "double", BorderOwner::Row),
+            ]),
+            (2.0, "double", BorderOwner::Row)
+        );
+        assert_eq!(
+            resolve(vec![
+                border(2.0, "solid", BorderOwner::Table),
+                None,
+                border(2.0, "solid", BorderOwner::Cell),
+            ]),
+            (2.0, "solid", BorderOwner::Cell)
+        );
+        // `none` loses to everything, and `hidden` wins over everything.
+        assert_eq!(
+            resolve(vec![
+                border(5.0, "none", BorderOwner::Cell),
+                border(1.0, "inset", BorderOwner::Table),
+            ]),
+            (1.0, "inset", BorderOwner::Table)
+        );
+        assert_eq!(
+            resolve(vec![
+                border(5.0, "solid", BorderOwner::Cell),
+                border(0.0, "hidden", BorderOwner::Table),
+            ]),
+            (0.0, "none", BorderOwner::Table)
+        );
+    }
+
+    #[test]
+    fn test_distribute() {
+        let limits = [(10.0, 30.0), (20.0, 20.0), (0.0, 50.0)];
+        // Between the minimum and maximum widths, columns grow in proportion to how much wider
+        // they can get.
+        assert_eq!(distribute(&limits, 30.0), vec![10.0, 20.0, 0.0]);
+        assert_eq!(distribute(&limits, 65.0), vec![20.0, 20.0, 25.0]);
+        // Past the maximum, in proportion to their maximum.
+        assert_eq!(distribute(&limits, 200.0), vec![60.0, 40.0, 100.0]);
+        assert_eq!(distribute(&[(0.0, 0.0), (0.0, 0.0)], 10.0), vec![5.0, 5.0]);
+    }
+
+    /// The row, column, row span and column span of every cell of a table.
+    fn slots(html: &str) -> Vec<(usize, usize, usize, usize)> {
+        let mut slots = Vec::new();
+        Page {
+            html,
+            ..Default::default()
+        }
+        .layout(|table| {
+            slots = table
+                .table()
+                .cells
+                .iter()
+                .map(|cell| (cell.row, cell.column, cell.rowspan, cell.colspan))
+                .collect();
+        });
+        slots
+    }
+
+    #[test]
+    fn test_span_slots() {
+        // Cells skip the slots of row spans from rows above. `rowspan="0"` reaches the end of the
+        // group, longer row spans stop there, and invalid column spans count as one.
+        let html = "<table><tr><td colspan=\"2\"></td><td rowspan=\"0\"></td></tr>\
+                    <tr><td rowspan=\"3\"></td><td></td></tr>\
+                    <tr><td colspan=\"0\"></td><td colspan=\"x\"></td></tr></table>";
+        assert_eq!(
+            slots(html),
+            [
+                (0, 0, 1, 2),
+                (0, 2, 3, 1),
+                (1, 0, 2, 1),
+                (1, 1, 1, 1),
+                (2, 1, 1, 1),
+                (2, 3, 1, 1),
+            ]
+        );
+        // Row spans do not cross into the next row group.
+        let html = "<table><tbody><tr><td rowspan=\"2\"></td></tr></tbody>\
+                    <tbody><tr><td></td></tr></tbody></table>";
+        assert_eq!(slots(html), [(0, 0, 1, 1), (1, 0, 1, 1)]);
+    }
+
+    #[test]
+    fn test_span_widths() {
+        let html = "<table><tr><td colspan=\"2\" class=\"wide\"></td></tr>\
+                    <tr><td class=\"a\"></td><td class=\"b\"></td></tr></table>";
+        let css = "table { border-spacing: 0px; } td { padding: 0px; } .wide { width: 90px; } \
+                   .a { width: 10px; } .b { width: 20px; }";
+        Page {
+            html,
+            css,
+            ..Default::default()
+        }
+        .layout(|table| {
+            let width = |row: usize, cell: usize| {
+                let content = table.children[row].children[cell].dimensions.content;
+                (content.x, content.width)
+            };
+            assert_eq!(width(0, 0), (0.0, 90.0));
+            // The columns it spans share the width it needs in proportion to their own.
+            assert_eq!(width(1, 0), (0.0, 30.0));
+            assert_eq!(width(1, 1), (30.0, 60.0));
+        });
+    }
+}
@@ -1,6 +1,11 @@
+pub mod bloom;
 pub mod css;
 pub mod display;
 pub mod dom;
+pub mod font;
+pub mod images;
 pub mod layout;
 pub mod parser;
 pub mod style;
+#[cfg(test)]
+mod testing;
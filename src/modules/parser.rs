@@ -2,6 +2,17 @@ use super::css;
 use super::dom;
 use std::cmp::Reverse;
 
+/// The default stylesheet applied beneath author styles, after the HTML rendering section.
+const USER_AGENT_CSS: &str = include_str!("ua.css");
+
+/// Elements that never have children, and so have no end tag.
+///
+/// https://html.spec.whatwg.org/multipage/syntax.html#void-elements
+const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
 pub fn parse_html(source: String) -> dom::Node {
     let mut nodes = Parser {
         pos: 0,
@@ -21,16 +32,49 @@ pub fn parse_css(source: String, node: &dom::Node) -> css::Stylesheet {
         pos: 0,
         input: source,
     };
-    let mut rules = parser.parse_rules();
-    let mut style_node = parse_style_node(node);
-    rules.append(&mut style_node);
+    let (mut rules, mut font_faces) = parser.parse_rule_list();
+    let (mut style_rules, mut style_font_faces) = parse_style_node(node);
+    rules.append(&mut style_rules);
+    font_faces.append(&mut style_font_faces);
     css::Stylesheet {
+        origin: css::Origin::Author,
         rules,
+        font_faces,
+    }
+}
+
+pub fn parse_user_agent_css() -> css::Stylesheet {
+    let mut parser = Parser {
+        pos: 0,
+        input: USER_AGENT_CSS.to_string(),
+    };
+    let (rules, font_faces) = parser.parse_rule_list();
+    css::Stylesheet {
+        origin: css::Origin::UserAgent,
+        rules,
+        font_faces,
+    }
+}
+
+/// Parse a single selector, e.g. for `StyledNode::query_selector`, or `None` if `source` is not
+/// one, like `div >` or `#`.
+pub fn parse_selector(source: String) -> Option<css::Selector> {
+    let mut parser = Parser {
+        pos: 0,
+        input: source,
+    };
+    parser.consume_whitespace();
+    let selector = parser.parse_selector()?;
+    if parser.eof() {
+        Some(selector)
+    } else {
+        None
     }
 }
 
-fn parse_style_node(node: &dom::Node) -> Vec::<css::Rule> {
+fn parse_style_node(node: &dom::Node) -> (Vec<css::Rule>, Vec<css::FontFaceRule>) {
     let mut rules = Vec::new();
+    let mut font_faces = Vec::new();
     if let dom::NodeType::Element(ref data) = node.node_type {
         if data.tag_name == "style" {
             for child in &node.children {
@@ -39,16 +83,20 @@ fn parse_style_node(node: &dom::Node) -> Vec::<css::Rule> {
                         pos: 0,
                         input: text.clone(),
                     };
-                    rules.append(&mut parser.parse_rules());
+                    let (mut child_rules, mut child_font_faces) = parser.parse_rule_list();
+                    rules.append(&mut child_rules);
+                    font_faces.append(&mut child_font_faces);
                 }
             }
         } else {
             for child in &node.children {
-                rules.append(&mut parse_style_node(child));
+                let (mut child_rules, mut child_font_faces) = parse_style_node(child);
+                rules.append(&mut child_rules);
+                font_faces.append(&mut child_font_faces);
             }
         }
     }
-    rules
+    (rules, font_faces)
 }
 
 struct Parser {
@@ -77,9 +125,9 @@ impl Parser {
         cur_char
     }
 
-    fn consume_while<F>(&mut self, test: F) -> String
+    fn consume_while<F>(&mut self, mut test: F) -> String
     where
-        F: Fn(char) -> bool,
+        F: FnMut(char) -> bool,
     {
         let mut result = String::new();
         while !self.eof() && test(self.next_char()) {
@@ -137,8 +185,15 @@ impl Parser {
         self.consume_char();
         let tag_name = self.parse_tag_name();
         let attrs = self.parse_attributes();
+        // Void elements end with their start tag, which may be written self-closing.
+        if self.starts_with("/>") {
+            self.consume_char();
+        }
         // FIX ME: When format is wrong
         self.consume_char();
+        if VOID_ELEMENTS.contains(&&*tag_name.to_ascii_lowercase()) {
+            return dom::Node::elem(tag_name, attrs, Vec::new());
+        }
 
         let children = self.parse_nodes();
 
@@ -170,7 +225,7 @@ impl Parser {
         let mut attributes = dom::AttrMap::new();
         loop {
             self.consume_whitespace();
-            if self.next_char() == '>' {
+            if self.next_char() == '>' || self.starts_with("/>") {
                 break;
             }
             let (name, value) = self.parse_attr();
@@ -195,23 +250,60 @@ impl Parser {
 
     // CSS Part Below
 
-    fn parse_rules(&mut self) -> Vec<css::Rule> {
+    /// Parse style rules along with `@font-face` rules. Other at-rules are skipped.
+    fn parse_rule_list(&mut self) -> (Vec<css::Rule>, Vec<css::FontFaceRule>) {
         let mut rules = Vec::new();
+        let mut font_faces = Vec::new();
         loop {
             self.consume_whitespace();
             if self.eof() {
                 break;
             }
-            rules.push(self.parse_rule());
+            if self.next_char() == '@' {
+                self.consume_char();
+                let name = self.parse_identifier();
+                self.consume_whitespace();
+                if name.eq_ignore_ascii_case("font-face") {
+                    font_faces.push(css::FontFaceRule {
+                        declarations: self.parse_declarations(),
+                    });
+                } else {
+                    self.skip_at_rule();
+                }
+            } else {
+                rules.push(self.parse_rule());
+            }
         }
-        rules
+        (rules, font_faces)
     }
 
-    fn parse_simple_selector(&mut self) -> css::SimpleSelector {
+    /// Skip the rest of an at-rule: up to a `;`, or past its block including any nested blocks.
+    fn skip_at_rule(&mut self) {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.consume_char() {
+                ';' if depth == 0 => break,
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Parse a simple selector, or `None` if there is none here, or if an id, class or
+    /// pseudo-element in it has no name, as in `#` or `div.`.
+    fn parse_simple_selector(&mut self) -> Option<css::SimpleSelector> {
+        let start = self.pos;
         let mut selector = css::SimpleSelector {
             tag_name: None,
             id: None,
             class: Vec::new(),
+            pseudo_element: None,
         };
         while !self.eof() {
             match self.next_char() {
@@ -226,13 +318,26 @@ impl Parser {
                 '*' => {
                     self.consume_char();
                 }
+                ':' if self.starts_with("::") => {
+                    self.consume_char();
+                    self.consume_char();
+                    selector.pseudo_element = Some(self.parse_identifier());
+                }
                 c if valid_identifier_char(c) => {
                     selector.tag_name = Some(self.parse_identifier());
                 }
                 _ => break,
             }
         }
-        selector
+        let mut names = selector
+            .id
+            .iter()
+            .chain(&selector.class)
+            .chain(&selector.pseudo_element);
+        if self.pos == start || names.any(String::is_empty) {
+            return None;
+        }
+        Some(selector)
     }
 
     fn parse_rule(&mut self) -> css::Rule {
@@ -242,19 +347,53 @@ impl Parser {
         }
     }
 
+    /// Parse a selector, or `None` if any part of it is invalid, like a combinator with nothing
+    /// after it.
+    fn parse_selector(&mut self) -> Option<css::Selector> {
+        let mut selector = css::Selector::Simple(self.parse_simple_selector()?);
+        loop {
+            self.consume_whitespace();
+            if self.eof() {
+                break;
+            }
+            let combinator = match self.next_char() {
+                '>' => {
+                    self.consume_char();
+                    self.consume_whitespace();
+                    css::Combinator::Child
+                }
+                '#' | '.' | '*' => css::Combinator::Descendant,
+                c if valid_identifier_char(c) => css::Combinator::Descendant,
+                _ => break,
+            };
+            let right = self.parse_simple_selector()?;
+            selector = css::Selector::Complex(Box::new(selector), combinator, right);
+        }
+        Some(selector)
+    }
+
+    /// Parse the comma-separated selectors of a rule, up to its `{`. If any of them is invalid,
+    /// the rule gets no selectors at all and so matches nothing.
+    ///
+    /// https://www.w3.org/TR/selectors-3/#grouping
     fn parse_selectors(&mut self) -> Vec<css::Selector> {
         let mut selectors: Vec<css::Selector> = Vec::new();
         loop {
-            selectors.push(css::Selector::Simple(self.parse_simple_selector()));
+            let selector = self.parse_selector();
             self.consume_whitespace();
-            match self.next_char() {
-                ',' => {
+            match (selector, self.next_char()) {
+                (Some(selector), ',') => {
+                    selectors.push(selector);
                     self.consume_char();
                     self.consume_whitespace();
                 }
-                '{' => break,
-                _c => {
-                    // FIX ME: When format is wrong
+                (Some(selector), '{') => {
+                    selectors.push(selector);
+                    break;
+                }
+                _ => {
+                    self.consume_while(|c| c != '{');
+                    return Vec::new();
                 }
             }
         }
@@ -262,6 +401,7 @@ impl Parser {
         selectors
     }
 
+    /// Parse a block of declarations, dropping those with an invalid value.
     fn parse_declarations(&mut self) -> Vec<css::Declaration> {
         assert_eq!(self.consume_char(), '{');
         let mut declarations = Vec::new();
@@ -271,61 +411,232 @@ impl Parser {
                 self.consume_char();
                 break;
             }
-            declarations.push(self.parse_declaration());
+            declarations.extend(self.parse_declaration());
         }
         declarations
     }
 
-    fn parse_declaration(&mut self) -> css::Declaration {
+    fn parse_declaration(&mut self) -> Option<css::Declaration> {
         let property_name = self.parse_identifier();
         self.consume_whitespace();
         assert_eq!(self.consume_char(), ':');
         self.consume_whitespace();
-        let value = self.parse_value();
-        self.consume_whitespace();
+        let value = self.parse_value_list();
         assert_eq!(self.consume_char(), ';');
 
-        css::Declaration {
+        Some(css::Declaration {
             name: property_name,
-            value,
-        }
+            value: value?,
+        })
     }
 
     fn parse_identifier(&mut self) -> String {
         self.consume_while(valid_identifier_char)
     }
 
-    fn parse_value(&mut self) -> css::Value {
-        match self.next_char() {
-            '0'..='9' => self.parse_length(),
+    /// Parse a declaration value up to the closing `;`. Comma-separated values become a
+    /// `Value::List`, and space-separated ones a `Value::Sequence`. Returns `None` if a part of
+    /// the value is invalid, like a number without digits, leaving the parser at the `;`.
+    fn parse_value_list(&mut self) -> Option<css::Value> {
+        let mut values = Vec::new();
+        let mut current: Option<css::Value> = None;
+        loop {
+            self.consume_whitespace();
+            if self.eof() || matches!(self.next_char(), ';' | '}') {
+                break;
+            }
+            if self.next_char() == ',' {
+                self.consume_char();
+                values.extend(current.take());
+                continue;
+            }
+            if self.next_char() == '!' {
+                // `!important` ends the value; importance isn't part of the cascade yet, so the
+                // flag is dropped.
+                self.consume_while(|c| c != ';' && c != '}');
+                break;
+            }
+            let start = self.pos;
+            let value = match self.parse_value() {
+                Some(value) => value,
+                None => {
+                    self.consume_while(|c| c != ';' && c != '}');
+                    return None;
+                }
+            };
+            if self.pos == start {
+                // Skip characters no value starts with.
+                self.consume_char();
+                continue;
+            }
+            current = Some(match (current, value) {
+                (None, value) => value,
+                (Some(css::Value::Sequence(mut values)), value) => {
+                    values.push(value);
+                    css::Value::Sequence(values)
+                }
+                (Some(current), value) => css::Value::Sequence(vec![current, value]),
+            });
+        }
+        values.extend(current);
+        if values.len() == 1 {
+            values.pop()
+        } else {
+            Some(css::Value::List(values))
+        }
+    }
+
+    fn parse_value(&mut self) -> Option<css::Value> {
+        let value = match self.next_char() {
+            '0'..='9' | '.' => return self.parse_length(),
+            '-' | '+' if self.starts_number() => return self.parse_length(),
             '#' => self.parse_color(),
-            _ => css::Value::Keyword(self.parse_identifier()),
+            '"' | '\'' => css::Value::String(self.parse_string()),
+            // Line names like `[header-start]` in grid track lists, kept as written.
+            '[' => css::Value::Keyword(self.parse_bracketed()),
+            '/' => css::Value::Keyword(self.consume_char().to_string()),
+            _ => {
+                let identifier = self.parse_identifier();
+                if !self.eof() && self.next_char() == '(' {
+                    return self.parse_function(identifier);
+                } else {
+                    css::Value::Keyword(identifier)
+                }
+            }
+        };
+        Some(value)
+    }
+
+    fn parse_string(&mut self) -> String {
+        let quote = self.consume_char();
+        let string = self.consume_while(|c| c != quote);
+        if !self.eof() {
+            self.consume_char();
         }
+        string
     }
 
-    fn parse_length(&mut self) -> css::Value {
-        css::Value::Length(self.parse_float(), self.parse_unit())
+    fn parse_bracketed(&mut self) -> String {
+        let mut text = self.consume_while(|c| c != ']');
+        if !self.eof() {
+            text.push(self.consume_char());
+        }
+        text
     }
 
-    fn parse_float(&mut self) -> f32 {
-        let s = self.consume_while(|c| matches!(c, '0'..='9' | '.'));
-        s.parse().unwrap()
+    /// Parse the arguments of a function like `url(font.ttf)`, after its name. Functions other
+    /// than `url()` and gradients are kept as keywords holding their source text, nested
+    /// functions included, as in `repeat(2, minmax(10px, 1fr))`. Returns `None` for an invalid
+    /// gradient.
+    fn parse_function(&mut self, name: String) -> Option<css::Value> {
+        assert_eq!(self.consume_char(), '(');
+        self.consume_whitespace();
+        let argument = match self.next_char() {
+            '"' | '\'' => self.parse_string(),
+            _ => {
+                let mut depth = 0;
+                self.consume_while(|c| {
+                    match c {
+                        '(' => depth += 1,
+                        ')' if depth == 0 => return false,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    true
+                })
+                .trim_end()
+                .to_string()
+            }
+        };
+        self.consume_while(|c| c != ')');
+        if !self.eof() {
+            self.consume_char();
+        }
+        let value = if name.eq_ignore_ascii_case("url") {
+            css::Value::Url(argument)
+        } else if is_gradient(&name) {
+            css::Value::Gradient(Box::new(parse_gradient(&name, &argument)?))
+        } else {
+            css::Value::Keyword(format!("{}({})", name, argument))
+        };
+        Some(value)
     }
 
-    fn parse_unit(&mut self) -> css::Unit {
-        match &*self.parse_identifier().to_ascii_lowercase() {
-            "px" => css::Unit::Px,
-            _ => panic!("unrecognized unit"),
+    /// Parse a number with an optional unit or `%`, or `None` if the number or the unit is
+    /// invalid, as in `.` or `10vw`.
+    fn parse_length(&mut self) -> Option<css::Value> {
+        let number = self.parse_float()?;
+        let value = if self.starts_with("%") {
+            self.consume_char();
+            css::Value::Length(number, css::Unit::Percent)
+        } else if !self.eof() && valid_identifier_char(self.next_char()) {
+            let (unit, scale) = self.parse_unit()?;
+            css::Value::Length(number * scale, unit)
+        } else {
+            css::Value::Number(number)
+        };
+        Some(value)
+    }
+
+    fn parse_float(&mut self) -> Option<f32> {
+        let negative = self.starts_with("-");
+        if negative || self.starts_with("+") {
+            self.consume_char();
         }
+        let s = self.consume_while(|c| matches!(c, '0'..='9' | '.'));
+        let number: f32 = s.parse().ok()?;
+        if negative {
+            Some(-number)
+        } else {
+            Some(number)
+        }
+    }
+
+    /// Whether a sign at the current position starts a number rather than an identifier.
+    fn starts_number(&self) -> bool {
+        self.input[self.pos + 1..].starts_with(|c: char| c.is_ascii_digit() || c == '.')
+    }
+
+    /// Parse a unit, with what to scale the number before it by to convert it to that unit.
+    /// Angles are all converted to degrees.
+    fn parse_unit(&mut self) -> Option<(css::Unit, f32)> {
+        let unit = match &*self.parse_identifier().to_ascii_lowercase() {
+            "px" => (css::Unit::Px, 1.0),
+            "em" => (css::Unit::Em, 1.0),
+            "fr" => (css::Unit::Fr, 1.0),
+            "deg" => (css::Unit::Deg, 1.0),
+            "grad" => (css::Unit::Deg, 0.9),
+            "rad" => (css::Unit::Deg, 180.0 / std::f32::consts::PI),
+            "turn" => (css::Unit::Deg, 360.0),
+            _ => return None,
+        };
+        Some(unit)
     }
 
+    /// Parse a `#rrggbb` color, or `#rrggbbaa` with an alpha.
     fn parse_color(&mut self) -> css::Value {
         assert_eq!(self.consume_char(), '#');
+        let (red, green, blue) = (
+            self.parse_hex_pair(),
+            self.parse_hex_pair(),
+            self.parse_hex_pair(),
+        );
+        let has_alpha = self.input[self.pos..]
+            .chars()
+            .take(2)
+            .filter(char::is_ascii_hexdigit)
+            .count()
+            == 2;
+        let alpha = if has_alpha {
+            self.parse_hex_pair()
+        } else {
+            255
+        };
         css::Value::Color(css::Color {
-            red: self.parse_hex_pair(),
-            green: self.parse_hex_pair(),
-            blue: self.parse_hex_pair(),
-            alpha: 255,
+            red,
+            green,
+            blue,
+            alpha,
         })
     }
 
@@ -342,6 +653,198 @@ fn valid_identifier_char(c: char) -> bool {
     matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_')
 }
 
+/// Parse the arguments of a gradient function, like `to right, #ff0000, #0000ff` for
+/// `linear-gradient`, or return `None` for other functions and arguments that are not valid.
+///
+/// http://www.w3.org/TR/css-images-3/#gradients
+/// Whether a function is a gradient, repeating or not.
+fn is_gradient(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    matches!(
+        name.strip_prefix("repeating-").unwrap_or(&name),
+        "linear-gradient" | "radial-gradient" | "conic-gradient"
+    )
+}
+
+fn parse_gradient(name: &str, arguments: &str) -> Option<css::Gradient> {
+    let name = name.to_ascii_lowercase();
+    let (repeating, name) = match name.strip_prefix("repeating-") {
+        Some(name) => (true, name),
+        None => (false, &*name),
+    };
+    if !is_gradient(name) {
+        return None;
+    }
+    let mut parser = Parser {
+        pos: 0,
+        input: arguments.to_string(),
+    };
+    let mut items: Vec<Vec<css::Value>> = match parser.parse_value_list()? {
+        css::Value::List(items) => items,
+        item => vec![item],
+    }
+    .into_iter()
+    .map(|item| item.parts().to_vec())
+    .collect();
+    // The shape comes first, unless it is left out.
+    let shape = match items.first() {
+        Some(item) if item.first().and_then(stop_color).is_none() => items.remove(0),
+        _ => Vec::new(),
+    };
+    let shape = match name {
+        "linear-gradient" => css::GradientShape::Linear(gradient_line(&shape)?),
+        "radial-gradient" => radial_shape(&shape)?,
+        _ => conic_shape(&shape)?,
+    };
+
+    // Each stop can have two positions, which makes two stops of the same color, and a hint can
+    // come between two stops.
+    let (mut stops, mut hint) = (Vec::new(), None);
+    for item in items {
+        match item.as_slice() {
+            [position] if stop_color(position).is_none() => {
+                if stops.is_empty() || hint.is_some() {
+                    return None;
+                }
+                hint = Some(position.clone());
+            }
+            [color] => stops.push(css::ColorStop {
+                color: stop_color(color)?,
+                position: None,
+                hint: hint.take(),
+            }),
+            [color, positions @ ..] if positions.len() <= 2 => {
+                let color = stop_color(color)?;
+                for position in positions {
+                    stops.push(css::ColorStop {
+                        color: color.clone(),
+                        position: Some(position.clone()),
+                        hint: hint.take(),
+                    });
+                }
+            }
+            _ => return None,
+        }
+    }
+    if stops.len() < 2 || hint.is_some() {
+        return None;
+    }
+    Some(css::Gradient {
+        shape,
+        repeating,
+        stops,
+    })
+}
+
+fn keyword(value: &css::Value) -> Option<&str> {
+    match value {
+        css::Value::Keyword(keyword) => Some(keyword),
+        _ => None,
+    }
+}
+
+/// The color of a color stop: a hex color, or `transparent`.
+fn stop_color(value: &css::Value) -> Option<css::Color> {
+    match value {
+        css::Value::Color(color) => Some(color.clone()),
+        css::Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("transparent") => {
+            Some(css::Color {
+                red: 0,
+                green: 0,
+                blue: 0,
+                alpha: 0,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// An angle, or a unitless zero.
+fn angle(value: &css::Value) -> Option<f32> {
+    match *value {
+        css::Value::Length(angle, css::Unit::Deg) => Some(angle),
+        css::Value::Number(0.0) => Some(0.0),
+        _ => None,
+    }
+}
+
+/// The direction of a linear gradient, from an angle or `to` and one or two sides. Gradients go
+/// down by default.
+fn gradient_line(shape: &[css::Value]) -> Option<css::GradientLine> {
+    let sides = match shape {
+        [] => return Some(css::GradientLine::Angle(180.0)),
+        [value] => return angle(value).map(css::GradientLine::Angle),
+        [to, sides @ ..] if keyword(to) == Some("to") && sides.len() <= 2 => sides,
+        _ => return None,
+    };
+    let (mut x, mut y) = (0.0, 0.0);
+    for side in sides {
+        match keyword(side)? {
+            "left" if x == 0.0 => x = -1.0,
+            "right" if x == 0.0 => x = 1.0,
+            "top" if y == 0.0 => y = -1.0,
+            "bottom" if y == 0.0 => y = 1.0,
+            _ => return None,
+        }
+    }
+    Some(match (x, y) {
+        (x, y) if x != 0.0 && y != 0.0 => css::GradientLine::Corner(x, y),
+        (x, _) if x != 0.0 => css::GradientLine::Angle(if x > 0.0 { 90.0 } else { 270.0 }),
+        (_, y) => css::GradientLine::Angle(if y < 0.0 { 0.0 } else { 180.0 }),
+    })
+}
+
+/// Split the shape of a radial or conic gradient at `at`, before the position of its center.
+fn split_at_position(shape: &[css::Value]) -> (&[css::Value], Vec<css::Value>) {
+    match shape.iter().position(|value| keyword(value) == Some("at")) {
+        Some(at) => (&shape[..at], shape[at + 1..].to_vec()),
+        None => (shape, Vec::new()),
+    }
+}
+
+/// The ending shape and position of a radial gradient: an ellipse to the farthest corner unless
+/// set otherwise, or a circle if it has one radius.
+fn radial_shape(shape: &[css::Value]) -> Option<css::GradientShape> {
+    let (ending_shape, position) = split_at_position(shape);
+    let (mut circle, mut size, mut radii) = (None, None, Vec::new());
+    for value in ending_shape {
+        match keyword(value) {
+            Some("circle") => circle = Some(true),
+            Some("ellipse") => circle = Some(false),
+            Some("closest-side") => size = Some(css::RadialSize::ClosestSide),
+            Some("farthest-side") => size = Some(css::RadialSize::FarthestSide),
+            Some("closest-corner") => size = Some(css::RadialSize::ClosestCorner),
+            Some("farthest-corner") => size = Some(css::RadialSize::FarthestCorner),
+            Some(_) => return None,
+            None => radii.push(value.clone()),
+        }
+    }
+    let circle = circle.unwrap_or(radii.len() == 1);
+    if !radii.is_empty() {
+        // A circle has one radius and an ellipse two.
+        if size.is_some() || radii.len() != if circle { 1 } else { 2 } {
+            return None;
+        }
+        size = Some(css::RadialSize::Radii(radii));
+    }
+    Some(css::GradientShape::Radial {
+        circle,
+        size: size.unwrap_or(css::RadialSize::FarthestCorner),
+        position,
+    })
+}
+
+/// The starting angle and position of a conic gradient, which starts at the top by default.
+fn conic_shape(shape: &[css::Value]) -> Option<css::GradientShape> {
+    let (from, position) = split_at_position(shape);
+    let from = match from {
+        [] => 0.0,
+        [keyword_from, value] if keyword(keyword_from) == Some("from") => angle(value)?,
+        _ => return None,
+    };
+    Some(css::GradientShape::Conic { from, position })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -444,6 +947,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_void_elements() {
+        let mut parser = Parser {
+            pos: 0,
+            input: "<p><img src=\"a.png\">a<br/>b<img src=\"b.png\" /></p>".to_string(),
+        };
+        let node = parser.parse_node();
+        let img = |src: &str| {
+            let mut attributes = dom::AttrMap::new();
+            attributes.insert("src".to_string(), src.to_string());
+            dom::Node::elem("img".to_string(), attributes, vec![])
+        };
+        assert_eq!(
+            node,
+            dom::Node::elem(
+                "p".to_string(),
+                dom::AttrMap::new(),
+                vec![
+                    img("a.png"),
+                    dom::Node::text("a".to_string()),
+                    dom::Node::elem("br".to_string(), dom::AttrMap::new(), vec![]),
+                    dom::Node::text("b".to_string()),
+                    img("b.png"),
+                ]
+            )
+        );
+    }
+
     #[test]
     fn test_parse_css() {
         use super::css::SimpleSelector;
@@ -451,7 +982,7 @@ mod tests {
             pos: 0,
             input: "p { color: #ff0000; }".to_string(),
         };
-        let rule = parser.parse_rules();
+        let (rule, _) = parser.parse_rule_list();
         assert_eq!(
             rule,
             vec![css::Rule {
@@ -459,6 +990,7 @@ mod tests {
                     tag_name: Some("p".to_string()),
                     id: None,
                     class: vec![],
+                    pseudo_element: None,
                 })],
                 declarations: vec![css::Declaration {
                     name: "color".to_string(),
@@ -472,4 +1004,285 @@ mod tests {
             }]
         );
     }
+
+    #[test]
+    fn test_parse_signed_lengths() {
+        let mut parser = Parser {
+            pos: 0,
+            input: "-12.5px +3em 50% -moz-foo".to_string(),
+        };
+        assert_eq!(
+            parser.parse_value(),
+            Some(css::Value::Length(-12.5, css::Unit::Px))
+        );
+        parser.consume_whitespace();
+        assert_eq!(
+            parser.parse_value(),
+            Some(css::Value::Length(3.0, css::Unit::Em))
+        );
+        parser.consume_whitespace();
+        assert_eq!(
+            parser.parse_value(),
+            Some(css::Value::Length(50.0, css::Unit::Percent))
+        );
+        parser.consume_whitespace();
+        assert_eq!(
+            parser.parse_value(),
+            Some(css::Value::Keyword("-moz-foo".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_numbers() {
+        let mut parser = Parser {
+            pos: 0,
+            input: "p { line-height: .; margin: -.px; width: 10vw; \
+                    background: linear-gradient(red -, blue); height: 5px; }"
+                .to_string(),
+        };
+        let rule = parser.parse_rule();
+        assert_eq!(
+            rule.declarations,
+            vec![css::Declaration {
+                name: "height".to_string(),
+                value: css::Value::Length(5.0, css::Unit::Px),
+            },]
+        );
+    }
+
+    #[test]
+    fn test_parse_grid_values() {
+        let mut parser = Parser {
+            pos: 0,
+            input: "{ grid-template-columns: [a] repeat(2, minmax(10px, 1fr)) [b c] auto; \
+                    grid-row: 1 / span 2; }"
+                .to_string(),
+        };
+        let declarations = parser.parse_declarations();
+        assert_eq!(
+            declarations[0].value,
+            css::Value::Sequence(vec![
+                css::Value::Keyword("[a]".to_string()),
+                css::Value::Keyword("repeat(2, minmax(10px, 1fr))".to_string()),
+                css::Value::Keyword("[b c]".to_string()),
+                css::Value::Keyword("auto".to_string()),
+            ])
+        );
+        assert_eq!(declarations[1].value.to_string(), "1 / span 2".to_string());
+    }
+
+    #[test]
+    fn test_parse_user_agent_css() {
+        let stylesheet = parse_user_agent_css();
+        assert_eq!(stylesheet.origin, css::Origin::UserAgent);
+        assert!(stylesheet.rules.iter().any(|rule| {
+            rule.declarations.contains(&css::Declaration {
+                name: "font-size".to_string(),
+                value: css::Value::Length(2.0, css::Unit::Em),
+            })
+        }));
+    }
+
+    #[test]
+    fn test_parse_combinators() {
+        use super::css::{Combinator, Selector, SimpleSelector};
+        let simple = |tag_name: &str| SimpleSelector {
+            tag_name: Some(tag_name.to_string()),
+            id: None,
+            class: vec![],
+            pseudo_element: None,
+        };
+        let mut parser = Parser {
+            pos: 0,
+            input: "ul li > a, p { }".to_string(),
+        };
+        let rule = parser.parse_rule();
+        assert_eq!(
+            rule.selectors,
+            vec![
+                Selector::Complex(
+                    Box::new(Selector::Complex(
+                        Box::new(Selector::Simple(simple("ul"))),
+                        Combinator::Descendant,
+                        simple("li"),
+                    )),
+                    Combinator::Child,
+                    simple("a"),
+                ),
+                Selector::Simple(simple("p")),
+            ]
+        );
+        assert_eq!(rule.selectors[0].specificity(), (0, 0, 3));
+    }
+
+    #[test]
+    fn test_parse_invalid_selectors() {
+        let mut parser = Parser {
+            pos: 0,
+            input: "p, div > { color: red; } a:hover { color: red; } * { color: red; }".to_string(),
+        };
+        let (rules, _) = parser.parse_rule_list();
+        assert_eq!(rules.len(), 3);
+        assert!(rules[0].selectors.is_empty());
+        assert!(rules[1].selectors.is_empty());
+        assert_eq!(rules[2].selectors.len(), 1);
+        assert!(parse_selector(" ul > li ".to_string()).is_some());
+        assert!(parse_selector("#".to_string()).is_none());
+        assert!(parse_selector("div {".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_parse_pseudo_element() {
+        let mut parser = Parser {
+            pos: 0,
+            input: "ol > li.done::marker { }".to_string(),
+        };
+        let rule = parser.parse_rule();
+        let subject = rule.selectors[0].subject();
+        assert_eq!(subject.class, vec!["done".to_string()]);
+        assert_eq!(subject.pseudo_element, Some("marker".to_string()));
+        assert_eq!(rule.selectors[0].specificity(), (0, 1, 3));
+    }
+
+    #[test]
+    fn test_parse_font_face() {
+        let mut parser = Parser {
+            pos: 0,
+            input: "@import \"a.css\"; @media print { p { color: #000000; } } \
+                    @font-face { font-family: \"Pony Sans\"; src: url(pony.ttf) format(\"truetype\"), local(Pony); } \
+                    p { font-family: Times New Roman, 'Pony Sans', serif; }"
+                .to_string(),
+        };
+        let (rules, font_faces) = parser.parse_rule_list();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(
+            rules[0].declarations[0].value,
+            css::Value::List(vec![
+                css::Value::Sequence(vec![
+                    css::Value::Keyword("Times".to_string()),
+                    css::Value::Keyword("New".to_string()),
+                    css::Value::Keyword("Roman".to_string()),
+                ]),
+                css::Value::String("Pony Sans".to_string()),
+                css::Value::Keyword("serif".to_string()),
+            ])
+        );
+        assert_eq!(font_faces.len(), 1);
+        let declarations = &font_faces[0].declarations;
+        assert_eq!(
+            declarations[0].value,
+            css::Value::String("Pony Sans".to_string())
+        );
+        assert_eq!(
+            declarations[1].value,
+            css::Value::List(vec![
+                css::Value::Sequence(vec![
+                    css::Value::Url("pony.ttf".to_string()),
+                    css::Value::Keyword("format(truetype)".to_string()),
+                ]),
+                css::Value::Keyword("local(Pony)".to_string()),
+            ])
+        );
+        assert_eq!(
+            declarations[1].value.to_string(),
+            "url(\"pony.ttf\") format(truetype), local(Pony)"
+        );
+    }
+
+    #[test]
+    fn test_parse_important() {
+        let mut parser = Parser {
+            pos: 0,
+            input: "{ display: none !important; color: #ff0000 ! important; margin: 0px 2px; }"
+                .to_string(),
+        };
+        let declarations = parser.parse_declarations();
+        assert_eq!(
+            declarations[0].value,
+            css::Value::Keyword("none".to_string())
+        );
+        assert_eq!(
+            declarations[1].value,
+            css::Value::Color(css::Color {
+                red: 255,
+                green: 0,
+                blue: 0,
+                alpha: 255
+            })
+        );
+        assert_eq!(
+            declarations[2].value,
+            css::Value::Sequence(vec![
+                css::Value::Length(0.0, css::Unit::Px),
+                css::Value::Length(2.0, css::Unit::Px)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_gradients() {
+        use super::css::{ColorStop, Gradient, GradientLine, GradientShape, RadialSize, Value};
+        let mut parser = Parser {
+            pos: 0,
+            input: "{ a: linear-gradient(0.25turn, #ff000080, 30%, #0000ff 50% 70%); \
+                    b: repeating-radial-gradient(circle 10px at left top, #000000, transparent); \
+                    c: conic-gradient(from 90deg, #ff0000, #0000ff) no-repeat; \
+                    d: linear-gradient(to left top, #ff0000, #0000ff); \
+                    e: linear-gradient(#ff0000); }"
+                .to_string(),
+        };
+        let declarations = parser.parse_declarations();
+        let color = |red, blue, alpha| css::Color {
+            red,
+            green: 0,
+            blue,
+            alpha,
+        };
+        let stop = |color, position, hint| ColorStop {
+            color,
+            position,
+            hint,
+        };
+        let percent = |percentage| Value::Length(percentage, css::Unit::Percent);
+        assert_eq!(
+            declarations[0].value,
+            Value::Gradient(Box::new(Gradient {
+                shape: GradientShape::Linear(GradientLine::Angle(90.0)),
+                repeating: false,
+                stops: vec![
+                    stop(color(255, 0, 128), None, None),
+                    stop(color(0, 255, 255), Some(percent(50.0)), Some(percent(30.0))),
+                    stop(color(0, 255, 255), Some(percent(70.0)), None),
+                ],
+            }))
+        );
+        assert_eq!(
+            declarations[1].value,
+            Value::Gradient(Box::new(Gradient {
+                shape: GradientShape::Radial {
+                    circle: true,
+                    size: RadialSize::Radii(vec![Value::Length(10.0, css::Unit::Px)]),
+                    position: vec![
+                        Value::Keyword("left".to_string()),
+                        Value::Keyword("top".to_string())
+                    ],
+                },
+                repeating: true,
+                stops: vec![
+                    stop(color(0, 0, 255), None, None),
+                    stop(color(0, 0, 0), None, None)
+                ],
+            }))
+        );
+        assert_eq!(
+            declarations[2].value.to_string(),
+            "conic-gradient(from 90deg, rgb(255, 0, 0), rgb(0, 0, 255)) no-repeat"
+        );
+        assert_eq!(
+            declarations[3].value.to_string(),
+            "linear-gradient(to left top, rgb(255, 0, 0), rgb(0, 0, 255))"
+        );
+        // A gradient needs two stops, and the declaration of an invalid one is dropped.
+        assert_eq!(declarations.len(), 4);
+    }
 }
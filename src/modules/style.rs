@@ -1,16 +1,197 @@
-use super::css::{Rule, Selector, SimpleSelector, Specificity, Stylesheet, Value};
+use super::bloom::{self, AncestorFilter};
+use super::css::{
+    Combinator, Origin, Rule, Selector, SimpleSelector, Specificity, Stylesheet, Unit, Value,
+};
 use super::dom::{ElementData, Node, NodeType};
 use super::layout::Display;
-use std::collections::HashMap;
+use super::parser;
+use std::collections::{BTreeMap, HashMap};
+use std::ptr;
+use std::sync::Arc;
 
-type PropertyMap = HashMap<String, Value>;
-type MatchedRule<'a> = (Specificity, &'a Rule);
+pub type PropertyMap = HashMap<String, Value>;
+
+/// Serialized computed values by property name, as `window.getComputedStyle` reports them.
+pub type ComputedStyle = BTreeMap<String, String>;
+type CascadedRule<'a> = ((Origin, Specificity, usize), &'a Rule);
+
+/// Properties whose value is taken from the parent when no rule sets them.
+const INHERITED_PROPERTIES: [&str; 22] = [
+    "border-collapse",
+    "border-spacing",
+    "caption-side",
+    "color",
+    "direction",
+    "font-family",
+    "font-feature-settings",
+    "font-size",
+    "font-stretch",
+    "font-style",
+    "font-weight",
+    "image-rendering",
+    "letter-spacing",
+    "line-height",
+    "list-style-image",
+    "list-style-position",
+    "list-style-type",
+    "text-align",
+    "text-indent",
+    "text-transform",
+    "white-space",
+    "word-spacing",
+];
+
+/// The `font-size` of the root element when nothing else sets it.
+const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+/// Serialized initial values of the properties the engine knows about, reported by
+/// `StyledNode::computed_style` when nothing sets them.
+const INITIAL_VALUES: [(&str, &str); 99] = [
+    ("align-content", "normal"),
+    ("align-items", "normal"),
+    ("align-self", "auto"),
+    ("background", "transparent"),
+    ("background-clip", "border-box"),
+    ("background-color", "transparent"),
+    ("background-image", "none"),
+    ("background-origin", "padding-box"),
+    ("background-position", "0% 0%"),
+    ("background-repeat", "repeat"),
+    ("background-size", "auto"),
+    ("border-bottom-width", "0px"),
+    ("border-collapse", "separate"),
+    ("border-color", "currentcolor"),
+    ("border-left-width", "0px"),
+    ("border-right-width", "0px"),
+    ("border-spacing", "0px"),
+    ("border-top-width", "0px"),
+    ("bottom", "auto"),
+    ("box-sizing", "content-box"),
+    ("caption-side", "top"),
+    ("clear", "none"),
+    ("color", "rgb(0, 0, 0)"),
+    ("column-gap", "normal"),
+    ("content", "normal"),
+    ("counter-increment", "none"),
+    ("counter-reset", "none"),
+    ("counter-set", "none"),
+    ("direction", "ltr"),
+    ("display", "inline"),
+    ("flex-basis", "auto"),
+    ("flex-direction", "row"),
+    ("flex-grow", "0"),
+    ("flex-shrink", "1"),
+    ("flex-wrap", "nowrap"),
+    ("float", "none"),
+    ("font-family", "serif"),
+    ("font-feature-settings", "normal"),
+    ("font-size", "16px"),
+    ("font-stretch", "normal"),
+    ("font-style", "normal"),
+    ("font-weight", "400"),
+    ("grid-auto-columns", "auto"),
+    ("grid-auto-flow", "row"),
+    ("grid-auto-rows", "auto"),
+    ("grid-column-end", "auto"),
+    ("grid-column-start", "auto"),
+    ("grid-row-end", "auto"),
+    ("grid-row-start", "auto"),
+    ("grid-template-areas", "none"),
+    ("grid-template-columns", "none"),
+    ("grid-template-rows", "none"),
+    ("height", "auto"),
+    ("image-rendering", "auto"),
+    ("justify-content", "normal"),
+    ("justify-items", "legacy"),
+    ("justify-self", "auto"),
+    ("left", "auto"),
+    ("letter-spacing", "normal"),
+    ("line-height", "normal"),
+    ("list-style-image", "none"),
+    ("list-style-position", "outside"),
+    ("list-style-type", "disc"),
+    ("margin-bottom", "0px"),
+    ("margin-left", "0px"),
+    ("margin-right", "0px"),
+    ("margin-top", "0px"),
+    ("max-height", "none"),
+    ("max-width", "none"),
+    ("min-height", "auto"),
+    ("min-width", "auto"),
+    ("object-fit", "fill"),
+    ("object-position", "50% 50%"),
+    ("opacity", "1"),
+    ("order", "0"),
+    ("overflow", "visible"),
+    ("padding-bottom", "0px"),
+    ("padding-left", "0px"),
+    ("padding-right", "0px"),
+    ("padding-top", "0px"),
+    ("position", "static"),
+    ("right", "auto"),
+    ("row-gap", "normal"),
+    ("table-layout", "auto"),
+    ("text-align", "start"),
+    ("text-decoration-color", "currentcolor"),
+    ("text-decoration-line", "none"),
+    ("text-decoration-style", "solid"),
+    ("text-indent", "0px"),
+    ("text-overflow", "clip"),
+    ("text-transform", "none"),
+    ("top", "auto"),
+    ("transform", "none"),
+    ("unicode-bidi", "normal"),
+    ("vertical-align", "baseline"),
+    ("white-space", "normal"),
+    ("width", "auto"),
+    ("word-spacing", "normal"),
+    ("z-index", "auto"),
+];
+
+/// Shorthands for the four sides of a box, e.g. `margin` for `margin-top`, `margin-right`,
+/// `margin-bottom` and `margin-left`. They are expanded into their longhands as they cascade, so
+/// that origin and specificity decide between a shorthand and a longhand.
+const SHORTHANDS: [(&str, [&str; 4]); 4] = [
+    (
+        "border-style",
+        [
+            "border-top-style",
+            "border-right-style",
+            "border-bottom-style",
+            "border-left-style",
+        ],
+    ),
+    (
+        "border-width",
+        [
+            "border-top-width",
+            "border-right-width",
+            "border-bottom-width",
+            "border-left-width",
+        ],
+    ),
+    (
+        "margin",
+        ["margin-top", "margin-right", "margin-bottom", "margin-left"],
+    ),
+    (
+        "padding",
+        [
+            "padding-top",
+            "padding-right",
+            "padding-bottom",
+            "padding-left",
+        ],
+    ),
+];
 
 #[derive(Debug, PartialEq)]
 pub struct StyledNode<'a> {
     node: &'a Node,
-    specified_values: PropertyMap,
+    specified_values: Arc<PropertyMap>,
     pub children: Vec<StyledNode<'a>>,
+    /// The style of the `::marker` pseudo-element of a list item.
+    marker: Option<Box<StyledNode<'a>>>,
 }
 
 impl<'a> StyledNode<'a> {
@@ -21,7 +202,15 @@ impl<'a> StyledNode<'a> {
     pub fn display(&self) -> Display {
         match self.value("display") {
             Some(Value::Keyword(s)) => match &*s {
-                "block" => Display::Block,
+                // List items are blocks with a marker, see `layout::list`.
+                "block" | "flow-root" | "list-item" | "flex" | "grid" | "table" => Display::Block,
+                "inline-block" | "inline-flex" | "inline-grid" | "inline-table" => {
+                    Display::InlineBlock
+                }
+                // Table parts are laid out by their table, see `layout::TablePart`.
+                "table-caption" | "table-header-group" | "table-row-group"
+                | "table-footer-group" | "table-row" | "table-cell" | "table-column-group"
+                | "table-column" => Display::Block,
                 "none" => Display::None,
                 _ => Display::Inline,
             },
@@ -29,15 +218,100 @@ impl<'a> StyledNode<'a> {
         }
     }
 
-    pub fn lookup(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
-        self.value(name)
-            .unwrap_or_else(|| self.value(fallback_name).unwrap_or_else(|| default.clone()))
+    pub fn lookup(&self, name: &str, default: &Value) -> Value {
+        self.value(name).unwrap_or_else(|| default.clone())
+    }
+
+    /// The DOM node this style belongs to.
+    pub fn node(&self) -> &'a Node {
+        self.node
+    }
+
+    /// The style of the node's `::marker` pseudo-element, which list items have. It inherits from
+    /// the node and has no children.
+    pub fn marker(&self) -> Option<&StyledNode<'a>> {
+        self.marker.as_deref()
+    }
+
+    /// The computed values set on this node, by the cascade or by inheritance.
+    pub fn specified_values(&self) -> &PropertyMap {
+        &self.specified_values
+    }
+
+    /// Every known property with its computed value serialized to CSS text, falling back to the
+    /// initial value for properties nothing sets, like `window.getComputedStyle`.
+    pub fn computed_style(&self) -> ComputedStyle {
+        let mut style: ComputedStyle = INITIAL_VALUES
+            .iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        for (name, value) in self.specified_values.iter() {
+            style.insert(name.clone(), value.to_string());
+        }
+        style
+    }
+
+    /// Find the styled node for a node of the styled DOM tree.
+    pub fn find(&self, node: &Node) -> Option<&StyledNode<'a>> {
+        if ptr::eq(self.node, node) {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(node))
+    }
+
+    /// The first element in document order matching a CSS selector such as `ul > li.row`, or
+    /// `None` if nothing matches or the selector is malformed.
+    pub fn query_selector(&self, selector: &str) -> Option<&StyledNode<'a>> {
+        self.query_selector_all(selector).into_iter().next()
+    }
+
+    /// All elements matching a CSS selector, in document order. A malformed selector matches
+    /// nothing.
+    pub fn query_selector_all(&self, selector: &str) -> Vec<&StyledNode<'a>> {
+        let mut found = Vec::new();
+        if let Some(selector) = parser::parse_selector(selector.to_string()) {
+            self.collect_matching(&selector, &mut Vec::new(), &mut found);
+        }
+        found
+    }
+
+    fn collect_matching<'s>(
+        &'s self,
+        selector: &Selector,
+        ancestors: &mut Vec<&'a ElementData>,
+        found: &mut Vec<&'s StyledNode<'a>>,
+    ) {
+        if let NodeType::Element(ref elem) = self.node.node_type {
+            if matches(elem, ancestors, selector) {
+                found.push(self);
+            }
+            ancestors.push(elem);
+            for child in &self.children {
+                child.collect_matching(selector, ancestors, found);
+            }
+            ancestors.pop();
+        }
     }
 }
 
-fn matches(elem: &ElementData, selector: &Selector) -> bool {
+fn matches(elem: &ElementData, ancestors: &[&ElementData], selector: &Selector) -> bool {
     match *selector {
         Selector::Simple(ref simple_selector) => matches_simple_selector(elem, simple_selector),
+        Selector::Complex(ref left, combinator, ref right) => {
+            if !matches_simple_selector(elem, right) {
+                return false;
+            }
+            // `ancestors` is ordered from the root down, so the parent is the last entry.
+            match combinator {
+                Combinator::Child => match ancestors.split_last() {
+                    Some((parent, rest)) => matches(parent, rest, left),
+                    None => false,
+                },
+                Combinator::Descendant => (0..ancestors.len())
+                    .rev()
+                    .any(|i| matches(ancestors[i], &ancestors[..i], left)),
+            }
+        }
     }
 }
 
@@ -66,57 +340,473 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
     true
 }
 
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchedRule<'a>> {
-    rule.selectors
-        .iter()
-        .find(|selector| matches(elem, *selector))
-        .map(|selector| (selector.specificity(), rule))
+/// A selector from a stylesheet, along with everything needed to place a match in the cascade.
+struct RuleEntry<'a> {
+    selector: &'a Selector,
+    rule: &'a Rule,
+    origin: Origin,
+    /// Position of the rule across all stylesheets, the last tie-breaker of the cascade.
+    source_order: usize,
+    /// Hashes of the names the selector requires of the element's ancestors, checked against the
+    /// ancestor Bloom filter before doing the real match.
+    ancestor_hashes: Vec<u32>,
 }
 
-fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a Stylesheet) -> Vec<MatchedRule<'a>> {
-    stylesheet
-        .rules
-        .iter()
-        .filter_map(|rule| match_rule(elem, rule))
-        .collect()
+impl<'a> RuleEntry<'a> {
+    fn matches(
+        &self,
+        elem: &ElementData,
+        ancestors: &[&ElementData],
+        filter: &AncestorFilter,
+    ) -> bool {
+        self.ancestor_hashes
+            .iter()
+            .all(|&hash| filter.might_contain(hash))
+            && matches(elem, ancestors, self.selector)
+    }
+}
+
+/// Selectors bucketed by the id, class or tag name of their rightmost compound selector, so an
+/// element is only tested against selectors that could possibly match it.
+struct RuleMap<'a> {
+    by_id: HashMap<&'a str, Vec<RuleEntry<'a>>>,
+    by_class: HashMap<&'a str, Vec<RuleEntry<'a>>>,
+    by_tag: HashMap<&'a str, Vec<RuleEntry<'a>>>,
+    universal: Vec<RuleEntry<'a>>,
+}
+
+impl<'a> RuleMap<'a> {
+    /// Bucket the selectors of the stylesheets by their rightmost id, class or tag name. With
+    /// `hashed` false, keep them all in one list instead, without ancestor hashes, so that every
+    /// element is tested against every selector in full.
+    fn new(stylesheets: &'a [Stylesheet], hashed: bool) -> RuleMap<'a> {
+        let mut map = RuleMap {
+            by_id: HashMap::new(),
+            by_class: HashMap::new(),
+            by_tag: HashMap::new(),
+            universal: Vec::new(),
+        };
+        let rules = stylesheets.iter().flat_map(|stylesheet| {
+            stylesheet
+                .rules
+                .iter()
+                .map(move |rule| (stylesheet.origin, rule))
+        });
+        for (source_order, (origin, rule)) in rules.enumerate() {
+            for selector in &rule.selectors {
+                if !hashed {
+                    map.universal.push(RuleEntry {
+                        selector,
+                        rule,
+                        origin,
+                        source_order,
+                        ancestor_hashes: Vec::new(),
+                    });
+                    continue;
+                }
+                map.insert(RuleEntry {
+                    selector,
+                    rule,
+                    origin,
+                    source_order,
+                    ancestor_hashes: ancestor_hashes(selector),
+                });
+            }
+        }
+        map
+    }
+
+    fn insert(&mut self, entry: RuleEntry<'a>) {
+        let subject = entry.selector.subject();
+        if let Some(ref id) = subject.id {
+            self.by_id.entry(id).or_default().push(entry);
+        } else if let Some(class) = subject.class.first() {
+            self.by_class.entry(class).or_default().push(entry);
+        } else if let Some(ref tag_name) = subject.tag_name {
+            self.by_tag.entry(tag_name).or_default().push(entry);
+        } else {
+            self.universal.push(entry);
+        }
+    }
+
+    /// The rules that apply to an element, or with `pseudo_element`, to that pseudo-element of
+    /// it.
+    fn matching_rules(
+        &self,
+        elem: &ElementData,
+        pseudo_element: Option<&str>,
+        ancestors: &[&ElementData],
+        filter: &AncestorFilter,
+    ) -> Vec<CascadedRule<'a>> {
+        let mut buckets = vec![&self.universal];
+        buckets.extend(elem.id().and_then(|id| self.by_id.get(id.as_str())));
+        buckets.extend(
+            elem.classes()
+                .iter()
+                .filter_map(|class| self.by_class.get(class)),
+        );
+        buckets.extend(self.by_tag.get(elem.tag_name.as_str()));
+
+        // A rule can match through several of its selectors; it applies once, with the
+        // specificity of the most specific one.
+        let mut matched: HashMap<usize, CascadedRule<'a>> = HashMap::new();
+        for entry in buckets.into_iter().flatten() {
+            if entry.selector.subject().pseudo_element.as_deref() != pseudo_element
+                || !entry.matches(elem, ancestors, filter)
+            {
+                continue;
+            }
+            let cascade_order = (
+                entry.origin,
+                entry.selector.specificity(),
+                entry.source_order,
+            );
+            let best = matched
+                .entry(entry.source_order)
+                .or_insert((cascade_order, entry.rule));
+            if cascade_order > best.0 {
+                best.0 = cascade_order;
+            }
+        }
+        matched.into_values().collect()
+    }
+}
+
+fn ancestor_hashes(selector: &Selector) -> Vec<u32> {
+    let mut hashes = Vec::new();
+    let mut current = selector;
+    while let Selector::Complex(ref left, _, _) = *current {
+        let simple = left.subject();
+        hashes.extend(simple.tag_name.iter().map(|name| bloom::tag_hash(name)));
+        hashes.extend(simple.id.iter().map(|id| bloom::id_hash(id)));
+        hashes.extend(simple.class.iter().map(|class| bloom::class_hash(class)));
+        current = left;
+    }
+    hashes
 }
 
-fn specified_values(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap {
+fn specified_values(
+    elem: &ElementData,
+    pseudo_element: Option<&str>,
+    context: &StyleContext,
+) -> PropertyMap {
     let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+    let mut rules =
+        context
+            .rules
+            .matching_rules(elem, pseudo_element, &context.ancestors, &context.filter);
 
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+    rules.sort_by_key(|&(cascade_order, _)| cascade_order);
     for (_, rule) in rules {
         for declaration in &rule.declarations {
-            values.insert(declaration.name.clone(), declaration.value.clone());
+            declare(&mut values, &declaration.name, &declaration.value);
         }
     }
     values
 }
 
-pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a Stylesheet) -> StyledNode<'a> {
-    StyledNode {
-        node: root,
-        specified_values: match root.node_type {
-            NodeType::Element(ref elem) => specified_values(elem, stylesheet),
-            NodeType::Text(_) => HashMap::new(),
-            NodeType::Comment(_) => HashMap::new(),
+/// Set a declared value, expanding the shorthands in `SHORTHANDS` into their longhands. One to
+/// four values give the top, right, bottom and left sides, with a missing side taking the value of
+/// the side opposite it, as in `margin: 0px 8px`.
+fn declare(values: &mut PropertyMap, name: &str, value: &Value) {
+    let longhands = match SHORTHANDS.iter().find(|&&(shorthand, _)| shorthand == name) {
+        Some((_, longhands)) => longhands,
+        None => {
+            values.insert(name.to_string(), value.clone());
+            return;
+        }
+    };
+    let sides = match value {
+        Value::Sequence(sides) if sides.len() <= 4 => sides.as_slice(),
+        value => std::slice::from_ref(value),
+    };
+    let [top, right, bottom, left] = match sides {
+        [all] => [all, all, all, all],
+        [vertical, horizontal] => [vertical, horizontal, vertical, horizontal],
+        [top, horizontal, bottom] => [top, horizontal, bottom, horizontal],
+        [top, right, bottom, left] => [top, right, bottom, left],
+        _ => return,
+    };
+    for (longhand, side) in longhands.iter().zip([top, right, bottom, left]) {
+        values.insert(longhand.to_string(), side.clone());
+    }
+}
+
+/// Turn specified values into computed values: fill in inherited properties from the parent,
+/// resolve `em` lengths to px and `font-weight` keywords to numbers.
+fn compute_values(values: &mut PropertyMap, parent: Option<&PropertyMap>) {
+    let parent_font_size = parent
+        .and_then(|parent| parent.get("font-size"))
+        .map(|size| size.to_px())
+        .unwrap_or(DEFAULT_FONT_SIZE);
+    let parent_font_weight = match parent.and_then(|parent| parent.get("font-weight")) {
+        Some(&Value::Number(weight)) => weight,
+        _ => 400.0,
+    };
+
+    for (name, value) in values.iter_mut() {
+        if *value == Value::Keyword("inherit".to_string()) {
+            match parent.and_then(|parent| parent.get(name)) {
+                Some(inherited) => *value = inherited.clone(),
+                None => *value = Value::Keyword("initial".to_string()),
+            }
+        }
+    }
+    if let Some(parent) = parent {
+        for name in INHERITED_PROPERTIES {
+            if !values.contains_key(name) {
+                if let Some(value) = parent.get(name) {
+                    values.insert(name.to_string(), value.clone());
+                }
+            }
+        }
+    }
+
+    // `em` and percentages in `font-size` refer to the parent's font size, `em` everywhere else to
+    // the element's own.
+    let font_size = match values.get("font-size") {
+        Some(&Value::Length(size, Unit::Em)) => size * parent_font_size,
+        Some(&Value::Length(size, Unit::Percent)) => size / 100.0 * parent_font_size,
+        Some(&Value::Length(size, Unit::Px)) => size,
+        _ => parent_font_size,
+    };
+    values.insert("font-size".to_string(), Value::Length(font_size, Unit::Px));
+
+    // `bolder` and `lighter` are relative to the parent's weight.
+    // http://www.w3.org/TR/css-fonts-4/#relative-weights
+    let font_weight = match values.get("font-weight") {
+        Some(&Value::Number(weight)) => weight.clamp(1.0, 1000.0),
+        Some(Value::Keyword(keyword)) => match &**keyword {
+            "bold" => 700.0,
+            "bolder" if parent_font_weight < 350.0 => 400.0,
+            "bolder" if parent_font_weight < 550.0 => 700.0,
+            "bolder" if parent_font_weight < 900.0 => 900.0,
+            "lighter" if parent_font_weight < 100.0 => parent_font_weight,
+            "lighter" if parent_font_weight < 550.0 => 100.0,
+            "lighter" if parent_font_weight < 750.0 => 400.0,
+            "lighter" => 700.0,
+            "bolder" => parent_font_weight,
+            _ => 400.0,
         },
-        children: root
+        _ => parent_font_weight,
+    };
+    values.insert("font-weight".to_string(), Value::Number(font_weight));
+
+    for value in values.values_mut() {
+        if let Value::Length(size, Unit::Em) = *value {
+            *value = Value::Length(size * font_size, Unit::Px);
+        }
+    }
+}
+
+/// Style a document against a set of stylesheets, e.g. the user agent stylesheet from
+/// `parser::parse_user_agent_css` and the author stylesheet. Leave the user agent stylesheet out
+/// to style the document with author rules only.
+pub fn style_tree<'a>(root: &'a Node, stylesheets: &[Stylesheet]) -> StyledNode<'a> {
+    let rules = RuleMap::new(stylesheets, true);
+    styled_node(root, &mut StyleContext::new(&rules), None, false)
+}
+
+/// Like `style_tree`, but styles sibling subtrees in parallel on the rayon thread pool. The result
+/// is the same tree `style_tree` builds.
+#[cfg(feature = "parallel")]
+pub fn parallel_style_tree<'a>(root: &'a Node, stylesheets: &[Stylesheet]) -> StyledNode<'a> {
+    let rules = RuleMap::new(stylesheets, true);
+    styled_node(root, &mut StyleContext::new(&rules), None, true)
+}
+
+/// Like `style_tree`, but tests every element against every selector in turn, without hashing the
+/// rules or filtering them by ancestors. Only meant as the baseline in `benches/style.rs`.
+#[doc(hidden)]
+pub fn linear_style_tree<'a>(root: &'a Node, stylesheets: &[Stylesheet]) -> StyledNode<'a> {
+    let rules = RuleMap::new(stylesheets, false);
+    styled_node(root, &mut StyleContext::new(&rules), None, false)
+}
+
+/// State carried down the style traversal: the hashed rules, the chain of ancestor elements of the
+/// node being styled along with a Bloom filter over them, and the styles computed so far.
+struct StyleContext<'r, 'n> {
+    rules: &'r RuleMap<'r>,
+    ancestors: Vec<&'n ElementData>,
+    filter: AncestorFilter,
+    sharing_cache: HashMap<SharingKey<'n>, Arc<PropertyMap>>,
+}
+
+impl<'r, 'n> StyleContext<'r, 'n> {
+    fn new(rules: &'r RuleMap<'r>) -> StyleContext<'r, 'n> {
+        StyleContext {
+            rules,
+            ancestors: Vec::new(),
+            filter: AncestorFilter::new(),
+            sharing_cache: HashMap::new(),
+        }
+    }
+
+    /// A context for styling a subtree on another thread. The sharing cache starts out empty,
+    /// which only costs some missed sharing.
+    #[cfg(feature = "parallel")]
+    fn fork(&self) -> StyleContext<'r, 'n> {
+        StyleContext {
+            rules: self.rules,
+            ancestors: self.ancestors.clone(),
+            filter: self.filter.clone(),
+            sharing_cache: HashMap::new(),
+        }
+    }
+}
+
+/// Nodes with equal keys are guaranteed to end up with the same computed style, so the style of
+/// the first one can be reused for the rest.
+///
+/// Selectors only look at the tag name, id and classes of an element and its ancestors, so two
+/// id-less elements with the same tag name and classes under parents sharing one style match the
+/// same rules. Parents are compared by the address of their property map, which stays alive, and
+/// therefore unique, until the whole tree is styled.
+#[derive(PartialEq, Eq, Hash)]
+enum SharingKey<'n> {
+    Element {
+        parent: usize,
+        tag_name: &'n str,
+        class: Option<&'n str>,
+        style: Option<&'n str>,
+    },
+    /// Text and comment nodes only inherit from their parent.
+    Text { parent: usize },
+}
+
+impl<'n> SharingKey<'n> {
+    fn new(node: &'n Node, parent: Option<&Arc<PropertyMap>>) -> Option<SharingKey<'n>> {
+        let parent = Arc::as_ptr(parent?) as usize;
+        match node.node_type {
+            NodeType::Element(ref elem) if elem.id().is_none() => Some(SharingKey::Element {
+                parent,
+                tag_name: &elem.tag_name,
+                class: elem.attributes.get("class").map(|class| class.as_str()),
+                style: elem.attributes.get("style").map(|style| style.as_str()),
+            }),
+            NodeType::Element(_) => None,
+            NodeType::Text(_) | NodeType::Comment(_) => Some(SharingKey::Text { parent }),
+        }
+    }
+}
+
+fn styled_node<'a>(
+    node: &'a Node,
+    context: &mut StyleContext<'_, 'a>,
+    parent: Option<&Arc<PropertyMap>>,
+    parallel: bool,
+) -> StyledNode<'a> {
+    let key = SharingKey::new(node, parent);
+    let shared = key
+        .as_ref()
+        .and_then(|key| context.sharing_cache.get(key))
+        .cloned();
+    let values = match shared {
+        Some(values) => values,
+        None => {
+            let mut values = match node.node_type {
+                NodeType::Element(ref elem) => specified_values(elem, None, context),
+                NodeType::Text(_) => HashMap::new(),
+                NodeType::Comment(_) => HashMap::new(),
+            };
+            compute_values(&mut values, parent.map(|parent| &**parent));
+            let values = Arc::new(values);
+            if let Some(key) = key {
+                context.sharing_cache.insert(key, values.clone());
+            }
+            values
+        }
+    };
+
+    let marker = match node.node_type {
+        NodeType::Element(ref elem)
+            if values.get("display") == Some(&Value::Keyword("list-item".to_string())) =>
+        {
+            let mut marker = specified_values(elem, Some("marker"), context);
+            compute_values(&mut marker, Some(&values));
+            Some(Box::new(StyledNode {
+                node,
+                specified_values: Arc::new(marker),
+                children: Vec::new(),
+                marker: None,
+            }))
+        }
+        _ => None,
+    };
+
+    if let NodeType::Element(ref elem) = node.node_type {
+        context.ancestors.push(elem);
+        context.filter.push_element(elem);
+    }
+    let children = styled_children(node, context, &values, parallel);
+    if let NodeType::Element(ref elem) = node.node_type {
+        context.ancestors.pop();
+        context.filter.pop_element(elem);
+    }
+
+    StyledNode {
+        node,
+        specified_values: values,
+        children,
+        marker,
+    }
+}
+
+fn styled_children<'a>(
+    node: &'a Node,
+    context: &mut StyleContext<'_, 'a>,
+    values: &Arc<PropertyMap>,
+    parallel: bool,
+) -> Vec<StyledNode<'a>> {
+    // Each rayon job gets its own fork of the context, so the ancestor chain and Bloom filter are
+    // only copied when work is actually split off to another thread.
+    #[cfg(feature = "parallel")]
+    if parallel && node.children.len() > 1 {
+        use rayon::prelude::*;
+        let context = &*context;
+        return node
             .children
-            .iter()
-            .map(|child| style_tree(child, stylesheet))
-            .collect(),
+            .par_iter()
+            .map_init(
+                || context.fork(),
+                |context, child| styled_node(child, context, Some(values), true),
+            )
+            .collect();
     }
+
+    node.children
+        .iter()
+        .map(|child| styled_node(child, context, Some(values), parallel))
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::super::css::Rule;
+    use super::super::css::{Declaration, Rule};
     use super::super::css::{Selector, SimpleSelector};
     use super::super::dom::{AttrMap, ElementData};
+    use super::super::parser;
     use super::*;
 
+    /// Match a single author rule against an element with no ancestors, through the same hashed
+    /// rule map that `style_tree` uses.
+    fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<CascadedRule<'a>> {
+        let mut rules = RuleMap::new(&[], true);
+        for selector in &rule.selectors {
+            rules.insert(RuleEntry {
+                selector,
+                rule,
+                origin: Origin::Author,
+                source_order: 0,
+                ancestor_hashes: ancestor_hashes(selector),
+            });
+        }
+        rules
+            .matching_rules(elem, None, &[], &AncestorFilter::new())
+            .into_iter()
+            .next()
+    }
+
     #[test]
     fn test_match_rule() {
         let elem = ElementData {
@@ -129,6 +819,7 @@ mod tests {
                 tag_name: Some("div".to_string()),
                 id: None,
                 class: vec![],
+                pseudo_element: None,
             })],
             declarations: vec![],
         };
@@ -148,6 +839,7 @@ mod tests {
                 tag_name: Some("span".to_string()),
                 id: None,
                 class: vec![],
+                pseudo_element: None,
             })],
             declarations: vec![],
         };
@@ -161,7 +853,7 @@ mod tests {
         attributes.insert("id".to_string(), "foo".to_string());
         let elem = ElementData {
             tag_name: "div".to_string(),
-            attributes: attributes,
+            attributes,
         };
 
         let rule = Rule {
@@ -169,10 +861,416 @@ mod tests {
                 tag_name: Some("div".to_string()),
                 id: Some("foo".to_string()),
                 class: vec![],
+                pseudo_element: None,
             })],
             declarations: vec![],
         };
 
         assert!(match_rule(&elem, &rule).is_some());
     }
+
+    fn stylesheet(
+        origin: Origin,
+        selector: SimpleSelector,
+        name: &str,
+        value: Value,
+    ) -> Stylesheet {
+        Stylesheet {
+            origin,
+            font_faces: Vec::new(),
+            rules: vec![Rule {
+                selectors: vec![Selector::Simple(selector)],
+                declarations: vec![Declaration {
+                    name: name.to_string(),
+                    value,
+                }],
+            }],
+        }
+    }
+
+    fn tag(name: &str) -> SimpleSelector {
+        SimpleSelector {
+            tag_name: Some(name.to_string()),
+            id: None,
+            class: vec![],
+            pseudo_element: None,
+        }
+    }
+
+    #[test]
+    fn test_author_origin_beats_user_agent_specificity() {
+        let mut attributes = AttrMap::new();
+        attributes.insert("id".to_string(), "foo".to_string());
+        let root = Node::elem("div".to_string(), attributes, vec![]);
+
+        let user_agent = stylesheet(
+            Origin::UserAgent,
+            SimpleSelector {
+                tag_name: Some("div".to_string()),
+                id: Some("foo".to_string()),
+                class: vec![],
+                pseudo_element: None,
+            },
+            "display",
+            Value::Keyword("none".to_string()),
+        );
+        let universal = SimpleSelector {
+            tag_name: None,
+            id: None,
+            class: vec![],
+            pseudo_element: None,
+        };
+        let author = stylesheet(
+            Origin::Author,
+            universal,
+            "display",
+            Value::Keyword("block".to_string()),
+        );
+
+        let styled = style_tree(&root, &[author, user_agent]);
+        assert_eq!(
+            styled.value("display"),
+            Some(Value::Keyword("block".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_author_shorthand_beats_user_agent_longhand() {
+        let root = parser::parse_html("<div><p></p><ul></ul></div>".to_string());
+        let stylesheets = [
+            parser::parse_css(
+                "p { margin: 0px; } ul { padding: 0px 4px; } \
+                 div { margin-top: 2px; margin: 5px; border-width: 1px 2px 3px; }"
+                    .to_string(),
+                &root,
+            ),
+            parser::parse_user_agent_css(),
+        ];
+        let styled = style_tree(&root, &stylesheets);
+        let px = |length| Some(Value::Length(length, Unit::Px));
+        let p = &styled.children[0];
+        assert_eq!(p.value("margin-top"), px(0.0));
+        assert_eq!(p.value("margin-bottom"), px(0.0));
+        assert!(p.value("margin").is_none());
+        let ul = &styled.children[1];
+        assert_eq!(ul.value("padding-left"), px(4.0));
+        assert_eq!(ul.value("padding-top"), px(0.0));
+        // Within a rule, the later declaration wins.
+        assert_eq!(styled.value("margin-top"), px(5.0));
+        assert_eq!(styled.value("border-bottom-width"), px(3.0));
+        assert_eq!(styled.value("border-left-width"), px(2.0));
+    }
+
+    #[test]
+    fn test_inherited_em_font_size() {
+        let root = Node::elem(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![Node::elem(
+                "h1".to_string(),
+                AttrMap::new(),
+                vec![Node::text("title".to_string())],
+            )],
+        );
+        let stylesheets = [
+            stylesheet(
+                Origin::Author,
+                tag("div"),
+                "font-size",
+                Value::Length(10.0, Unit::Px),
+            ),
+            stylesheet(
+                Origin::Author,
+                tag("h1"),
+                "font-size",
+                Value::Length(2.0, Unit::Em),
+            ),
+            stylesheet(
+                Origin::Author,
+                tag("h1"),
+                "margin-top",
+                Value::Length(0.5, Unit::Em),
+            ),
+        ];
+
+        let styled = style_tree(&root, &stylesheets);
+        let h1 = &styled.children[0];
+        assert_eq!(h1.value("font-size"), Some(Value::Length(20.0, Unit::Px)));
+        assert_eq!(h1.value("margin-top"), Some(Value::Length(10.0, Unit::Px)));
+        assert_eq!(
+            h1.children[0].value("font-size"),
+            Some(Value::Length(20.0, Unit::Px))
+        );
+        assert_eq!(h1.children[0].value("margin-top"), None);
+    }
+
+    #[test]
+    fn test_relative_font_weight() {
+        let root = parser::parse_html("<p><b><i>a</i></b><em>b</em></p>".to_string());
+        let stylesheets = [parser::parse_css(
+            "p { font-weight: 300; } b { font-weight: bolder; } i { font-weight: bolder; } \
+             em { font-weight: lighter; }"
+                .to_string(),
+            &root,
+        )];
+        let styled = style_tree(&root, &stylesheets);
+        let weight = |selector: &str| {
+            styled
+                .query_selector(selector)
+                .unwrap()
+                .value("font-weight")
+        };
+        assert_eq!(weight("b"), Some(Value::Number(400.0)));
+        assert_eq!(weight("i"), Some(Value::Number(700.0)));
+        assert_eq!(weight("em"), Some(Value::Number(100.0)));
+    }
+
+    #[test]
+    fn test_match_combinators() {
+        let ul = ElementData {
+            tag_name: "ul".to_string(),
+            attributes: AttrMap::new(),
+        };
+        let li = ElementData {
+            tag_name: "li".to_string(),
+            attributes: AttrMap::new(),
+        };
+        let a = ElementData {
+            tag_name: "a".to_string(),
+            attributes: AttrMap::new(),
+        };
+        let descendant = Selector::Complex(
+            Box::new(Selector::Simple(tag("ul"))),
+            Combinator::Descendant,
+            tag("a"),
+        );
+        let child = Selector::Complex(
+            Box::new(Selector::Simple(tag("ul"))),
+            Combinator::Child,
+            tag("a"),
+        );
+
+        assert!(matches(&a, &[&ul, &li], &descendant));
+        assert!(!matches(&a, &[&ul, &li], &child));
+        assert!(matches(&a, &[&li, &ul], &child));
+        assert!(!matches(&a, &[&li], &descendant));
+    }
+
+    #[test]
+    fn test_hashed_rules_cascade() {
+        let mut attributes = AttrMap::new();
+        attributes.insert("class".to_string(), "row odd".to_string());
+        let root = Node::elem(
+            "ul".to_string(),
+            AttrMap::new(),
+            vec![Node::elem("li".to_string(), attributes, vec![])],
+        );
+        let class = |name: &str| SimpleSelector {
+            tag_name: None,
+            id: None,
+            class: vec![name.to_string()],
+            pseudo_element: None,
+        };
+        let color = |name: &str| Value::Keyword(name.to_string());
+        let stylesheets = [Stylesheet {
+            origin: Origin::Author,
+            font_faces: Vec::new(),
+            rules: vec![
+                Rule {
+                    selectors: vec![Selector::Simple(class("odd"))],
+                    declarations: vec![Declaration {
+                        name: "color".to_string(),
+                        value: color("red"),
+                    }],
+                },
+                Rule {
+                    selectors: vec![Selector::Complex(
+                        Box::new(Selector::Simple(tag("ul"))),
+                        Combinator::Child,
+                        class("row"),
+                    )],
+                    declarations: vec![Declaration {
+                        name: "color".to_string(),
+                        value: color("green"),
+                    }],
+                },
+                Rule {
+                    selectors: vec![Selector::Complex(
+                        Box::new(Selector::Simple(tag("ol"))),
+                        Combinator::Descendant,
+                        class("row"),
+                    )],
+                    declarations: vec![Declaration {
+                        name: "color".to_string(),
+                        value: color("blue"),
+                    }],
+                },
+                Rule {
+                    selectors: vec![Selector::Simple(class("row"))],
+                    declarations: vec![Declaration {
+                        name: "color".to_string(),
+                        value: color("yellow"),
+                    }],
+                },
+            ],
+        }];
+
+        let styled = style_tree(&root, &stylesheets);
+        assert_eq!(styled.children[0].value("color"), Some(color("green")));
+    }
+
+    #[test]
+    fn test_share_sibling_styles() {
+        let li = |class: &str, id: Option<&str>| {
+            let mut attributes = AttrMap::new();
+            attributes.insert("class".to_string(), class.to_string());
+            if let Some(id) = id {
+                attributes.insert("id".to_string(), id.to_string());
+            }
+            Node::elem(
+                "li".to_string(),
+                attributes,
+                vec![Node::text("row".to_string())],
+            )
+        };
+        let root = Node::elem(
+            "ul".to_string(),
+            AttrMap::new(),
+            vec![
+                li("row", None),
+                li("row", None),
+                li("row", Some("last")),
+                li("row odd", None),
+            ],
+        );
+        let stylesheets = [stylesheet(
+            Origin::Author,
+            tag("li"),
+            "display",
+            Value::Keyword("block".to_string()),
+        )];
+
+        let styled = style_tree(&root, &stylesheets);
+        let rows = &styled.children;
+        assert!(Arc::ptr_eq(
+            &rows[0].specified_values,
+            &rows[1].specified_values
+        ));
+        assert!(!Arc::ptr_eq(
+            &rows[0].specified_values,
+            &rows[2].specified_values
+        ));
+        assert!(!Arc::ptr_eq(
+            &rows[0].specified_values,
+            &rows[3].specified_values
+        ));
+        assert_eq!(rows[2].specified_values, rows[0].specified_values);
+        assert!(Arc::ptr_eq(
+            &rows[0].children[0].specified_values,
+            &rows[1].children[0].specified_values
+        ));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_style_tree() {
+        let mut rows = Vec::new();
+        for i in 0..200 {
+            let mut attributes = AttrMap::new();
+            attributes.insert("class".to_string(), format!("row row-{}", i % 3));
+            rows.push(Node::elem(
+                "li".to_string(),
+                attributes,
+                vec![Node::text("row".to_string())],
+            ));
+        }
+        let root = Node::elem(
+            "div".to_string(),
+            AttrMap::new(),
+            vec![Node::elem("ul".to_string(), AttrMap::new(), rows)],
+        );
+        let class = |name: &str| SimpleSelector {
+            tag_name: None,
+            id: None,
+            class: vec![name.to_string()],
+            pseudo_element: None,
+        };
+        let stylesheets = [
+            stylesheet(
+                Origin::Author,
+                tag("div"),
+                "font-size",
+                Value::Length(10.0, Unit::Px),
+            ),
+            stylesheet(
+                Origin::Author,
+                class("row-1"),
+                "margin-top",
+                Value::Length(1.0, Unit::Em),
+            ),
+            Stylesheet {
+                origin: Origin::Author,
+                font_faces: Vec::new(),
+                rules: vec![Rule {
+                    selectors: vec![Selector::Complex(
+                        Box::new(Selector::Simple(tag("div"))),
+                        Combinator::Descendant,
+                        class("row-2"),
+                    )],
+                    declarations: vec![Declaration {
+                        name: "display".to_string(),
+                        value: Value::Keyword("none".to_string()),
+                    }],
+                }],
+            },
+        ];
+
+        let sequential = style_tree(&root, &stylesheets);
+        let parallel = parallel_style_tree(&root, &stylesheets);
+        assert_eq!(sequential, parallel);
+        assert_eq!(
+            parallel.children[0].children[2].value("display"),
+            Some(Value::Keyword("none".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_computed_style() {
+        let root = parser::parse_html(
+            "<div class=\"card\"><h1>Title</h1><p id=\"body\">Text</p></div>".to_string(),
+        );
+        let stylesheets = [
+            parser::parse_css(
+                ".card { color: #ff0000; padding: 4px; } .card > h1 { margin-top: 1em; }"
+                    .to_string(),
+                &root,
+            ),
+            parser::parse_user_agent_css(),
+        ];
+        let styled = style_tree(&root, &stylesheets);
+
+        let h1 = styled.query_selector("div h1").unwrap().computed_style();
+        assert_eq!(h1["display"], "block");
+        assert_eq!(h1["font-size"], "32px");
+        assert_eq!(h1["margin-top"], "32px");
+        assert_eq!(h1["color"], "rgb(255, 0, 0)");
+        assert_eq!(h1["padding-top"], "0px");
+        assert_eq!(h1["font-weight"], "700");
+
+        let card = styled.query_selector(".card").unwrap().computed_style();
+        assert_eq!(card["padding-left"], "4px");
+        assert!(!card.contains_key("padding"));
+
+        let p = &root.children[1];
+        assert!(ptr::eq(styled.find(p).unwrap().node(), p));
+        assert_eq!(
+            styled.find(p).unwrap().computed_style(),
+            styled.query_selector("#body").unwrap().computed_style()
+        );
+        assert_eq!(styled.query_selector_all("div > *").len(), 2);
+        assert!(styled.query_selector("span").is_none());
+        for malformed in ["", "div >", "#", "div.", "p::", "div ~ p"] {
+            assert!(styled.query_selector(malformed).is_none());
+        }
+    }
 }
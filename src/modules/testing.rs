@@ -0,0 +1,85 @@
+//! Parse, style and lay out documents in tests, and paint them.
+
+use super::display::{paint, Canvas};
+use super::font::FontDatabase;
+use super::images::ImageCache;
+use super::layout::{layout_scrolled_tree, Dimensions, LayoutBox, Rect};
+use super::{parser, style};
+
+/// A document to lay out in a test, and the viewport to lay it out in.
+pub struct Page<'a> {
+    pub html: &'a str,
+    pub css: &'a str,
+    pub width: f32,
+    pub height: f32,
+    /// How far the viewport is scrolled right and down.
+    pub scroll: (f32, f32),
+    /// Whether to style the document with the user agent stylesheet after `css`. Without it,
+    /// every element is a block unless `css` says otherwise.
+    pub user_agent: bool,
+    /// Where `src` attributes and `url()` values load images from. Without a cache, every image
+    /// fails to load.
+    pub images: Option<&'a ImageCache>,
+}
+
+impl Default for Page<'_> {
+    fn default() -> Self {
+        Page {
+            html: "",
+            css: "",
+            width: 200.0,
+            height: 0.0,
+            scroll: (0.0, 0.0),
+            user_agent: true,
+            images: None,
+        }
+    }
+}
+
+impl Page<'_> {
+    /// Lay out the document and pass the root box to `check`.
+    pub fn layout(&self, check: impl FnOnce(&mut LayoutBox)) {
+        let root = parser::parse_html(self.html.to_string());
+        let stylesheets = if self.user_agent {
+            vec![
+                parser::parse_css(self.css.to_string(), &root),
+                parser::parse_user_agent_css(),
+            ]
+        } else {
+            let css = format!("* {{ display: block; }} {}", self.css);
+            vec![parser::parse_css(css, &root)]
+        };
+        let styled = style::style_tree(&root, &stylesheets);
+        let default_images = ImageCache::default();
+        let images = self.images.unwrap_or(&default_images);
+        check(&mut layout_scrolled_tree(
+            &styled,
+            self.viewport(),
+            self.scroll,
+            &FontDatabase::new(),
+            images,
+        ));
+    }
+
+    /// Lay out the document and paint it on a canvas the size of the viewport.
+    pub fn paint(&self) -> Canvas {
+        let mut canvas = None;
+        self.layout(|root| {
+            let default_images = ImageCache::default();
+            let images = self.images.unwrap_or(&default_images);
+            canvas = Some(paint(root, self.viewport().content, images));
+        });
+        canvas.unwrap()
+    }
+
+    fn viewport(&self) -> Dimensions {
+        Dimensions {
+            content: Rect {
+                width: self.width,
+                height: self.height,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+}